@@ -3,9 +3,15 @@ use std::path::PathBuf;
 use anyhow::{Context, Result};
 
 pub const IMAGE_NAME: &str = "corrosios.img";
+pub const TEST_IMAGE_NAME: &str = "corrosios-test.img";
 
 pub const BOOTLOADER_PACKAGE_NAME: &str = "efiboot";
 pub const BOOTLOADER_PACKAGE_TARGET: &str = "x86_64-unknown-uefi";
+/// Target directory used for all bootloader builds, relative to the workspace root.
+///
+/// Keeping the bootloader out of the default target directory allows it to be built concurrently
+/// with the kernel, as cargo holds a lock on the target directory for the duration of a build.
+pub const BOOTLOADER_TARGET_DIR: &str = "target/efiboot";
 
 pub const KERNEL_PACKAGE_NAME: &str = "kernel";
 pub const KERNEL_PACKAGE_TARGET: &str = "kernel/kernel/x86_64-corrosios-kernel.json";
@@ -14,6 +20,16 @@ pub const QEMU_FIRMWARE_DIR: &str = "qemu/firmware/x64";
 pub const QEMU_FIRMWARE_CODE: &str = "OVMF_CODE.fd";
 pub const QEMU_FIRMWARE_VARS: &str = "OVMF_VARS.fd";
 
+/// IO port of QEMU's `isa-debug-exit` device; must match the kernel's `DEBUG_EXIT_PORT`.
+pub const QEMU_DEBUG_EXIT_PORT: u16 = 0xf4;
+/// IO port at which `-debugcon` maps QEMU's `isa-debugcon` device.
+pub const QEMU_DEBUGCON_PORT: u16 = 0xe9;
+/// Debug exit code written by the kernel test runner when all tests pass.
+pub const QEMU_TEST_SUCCESS_CODE: u8 = 0x10;
+
+pub const OBJDUMP: &str = "objdump";
+pub const NM: &str = "nm";
+
 pub const GDB_INIT_SCRIPT: &str = "scripts/gdb/x64.gdb";
 pub const GDB_CUSTOM_COMMAND_SCRIPT: &str = "scripts/gdb/custom_commands.py";
 
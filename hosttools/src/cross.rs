@@ -1,40 +1,115 @@
 use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use cargo_metadata::Message;
 use xshell::{cmd, Cmd, Shell};
 
 use crate::config;
 
+/// Runs `cargo {subcommand}` for both the kernel and the bootloader.
+///
+/// The two packages are independent and use separate target directories, so both invocations are
+/// run concurrently. If either of them fails, the first failure (in package order) is reported once
+/// both have exited.
 pub fn cross_run_all(sh: &Shell, subcommand: &str, additional_args: &[String]) -> Result<()> {
-    cross_run(
+    let mut kernel = spawn_cross(
         sh,
         subcommand,
         config::KERNEL_PACKAGE_NAME,
         config::KERNEL_PACKAGE_TARGET,
         additional_args,
     )?;
-    cross_run(
+
+    let bootloader = spawn_cross(
         sh,
         subcommand,
         config::BOOTLOADER_PACKAGE_NAME,
         config::BOOTLOADER_PACKAGE_TARGET,
         additional_args,
+    );
+
+    let mut bootloader = match bootloader {
+        Ok(bootloader) => bootloader,
+        Err(err) => {
+            // Don't leave the kernel build running in the background.
+            let _ = kernel.wait();
+            return Err(err);
+        }
+    };
+
+    let kernel_status = kernel.wait();
+    let bootloader_status = bootloader.wait();
+
+    check_cross_status(sh, subcommand, config::KERNEL_PACKAGE_NAME, kernel_status?)?;
+    check_cross_status(
+        sh,
+        subcommand,
+        config::BOOTLOADER_PACKAGE_NAME,
+        bootloader_status?,
     )
 }
 
+fn spawn_cross(
+    sh: &Shell,
+    subcommand: &str,
+    package_name: &str,
+    target: &str,
+    additional_args: &[String],
+) -> Result<Child> {
+    let cmd = freestanding_cross_cmd(sh, subcommand, package_name, target, additional_args);
+    Command::from(cmd)
+        .spawn()
+        .with_context(|| format!("failed to spawn `cargo {subcommand}` for `{package_name}`"))
+}
+
+fn check_cross_status(
+    sh: &Shell,
+    subcommand: &str,
+    package_name: &str,
+    status: ExitStatus,
+) -> Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+
+    // The compiler's output has already been forwarded to the terminal, so check for the missing
+    // component directly.
+    if !rust_src_installed(sh) {
+        bail!(MISSING_RUST_SRC_MESSAGE);
+    }
+
+    bail!("`cargo {subcommand}` for `{package_name}` failed with status {status}")
+}
+
 pub fn kernel_binary_path(sh: &Shell, additional_args: &[String]) -> Result<PathBuf> {
     built_binary_path(
         sh,
+        "build",
         config::KERNEL_PACKAGE_NAME,
         config::KERNEL_PACKAGE_TARGET,
         additional_args,
     )
 }
 
+/// Builds the kernel's in-tree test runner, returning the path to the resulting kernel binary.
+pub fn kernel_test_binary_path(sh: &Shell, additional_args: &[String]) -> Result<PathBuf> {
+    let mut args = vec!["--no-run".to_owned()];
+    args.extend(additional_args.iter().cloned());
+
+    built_binary_path(
+        sh,
+        "test",
+        config::KERNEL_PACKAGE_NAME,
+        config::KERNEL_PACKAGE_TARGET,
+        &args,
+    )
+}
+
 pub fn bootloader_binary_path(sh: &Shell, additional_args: &[String]) -> Result<PathBuf> {
     built_binary_path(
         sh,
+        "build",
         config::BOOTLOADER_PACKAGE_NAME,
         config::BOOTLOADER_PACKAGE_TARGET,
         additional_args,
@@ -43,16 +118,28 @@ pub fn bootloader_binary_path(sh: &Shell, additional_args: &[String]) -> Result<
 
 fn built_binary_path(
     sh: &Shell,
+    subcommand: &str,
     package_name: &str,
     target: &str,
     additional_args: &[String],
 ) -> Result<PathBuf> {
-    let cmd = freestanding_cross_cmd(sh, "build", package_name, target, additional_args)
+    let cmd = freestanding_cross_cmd(sh, subcommand, package_name, target, additional_args)
         .arg("--message-format=json");
 
-    let output = cmd.output()?.stdout;
+    let output = cmd.ignore_status().output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        eprint!("{stderr}");
 
-    for message in Message::parse_stream(&output[..]) {
+        if is_missing_rust_src(&stderr) {
+            bail!(MISSING_RUST_SRC_MESSAGE);
+        }
+
+        bail!("`cargo {subcommand}` failed with status {}", output.status);
+    }
+
+    for message in Message::parse_stream(&output.stdout[..]) {
         if let Message::CompilerArtifact(artifact) = message? {
             if let Some(path) = artifact.executable {
                 return Ok(path.into());
@@ -63,7 +150,7 @@ fn built_binary_path(
     bail!("failed to extract binary path")
 }
 
-fn cross_run(
+pub fn cross_run(
     sh: &Shell,
     subcommand: &str,
     package_name: &str,
@@ -72,7 +159,36 @@ fn cross_run(
 ) -> Result<()> {
     freestanding_cross_cmd(sh, subcommand, package_name, target, additional_args)
         .run()
-        .with_context(|| format!("`cargo {subcommand}` failed"))
+        .map_err(|err| {
+            // The compiler's output has already been forwarded to the terminal, so check for the
+            // missing component directly.
+            if !rust_src_installed(sh) {
+                anyhow!(MISSING_RUST_SRC_MESSAGE)
+            } else {
+                anyhow!(err).context(format!("`cargo {subcommand}` failed"))
+            }
+        })
+}
+
+const MISSING_RUST_SRC_MESSAGE: &str = "the standard library sources are not installed, which are \
+    required by `-Zbuild-std`; run `rustup component add rust-src`";
+
+/// Checks whether the compiler error output in `stderr` indicates that `-Zbuild-std` failed because
+/// the `rust-src` component is not installed.
+fn is_missing_rust_src(stderr: &str) -> bool {
+    stderr.contains("rustup component add rust-src")
+        || stderr.contains("can't find crate for `core`")
+}
+
+fn rust_src_installed(sh: &Shell) -> bool {
+    let Ok(sysroot) = cmd!(sh, "rustc --print sysroot").quiet().read() else {
+        // Don't report a missing component if we can't tell for sure.
+        return true;
+    };
+
+    PathBuf::from(sysroot)
+        .join("lib/rustlib/src/rust/library/core")
+        .exists()
 }
 
 fn freestanding_cross_cmd<'a>(
@@ -84,8 +200,50 @@ fn freestanding_cross_cmd<'a>(
 ) -> Cmd<'a> {
     let cargo = env!("CARGO");
 
+    let target_dir_args = if package_name == config::BOOTLOADER_PACKAGE_NAME {
+        vec!["--target-dir", config::BOOTLOADER_TARGET_DIR]
+    } else {
+        vec![]
+    };
+
     cmd!(
         sh,
-        "{cargo} {subcommand} -p {package_name} --target {target} -Zbuild-std=core,alloc -Zbuild-std-features=compiler-builtins-mem {additional_args...}"
+        "{cargo} {subcommand} -p {package_name} --target {target} {target_dir_args...} -Zbuild-std=core,alloc -Zbuild-std-features=compiler-builtins-mem {additional_args...}"
     ).quiet()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_rust_src_detected() {
+        let stderr = "\
+error: \"/home/user/.rustup/toolchains/nightly-x86_64-unknown-linux-gnu/lib/rustlib/src/rust/Cargo.lock\" does not exist, unable to build with the standard library, try:
+        rustup component add rust-src --toolchain nightly-x86_64-unknown-linux-gnu
+";
+        assert!(is_missing_rust_src(stderr));
+    }
+
+    #[test]
+    fn missing_core_detected() {
+        let stderr = "\
+   Compiling kernel v0.1.0 (/src/kernel/kernel)
+error[E0463]: can't find crate for `core`
+  |
+  = note: the `x86_64-unknown-uefi` target may not be installed
+";
+        assert!(is_missing_rust_src(stderr));
+    }
+
+    #[test]
+    fn unrelated_failure_not_detected() {
+        let stderr = "\
+   Compiling kernel v0.1.0 (/src/kernel/kernel)
+error[E0425]: cannot find value `foo` in this scope
+ --> kernel/kernel/src/main.rs:10:5
+";
+        assert!(!is_missing_rust_src(stderr));
+        assert!(!is_missing_rust_src(""));
+    }
+}
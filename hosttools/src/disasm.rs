@@ -0,0 +1,89 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use xshell::{cmd, Shell};
+
+use crate::config;
+
+pub struct DisasmOptions<'a> {
+    pub kernel_binary: &'a Path,
+    pub symbol: Option<&'a str>,
+    pub start_address: Option<u64>,
+    pub stop_address: Option<u64>,
+    pub additional_args: &'a [String],
+}
+
+pub fn run_disasm(sh: &Shell, opts: &DisasmOptions<'_>) -> Result<()> {
+    let &DisasmOptions {
+        kernel_binary,
+        symbol,
+        mut start_address,
+        mut stop_address,
+        additional_args,
+    } = opts;
+
+    if let Some(symbol) = symbol {
+        let (start, size) = lookup_symbol(sh, kernel_binary, symbol)?;
+        start_address = Some(start);
+        stop_address = Some(start + size);
+    }
+
+    let mut args = vec![
+        "--disassemble".to_owned(),
+        "--demangle".to_owned(),
+        "--no-show-raw-insn".to_owned(),
+        "-M".to_owned(),
+        "intel".to_owned(),
+    ];
+
+    if let Some(start_address) = start_address {
+        args.push(format!("--start-address={start_address:#x}"));
+    }
+
+    if let Some(stop_address) = stop_address {
+        args.push(format!("--stop-address={stop_address:#x}"));
+    }
+
+    let objdump = config::OBJDUMP;
+    cmd!(
+        sh,
+        "{objdump} {args...} {additional_args...} {kernel_binary}"
+    )
+    .run()
+    .with_context(|| format!("failed to run {objdump}"))
+}
+
+/// Finds the address and size of the symbol `name` in `binary`, matching either its mangled or
+/// demangled (hash-free) name.
+fn lookup_symbol(sh: &Shell, binary: &Path, name: &str) -> Result<(u64, u64)> {
+    let nm = config::NM;
+
+    // Try mangled names first, so that an exact mangled name is never shadowed by a demangled one.
+    for demangle in [false, true] {
+        let demangle_arg = demangle.then_some("--demangle");
+        let output = cmd!(
+            sh,
+            "{nm} --defined-only --print-size {demangle_arg...} {binary}"
+        )
+        .quiet()
+        .read()
+        .with_context(|| format!("failed to run {nm}"))?;
+
+        for line in output.lines() {
+            let mut fields = line.splitn(4, ' ');
+            let (Some(addr), Some(size), Some(_kind), Some(sym_name)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+
+            if sym_name == name {
+                let addr = u64::from_str_radix(addr, 16).context("invalid symbol address")?;
+                let size = u64::from_str_radix(size, 16).context("invalid symbol size")?;
+                return Ok((addr, size));
+            }
+        }
+    }
+
+    Err(anyhow!("symbol '{name}' not found"))
+}
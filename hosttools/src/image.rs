@@ -12,14 +12,16 @@ use gpt::{GptConfig, GptDisk};
 use xshell::Shell;
 
 use crate::config;
-use crate::cross::{bootloader_binary_path, cross_run_all, kernel_binary_path};
+use crate::cross::{
+    bootloader_binary_path, cross_run_all, kernel_binary_path, kernel_test_binary_path,
+};
 
 const KB: u64 = 1024;
 const MB: u64 = KB * KB;
 
 const LB_SIZE: u64 = 512;
 
-const EFI_PARTITION_SIZE: u64 = 10 * MB;
+const EFI_PARTITION_SIZE: u64 = 32 * MB;
 const DISK_SIZE: u64 = EFI_PARTITION_SIZE + 64 * KB;
 
 pub struct ImageBuildOptions<'a> {
@@ -52,13 +54,55 @@ pub fn create_disk_image(
     let bootloader_path = bootloader_binary_path(sh, &build_args)?;
 
     let image_path = bootloader_path.with_file_name(config::IMAGE_NAME);
+    write_disk_image(
+        &image_path,
+        &kernel_path,
+        &bootloader_path,
+        kernel_command_line,
+    )?;
+
+    println!("Created UEFI image: {}", image_path.display());
+
+    Ok(image_path)
+}
+
+/// Creates a bootable UEFI image containing the kernel's test runner instead of the kernel itself.
+pub fn create_test_disk_image(
+    sh: &Shell,
+    build_opts: &ImageBuildOptions<'_>,
+    kernel_command_line: &[u8],
+) -> Result<PathBuf> {
+    let build_args = build_opts.build_args();
+
+    // Locating the binaries builds them as well, so there is no need for a separate build step.
+    let kernel_path = kernel_test_binary_path(sh, &build_args)?;
+    let bootloader_path = bootloader_binary_path(sh, &build_args)?;
+
+    let image_path = bootloader_path.with_file_name(config::TEST_IMAGE_NAME);
+    write_disk_image(
+        &image_path,
+        &kernel_path,
+        &bootloader_path,
+        kernel_command_line,
+    )?;
 
+    println!("Created UEFI test image: {}", image_path.display());
+
+    Ok(image_path)
+}
+
+fn write_disk_image(
+    image_path: &Path,
+    kernel_path: &Path,
+    bootloader_path: &Path,
+    kernel_command_line: &[u8],
+) -> Result<()> {
     let mut disk = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
         .truncate(true)
-        .open(&image_path)?;
+        .open(image_path)?;
     disk.set_len(DISK_SIZE)?;
 
     let mut gdisk = format_gpt(&mut disk).context("failed to format GPT disk")?;
@@ -68,15 +112,11 @@ pub fn create_disk_image(
     let efi_part_data = StreamSlice::new(disk, start, end)?;
     format_efi_partition(
         efi_part_data,
-        &kernel_path,
-        &bootloader_path,
+        kernel_path,
+        bootloader_path,
         kernel_command_line,
     )
-    .context("failed to write EFI system partition")?;
-
-    println!("Created UEFI image: {}", image_path.display());
-
-    Ok(image_path)
+    .context("failed to write EFI system partition")
 }
 
 fn format_gpt(disk: &mut File) -> Result<GptDisk<'_>> {
@@ -1,5 +1,6 @@
 pub mod config;
 pub mod cross;
+pub mod disasm;
 pub mod gdb;
 pub mod image;
 pub mod qemu;
@@ -5,9 +5,10 @@ use clap::{Args, Parser, Subcommand};
 
 use hosttools::config;
 use hosttools::cross::{cross_run_all, kernel_binary_path};
+use hosttools::disasm::{run_disasm, DisasmOptions};
 use hosttools::gdb::{run_gdb, GdbOptions};
-use hosttools::image::{create_disk_image, ImageBuildOptions};
-use hosttools::qemu::{run_qemu, QemuOptions};
+use hosttools::image::{create_disk_image, create_test_disk_image, ImageBuildOptions};
+use hosttools::qemu::{run_qemu, run_qemu_tests, QemuOptions};
 use xshell::{cmd, Shell};
 
 /// Tools for use on the host.
@@ -22,8 +23,10 @@ enum Command {
     Cross(CrossCommand),
     Image(ImageCommand),
     Qemu(QemuCommand),
+    Test(TestCommand),
     GdbAttach(GdbAttachCommand),
     GdbSplit(GdbSplitSubcommand),
+    Disasm(DisasmCommand),
 }
 
 /// Run cargo subcommand with appropriate cross-compilation flags.
@@ -78,6 +81,24 @@ struct QemuCommand {
     image: ImageArgs,
 }
 
+/// Build the kernel test runner and run it in QEMU.
+#[derive(Args)]
+struct TestCommand {
+    /// Amount of memory to give guest
+    #[clap(short = 'm', long = "mem", default_value = "1G")]
+    mem: String,
+
+    /// Enable KVM acceleration
+    #[clap(long)]
+    kvm: bool,
+
+    #[clap(flatten)]
+    machine: MachineArgs,
+
+    #[clap(flatten)]
+    image: ImageArgs,
+}
+
 /// Run QEMU and GDB together in Tilix.
 #[derive(Args)]
 struct GdbSplitSubcommand {
@@ -103,6 +124,9 @@ struct QemuArgs {
     #[clap(long)]
     kvm: bool,
 
+    #[clap(flatten)]
+    machine: MachineArgs,
+
     /// Run in headless mode
     #[clap(long)]
     headless: bool,
@@ -110,6 +134,22 @@ struct QemuArgs {
     /// Serial value to pass to QEMU
     #[clap(long, default_value = "mon:stdio")]
     serial: String,
+
+    /// Forward kernel console output to a QEMU debugcon device attached to the specified character
+    /// device (e.g. `file:debugcon.log`), instead of the serial port
+    #[clap(long)]
+    debugcon: Option<String>,
+}
+
+#[derive(Args)]
+struct MachineArgs {
+    /// QEMU machine type to emulate (e.g. `q35`)
+    #[clap(long)]
+    machine: Option<String>,
+
+    /// CPU model to emulate, with optional feature flags (e.g. `host` or `qemu64,+pdpe1gb`)
+    #[clap(long)]
+    cpu: Option<String>,
 }
 
 /// Attach GDB to a running QEMU instance.
@@ -123,6 +163,28 @@ struct GdbAttachCommand {
     build: BuildArgs,
 }
 
+/// Disassemble the kernel binary with objdump.
+#[derive(Args)]
+struct DisasmCommand {
+    /// Only disassemble the specified symbol (mangled or demangled)
+    #[clap(short = 's', long, conflicts_with_all = ["start", "stop"])]
+    symbol: Option<String>,
+
+    /// Start disassembling at the specified address
+    #[clap(long, value_parser = parse_address)]
+    start: Option<u64>,
+
+    /// Stop disassembling at the specified address
+    #[clap(long, value_parser = parse_address)]
+    stop: Option<u64>,
+
+    /// Additional arguments to pass to objdump
+    additional_args: Vec<String>,
+
+    #[clap(flatten)]
+    build: BuildArgs,
+}
+
 fn main() -> Result<()> {
     let args = Cli::parse();
 
@@ -132,26 +194,52 @@ fn main() -> Result<()> {
     match &args.command {
         Command::Cross(cross) => cross_run_all(&sh, &cross.subcommand, &cross.additional_args),
         Command::Image(image) => {
-            create_disk_image_from_args(&sh, &image.args)?;
+            create_disk_image_from_args(&sh, &image.args, &[])?;
             Ok(())
         }
 
         Command::Qemu(qemu) => {
-            let image_path = create_disk_image_from_args(&sh, &qemu.image)?;
+            let image_path =
+                create_disk_image_from_args(&sh, &qemu.image, &debugcon_kernel_args(&qemu.common))?;
 
             let opts = QemuOptions {
                 image_path: &image_path,
                 mem: &qemu.common.mem,
+                machine: qemu.common.machine.machine.as_deref().unwrap_or_default(),
+                cpu: qemu.common.machine.cpu.as_deref().unwrap_or_default(),
                 enable_gdbserver: qemu.gdbserver,
                 use_kvm: qemu.common.kvm,
                 headless: qemu.common.headless,
                 serial: &qemu.common.serial,
+                debugcon: qemu.common.debugcon.as_deref().unwrap_or_default(),
                 additional_args: &qemu.additional_args,
             };
 
             run_qemu(&sh, &opts)
         }
 
+        Command::Test(test) => {
+            let build_opts = build_opts_from_build_args(&test.image.build);
+            let kernel_command_line =
+                kernel_command_line_from_args(&test.image.kernel_command_line);
+            let image_path = create_test_disk_image(&sh, &build_opts, &kernel_command_line)?;
+
+            let opts = QemuOptions {
+                image_path: &image_path,
+                mem: &test.mem,
+                machine: test.machine.machine.as_deref().unwrap_or_default(),
+                cpu: test.machine.cpu.as_deref().unwrap_or_default(),
+                enable_gdbserver: false,
+                use_kvm: test.kvm,
+                headless: true,
+                serial: "",
+                debugcon: "",
+                additional_args: &[],
+            };
+
+            run_qemu_tests(&sh, &opts)
+        }
+
         Command::GdbAttach(gdb) => {
             let build_opts = build_opts_from_build_args(&gdb.build);
             let kernel_path = kernel_binary_path(&sh, &build_opts.build_args())?;
@@ -169,19 +257,30 @@ fn main() -> Result<()> {
                 additional_build_args: &[],
             };
 
+            let mut kernel_args = gdb_split.kernel_command_line.clone();
+            kernel_args.extend(debugcon_kernel_args(&gdb_split.qemu));
+
             let image_path = create_disk_image(
                 &sh,
                 &image_opts,
-                &kernel_command_line_from_args(&gdb_split.kernel_command_line),
+                &kernel_command_line_from_args(&kernel_args),
             )?;
 
             let qemu_opts = QemuOptions {
                 image_path: &image_path,
                 mem: &gdb_split.qemu.mem,
+                machine: gdb_split
+                    .qemu
+                    .machine
+                    .machine
+                    .as_deref()
+                    .unwrap_or_default(),
+                cpu: gdb_split.qemu.machine.cpu.as_deref().unwrap_or_default(),
                 enable_gdbserver: true,
                 use_kvm: gdb_split.qemu.kvm,
                 headless: gdb_split.qemu.headless,
                 serial: &gdb_split.qemu.serial,
+                debugcon: gdb_split.qemu.debugcon.as_deref().unwrap_or_default(),
                 additional_args: &[],
             };
 
@@ -197,15 +296,44 @@ fn main() -> Result<()> {
 
             run_qemu(&sh, &qemu_opts)
         }
+
+        Command::Disasm(disasm) => {
+            let build_opts = build_opts_from_build_args(&disasm.build);
+            let kernel_path = kernel_binary_path(&sh, &build_opts.build_args())?;
+            let disasm_opts = DisasmOptions {
+                kernel_binary: &kernel_path,
+                symbol: disasm.symbol.as_deref(),
+                start_address: disasm.start,
+                stop_address: disasm.stop,
+                additional_args: &disasm.additional_args,
+            };
+
+            run_disasm(&sh, &disasm_opts)
+        }
     }
 }
 
-fn create_disk_image_from_args(sh: &Shell, args: &ImageArgs) -> Result<PathBuf> {
+fn create_disk_image_from_args(
+    sh: &Shell,
+    args: &ImageArgs,
+    extra_kernel_args: &[String],
+) -> Result<PathBuf> {
     let build_opts = build_opts_from_build_args(&args.build);
-    let kernel_command_line = kernel_command_line_from_args(&args.kernel_command_line);
+    let mut kernel_args = args.kernel_command_line.clone();
+    kernel_args.extend_from_slice(extra_kernel_args);
+    let kernel_command_line = kernel_command_line_from_args(&kernel_args);
     create_disk_image(sh, &build_opts, &kernel_command_line)
 }
 
+/// Returns the kernel arguments needed to route console output to the debugcon device requested in
+/// `args`, if any.
+fn debugcon_kernel_args(args: &QemuArgs) -> Vec<String> {
+    args.debugcon
+        .iter()
+        .map(|_| format!("x86.debugcon={:x}", config::QEMU_DEBUGCON_PORT))
+        .collect()
+}
+
 const DEFAULT_KERNEL_COMMAND_LINE: &[u8] = b"x86.serial=3f8";
 
 fn kernel_command_line_from_args(args: &[String]) -> Vec<u8> {
@@ -225,3 +353,10 @@ fn build_opts_from_build_args(args: &BuildArgs) -> ImageBuildOptions<'_> {
         additional_build_args: &args.additional_build_args,
     }
 }
+
+fn parse_address(s: &str) -> Result<u64, std::num::ParseIntError> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+}
@@ -1,8 +1,12 @@
+use std::io::Read;
 use std::path::{Path, PathBuf};
-use std::{fs, vec};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{fs, thread, vec};
 
-use anyhow::{Context, Result};
-use xshell::{cmd, Shell, TempDir};
+use anyhow::{bail, Context, Result};
+use xshell::{cmd, Cmd, Shell, TempDir};
 
 use crate::config;
 use crate::utils::run_interactive;
@@ -10,16 +14,137 @@ use crate::utils::run_interactive;
 pub struct QemuOptions<'a> {
     pub image_path: &'a Path,
     pub mem: &'a str,
+    /// The QEMU machine type to emulate (e.g. `q35`); empty to use QEMU's default.
+    pub machine: &'a str,
+    /// The CPU model to emulate, optionally followed by feature flags (e.g. `qemu64,+pdpe1gb`);
+    /// empty to use QEMU's default.
+    pub cpu: &'a str,
     pub enable_gdbserver: bool,
     pub use_kvm: bool,
     pub headless: bool,
     pub serial: &'a str,
+    /// The QEMU character device to which the guest's debugcon output is forwarded, such as
+    /// `stdio` or `file:<path>`; empty if the debugcon device should not be present.
+    pub debugcon: &'a str,
     pub additional_args: &'a [String],
 }
 
 pub fn run_qemu(sh: &Shell, opts: &QemuOptions<'_>) -> Result<()> {
     let firmware_paths = get_firmware_paths(sh)?;
+    run_interactive(qemu_cmd(sh, opts, &firmware_paths)).context("failed to start QEMU")
+}
+
+/// Runs a test image in QEMU, succeeding only if the kernel test runner reports that all tests
+/// passed.
+pub fn run_qemu_tests(sh: &Shell, opts: &QemuOptions<'_>) -> Result<()> {
+    match run_qemu_with_debug_exit(sh, opts)? {
+        Some(config::QEMU_TEST_SUCCESS_CODE) => Ok(()),
+        code => bail!("kernel tests failed (kernel exit code {code:?})"),
+    }
+}
+
+/// Runs an image in QEMU with an `isa-debug-exit` device attached, waiting for QEMU to exit.
+///
+/// Returns the code the kernel wrote to the device, or `None` if QEMU exited in some other way
+/// (such as the guest shutting down or QEMU itself failing).
+pub fn run_qemu_with_debug_exit(sh: &Shell, opts: &QemuOptions<'_>) -> Result<Option<u8>> {
+    let firmware_paths = get_firmware_paths(sh)?;
+
+    let debug_exit = format!(
+        "isa-debug-exit,iobase={:#x},iosize=0x01",
+        config::QEMU_DEBUG_EXIT_PORT
+    );
+    let cmd = qemu_cmd(sh, opts, &firmware_paths).args(["-device", &debug_exit]);
+
+    eprintln!("$ {cmd}");
+    let mut cmd: Command = cmd.into();
+    let status = cmd.status().context("failed to start QEMU")?;
+
+    // The device makes QEMU exit with status `(code << 1) | 1`.
+    Ok(status
+        .code()
+        .filter(|status| status & 1 == 1)
+        .and_then(|status| u8::try_from(status >> 1).ok()))
+}
+
+/// Runs an image headlessly in QEMU, capturing everything the guest writes to its first serial
+/// port.
+///
+/// QEMU is killed as soon as `done` returns `true` for the output captured so far, or once
+/// `timeout` has elapsed, whichever comes first. The `headless` and `serial` fields of `opts` are
+/// ignored, as the serial port is always redirected to a pipe.
+///
+/// `done` is also polled periodically while the guest is silent, so it may inspect other outputs,
+/// such as files written by a debugcon device.
+///
+/// Returns the captured output; reaching the timeout is not considered an error, so callers should
+/// inspect the output to determine whether the guest got as far as they expected.
+pub fn run_qemu_captured(
+    sh: &Shell,
+    opts: &QemuOptions<'_>,
+    timeout: Duration,
+    mut done: impl FnMut(&str) -> bool,
+) -> Result<String> {
+    let firmware_paths = get_firmware_paths(sh)?;
 
+    let opts = QemuOptions {
+        headless: false,
+        serial: "stdio",
+        ..*opts
+    };
+    let cmd = qemu_cmd(sh, &opts, &firmware_paths).args(["-display", "none"]);
+
+    eprintln!("$ {cmd}");
+    let mut cmd: Command = cmd.into();
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .context("failed to start QEMU")?;
+
+    let mut stdout = child
+        .stdout
+        .take()
+        .context("failed to capture QEMU output")?;
+    let (sender, receiver) = mpsc::channel();
+
+    let reader = thread::spawn(move || {
+        let mut buf = [0; 1024];
+        while let Ok(len @ 1..) = stdout.read(&mut buf) {
+            if sender.send(buf[..len].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = Instant::now() + timeout;
+    let mut output = Vec::new();
+
+    while !done(&String::from_utf8_lossy(&output)) {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        match receiver.recv_timeout(remaining.min(CAPTURE_POLL_INTERVAL)) {
+            Ok(chunk) => output.extend(chunk),
+            Err(RecvTimeoutError::Timeout) => {}
+            // QEMU exited, so there's nothing more to wait for.
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    // QEMU may already have exited on its own, in which case there's nothing left to kill.
+    let _ = child.kill();
+    child.wait().context("failed to wait for QEMU")?;
+    let _ = reader.join();
+
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+const CAPTURE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn qemu_cmd<'a>(sh: &'a Shell, opts: &QemuOptions<'_>, firmware_paths: &FirmwarePaths) -> Cmd<'a> {
     let disk = format!("file={},format=raw", opts.image_path.display());
     let uefi_flash = format!(
         "if=pflash,format=raw,readonly=on,file={}",
@@ -40,6 +165,14 @@ pub fn run_qemu(sh: &Shell, opts: &QemuOptions<'_>) -> Result<()> {
         extra_args.extend(["-accel", "kvm"]);
     }
 
+    if !opts.machine.is_empty() {
+        extra_args.extend(["-machine", opts.machine]);
+    }
+
+    if !opts.cpu.is_empty() {
+        extra_args.extend(["-cpu", opts.cpu]);
+    }
+
     if opts.headless {
         extra_args.extend(["-nographic"]);
     }
@@ -48,14 +181,18 @@ pub fn run_qemu(sh: &Shell, opts: &QemuOptions<'_>) -> Result<()> {
         extra_args.extend(["-serial", opts.serial]);
     }
 
+    if !opts.debugcon.is_empty() {
+        extra_args.extend(["-debugcon", opts.debugcon]);
+    }
+
     extra_args.extend(opts.additional_args.iter().map(|arg| arg.as_str()));
 
     let mem = opts.mem;
 
-    run_interactive(cmd!(
+    cmd!(
         sh,
         "qemu-system-x86_64 -m {mem} -drive {uefi_flash} -drive {uefi_vars} -drive {disk} {extra_args...}"
-    )).context("failed to start QEMU")
+    )
 }
 
 struct FirmwarePaths {
@@ -0,0 +1,157 @@
+//! Boots the kernel in QEMU and checks that it reaches key milestones.
+//!
+//! These tests require QEMU and a full cross-compilation toolchain, so they are ignored by default.
+//! Run them with `cargo test -p hosttools -- --ignored`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Result;
+use xshell::Shell;
+
+use hosttools::config;
+use hosttools::image::{create_disk_image, ImageBuildOptions};
+use hosttools::qemu::{run_qemu_captured, run_qemu_with_debug_exit, QemuOptions};
+
+const BOOT_TIMEOUT: Duration = Duration::from_secs(60);
+
+const BOOT_MILESTONES: &[&str] = &["corrosios starting", "memory manager initialized"];
+
+/// Returns a shell rooted at the workspace, as the host tools expect.
+fn workspace_shell() -> Result<Shell> {
+    let sh = Shell::new()?;
+    sh.change_dir(config::get_workspace_root()?);
+    Ok(sh)
+}
+
+/// Builds a debug disk image that boots the kernel with `command_line`.
+fn build_image(sh: &Shell, command_line: &[u8]) -> Result<PathBuf> {
+    let build_opts = ImageBuildOptions {
+        release: false,
+        additional_build_args: &[],
+    };
+    create_disk_image(sh, &build_opts, command_line)
+}
+
+/// Returns the options used to boot `image_path` headlessly, with QEMU's default machine, CPU and
+/// devices.
+fn qemu_options(image_path: &Path) -> QemuOptions<'_> {
+    QemuOptions {
+        image_path,
+        mem: "1G",
+        machine: "",
+        cpu: "",
+        enable_gdbserver: false,
+        use_kvm: false,
+        headless: true,
+        serial: "",
+        debugcon: "",
+        additional_args: &[],
+    }
+}
+
+#[test]
+#[ignore = "requires QEMU"]
+fn boot_reaches_milestones() -> Result<()> {
+    let sh = workspace_shell()?;
+
+    let image_path = build_image(&sh, b"x86.serial=3f8")?;
+
+    let opts = qemu_options(&image_path);
+
+    let output = run_qemu_captured(&sh, &opts, BOOT_TIMEOUT, |output| {
+        BOOT_MILESTONES
+            .iter()
+            .all(|milestone| output.contains(milestone))
+    })?;
+
+    for milestone in BOOT_MILESTONES {
+        assert!(
+            output.contains(milestone),
+            "boot milestone {milestone:?} not reached within {BOOT_TIMEOUT:?}; serial output:\n{output}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires QEMU"]
+fn debugcon_captures_console_output() -> Result<()> {
+    let sh = workspace_shell()?;
+
+    let command_line = format!(
+        "x86.serial=3f8 x86.debugcon={:x}",
+        config::QEMU_DEBUGCON_PORT
+    );
+    let image_path = build_image(&sh, command_line.as_bytes())?;
+
+    let temp_dir = sh.create_temp_dir()?;
+    let debugcon_path = temp_dir.path().join("debugcon.log");
+    let debugcon = format!("file:{}", debugcon_path.display());
+
+    let opts = QemuOptions {
+        debugcon: &debugcon,
+        ..qemu_options(&image_path)
+    };
+
+    let read_debugcon = || fs::read_to_string(&debugcon_path).unwrap_or_default();
+
+    run_qemu_captured(&sh, &opts, BOOT_TIMEOUT, |_| {
+        let output = read_debugcon();
+        BOOT_MILESTONES
+            .iter()
+            .all(|milestone| output.contains(milestone))
+    })?;
+
+    let output = read_debugcon();
+    for milestone in BOOT_MILESTONES {
+        assert!(
+            output.contains(milestone),
+            "boot milestone {milestone:?} not reached within {BOOT_TIMEOUT:?}; debugcon output:\n{output}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires QEMU"]
+fn kernel_exits_qemu_with_code() -> Result<()> {
+    const EXIT_CODE: u8 = 0x2a;
+
+    let sh = workspace_shell()?;
+
+    let command_line = format!("x86.serial=3f8 qemu.exit={EXIT_CODE}");
+    let image_path = build_image(&sh, command_line.as_bytes())?;
+
+    let opts = qemu_options(&image_path);
+
+    assert_eq!(run_qemu_with_debug_exit(&sh, &opts)?, Some(EXIT_CODE));
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires QEMU"]
+fn nested_page_fault_is_reported() -> Result<()> {
+    const NESTED_FAULT_MESSAGE: &str = "double fault in page-fault handler";
+
+    let sh = workspace_shell()?;
+
+    let image_path = build_image(&sh, b"x86.serial=3f8 nestedfault")?;
+
+    let opts = qemu_options(&image_path);
+
+    let output = run_qemu_captured(&sh, &opts, BOOT_TIMEOUT, |output| {
+        output.contains(NESTED_FAULT_MESSAGE)
+    })?;
+
+    assert!(
+        output.contains(NESTED_FAULT_MESSAGE),
+        "nested page fault not reported within {BOOT_TIMEOUT:?}; serial output:\n{output}"
+    );
+
+    Ok(())
+}
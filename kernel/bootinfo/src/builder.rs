@@ -5,7 +5,7 @@ use num_utils::align_up;
 use uninit::extension_traits::AsOut;
 use uninit::out_ref::Out;
 
-use crate::{Error, ItemHeader, ItemKind, ITEM_ALIGN};
+use crate::{Error, ItemHeader, ItemKind, ITEM_ALIGN, MAX_ITEM_ALIGN};
 
 pub struct Builder<'a> {
     buffer: Out<'a, [u8]>,
@@ -18,11 +18,6 @@ impl<'a> Builder<'a> {
             return Err(Error::BadAlign);
         }
 
-        let len = buffer.len();
-        if len >= i32::MAX as usize {
-            return Err(Error::BadSize);
-        }
-
         Ok(Self { buffer, off: 0 })
     }
 
@@ -34,45 +29,38 @@ impl<'a> Builder<'a> {
         kind: ItemKind,
         count: usize,
     ) -> Result<&mut [MaybeUninit<T>], Error> {
-        if mem::align_of::<T>() > ITEM_ALIGN {
-            return Err(Error::BadAlign);
-        }
+        // Safety: our caller guarantees that the buffer will be initialized.
+        unsafe { self.reserve_aligned(kind, count, mem::align_of::<T>()) }
+    }
 
+    /// Reserves space for an item of kind `kind` containing `count` elements of type `T`, with the
+    /// payload aligned to at least `align` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BadAlign`] if `align` is not a power of two, exceeds [`MAX_ITEM_ALIGN`] or
+    /// exceeds the alignment of the underlying buffer. Returns [`Error::BadSize`] if there is not
+    /// enough space left in the buffer.
+    ///
+    /// # Safety
+    ///
+    /// The caller must initialize the entire buffer reserved.
+    pub unsafe fn reserve_aligned<T>(
+        &mut self,
+        kind: ItemKind,
+        count: usize,
+        align: usize,
+    ) -> Result<&mut [MaybeUninit<T>], Error> {
         let size = mem::size_of::<T>()
             .checked_mul(count)
             .ok_or(Error::BadSize)?;
 
-        let total_size = size
-            .checked_add(mem::size_of::<ItemHeader>())
-            .ok_or(Error::BadSize)?;
-
-        let off = align_up(self.off, ITEM_ALIGN);
-        let next_off = off.checked_add(total_size).ok_or(Error::BadSize)?;
-
-        if next_off > self.buffer.len() {
-            return Err(Error::BadSize);
-        }
-
-        self.off = next_off;
-
-        // Safety: offset has been checked, pointer is suitably aligned thanks to `align_to_offset`.
-        unsafe {
-            ptr::write(
-                self.buffer.as_mut_ptr().add(off) as *mut _,
-                ItemHeader {
-                    kind,
-                    payload_len: size as u32,
-                },
-            );
-        }
+        let payload_off = self.reserve_raw(kind, size, align.max(mem::align_of::<T>()))?;
 
-        // Safety: alignment and validity of offset checked above.
+        // Safety: alignment and validity of offset checked by `reserve_raw`.
         unsafe {
             Ok(slice::from_raw_parts_mut(
-                self.buffer
-                    .as_mut_ptr()
-                    .add(off + mem::size_of::<ItemHeader>())
-                    .cast(),
+                self.buffer.as_mut_ptr().add(payload_off).cast(),
                 count,
             ))
         }
@@ -92,6 +80,125 @@ impl<'a> Builder<'a> {
         Ok(())
     }
 
+    /// Appends an item of kind `kind` with a payload of `len` bytes aligned to `align`, invoking `f`
+    /// to initialize the payload in place.
+    ///
+    /// `f` receives the reserved payload and must return it once it has been fully initialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slice returned by `f` does not cover the entire reserved payload.
+    pub fn append_with(
+        &mut self,
+        kind: ItemKind,
+        len: usize,
+        align: usize,
+        f: impl for<'b> FnOnce(Out<'b, [u8]>) -> &'b mut [u8],
+    ) -> Result<(), Error> {
+        // Safety: we check below that `f` has initialized the entire buffer.
+        let buf = unsafe { self.reserve_aligned::<u8>(kind, len, align)? };
+        let buf_ptr = buf.as_ptr();
+
+        let init = f(buf.as_out());
+        assert!(
+            ptr::eq(init.as_ptr(), buf_ptr.cast()) && init.len() == len,
+            "initialized slice does not cover reserved buffer"
+        );
+
+        Ok(())
+    }
+
+    /// Appends an item of kind `kind` containing up to `max_count` elements of type `T`, invoking
+    /// `f` to initialize them in place.
+    ///
+    /// `f` receives the entire reserved space and must return the prefix of it that it has
+    /// initialized; this prefix becomes the item's payload, and any remaining space is released.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slice returned by `f` is not a prefix of the reserved space.
+    pub fn append_slice_with<T>(
+        &mut self,
+        kind: ItemKind,
+        max_count: usize,
+        f: impl for<'b> FnOnce(Out<'b, [T]>) -> &'b mut [T],
+    ) -> Result<(), Error> {
+        let header_off = align_up(self.off, ITEM_ALIGN);
+        let buffer_addr = self.buffer.as_ptr() as usize;
+
+        // Safety: only the prefix initialized by `f` is retained below.
+        let buf = unsafe { self.reserve::<T>(kind, max_count)? };
+        let buf_ptr = buf.as_ptr();
+        let payload_off = buf_ptr as usize - buffer_addr;
+
+        let init = f(buf.as_out());
+        assert!(
+            ptr::eq(init.as_ptr(), buf_ptr.cast()) && init.len() <= max_count,
+            "initialized slice not a prefix of reserved buffer"
+        );
+
+        let size = mem::size_of_val(init);
+        self.off = payload_off + size;
+
+        // Safety: the header was written at this offset by `reserve` above.
+        unsafe {
+            (*(self.buffer.as_mut_ptr().add(header_off) as *mut ItemHeader)).payload_len =
+                size as u64;
+        }
+
+        Ok(())
+    }
+
+    /// Writes a header for an item of kind `kind` with a payload of `size` bytes aligned to
+    /// `align`, returning the offset of the payload.
+    fn reserve_raw(&mut self, kind: ItemKind, size: usize, align: usize) -> Result<usize, Error> {
+        if !align.is_power_of_two()
+            || align > MAX_ITEM_ALIGN
+            || self.buffer.as_ptr() as usize % align != 0
+        {
+            return Err(Error::BadAlign);
+        }
+
+        let align = align.max(ITEM_ALIGN);
+
+        let header_off = align_up(self.off, ITEM_ALIGN);
+        let payload_off = header_off
+            .checked_add(mem::size_of::<ItemHeader>())
+            .and_then(|off| off.checked_next_multiple_of(align))
+            .ok_or(Error::BadSize)?;
+        let next_off = payload_off.checked_add(size).ok_or(Error::BadSize)?;
+
+        if next_off > self.buffer.len() {
+            return Err(Error::BadSize);
+        }
+
+        // Safety: offsets have been checked above. Zero any padding before the header and the
+        // payload, as the entire prefix of the buffer is treated as initialized by `finish`.
+        unsafe {
+            let base = self.buffer.as_mut_ptr();
+            ptr::write_bytes(base.add(self.off), 0, header_off - self.off);
+
+            let header_end = header_off + mem::size_of::<ItemHeader>();
+            ptr::write_bytes(base.add(header_end), 0, payload_off - header_end);
+        }
+
+        self.off = next_off;
+
+        // Safety: offset has been checked, pointer is suitably aligned thanks to `align_up`.
+        unsafe {
+            ptr::write(
+                self.buffer.as_mut_ptr().add(header_off) as *mut _,
+                ItemHeader {
+                    kind,
+                    payload_align_log2: align.trailing_zeros(),
+                    payload_len: size as u64,
+                },
+            );
+        }
+
+        Ok(payload_off)
+    }
+
     pub fn finish(self) -> &'a [u8] {
         // Safety: this entire portion of the buffer should have been initialized by previous
         // calls to `append` and the like.
@@ -1,3 +1,5 @@
+use core::fmt;
+
 use struct_enum::struct_enum;
 
 struct_enum! {
@@ -11,6 +13,22 @@ struct_enum! {
     }
 }
 
+impl fmt::Display for MemoryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match *self {
+            Self::RESERVED => "reserved",
+            Self::USABLE => "usable",
+            Self::FIRMWARE_BOOT => "firmware (boot)",
+            Self::FIRMWARE_RUNIME => "firmware (runtime)",
+            Self::ACPI_TABLES => "ACPI tables",
+            Self::UNUSABLE => "unusable",
+            _ => "other",
+        };
+
+        f.write_str(name)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct MemoryRange {
@@ -19,6 +37,19 @@ pub struct MemoryRange {
     pub kind: MemoryKind,
 }
 
+/// Formats the range in units of pages, as they are stored; e.g. `pages 0x100-0x120: usable`.
+impl fmt::Display for MemoryRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "pages {:#x}-{:#x}: {}",
+            self.start_page,
+            self.start_page + self.page_count,
+            self.kind
+        )
+    }
+}
+
 struct_enum! {
     pub struct PixelFormat: u32 {
         RGB = 0;
@@ -36,3 +67,66 @@ pub struct FramebufferInfo {
     pub pixel_stride: u32,
     pub pixel_format: PixelFormat,
 }
+
+impl fmt::Display for FramebufferInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "phys range {:#x}-{:#x}, dimensions {}x{}, stride {}, format {:?}",
+            self.paddr,
+            self.paddr + self.byte_size,
+            self.pixel_width,
+            self.pixel_height,
+            self.pixel_stride,
+            self.pixel_format
+        )
+    }
+}
+
+/// Describes where the loader placed the kernel image.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct KernelImageInfo {
+    /// The physical address at which the image was loaded.
+    pub phys_base: usize,
+    /// The size of the loaded image in memory, in bytes.
+    pub byte_size: usize,
+    /// The physical address of the image's entry point.
+    pub entry: usize,
+    /// The lowest virtual address requested by the image's loadable segments.
+    pub virt_base: usize,
+}
+
+impl fmt::Display for KernelImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "phys range {:#x}-{:#x}, entry {:#x}, virt base {:#x}",
+            self.phys_base,
+            self.phys_base + self.byte_size,
+            self.entry,
+            self.virt_base
+        )
+    }
+}
+
+/// Describes where the loader placed the initial ramdisk.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct InitrdInfo {
+    /// The physical address of the start of the ramdisk, which is always page-aligned.
+    pub paddr: usize,
+    /// The size of the ramdisk, in bytes.
+    pub byte_size: usize,
+}
+
+impl fmt::Display for InitrdInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "phys range {:#x}-{:#x}",
+            self.paddr,
+            self.paddr + self.byte_size
+        )
+    }
+}
@@ -16,14 +16,24 @@ pub enum Error {
     BadAlign,
 }
 
+/// The minimum alignment of every item in the bootinfo, relative to the start of the bootinfo.
 pub const ITEM_ALIGN: usize = 8;
 
+/// The largest payload alignment an item may request.
+///
+/// Payload alignment is relative to the start of the bootinfo, so the bootinfo must be placed at an
+/// address aligned to the largest alignment of its items for their payloads to be aligned in
+/// memory.
+pub const MAX_ITEM_ALIGN: usize = 4096;
+
 struct_enum! {
     pub struct ItemKind: u32 {
         EFI_SYSTEM_TABLE = 1;
         MEMORY_MAP = 2;
         FRAMEBUFFER = 3;
         COMMAND_LINE = 4;
+        KERNEL_IMAGE = 5;
+        INITRD = 6;
     }
 }
 
@@ -31,10 +41,27 @@ struct_enum! {
 #[repr(C)]
 pub struct ItemHeader {
     pub kind: ItemKind,
-    pub payload_len: u32,
+    /// Base-2 logarithm of the payload's alignment, which is never less than [`ITEM_ALIGN`].
+    ///
+    /// The payload starts at the first suitably-aligned offset following the header.
+    pub payload_align_log2: u32,
+    pub payload_len: u64,
+}
+
+impl ItemHeader {
+    /// Returns the alignment of the payload following this header, or `None` if the header
+    /// specifies an invalid alignment.
+    pub fn payload_align(&self) -> Option<usize> {
+        let align = 1usize.checked_shl(self.payload_align_log2)?;
+        (ITEM_ALIGN..=MAX_ITEM_ALIGN)
+            .contains(&align)
+            .then_some(align)
+    }
 }
 
 const _: () = {
     assert!(mem::align_of::<ItemHeader>() <= ITEM_ALIGN);
-    assert!(mem::size_of::<ItemHeader>() == ITEM_ALIGN);
+    assert!(mem::size_of::<ItemHeader>() == 16);
+    assert!(mem::size_of::<ItemHeader>() % ITEM_ALIGN == 0);
+    assert!(MAX_ITEM_ALIGN.is_power_of_two() && MAX_ITEM_ALIGN >= ITEM_ALIGN);
 };
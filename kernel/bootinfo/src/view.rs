@@ -33,7 +33,8 @@ impl<'a> View<'a> {
     ///
     /// # Panics
     ///
-    /// The returned iterator will panic if it encounters malformed bootinfo ()
+    /// The returned iterator will panic if it encounters malformed bootinfo (out-of-bounds items or
+    /// invalid payload alignments).
     pub fn items(&self) -> impl Iterator<Item = ItemView<'a>> + Clone {
         let buffer = self.buffer;
         let size = self.size();
@@ -44,15 +45,21 @@ impl<'a> View<'a> {
                 return None;
             }
 
-            let payload_off = off + mem::size_of::<ItemHeader>();
+            let header_end_off = off + mem::size_of::<ItemHeader>();
 
             // Safety: `ItemHeader` is a POD
             let header: &ItemHeader =
-                unsafe { get_slice_ref(&buffer[off..payload_off]) }.expect("malformed bootinfo");
+                unsafe { get_slice_ref(&buffer[off..header_end_off]) }.expect("malformed bootinfo");
 
-            debug_assert_eq!(payload_off % ITEM_ALIGN, 0);
+            debug_assert_eq!(header_end_off % ITEM_ALIGN, 0);
 
-            let payload_end_off = payload_off + header.payload_len as usize;
+            let payload_align = header.payload_align().expect("malformed bootinfo");
+            let payload_off = align_up(header_end_off, payload_align);
+
+            let payload_end_off = usize::try_from(header.payload_len)
+                .ok()
+                .and_then(|len| payload_off.checked_add(len))
+                .expect("malformed bootinfo");
             let payload = &buffer[payload_off..payload_end_off];
 
             off = align_up(payload_end_off, ITEM_ALIGN);
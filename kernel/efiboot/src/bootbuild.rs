@@ -7,27 +7,34 @@ use bootinfo::item as bootitem;
 use bootinfo::ItemKind;
 use uefi::proto::gop::{self, GraphicsOutput};
 use uefi::table::{BootServices, BootTable};
-use uefi::{MemoryDescriptor, MemoryType, Result, Status};
+use uefi::{MemoryDescriptor, MemoryMapSummary, MemoryType, Result, Status, Tpl};
 
 use crate::page::{alloc_uninit_data, alloc_uninit_pages, PAGE_SIZE};
+use crate::KernelDesc;
 
 const BOOTINFO_FIXED_SIZE: usize = 0x1000;
 const MMAP_EXTRA_ENTRIES: usize = 8;
 
 pub struct BootinfoCtx {
     pub efi_mmap_buf: &'static mut [MaybeUninit<u8>],
-    pub mmap_scratch: &'static mut [MaybeUninit<bootitem::MemoryRange>],
     pub builder: Builder<'static>,
 }
 
-pub fn prepare_bootinfo(
-    command_line: Option<&[u8]>,
-    boot_table: &BootTable,
-) -> Result<BootinfoCtx> {
+pub fn prepare_bootinfo(kernel_desc: &KernelDesc, boot_table: &BootTable) -> Result<BootinfoCtx> {
     let boot_services = boot_table.boot_services();
 
-    let (mmap_size, desc_size) = boot_services.memory_map_size()?;
-    let max_mmap_entries = mmap_size / desc_size + MMAP_EXTRA_ENTRIES;
+    // Keep event notifications (which may allocate) from growing the memory map between measuring
+    // it and allocating the buffer that will eventually hold it.
+    let (max_mmap_entries, efi_mmap_buf) = {
+        // Safety: images are started at `Tpl::APPLICATION`, and the guard is dropped at the end of
+        // this block.
+        let _tpl_guard = unsafe { boot_services.raise_tpl(Tpl::NOTIFY) };
+
+        let (mmap_size, desc_size) = boot_services.memory_map_size()?;
+        let max_mmap_entries = mmap_size / desc_size + MMAP_EXTRA_ENTRIES;
+        let efi_mmap_buf = alloc_uninit_data(boot_services, max_mmap_entries * desc_size)?;
+        (max_mmap_entries, efi_mmap_buf)
+    };
 
     let mut bootinfo_builder = make_bootinfo_builder(boot_services, max_mmap_entries)?;
 
@@ -35,36 +42,73 @@ pub fn prepare_bootinfo(
         append_bootinfo(&mut bootinfo_builder, ItemKind::FRAMEBUFFER, framebuffer)?;
     }
 
-    if let Some(command_line) = command_line {
-        append_bootinfo_slice(&mut bootinfo_builder, ItemKind::COMMAND_LINE, command_line)?;
+    append_bootinfo_slice(
+        &mut bootinfo_builder,
+        ItemKind::COMMAND_LINE,
+        kernel_desc.command_line,
+    )?;
+
+    let image = &kernel_desc.image;
+    append_bootinfo(
+        &mut bootinfo_builder,
+        ItemKind::KERNEL_IMAGE,
+        bootitem::KernelImageInfo {
+            phys_base: image.phys_base as usize,
+            byte_size: image.byte_size as usize,
+            entry: image.entry as usize,
+            virt_base: image.virt_base as usize,
+        },
+    )?;
+
+    if let Some(initrd) = kernel_desc.initrd {
+        append_bootinfo(
+            &mut bootinfo_builder,
+            ItemKind::INITRD,
+            bootitem::InitrdInfo {
+                paddr: initrd.as_ptr() as usize,
+                byte_size: initrd.len(),
+            },
+        )?;
     }
 
     Ok(BootinfoCtx {
-        efi_mmap_buf: alloc_uninit_data(boot_services, max_mmap_entries * desc_size)?,
-        mmap_scratch: alloc_uninit_data(boot_services, max_mmap_entries)?,
+        efi_mmap_buf,
         builder: bootinfo_builder,
     })
 }
 
+/// Appends the final memory map to `builder`.
+///
+/// # Panics
+///
+/// Panics if the map contains no memory that is usable once boot services have been exited, as
+/// the kernel has nowhere to run in that case.
 pub fn append_mmap<'a>(
     builder: &mut Builder<'_>,
     efi_mmap: impl ExactSizeIterator<Item = &'a MemoryDescriptor>,
-    scratch: &mut [MaybeUninit<bootitem::MemoryRange>],
 ) {
-    let tmp_mmap = scratch[..efi_mmap.len()]
-        .as_out()
-        .init_with(efi_mmap.map(|efi_desc| bootitem::MemoryRange {
-            start_page: efi_desc.phys_start as usize / PAGE_SIZE,
-            page_count: efi_desc.page_count as usize,
-            kind: mem_kind_from_efi(efi_desc.mem_type),
-        }));
-
-    tmp_mmap.sort_unstable_by_key(|range| range.start_page);
-    let tmp_mmap = coalesce_mmap(tmp_mmap);
+    let mut summary = MemoryMapSummary::default();
 
     builder
-        .append_slice(ItemKind::MEMORY_MAP, tmp_mmap)
+        .append_slice_with(ItemKind::MEMORY_MAP, efi_mmap.len(), |buf| {
+            // Gather the summary in the same pass used to translate the descriptors.
+            let efi_mmap = efi_mmap.inspect(|efi_desc| summary.add(efi_desc));
+            let mmap = buf.init_with(efi_mmap.map(|efi_desc| bootitem::MemoryRange {
+                start_page: efi_desc.phys_start as usize / PAGE_SIZE,
+                page_count: efi_desc.page_count as usize,
+                kind: mem_kind_from_efi(efi_desc.mem_type),
+            }));
+
+            mmap.sort_unstable_by_key(|range| range.start_page);
+            coalesce_mmap(mmap)
+        })
         .unwrap();
+
+    assert!(
+        summary.largest_usable.is_some(),
+        "no usable memory in {} memory map entries",
+        summary.descriptor_count
+    );
 }
 
 fn coalesce_mmap(mmap: &mut [bootitem::MemoryRange]) -> &mut [bootitem::MemoryRange] {
@@ -11,13 +11,65 @@ use uninit::extension_traits::AsOut;
 
 use crate::page::{self, PAGE_SIZE};
 
-pub fn load_elf(boot_services: &BootServices, file: &mut File<'_>) -> Result<u64> {
+/// Information about an ELF image loaded by [`load_elf`].
+pub struct LoadedElf {
+    /// The physical address at which the image was placed.
+    pub phys_base: u64,
+    /// The size of the image in memory, in bytes.
+    pub byte_size: u64,
+    /// The physical address of the image's entry point.
+    pub entry: u64,
+    /// The lowest virtual address requested by the image's loadable segments.
+    pub virt_base: u64,
+}
+
+/// Loads the ELF image in `file` into memory, returning information about where it was placed.
+///
+/// The image is placed at its preferred physical address (as specified by its program headers) if
+/// possible. If the firmware refuses to allocate that range, the image is placed at an arbitrary
+/// physical address instead; it is then up to the image to relocate itself as necessary. The kernel
+/// derives its physical base from its own instruction pointer, so no additional information needs
+/// to be passed to it in this case.
+pub fn load_elf(boot_services: &BootServices, file: &mut File<'_>) -> Result<LoadedElf> {
     let header = read_header(file)?;
     let pheaders = read_pheaders(boot_services, &header, file)?;
+    let layout = image_layout(&header, &pheaders)?;
 
-    let loadable = pheaders
-        .iter()
-        .filter(|pheader| pheader.ty == SEGMENT_TYPE_LOAD);
+    let buf = alloc_image_pages(boot_services, layout.min_paddr, layout.byte_size as usize)?;
+
+    for pheader in loadable(&pheaders) {
+        load_segment(buf, layout.min_paddr, file, pheader)?;
+    }
+
+    let phys_base = buf.as_ptr() as u64;
+
+    Ok(LoadedElf {
+        phys_base,
+        byte_size: layout.byte_size,
+        entry: header.entry - layout.min_paddr + phys_base,
+        virt_base: layout.virt_base,
+    })
+}
+
+/// The placement of an image's loadable segments in memory, as requested by its program headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ImageLayout {
+    /// The lowest physical address requested by the loadable segments.
+    min_paddr: u64,
+    /// The size of the physical range spanned by the loadable segments, in bytes.
+    byte_size: u64,
+    /// The lowest virtual address requested by the loadable segments.
+    virt_base: u64,
+}
+
+/// Validates the loadable segments described by `pheaders` and computes the memory they span.
+///
+/// # Errors
+///
+/// * `LOAD_ERROR` - The image has no loadable segments, its entry point does not lie within one of
+///   them, or one of them requires an alignment larger than a page.
+fn image_layout(header: &Header, pheaders: &[ProgramHeader]) -> Result<ImageLayout> {
+    let loadable = loadable(pheaders);
 
     let entry_covered = loadable.clone().any(|pheader| {
         (pheader.phys_addr..pheader.phys_addr + pheader.mem_size).contains(&header.entry)
@@ -49,13 +101,39 @@ pub fn load_elf(boot_services: &BootServices, file: &mut File<'_>) -> Result<u64
         return Err(Status::LOAD_ERROR);
     }
 
-    let buf = page::alloc_uninit_pages(boot_services, (max_paddr - min_paddr) as usize)?;
+    let virt_base = loadable
+        .clone()
+        .map(|pheader| pheader.virt_addr)
+        .min()
+        .ok_or(Status::LOAD_ERROR)?;
 
-    for pheader in loadable {
-        load_segment(buf, min_paddr, file, pheader)?;
-    }
+    Ok(ImageLayout {
+        min_paddr,
+        byte_size: max_paddr - min_paddr,
+        virt_base,
+    })
+}
 
-    Ok(header.entry - min_paddr + buf.as_ptr() as u64)
+fn loadable(pheaders: &[ProgramHeader]) -> impl Iterator<Item = &ProgramHeader> + Clone {
+    pheaders
+        .iter()
+        .filter(|pheader| pheader.ty == SEGMENT_TYPE_LOAD)
+}
+
+fn alloc_image_pages(
+    boot_services: &BootServices,
+    preferred_paddr: u64,
+    bytes: usize,
+) -> Result<&'static mut [MaybeUninit<u8>]> {
+    match page::alloc_uninit_pages_at(boot_services, preferred_paddr, bytes) {
+        Ok(buf) => Ok(buf),
+        // The preferred range is either in use or not backed by memory at all; load the image
+        // elsewhere.
+        Err(e) if e.is_not_found() || e.is_invalid_parameter() => {
+            page::alloc_uninit_pages(boot_services, bytes)
+        }
+        Err(e) => Err(e),
+    }
 }
 
 fn load_segment(
@@ -132,3 +210,82 @@ unsafe fn read<T>(file: &mut File<'_>) -> Result<T> {
 
     Ok(unsafe { val.assume_init() })
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use minielf::{ElfBuilder, Segment, SEGMENT_FLAG_EXEC, SEGMENT_FLAG_READ, SEGMENT_FLAG_WRITE};
+
+    use super::*;
+
+    fn test_image() -> ElfBuilder {
+        ElfBuilder::new(0x200010)
+            .segment(Segment::load(
+                SEGMENT_FLAG_READ | SEGMENT_FLAG_EXEC,
+                0x200000,
+                vec![0xcc; 0x30],
+            ))
+            .segment(
+                Segment::load(
+                    SEGMENT_FLAG_READ | SEGMENT_FLAG_WRITE,
+                    0x201000,
+                    vec![1, 2, 3],
+                )
+                .with_mem_size(0x100),
+            )
+    }
+
+    fn layout_of(image: &[u8]) -> Result<ImageLayout> {
+        let elf = minielf::parse(image).unwrap();
+        let pheaders: Vec<_> = elf.program_headers().collect();
+        image_layout(elf.header(), &pheaders)
+    }
+
+    #[test]
+    fn layout_spans_loadable_segments() {
+        let image = test_image()
+            .segment(Segment::note(b"Xen", 1, &[1, 2, 3, 4]))
+            .build();
+        assert_eq!(
+            layout_of(&image),
+            Ok(ImageLayout {
+                min_paddr: 0x200000,
+                byte_size: 0x1100,
+                virt_base: 0x200000,
+            })
+        );
+    }
+
+    #[test]
+    fn layout_uses_lowest_virtual_address() {
+        let mut text = Segment::load(SEGMENT_FLAG_READ | SEGMENT_FLAG_EXEC, 0x200000, vec![0; 8]);
+        text.virt_addr = 0xffffffff80000000;
+        let image = ElfBuilder::new(0x200000).segment(text).build();
+
+        let layout = layout_of(&image).unwrap();
+        assert_eq!(layout.min_paddr, 0x200000);
+        assert_eq!(layout.virt_base, 0xffffffff80000000);
+    }
+
+    #[test]
+    fn layout_rejects_uncovered_entry() {
+        let image = test_image().build_with(|header, _| header.entry = 0x300000);
+        assert_eq!(layout_of(&image), Err(Status::LOAD_ERROR));
+    }
+
+    #[test]
+    fn layout_rejects_image_without_loadable_segments() {
+        let image = ElfBuilder::new(0)
+            .segment(Segment::note(b"Xen", 1, &[1, 2, 3, 4]))
+            .build();
+        assert_eq!(layout_of(&image), Err(Status::LOAD_ERROR));
+    }
+
+    #[test]
+    fn layout_rejects_large_alignment() {
+        let image = test_image().build_with(|_, pheaders| pheaders[1].align = 0x200000);
+        assert_eq!(layout_of(&image), Err(Status::LOAD_ERROR));
+    }
+}
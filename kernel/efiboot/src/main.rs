@@ -1,30 +1,41 @@
 #![feature(alloc_error_handler, allocator_api, new_uninit)]
 #![warn(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
-#![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(test), no_main)]
 
 extern crate alloc;
 
-use core::arch::asm;
+use core::fmt::Write;
 use core::mem;
-use core::panic::PanicInfo;
 
 use alloc::boxed::Box;
-use page::alloc_uninit_data;
+use page::{alloc_uninit_data, alloc_uninit_pages};
 use uefi::proto::fs::{File, OpenMode, SimpleFileSystem};
 use uefi::proto::image::LoadedImage;
+use uefi::proto::io::TextColor;
 use uninit::extension_traits::AsOut;
 
 use bootinfo::ItemKind;
+use elfload::LoadedElf;
 use uefi::table::{BootServices, BootTable};
 use uefi::{u16cstr, BootAlloc, Handle, Result, Status};
 
+#[cfg(not(test))]
+use core::arch::asm;
+#[cfg(not(test))]
+use core::panic::PanicInfo;
+
 mod bootbuild;
 mod elfload;
+#[cfg(not(test))]
 mod global_alloc;
 mod page;
 
+/// The command line passed to the kernel when no `cmdline` file is present alongside it.
+const DEFAULT_COMMAND_LINE: &[u8] = b"x86.serial=3f8";
+
+#[cfg(not(test))]
 fn halt() -> ! {
     unsafe {
         asm!("cli");
@@ -34,6 +45,7 @@ fn halt() -> ! {
     }
 }
 
+#[cfg(not(test))]
 #[panic_handler]
 fn handle_panic(_info: &PanicInfo<'_>) -> ! {
     halt()
@@ -47,8 +59,15 @@ pub extern "efiapi" fn efi_main(image_handle: Handle, boot_table: BootTable) ->
 }
 
 fn run(image_handle: Handle, boot_table: BootTable) -> Result<()> {
-    let kernel_desc = load_kernel(image_handle, boot_table.boot_services())?;
-    let bootinfo_ctx = bootbuild::prepare_bootinfo(kernel_desc.command_line, &boot_table)?;
+    let prepared = load_kernel(image_handle, boot_table.boot_services()).and_then(|kernel_desc| {
+        let bootinfo_ctx = bootbuild::prepare_bootinfo(&kernel_desc, &boot_table)?;
+        Ok((kernel_desc, bootinfo_ctx))
+    });
+
+    let (kernel_desc, bootinfo_ctx) = prepared.map_err(|status| {
+        report_error(&boot_table, status);
+        status
+    })?;
 
     boot_table.exit_boot_services(
         image_handle,
@@ -59,11 +78,11 @@ fn run(image_handle: Handle, boot_table: BootTable) -> Result<()> {
                 .append(ItemKind::EFI_SYSTEM_TABLE, runtime_table)
                 .unwrap();
 
-            bootbuild::append_mmap(&mut builder, mmap, bootinfo_ctx.mmap_scratch);
+            bootbuild::append_mmap(&mut builder, mmap);
 
             let bootinfo_slice = builder.finish();
             let entry: extern "sysv64" fn(usize, usize) -> ! =
-                unsafe { mem::transmute(kernel_desc.kernel_entry) };
+                unsafe { mem::transmute(kernel_desc.image.entry) };
 
             entry(bootinfo_slice.as_ptr() as usize, bootinfo_slice.len());
         },
@@ -71,8 +90,17 @@ fn run(image_handle: Handle, boot_table: BootTable) -> Result<()> {
 }
 
 struct KernelDesc {
-    kernel_entry: u64,
-    command_line: Option<&'static [u8]>,
+    image: LoadedElf,
+    command_line: &'static [u8],
+    initrd: Option<&'static [u8]>,
+}
+
+/// Reports a failure to load the kernel on the firmware console, highlighted in red.
+fn report_error(boot_table: &BootTable, status: Status) {
+    let mut stdout = boot_table.stdout();
+    let _ = stdout.set_attribute(TextColor::LIGHT_RED, TextColor::BLACK);
+    let _ = writeln!(stdout, "failed to load kernel: {status:?}");
+    let _ = stdout.set_attribute(TextColor::LIGHT_GRAY, TextColor::BLACK);
 }
 
 fn load_kernel(image_handle: Handle, boot_services: &BootServices) -> Result<KernelDesc> {
@@ -85,23 +113,25 @@ fn load_kernel(image_handle: Handle, boot_services: &BootServices) -> Result<Ker
     let corrosios_dir = root_dir.open(u16cstr!("corrosios"), OpenMode::READ)?;
 
     let mut kernel_file = corrosios_dir.open(u16cstr!("kernel"), OpenMode::READ)?;
-    let kernel_entry = elfload::load_elf(boot_services, &mut kernel_file)?;
+    let image = elfload::load_elf(boot_services, &mut kernel_file)?;
 
     let command_line = load_command_line(&corrosios_dir, boot_services)?;
+    let initrd = load_initrd(&corrosios_dir, boot_services)?;
 
     Ok(KernelDesc {
-        kernel_entry,
+        image,
         command_line,
+        initrd,
     })
 }
 
 fn load_command_line(
     corrosios_dir: &File<'_>,
     boot_services: &BootServices,
-) -> Result<Option<&'static [u8]>> {
+) -> Result<&'static [u8]> {
     let mut command_line_file = match corrosios_dir.open(u16cstr!("cmdline"), OpenMode::READ) {
         Ok(file) => file,
-        Err(Status::NOT_FOUND) => return Ok(None),
+        Err(e) if e.is_not_found() => return Ok(DEFAULT_COMMAND_LINE),
         Err(e) => return Err(e),
     };
 
@@ -113,5 +143,31 @@ fn load_command_line(
     let command_line = alloc_uninit_data(boot_services, command_line_size)?;
 
     let command_line = command_line_file.read_exact(command_line.as_out())?;
-    Ok(Some(command_line))
+    Ok(command_line)
+}
+
+/// Loads the `initrd` file alongside the kernel into freshly-allocated pages, if it is present.
+fn load_initrd(
+    corrosios_dir: &File<'_>,
+    boot_services: &BootServices,
+) -> Result<Option<&'static [u8]>> {
+    let mut initrd_file = match corrosios_dir.open(u16cstr!("initrd"), OpenMode::READ) {
+        Ok(file) => file,
+        Err(e) if e.is_not_found() => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let info_size = initrd_file.info_size()?;
+    let mut info_buf = Box::new_uninit_slice_in(info_size, BootAlloc::new(boot_services));
+    let info = initrd_file.info(info_buf.as_out())?;
+
+    let initrd_size = info.size() as usize;
+    if initrd_size == 0 {
+        return Ok(None);
+    }
+
+    let initrd = alloc_uninit_pages(boot_services, initrd_size)?;
+
+    let initrd = initrd_file.read_exact(initrd[..initrd_size].as_out())?;
+    Ok(Some(initrd))
 }
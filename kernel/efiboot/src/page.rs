@@ -16,6 +16,22 @@ pub fn alloc_uninit_pages(
     Ok(unsafe { core::slice::from_raw_parts_mut(p as *mut _, pages * PAGE_SIZE) })
 }
 
+/// Allocates enough pages to hold `bytes` bytes at physical address `addr`.
+///
+/// # Errors
+///
+/// Fails if the firmware cannot allocate the requested range, usually with `NOT_FOUND` if any of
+/// the pages are already in use.
+pub fn alloc_uninit_pages_at(
+    boot_services: &BootServices,
+    addr: u64,
+    bytes: usize,
+) -> Result<&'static mut [MaybeUninit<u8>]> {
+    let pages = div_ceil(bytes, PAGE_SIZE);
+    let p = boot_services.alloc_pages(AllocMode::At(addr), pages)?;
+    Ok(unsafe { core::slice::from_raw_parts_mut(p as *mut _, pages * PAGE_SIZE) })
+}
+
 pub fn alloc_uninit_data<T>(
     boot_services: &BootServices,
     len: usize,
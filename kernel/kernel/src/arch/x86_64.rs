@@ -2,13 +2,17 @@ pub mod context;
 pub mod cpu;
 pub mod mm;
 pub mod mmu;
+pub mod power;
+pub mod qemu;
 pub mod serial;
 
 #[macro_use]
 mod interrupt_vectors;
 
 mod boot;
+mod debugcon;
 mod descriptor;
 mod interrupt;
+mod msr;
 mod percpu;
 mod x64_cpu;
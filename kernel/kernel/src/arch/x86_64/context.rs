@@ -1,10 +1,11 @@
 use core::arch::global_asm;
 use core::mem;
-use core::ptr::addr_of_mut;
+use core::ptr::{self, addr_of_mut};
 
 use crate::mm::types::VirtAddr;
 use crate::sync::irq::IrqDisabled;
 
+use super::interrupt::ActivePageFault;
 use super::percpu;
 
 /// The kernel-mode register context saved to the stack when a thread is switched out.
@@ -28,8 +29,14 @@ pub struct InactiveKernelFrame {
 pub struct ThreadContext {
     sp: VirtAddr,
     stack_top: VirtAddr,
+    /// The page fault being handled by the thread when it was switched out, if any.
+    active_page_fault: *const ActivePageFault<'static>,
 }
 
+// Safety: the saved page fault always lives on the thread's own stack, and is only accessed by the
+// thread itself.
+unsafe impl Send for ThreadContext {}
+
 impl ThreadContext {
     /// Creates a new thread context with stack pointer `sp` for entry via `entry_point`.
     ///
@@ -66,7 +73,11 @@ impl ThreadContext {
             push_data(&mut sp, &frame, 1);
         }
 
-        Self { sp, stack_top }
+        Self {
+            sp,
+            stack_top,
+            active_page_fault: ptr::null(),
+        }
     }
 }
 
@@ -90,7 +101,7 @@ impl ThreadContext {
 pub unsafe fn switch(old: *mut ThreadContext, new: *const ThreadContext) {
     unsafe {
         // Safe by function contract.
-        set_common(&*new);
+        (*old).active_page_fault = set_common(&*new);
         do_context_switch(addr_of_mut!((*old).sp), (*new).sp);
     }
 }
@@ -110,10 +121,14 @@ pub unsafe fn set(new: *const ThreadContext) -> ! {
     }
 }
 
-fn set_common(new: &ThreadContext) {
+/// Installs the per-CPU state of `new`, returning the page fault being handled by the outgoing
+/// thread.
+fn set_common(new: &ThreadContext) -> *const ActivePageFault<'static> {
     unsafe {
         let irq_disabled = IrqDisabled::new();
-        (*percpu::current_x64(&irq_disabled).tss.get()).set_rsp0(new.stack_top);
+        let percpu = percpu::current_x64(&irq_disabled);
+        (*percpu.tss.get()).set_rsp0(new.stack_top);
+        percpu.active_page_fault.replace(new.active_page_fault)
     }
 }
 
@@ -4,9 +4,10 @@ use core::mem;
 use crate::sync::irq::IrqDisabled;
 
 use super::descriptor::{get_idt, get_idt_size, init_idt, Gdt, KERNEL_CODE_SELECTOR, TSS_SELECTOR};
+use super::interrupt_vectors::VECTOR_NMI;
 use super::percpu;
 use super::x64_cpu::{
-    cli, get_rflags, hlt, lgdt, lidt, lldt, ltr, sti, DescriptorRegister, Rflags,
+    cli, cpuid, get_rflags, hlt, lgdt, lidt, lldt, ltr, rdtsc, sti, DescriptorRegister, Rflags,
 };
 
 pub use percpu::{disable_resched, enable_resched, resched_disable_count};
@@ -45,6 +46,33 @@ pub unsafe fn enable_irq() {
     }
 }
 
+/// Delivers an NMI to the current core, returning once it has been handled.
+pub fn send_self_nmi() {
+    unsafe {
+        asm!("int {vector}", vector = const VECTOR_NMI);
+    }
+}
+
+/// Returns the current value of the core's timestamp counter.
+///
+/// The counter increases monotonically at a fixed but uncalibrated rate, making it suitable only
+/// for coarse relative timing.
+pub fn read_timestamp() -> u64 {
+    rdtsc()
+}
+
+/// Returns the hardware identifier (initial APIC ID) of the current core.
+pub fn current_hw_id() -> u32 {
+    cpuid(1).ebx >> 24
+}
+
+/// Returns the hardware identifier of the current core as cached in its per-CPU data.
+///
+/// This is cheaper than [`current_hw_id`], but may only be used once per-CPU data has been set up.
+pub fn current_percpu_hw_id() -> u32 {
+    percpu::current_hw_id()
+}
+
 pub fn current_percpu() -> *const () {
     percpu::current_common()
 }
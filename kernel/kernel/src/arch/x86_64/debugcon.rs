@@ -0,0 +1,40 @@
+use super::x64_cpu::outb;
+
+/// The IO port at which QEMU's `isa-debugcon` device is mapped by default.
+pub const DEBUGCON_PORT: u16 = 0xe9;
+
+/// A debug console port, such as QEMU's `isa-debugcon` or Bochs' port `0xe9` hack.
+///
+/// Every byte written to the port is forwarded to the host immediately, with no baud rate or FIFO
+/// to wait for, making it much faster than a UART for high-volume output.
+pub struct DebugconPort {
+    port: u16,
+}
+
+impl DebugconPort {
+    /// Creates a new debug console writing to the IO port `port`.
+    ///
+    /// # Safety
+    ///
+    /// * `port` must either map a debug console device or be unused, so that writes to it have no
+    ///   other side effects.
+    pub unsafe fn new(port: u16) -> Self {
+        Self { port }
+    }
+
+    /// Returns the IO port written by this console.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Writes raw bytes to the port.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            // Safety: the creator of `self` has guaranteed that the write has no side effects other
+            // than producing output.
+            unsafe {
+                outb(self.port, byte);
+            }
+        }
+    }
+}
@@ -7,7 +7,7 @@ use spin_once::Once;
 
 use crate::mm::types::VirtAddr;
 
-use super::interrupt_vectors::{TOTAL_VECTORS, VECTOR_DOUBLE_FAULT, VECTOR_NMI};
+use super::interrupt_vectors::{TOTAL_VECTORS, VECTOR_DOUBLE_FAULT, VECTOR_NMI, VECTOR_SYSCALL};
 
 pub const IOPB_BITS: usize = 0x10000;
 pub const IOPB_BYTES: usize = bitmap::bytes_required(IOPB_BITS);
@@ -167,7 +167,7 @@ pub fn init_idt() {
                 // early stack and clobbers the image.
                 fn [<set_entry_ $vector>](slots: &mut [MaybeUninit<IdtEntry>]) {
                     let entry_point = [<interrupt_vector_ $vector>] as unsafe extern "C" fn() as u64;
-                    slots[$vector].write(make_idt_entry(entry_point, KERNEL_CODE_SELECTOR, get_ist($vector)));
+                    slots[$vector].write(make_idt_entry(entry_point, KERNEL_CODE_SELECTOR, get_ist($vector), get_dpl($vector)));
                 }
 
                 [<set_entry_ $vector>]($slots);
@@ -204,9 +204,17 @@ fn get_ist(vector: u64) -> u8 {
     }
 }
 
-fn make_idt_entry(entry_point: u64, cs_selector: u16, ist: u8) -> IdtEntry {
+fn get_dpl(vector: u64) -> u8 {
+    match vector {
+        VECTOR_SYSCALL => 3,
+        _ => 0,
+    }
+}
+
+fn make_idt_entry(entry_point: u64, cs_selector: u16, ist: u8, dpl: u8) -> IdtEntry {
     let selector = cs_selector as u64;
     let ist = (ist & 0b111) as u64;
+    let dpl = (dpl & 0b11) as u64;
 
     let offset_0_15 = entry_point & 0xffff;
     let offset_16_31 = (entry_point >> 16) & 0xffff;
@@ -215,7 +223,12 @@ fn make_idt_entry(entry_point: u64, cs_selector: u16, ist: u8) -> IdtEntry {
     let flags = IdtFlags::PRESENT | IdtFlags::TYPE_INTERRUPT_64;
 
     [
-        flags.bits() | offset_0_15 | (selector << 16) | (ist << 32) | (offset_16_31 << 48),
+        flags.bits()
+            | offset_0_15
+            | (selector << 16)
+            | (ist << 32)
+            | (dpl << 45)
+            | (offset_16_31 << 48),
         offset_32_63,
     ]
 }
@@ -1,22 +1,51 @@
-use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::{fmt, ptr};
 
+use bitflags::bitflags;
 use log::debug;
 
 use crate::arch::x86_64::x64_cpu::read_cr2;
-use crate::mm::types::{AccessMode, AccessType, VirtAddr};
+use crate::deferred;
+use crate::mm::types::{AccessMode, AccessType, PageFaultInfo, VirtAddr};
 use crate::mm::vm;
+use crate::mp;
 use crate::sched::Thread;
-use crate::sync::irq;
-use crate::sync::resched;
+use crate::sync::irq::{self, IrqDisabled};
+use crate::sync::resched::{self, ReschedDisabled};
+use crate::syscall::{self, SyscallArgs};
 
 use super::interrupt_vectors::{
     VECTOR_ALIGNMENT_CHECK, VECTOR_BOUND, VECTOR_BREAKPOINT, VECTOR_DEBUG, VECTOR_DEVICE_NOT_AVAIL,
     VECTOR_DIVIDE_ERROR, VECTOR_DOUBLE_FAULT, VECTOR_FPU_ERROR, VECTOR_GP_FAULT,
     VECTOR_INVALID_OPCODE, VECTOR_INVALID_TSS, VECTOR_MACHINE_CHECK, VECTOR_NMI, VECTOR_OVERFLOW,
-    VECTOR_PAGE_FAULT, VECTOR_SEGMENT_NP, VECTOR_SIMD_ERROR, VECTOR_STACK_FAULT,
+    VECTOR_PAGE_FAULT, VECTOR_SEGMENT_NP, VECTOR_SIMD_ERROR, VECTOR_STACK_FAULT, VECTOR_SYSCALL,
 };
+use super::percpu;
 use super::x64_cpu::Rflags;
 
+bitflags! {
+    /// The error code pushed by the processor on a page fault.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(transparent)]
+    pub struct PageFaultError: u64 {
+        /// The fault was caused by a protection violation on a present page, rather than by a
+        /// non-present page.
+        const PRESENT = 1 << 0;
+
+        /// The faulting access was a write.
+        const WRITE = 1 << 1;
+
+        /// The faulting access was made in user mode.
+        const USER = 1 << 2;
+
+        /// A reserved bit was set in one of the paging structures.
+        const RESERVED = 1 << 3;
+
+        /// The faulting access was an instruction fetch.
+        const INSTRUCTION_FETCH = 1 << 4;
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 struct InterruptFrame {
@@ -118,26 +147,46 @@ fn handle_double_fault(frame: &InterruptFrame) {
     report_fatal_exception(frame);
 }
 
+/// A page fault currently being handled, recorded so that faults taken by the handler itself can be
+/// diagnosed.
+pub struct ActivePageFault<'a> {
+    frame: &'a InterruptFrame,
+    info: PageFaultInfo,
+}
+
 fn handle_page_fault(frame: &InterruptFrame) {
-    let addr = read_cr2();
+    let error = PageFaultError::from_bits_retain(frame.error_code);
+    let info = decode_page_fault(read_cr2(), error);
 
-    let was_write = (frame.error_code >> 1) & 1 != 0;
-    let was_instr = (frame.error_code >> 4) & 1 != 0;
-    let was_user = (frame.error_code >> 2) & 1 != 0;
+    let fault = ActivePageFault { frame, info };
 
-    let access_type = if was_instr {
-        AccessType::Execute
-    } else if was_write {
-        AccessType::Write
-    } else {
-        AccessType::Read
-    };
+    // Safety: we are running in an interrupt handler, which is entered with interrupts disabled.
+    if let Some(outer) = begin_page_fault(&fault, unsafe { &IrqDisabled::new() }) {
+        panic!(
+            "double fault in page-fault handler: {} {}\n\n{}\n\nwhile handling {} {}\n\n{}",
+            describe_access_type(info.access_type),
+            info.addr,
+            frame,
+            describe_access_type(outer.info.access_type),
+            outer.info.addr,
+            outer.frame
+        );
+    }
+
+    if error.contains(PageFaultError::RESERVED) {
+        panic!(
+            "page fault with reserved page table bits set: {} {}\n\n{}",
+            describe_access_type(info.access_type),
+            info.addr,
+            frame
+        );
+    }
 
     if !resched::enabled_in_irq() || !frame.rflags.contains(Rflags::IF) {
         panic!(
             "page fault with rescheduling disabled: {} {}\n\n{}",
-            describe_access_type(access_type),
-            addr,
+            describe_access_type(info.access_type),
+            info.addr,
             frame
         );
     }
@@ -148,22 +197,23 @@ fn handle_page_fault(frame: &InterruptFrame) {
         irq::enable();
     }
 
-    let access_mode = match was_user {
-        true => AccessMode::User,
-        false => AccessMode::Kernel,
-    };
-
-    if let Err(err) = vm::page_fault(addr, access_type) {
-        let mode_str = match access_mode {
+    if let Err(err) = vm::page_fault(&info) {
+        let mode_str = match info.access_mode {
             AccessMode::User => "user",
             AccessMode::Kernel => "kernel",
         };
 
+        let cause_str = match info.was_present {
+            true => "protection violation",
+            false => "page not present",
+        };
+
         panic!(
-            "fatal page fault: {}-mode {} {}: {:?}\n\n{}",
+            "fatal page fault: {}-mode {} {} ({}): {:?}\n\n{}",
             mode_str,
-            describe_access_type(access_type),
-            addr,
+            describe_access_type(info.access_type),
+            info.addr,
+            cause_str,
             err,
             frame
         );
@@ -171,6 +221,62 @@ fn handle_page_fault(frame: &InterruptFrame) {
 
     // Disable interrupts again before executing the general interrupt-return path.
     irq::disable();
+
+    // Safety: we have just disabled interrupts.
+    end_page_fault(unsafe { &IrqDisabled::new() });
+}
+
+/// Records `fault` as the page fault being handled by the current thread.
+///
+/// If the thread was already handling a page fault, returns that fault and leaves it recorded
+/// instead.
+fn begin_page_fault<'a>(
+    fault: &ActivePageFault<'_>,
+    irq_disabled: &'a IrqDisabled,
+) -> Option<&'a ActivePageFault<'a>> {
+    let active = &percpu::current_x64(irq_disabled).active_page_fault;
+
+    // Safety: any recorded fault belongs to a handler that has been interrupted further up the
+    // current thread's stack, and so is still alive.
+    if let Some(outer) = unsafe { active.get().as_ref() } {
+        return Some(outer);
+    }
+
+    // The lifetime is erased here; the entry is cleared by `end_page_fault` before `fault` goes out
+    // of scope.
+    active.set((fault as *const ActivePageFault<'_>).cast());
+    None
+}
+
+/// Clears the page fault recorded by [`begin_page_fault`], once it has been handled.
+fn end_page_fault(irq_disabled: &IrqDisabled) {
+    percpu::current_x64(irq_disabled)
+        .active_page_fault
+        .set(ptr::null());
+}
+
+/// Decodes a page fault on `addr` with error code `error` into an architecture-independent form.
+fn decode_page_fault(addr: VirtAddr, error: PageFaultError) -> PageFaultInfo {
+    let access_type = if error.contains(PageFaultError::INSTRUCTION_FETCH) {
+        AccessType::Execute
+    } else if error.contains(PageFaultError::WRITE) {
+        AccessType::Write
+    } else {
+        AccessType::Read
+    };
+
+    let access_mode = if error.contains(PageFaultError::USER) {
+        AccessMode::User
+    } else {
+        AccessMode::Kernel
+    };
+
+    PageFaultInfo {
+        addr,
+        access_type,
+        access_mode,
+        was_present: error.contains(PageFaultError::PRESENT),
+    }
 }
 
 fn describe_access_type(access_type: AccessType) -> &'static str {
@@ -212,10 +318,110 @@ fn exception_vector_to_str(vector: u64) -> &'static str {
     }
 }
 
-unsafe fn handle_nmi(_frame: &mut InterruptFrame) {}
+unsafe fn handle_nmi(frame: &mut InterruptFrame) {
+    // Safety: we are running in an interrupt handler, so nothing can reschedule us.
+    let resched_disabled = unsafe { ReschedDisabled::new_unchecked() };
+
+    if mp::take_nmi_dump_request(&resched_disabled) {
+        dump_nmi_state(frame, &resched_disabled);
+    }
+}
+
+fn dump_nmi_state(frame: &InterruptFrame, resched_disabled: &ReschedDisabled) {
+    let cpu_num = mp::current_percpu(resched_disabled).cpu_num;
+
+    // Note: we may have interrupted code holding the console lock, so use forced output.
+    let printed = Thread::try_with_current(resched_disabled, |thread| {
+        force_println!("cpu {cpu_num}: NMI in thread '{}'", thread.name())
+    });
+    if printed.is_none() {
+        force_println!("cpu {cpu_num}: NMI with no current thread");
+    }
+    force_println!("{frame}");
+}
+
+/// Handles a system call made via [`VECTOR_SYSCALL`].
+///
+/// The system call number is passed in `rax` and arguments in `rdi`, `rsi`, `rdx`, `r10`, `r8` and
+/// `r9`. On return, `rax` holds the status (0 on success or an error code on failure) and `rdx`
+/// holds the returned value.
+fn handle_syscall(frame: &mut InterruptFrame) {
+    let args = SyscallArgs([
+        frame.rdi, frame.rsi, frame.rdx, frame.r10, frame.r8, frame.r9,
+    ]);
+
+    // Run the handler with interrupts enabled if the caller was in a context that allowed it.
+    let enable_irq = resched::enabled_in_irq() && frame.rflags.contains(Rflags::IF);
+    if enable_irq {
+        // Safety: the caller was running with interrupts enabled, and we were the ones who disabled
+        // them upon entry.
+        unsafe {
+            irq::enable();
+        }
+    }
+
+    let (status, value) = match syscall::dispatch(frame.rax, &args) {
+        Ok(value) => (0, value),
+        Err(err) => (err.to_raw() as u64, 0),
+    };
+
+    if enable_irq {
+        // Disable interrupts again before executing the general interrupt-return path.
+        irq::disable();
+    }
+
+    frame.rax = status;
+    frame.rdx = value;
+}
+
+/// IRQ vectors that have been received but not yet logged, one bit per vector.
+static UNLOGGED_IRQS: [AtomicU64; 4] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Whether [`log_irqs`] is already queued to run.
+static IRQ_LOG_QUEUED: AtomicBool = AtomicBool::new(false);
 
 unsafe fn handle_irq(frame: &mut InterruptFrame) {
-    debug!("got IRQ {}", frame.vector);
+    // Safety: we are running in an interrupt handler, so interrupts are disabled.
+    let irq_disabled = unsafe { IrqDisabled::new_unchecked() };
+
+    // Logging can be slow, so leave it to deferred work instead of doing it here.
+    let vector = frame.vector as usize;
+    UNLOGGED_IRQS[vector / 64].fetch_or(1 << (vector % 64), Ordering::Relaxed);
+    if !IRQ_LOG_QUEUED.swap(true, Ordering::Relaxed)
+        && deferred::defer(&irq_disabled, log_irqs).is_err()
+    {
+        // The queue is full; try again on the next IRQ.
+        IRQ_LOG_QUEUED.store(false, Ordering::Relaxed);
+    }
+
+    // Run any work deferred by the handler before returning, as long as we aren't returning to a
+    // context that has interrupts disabled. Work that can't be run now will be picked up by the
+    // next eligible IRQ.
+    if resched::enabled_in_irq() && frame.rflags.contains(Rflags::IF) {
+        // Safety: we just checked that the interrupted context had interrupts and rescheduling
+        // enabled.
+        unsafe {
+            deferred::run_pending();
+        }
+    }
+}
+
+fn log_irqs() {
+    IRQ_LOG_QUEUED.store(false, Ordering::Relaxed);
+
+    for (i, unlogged) in UNLOGGED_IRQS.iter().enumerate() {
+        let mut vectors = unlogged.swap(0, Ordering::Relaxed);
+        while vectors != 0 {
+            let vector = i * 64 + vectors.trailing_zeros() as usize;
+            vectors &= vectors - 1;
+            rate_limited!(debug!("got IRQ {}", vector));
+        }
+    }
 }
 
 #[no_mangle]
@@ -225,6 +431,8 @@ unsafe extern "C" fn handle_interrupt(frame: &mut InterruptFrame) {
             handle_nmi(frame);
         } else if frame.vector < 32 {
             handle_exception(frame);
+        } else if frame.vector == VECTOR_SYSCALL {
+            handle_syscall(frame);
         } else {
             handle_irq(frame);
         }
@@ -286,3 +494,132 @@ pub mod entry_points {
     for_each_interrupt!(interrupt_stub);
     global_asm!(include_str!("interrupt.s"));
 }
+
+#[cfg(test)]
+mod tests {
+    use core::arch::asm;
+
+    use core::mem;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::err::Error;
+    use crate::syscall::SYSCALL_ECHO;
+
+    use super::*;
+
+    fn raw_syscall(num: u64, arg0: u64) -> (u64, u64) {
+        let status;
+        let value;
+
+        unsafe {
+            asm!(
+                "int {vector}",
+                vector = const VECTOR_SYSCALL,
+                inout("rax") num => status,
+                in("rdi") arg0,
+                lateout("rdx") value,
+            );
+        }
+
+        (status, value)
+    }
+
+    const VECTOR_TEST_IRQ: u64 = 0x40;
+
+    static DEFERRED_RAN: AtomicBool = AtomicBool::new(false);
+    static DEFERRED_RAN_WITH_IRQ: AtomicBool = AtomicBool::new(false);
+
+    #[test_case]
+    fn irq_runs_deferred_work_with_irq_enabled() {
+        fn work() {
+            DEFERRED_RAN_WITH_IRQ.store(irq::enabled(), Ordering::Relaxed);
+            DEFERRED_RAN.store(true, Ordering::Relaxed);
+        }
+
+        irq::disable_with(|irq_disabled| {
+            deferred::defer(irq_disabled, work).unwrap();
+        });
+
+        // Work may already have been drained by a real interrupt by now, but it must have run by
+        // the time this one returns.
+        unsafe {
+            asm!("int {vector}", vector = const VECTOR_TEST_IRQ);
+        }
+
+        assert!(DEFERRED_RAN.load(Ordering::Relaxed));
+        assert!(DEFERRED_RAN_WITH_IRQ.load(Ordering::Relaxed));
+    }
+
+    #[test_case]
+    fn page_fault_error_decoding() {
+        use AccessMode::{Kernel, User};
+        use AccessType::{Execute, Read, Write};
+
+        // (error code, access type, access mode, was present)
+        let cases = [
+            (0b00000, Read, Kernel, false),
+            (0b00001, Read, Kernel, true),
+            (0b00010, Write, Kernel, false),
+            (0b00011, Write, Kernel, true),
+            (0b00100, Read, User, false),
+            (0b00101, Read, User, true),
+            (0b00110, Write, User, false),
+            (0b00111, Write, User, true),
+            (0b01000, Read, Kernel, false),
+            (0b01111, Write, User, true),
+            (0b10000, Execute, Kernel, false),
+            (0b10001, Execute, Kernel, true),
+            (0b10101, Execute, User, true),
+            // Instruction fetches take precedence over a (bogus) write bit.
+            (0b10010, Execute, Kernel, false),
+            (0b11111, Execute, User, true),
+            // Unknown bits are ignored.
+            (0b1000_0000_0000_0000_0110, Write, User, false),
+        ];
+
+        let addr = VirtAddr::new(0x1000);
+
+        for (error_code, access_type, access_mode, was_present) in cases {
+            let info = decode_page_fault(addr, PageFaultError::from_bits_retain(error_code));
+
+            assert_eq!(info.addr, addr);
+            assert_eq!(info.access_type, access_type, "{error_code:#b}");
+            assert_eq!(info.access_mode, access_mode, "{error_code:#b}");
+            assert_eq!(info.was_present, was_present, "{error_code:#b}");
+        }
+    }
+
+    #[test_case]
+    fn nested_page_faults_are_detected() {
+        // Safety: all-zero bit patterns are valid for every field of the frame.
+        let frame: InterruptFrame = unsafe { mem::zeroed() };
+        let outer = ActivePageFault {
+            frame: &frame,
+            info: decode_page_fault(VirtAddr::new(0x1000), PageFaultError::empty()),
+        };
+        let inner = ActivePageFault {
+            frame: &frame,
+            info: decode_page_fault(VirtAddr::new(0x2000), PageFaultError::WRITE),
+        };
+
+        irq::disable_with(|irq_disabled| {
+            assert!(begin_page_fault(&outer, irq_disabled).is_none());
+
+            let nested = begin_page_fault(&inner, irq_disabled).unwrap();
+            assert_eq!(nested.info.addr, outer.info.addr);
+
+            end_page_fault(irq_disabled);
+            assert!(begin_page_fault(&inner, irq_disabled).is_none());
+            end_page_fault(irq_disabled);
+        });
+    }
+
+    #[test_case]
+    fn syscall_returns_value() {
+        assert_eq!(raw_syscall(SYSCALL_ECHO, 0x1234), (0, 0x1234));
+        assert_eq!(
+            raw_syscall(u64::MAX, 0),
+            (Error::INVALID_ARGUMENT.to_raw() as u64, 0)
+        );
+    }
+}
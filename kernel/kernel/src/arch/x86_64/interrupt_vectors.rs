@@ -19,6 +19,9 @@ pub const VECTOR_ALIGNMENT_CHECK: u64 = 17;
 pub const VECTOR_MACHINE_CHECK: u64 = 18;
 pub const VECTOR_SIMD_ERROR: u64 = 19;
 
+/// The software interrupt vector used for system calls, invocable from user mode.
+pub const VECTOR_SYSCALL: u64 = 0x80;
+
 macro_rules! for_each_interrupt {
     ($vector:ident $(, $ctx:tt)?) => {
         // Faults/exceptions (and NMI :))
@@ -5,16 +5,15 @@ use core::slice;
 use bitflags::bitflags;
 use log::trace;
 
-use crate::arch::x86_64::x64_cpu::write_pat;
 use crate::kimage;
 use crate::mm::physmap::pfn_to_physmap;
 use crate::mm::pmm;
 use crate::mm::types::{CacheMode, PageTablePerms, PhysFrameNum, VirtAddr, VirtPageNum};
 use crate::sync::irq::IrqDisabled;
 
+use super::msr::{Ia32Efer, IA32_EFER, IA32_MTRR_DEF_TYPE, IA32_PAT};
 use super::x64_cpu::{
-    read_cr0, read_cr3, read_cr4, read_ia32_efer, read_mtrr_def_type, wbinvd, write_cr0, write_cr3,
-    write_cr4, write_ia32_efer, write_mtrr_def_type, Cr0, Cr4, Ia32Efer,
+    read_cr0, read_cr3, read_cr4, wbinvd, write_cr0, write_cr3, write_cr4, Cr0, Cr4,
 };
 
 pub const PAGE_SHIFT: usize = 12;
@@ -29,35 +28,56 @@ pub const PT_LEVEL_MASK: usize = PT_ENTRY_COUNT - 1;
 const MTRR_DEF_TYPE_E: u64 = 1 << 11;
 const MTRR_DEF_TYPE_TYPE_MASK: u64 = 0xff;
 
-const MEM_TYPE_UC: u64 = 0;
-const MEM_TYPE_WC: u64 = 1;
-const MEM_TYPE_WT: u64 = 4;
-const MEM_TYPE_WB: u64 = 6;
-const MEM_TYPE_UC_WEAK: u64 = 6;
+/// Memory types as encoded in the PAT and MTRRs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum MemType {
+    Uc = 0,
+    Wc = 1,
+    Wt = 4,
+    Wb = 6,
+    /// UC-, which can be overridden to WC by the MTRRs. This encoding is only valid in the PAT
+    /// (7 is reserved in the MTRRs), and must not be confused with WB (6).
+    UcWeak = 7,
+}
 
 // We use the hardware (boot-up) defaults for most of the PAT entries, but change one to support
-// WC.
-const PA0_VAL: u64 = MEM_TYPE_WB; // Default
-const PA1_VAL: u64 = MEM_TYPE_WT; // Default
-const PA2_VAL: u64 = MEM_TYPE_UC_WEAK; // Default
-const PA3_VAL: u64 = MEM_TYPE_UC; // Default
-const PA4_VAL: u64 = MEM_TYPE_WB; // Default
-const PA5_VAL: u64 = MEM_TYPE_WT; // Default
-const PA6_VAL: u64 = MEM_TYPE_UC_WEAK; // Default
-const PA7_VAL: u64 = MEM_TYPE_WC; // Weakened from default UC
-
-// Keep these in sync with the `PA` values above!
-
-// This should always be 0 so we have a safe default if someone mapping a page ignores the PAT bits.
-const PAT_SELECTOR_WB: u64 = 0;
-const PAT_SELECTOR_WT: u64 = 1;
-const PAT_SELECTOR_UC: u64 = 3;
-const PAT_SELECTOR_WC: u64 = 7;
+// WC. The PTE bits for each cache mode are derived from this table by `pat_selector_for`.
+const PAT: [MemType; 8] = [
+    MemType::Wb,     // Default
+    MemType::Wt,     // Default
+    MemType::UcWeak, // Default
+    MemType::Uc,     // Default
+    MemType::Wb,     // Default
+    MemType::Wt,     // Default
+    MemType::UcWeak, // Default
+    MemType::Wc,     // Weakened from default UC
+];
+
+const _: () = {
+    // Entry 0 should always be WB so we have a safe default if someone mapping a page ignores the
+    // PAT bits.
+    assert!(matches!(pat_selector_for(MemType::Wb), Some(0)));
+
+    // Every cache mode we hand out must be present in the table.
+    assert!(pat_selector_for(mem_type_for_cache_mode(CacheMode::Cached)).is_some());
+    assert!(pat_selector_for(mem_type_for_cache_mode(CacheMode::WriteThrough)).is_some());
+    assert!(pat_selector_for(mem_type_for_cache_mode(CacheMode::WriteCombining)).is_some());
+    assert!(pat_selector_for(mem_type_for_cache_mode(CacheMode::Uncached)).is_some());
+};
 
 const PT_RANGE: usize = 1 << (PT_LEVEL_SHIFT + PAGE_SHIFT);
 const MB: usize = 0x100000;
 const PADDR_MASK: u64 = (1u64 << 52) - 1;
 
+// Non-present PTEs are entirely ignored by the processor apart from the present bit, so we use bit
+// 9 to mark "special" entries and stash their tag in bits 12-62.
+const SPECIAL_PTE_MARKER: u64 = 1 << 9;
+const SPECIAL_PTE_TAG_SHIFT: u32 = 12;
+
+/// The number of bits available for tags stored in special PTEs.
+pub const SPECIAL_PTE_TAG_BITS: u32 = 51;
+
 // Note: keep in sync with linker script and early mapping in `boot.s`
 const KERNEL_MAX: usize = 8 * MB;
 const KERNEL_PT_COUNT: usize = KERNEL_MAX / PT_RANGE;
@@ -86,6 +106,10 @@ impl PageTableSpace {
             entries: UnsafeCell::new([make_empty_pte(); PT_ENTRY_COUNT]),
         }
     }
+
+    pub fn entries_mut(&mut self) -> &mut [PageTableEntry] {
+        self.entries.get_mut()
+    }
 }
 
 // Safety: this structure exists only to reserve BSS space for page tables, all accesses require
@@ -259,14 +283,36 @@ pub const fn make_empty_pte() -> PageTableEntry {
     PageTableEntry(0)
 }
 
+/// Creates a special non-present PTE carrying `tag`.
+///
+/// Special PTEs never translate addresses, but are distinguishable from empty PTEs so that the
+/// page table code can preserve them and report them on faults.
+///
+/// `tag` must fit in [`SPECIAL_PTE_TAG_BITS`] bits.
+pub fn make_special_pte(tag: u64) -> PageTableEntry {
+    debug_assert!(
+        tag >> SPECIAL_PTE_TAG_BITS == 0,
+        "special PTE tag {tag:#x} too large"
+    );
+    PageTableEntry((tag << SPECIAL_PTE_TAG_SHIFT) | SPECIAL_PTE_MARKER)
+}
+
 /// Creates leaf a PTE mapping `frame` with permissions `perms` for use with the specified page
 /// table level.
+///
+/// Present x86 PTEs are always readable, so `perms` must grant at least one kind of access;
+/// inaccessible pages should be left unmapped instead.
 pub fn make_terminal_pte(
     level: usize,
     frame: PhysFrameNum,
     perms: PageTablePerms,
     cache_mode: CacheMode,
 ) -> PageTableEntry {
+    debug_assert!(
+        perms.intersects(PageTablePerms::READ | PageTablePerms::WRITE | PageTablePerms::EXECUTE),
+        "x86 cannot express present no-access mappings"
+    );
+
     let mut x86_flags = X86PageTableFlags::PRESENT | flags_from_perms(perms);
 
     x86_flags.set(X86PageTableFlags::LARGE, level > 0);
@@ -274,7 +320,7 @@ pub fn make_terminal_pte(
     PageTableEntry(
         frame.addr().as_u64()
             | x86_flags.bits()
-            | pat_selector_to_pte_bits(pat_selector_for_cache_mode(cache_mode)),
+            | pat_selector_to_pte_bits(pat_selector_for_cache_mode(cache_mode), level),
     )
 }
 
@@ -294,14 +340,26 @@ pub fn update_pte_perms(
     PageTableEntry((pte.0 & !X86PageTableFlags::PERMS_MASK.bits()) | flags_from_perms(perms).bits())
 }
 
-pub fn get_pte_frame(pte: PageTableEntry, _level: usize) -> PhysFrameNum {
-    PhysFrameNum::new(((pte.0 & PADDR_MASK) >> PAGE_SHIFT) as usize)
+pub fn get_pte_frame(pte: PageTableEntry, level: usize) -> PhysFrameNum {
+    // Large pages store their PAT bit in what would otherwise be the lowest address bit.
+    let mask = if level > 0 && pte_is_terminal(pte, level) {
+        PADDR_MASK & !PTE_PAT_LARGE
+    } else {
+        PADDR_MASK
+    };
+    PhysFrameNum::new(((pte.0 & mask) >> PAGE_SHIFT) as usize)
 }
 
 pub fn pte_is_present(pte: PageTableEntry, _level: usize) -> bool {
     X86PageTableFlags::from_bits_truncate(pte.0).contains(X86PageTableFlags::PRESENT)
 }
 
+/// Returns the tag stored in `pte` if it is a special PTE created by [`make_special_pte`].
+pub fn pte_special_tag(pte: PageTableEntry, level: usize) -> Option<u64> {
+    (!pte_is_present(pte, level) && pte.0 & SPECIAL_PTE_MARKER != 0)
+        .then_some(pte.0 >> SPECIAL_PTE_TAG_SHIFT)
+}
+
 pub fn pte_is_terminal(pte: PageTableEntry, level: usize) -> bool {
     if level == 0 {
         true
@@ -321,7 +379,7 @@ fn init_mmu_regs() {
         let cr4 = read_cr4();
         write_cr4(cr4 & !Cr4::PGE);
 
-        write_ia32_efer(read_ia32_efer() | Ia32Efer::NXE);
+        IA32_EFER.update(|efer| efer | Ia32Efer::NXE);
     }
 }
 
@@ -342,28 +400,19 @@ fn init_pat() {
         write_cr3(read_cr3());
 
         // 8. Disable all MTRRs by clearing the `E` flag in `MTRR_DEF_TYPE`
-        let mut mtrr_def_type = read_mtrr_def_type();
-        write_mtrr_def_type(mtrr_def_type & !MTRR_DEF_TYPE_E);
+        let mut mtrr_def_type = IA32_MTRR_DEF_TYPE.read();
+        IA32_MTRR_DEF_TYPE.write(mtrr_def_type & !MTRR_DEF_TYPE_E);
 
         // 9. Update the MTRRs and PAT
 
-        write_pat(
-            PA0_VAL
-                | (PA1_VAL << 8)
-                | (PA2_VAL << 16)
-                | (PA3_VAL << 24)
-                | (PA4_VAL << 32)
-                | (PA5_VAL << 40)
-                | (PA6_VAL << 48)
-                | (PA7_VAL << 56),
-        );
+        IA32_PAT.write(pat_msr_value());
 
         // Override the default memory type to UC for consistency, all of our page tables should be
         // mapping WB (PAT index 0) by default anyway.
-        mtrr_def_type = (mtrr_def_type & !MTRR_DEF_TYPE_TYPE_MASK) | MEM_TYPE_UC;
+        mtrr_def_type = (mtrr_def_type & !MTRR_DEF_TYPE_TYPE_MASK) | MemType::Uc as u64;
 
         // 10. Re-enable MTRRs
-        write_mtrr_def_type(mtrr_def_type);
+        IA32_MTRR_DEF_TYPE.write(mtrr_def_type);
 
         // 11. Flush caches and TLB once more
         wbinvd();
@@ -400,16 +449,140 @@ fn flags_from_perms(perms: PageTablePerms) -> X86PageTableFlags {
     x86_flags
 }
 
-fn pat_selector_for_cache_mode(cache_mode: CacheMode) -> u64 {
+const fn mem_type_for_cache_mode(cache_mode: CacheMode) -> MemType {
     match cache_mode {
-        CacheMode::Cached => PAT_SELECTOR_WB,
-        CacheMode::WriteThrough => PAT_SELECTOR_WT,
-        CacheMode::WriteCombining => PAT_SELECTOR_WC,
-        CacheMode::Uncached => PAT_SELECTOR_UC,
+        CacheMode::Cached => MemType::Wb,
+        CacheMode::WriteThrough => MemType::Wt,
+        CacheMode::WriteCombining => MemType::Wc,
+        CacheMode::Uncached => MemType::Uc,
+    }
+}
+
+/// Returns the index of the first entry in `PAT` with memory type `mem_type`, if any.
+const fn pat_selector_for(mem_type: MemType) -> Option<u64> {
+    let mut i = 0;
+    while i < PAT.len() {
+        if PAT[i] as u8 == mem_type as u8 {
+            return Some(i as u64);
+        }
+        i += 1;
     }
+    None
 }
 
-fn pat_selector_to_pte_bits(pat_selector: u64) -> u64 {
+const fn pat_msr_value() -> u64 {
+    let mut value = 0;
+    let mut i = 0;
+    while i < PAT.len() {
+        value |= (PAT[i] as u64) << (i * 8);
+        i += 1;
+    }
+    value
+}
+
+fn pat_selector_for_cache_mode(cache_mode: CacheMode) -> u64 {
+    pat_selector_for(mem_type_for_cache_mode(cache_mode))
+        .expect("cache mode should be present in PAT")
+}
+
+const PTE_PWT: u64 = 1 << 3;
+const PTE_PCD: u64 = 1 << 4;
+const PTE_PAT: u64 = 1 << 7;
+// In large page entries, bit 7 is the page size bit and the PAT bit is moved to bit 12.
+const PTE_PAT_LARGE: u64 = 1 << 12;
+
+fn pat_selector_to_pte_bits(pat_selector: u64, level: usize) -> u64 {
     // Split the 3 bits of the pat selector across the `PWT`, `PCD` and `PAT` bits.
-    ((pat_selector & 0b001) << 3) | ((pat_selector & 0b010) << 4) | ((pat_selector & 0b100) << 7)
+    let pat = if level > 0 { PTE_PAT_LARGE } else { PTE_PAT };
+
+    let mut bits = 0;
+    if pat_selector & 0b001 != 0 {
+        bits |= PTE_PWT;
+    }
+    if pat_selector & 0b010 != 0 {
+        bits |= PTE_PCD;
+    }
+    if pat_selector & 0b100 != 0 {
+        bits |= pat;
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_CACHE_MODES: [CacheMode; 4] = [
+        CacheMode::Cached,
+        CacheMode::WriteThrough,
+        CacheMode::WriteCombining,
+        CacheMode::Uncached,
+    ];
+
+    #[test_case]
+    fn every_cache_mode_has_pat_entry() {
+        for cache_mode in ALL_CACHE_MODES {
+            let selector = pat_selector_for_cache_mode(cache_mode);
+            assert_eq!(PAT[selector as usize], mem_type_for_cache_mode(cache_mode));
+            assert_eq!(
+                (pat_msr_value() >> (selector * 8)) & 0xff,
+                mem_type_for_cache_mode(cache_mode) as u64
+            );
+        }
+    }
+
+    #[test_case]
+    fn terminal_pte_cache_and_protection_bits() {
+        let frame = PhysFrameNum::new(0x40000);
+
+        for level in [0, 1] {
+            let pat = if level > 0 { PTE_PAT_LARGE } else { PTE_PAT };
+
+            for cache_mode in ALL_CACHE_MODES {
+                for perms in [
+                    PageTablePerms::READ,
+                    PageTablePerms::READ | PageTablePerms::WRITE,
+                ] {
+                    let pte = make_terminal_pte(level, frame, perms, cache_mode);
+
+                    let selector = ((pte.0 & PTE_PWT != 0) as u64)
+                        | (((pte.0 & PTE_PCD != 0) as u64) << 1)
+                        | (((pte.0 & pat != 0) as u64) << 2);
+                    assert_eq!(selector, pat_selector_for_cache_mode(cache_mode));
+
+                    // The cache mode bits must not leak into the permissions or the frame.
+                    let flags = X86PageTableFlags::from_bits_truncate(pte.0);
+                    assert_eq!(
+                        flags.contains(X86PageTableFlags::WRITABLE),
+                        perms.contains(PageTablePerms::WRITE)
+                    );
+                    assert!(
+                        !flags.intersects(X86PageTableFlags::ACCESSED | X86PageTableFlags::DIRTY)
+                    );
+                    assert_eq!(flags.contains(X86PageTableFlags::LARGE), level > 0);
+                    assert_eq!(get_pte_frame(pte, level), frame);
+                }
+            }
+        }
+    }
+
+    #[test_case]
+    fn special_pte_round_trip() {
+        let max_tag = (1 << SPECIAL_PTE_TAG_BITS) - 1;
+        for tag in [0, 1, 0x1234, max_tag] {
+            let pte = make_special_pte(tag);
+            assert!(!pte_is_present(pte, 0));
+            assert_eq!(pte_special_tag(pte, 0), Some(tag));
+        }
+
+        assert_eq!(pte_special_tag(make_empty_pte(), 0), None);
+
+        let present = make_terminal_pte(
+            0,
+            PhysFrameNum::new(0x1234),
+            PageTablePerms::READ,
+            CacheMode::Cached,
+        );
+        assert_eq!(pte_special_tag(present, 0), None);
+    }
 }
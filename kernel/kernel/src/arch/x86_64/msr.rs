@@ -0,0 +1,206 @@
+//! Typed access to model-specific registers.
+
+use core::arch::asm;
+use core::marker::PhantomData;
+
+use bitflags::bitflags;
+
+use crate::mm::types::{PhysAddr, VirtAddr};
+
+/// A value that can be stored in a model-specific register.
+pub trait MsrValue: Copy {
+    fn from_raw(raw: u64) -> Self;
+    fn to_raw(self) -> u64;
+}
+
+impl MsrValue for u64 {
+    fn from_raw(raw: u64) -> Self {
+        raw
+    }
+
+    fn to_raw(self) -> u64 {
+        self
+    }
+}
+
+impl MsrValue for VirtAddr {
+    fn from_raw(raw: u64) -> Self {
+        VirtAddr::new(raw as usize)
+    }
+
+    fn to_raw(self) -> u64 {
+        self.as_u64()
+    }
+}
+
+macro_rules! impl_flags_msr_value {
+    ($ty:ty) => {
+        impl MsrValue for $ty {
+            fn from_raw(raw: u64) -> Self {
+                Self::from_bits_retain(raw)
+            }
+
+            fn to_raw(self) -> u64 {
+                self.bits()
+            }
+        }
+    };
+}
+
+/// The model-specific register at address `ADDR`, holding values of type `T`.
+///
+/// Values of this type can only be created by [`Msr::new`], whose caller asserts that the register
+/// exists on every supported processor, making reads safe.
+pub struct Msr<const ADDR: u32, T = u64> {
+    _marker: PhantomData<T>,
+}
+
+impl<const ADDR: u32, T: MsrValue> Msr<ADDR, T> {
+    /// Creates a new MSR definition.
+    ///
+    /// # Safety
+    ///
+    /// The MSR at address `ADDR` must be present on every processor the kernel supports, and must
+    /// be safely readable at any time.
+    pub const unsafe fn new() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the address of the register.
+    pub const fn addr(&self) -> u32 {
+        ADDR
+    }
+
+    /// Reads the current value of the register.
+    #[inline]
+    pub fn read(&self) -> T {
+        // Safety: the creator of `self` has guaranteed that the register exists.
+        T::from_raw(unsafe { rdmsr(ADDR) })
+    }
+
+    /// Writes `value` to the register.
+    ///
+    /// # Safety
+    ///
+    /// Writing to MSRs can change fundamental properties of the processor's operation; the caller
+    /// must guarantee that the new value does not break any invariants relied on by the kernel.
+    #[inline]
+    pub unsafe fn write(&self, value: T) {
+        unsafe { wrmsr(ADDR, value.to_raw()) }
+    }
+
+    /// Reads the register, applies `f` to its value and writes the result back.
+    ///
+    /// # Safety
+    ///
+    /// See [`write`](Msr::write).
+    #[inline]
+    pub unsafe fn update(&self, f: impl FnOnce(T) -> T) {
+        unsafe { self.write(f(self.read())) }
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    #[repr(transparent)]
+    pub struct Ia32Efer: u64 {
+        /// Syscall enable
+        const SCE = 1 << 0;
+
+        /// Long mode enable
+        const LME = 1 << 8;
+
+        /// Long mode active
+        const LMA = 1 << 10;
+
+        /// NX bit enable
+        const NXE = 1 << 11;
+    }
+}
+
+impl_flags_msr_value!(Ia32Efer);
+
+bitflags! {
+    #[derive(Debug, Clone, Copy)]
+    #[repr(transparent)]
+    pub struct ApicBase: u64 {
+        /// The current core is the bootstrap processor
+        const BSP = 1 << 8;
+
+        /// x2APIC mode enable
+        const X2APIC_ENABLE = 1 << 10;
+
+        /// Global APIC enable
+        const GLOBAL_ENABLE = 1 << 11;
+    }
+}
+
+impl ApicBase {
+    const BASE_MASK: u64 = ((1 << 52) - 1) & !0xfff;
+
+    /// Returns the physical base address of the local APIC's MMIO registers.
+    pub fn base_addr(self) -> PhysAddr {
+        PhysAddr::new((self.bits() & Self::BASE_MASK) as usize)
+    }
+}
+
+impl_flags_msr_value!(ApicBase);
+
+pub const IA32_APIC_BASE: Msr<0x1b, ApicBase> = unsafe { Msr::new() };
+pub const IA32_PAT: Msr<0x277> = unsafe { Msr::new() };
+pub const IA32_MTRR_DEF_TYPE: Msr<0x2ff> = unsafe { Msr::new() };
+pub const IA32_EFER: Msr<0xc0000080, Ia32Efer> = unsafe { Msr::new() };
+pub const IA32_FS_BASE: Msr<0xc0000100, VirtAddr> = unsafe { Msr::new() };
+pub const IA32_GS_BASE: Msr<0xc0000101, VirtAddr> = unsafe { Msr::new() };
+pub const IA32_KERNEL_GS_BASE: Msr<0xc0000102, VirtAddr> = unsafe { Msr::new() };
+
+#[inline]
+unsafe fn rdmsr(num: u32) -> u64 {
+    let eax: u32;
+    let edx: u32;
+
+    unsafe {
+        asm!("rdmsr", in("ecx") num, out("eax") eax, out("edx") edx, options(nostack));
+    }
+
+    ((edx as u64) << 32) | (eax as u64)
+}
+
+#[inline]
+unsafe fn wrmsr(num: u32, val: u64) {
+    unsafe {
+        asm!("wrmsr", in("ecx") num, in("eax") val as u32, in("edx") (val >> 32) as u32, options(nostack));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn read_known_msrs() {
+        // We're running in long mode with NX enabled by `mmu::init_early`.
+        let efer = IA32_EFER.read();
+        assert!(efer.contains(Ia32Efer::LME | Ia32Efer::LMA | Ia32Efer::NXE));
+
+        // Tests always run on the BSP for now.
+        let apic_base = IA32_APIC_BASE.read();
+        assert!(apic_base.contains(ApicBase::BSP));
+        assert_eq!(apic_base.base_addr().frame_offset(), 0);
+    }
+
+    #[test_case]
+    fn fs_base_round_trip() {
+        let old = IA32_FS_BASE.read();
+        let value = VirtAddr::new(0xffff_8000_1234_5000);
+
+        // Safety: the kernel doesn't use the FS segment.
+        unsafe {
+            IA32_FS_BASE.write(value);
+            assert_eq!(IA32_FS_BASE.read(), value);
+            IA32_FS_BASE.write(old);
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use core::cell::{Cell, UnsafeCell};
 use core::mem::MaybeUninit;
-use core::ptr::{addr_of, addr_of_mut};
+use core::ptr::{self, addr_of, addr_of_mut};
 
 use spin_once::TakeOnce;
 
@@ -8,6 +8,7 @@ use crate::mm::types::VirtAddr;
 use crate::sync::irq::IrqDisabled;
 
 use super::descriptor::{Gdt, Tss};
+use super::interrupt::ActivePageFault;
 use super::x64_cpu::{read_gs_dword, read_gs_qword, wrgsbase, xadd_gs_dword};
 
 const INTERRUPT_STACK_SIZE: usize = 0x2000;
@@ -20,6 +21,11 @@ pub struct X64PerCpu {
     pub gdt: Gdt,
     pub nmi_stack: InterruptStack,
     pub double_fault_stack: InterruptStack,
+    /// The page fault currently being handled by the running thread, if any.
+    ///
+    /// This is saved and restored on context switches, as page faults are handled with rescheduling
+    /// enabled.
+    pub active_page_fault: Cell<*const ActivePageFault<'static>>,
 }
 
 #[repr(C, align(64))]
@@ -30,12 +36,15 @@ struct X64PerCpuWrapper {
     /// Pointer to the common (architecture-independent) per-cpu structure.
     common_ptr: *const (),
     preempt_blocks: Cell<u32>,
+    /// Hardware ID of the core, cached to avoid executing `cpuid` on every lookup.
+    hw_id: u32,
     inner: X64PerCpu,
 }
 
 const PERCPU_PTR_OFFSET: usize = 0;
 const PERCPU_COMMON_PTR_OFFSET: usize = 8;
 const PERCPU_RESCHED_BLOCKS_OFFSET: usize = 0x10;
+const PERCPU_HW_ID_OFFSET: usize = 0x14;
 
 #[inline]
 pub fn current_x64(_irq_disabled: &IrqDisabled) -> &X64PerCpu {
@@ -47,6 +56,11 @@ pub fn current_common() -> *const () {
     unsafe { read_gs_qword::<PERCPU_COMMON_PTR_OFFSET>() as *const _ }
 }
 
+#[inline]
+pub fn current_hw_id() -> u32 {
+    unsafe { read_gs_dword::<PERCPU_HW_ID_OFFSET>() }
+}
+
 #[inline]
 pub fn disable_resched() {
     // This operation doesn't need to be atomic (it is for this core only), but it does need to be
@@ -86,6 +100,7 @@ unsafe fn init_current_with(
     unsafe {
         addr_of_mut!((*wrapper).ptr).write(wrapper as *const _);
         addr_of_mut!((*wrapper).common_ptr).write(common_percpu);
+        addr_of_mut!((*wrapper).hw_id).write(super::cpu::current_hw_id());
 
         let inner = addr_of_mut!((*wrapper).inner);
         let nmi_stack = VirtAddr::from_ptr(addr_of!((*inner).nmi_stack).add(1));
@@ -97,6 +112,8 @@ unsafe fn init_current_with(
         let gdt = addr_of_mut!((*inner).gdt);
         gdt.write(Gdt::new(VirtAddr::from_ptr(tss)));
 
+        addr_of_mut!((*inner).active_page_fault).write(Cell::new(ptr::null()));
+
         wrgsbase(VirtAddr::from_ptr(wrapper));
     }
 }
@@ -0,0 +1,144 @@
+//! System shutdown and reset.
+
+use core::arch::asm;
+use core::hint;
+
+use super::cpu::halt;
+use super::qemu::debug_exit;
+use super::x64_cpu::{cli, cpuid, inb, lidt, outb, outw, DescriptorRegister};
+
+/// PM1a control port and `SLP_TYPa | SLP_EN` value pairs to write when entering S5.
+type Pm1aS5 = &'static [(u16, u16)];
+
+const CPUID_FEATURE_LEAF: u32 = 1;
+const CPUID_FEATURE_ECX_HYPERVISOR: u32 = 1 << 31;
+const CPUID_HYPERVISOR_LEAF: u32 = 0x4000_0000;
+
+/// PM1a control ports and `SLP_TYPa | SLP_EN` values that enter the ACPI S5 (soft-off) state on
+/// common emulators, keyed by the hypervisor vendor signature they report through CPUID.
+///
+/// The kernel does not parse the ACPI tables yet (in particular, the `\_S5` object in the DSDT), so
+/// these stand in for the values it would otherwise discover. They are only used when the matching
+/// hypervisor is detected, as the same ports may belong to unrelated devices on other machines.
+const EMULATOR_PM1A_S5: &[(&[u8; 12], Pm1aS5)] = &[
+    // QEMU with TCG or KVM, covering both q35/recent i440fx and older machine types
+    (b"TCGTCGTCGTCG", QEMU_PM1A_S5),
+    (b"KVMKVMKVM\0\0\0", QEMU_PM1A_S5),
+    (b"VBoxVBoxVBox", &[(0x4004, 0x3400)]),
+];
+
+/// Hypervisor vendor signatures reported by QEMU, with TCG and KVM acceleration respectively.
+const QEMU_VENDORS: &[&[u8; 12]] = &[b"TCGTCGTCGTCG", b"KVMKVMKVM\0\0\0"];
+
+const QEMU_PM1A_S5: Pm1aS5 = &[(0x604, 0x2000), (0xb004, 0x2000)];
+
+const KBC_STATUS_PORT: u16 = 0x64;
+const KBC_COMMAND_PORT: u16 = 0x64;
+const KBC_STATUS_INPUT_FULL: u8 = 1 << 1;
+const KBC_CMD_PULSE_RESET: u8 = 0xfe;
+const KBC_READY_SPINS: usize = 0x10000;
+
+/// Powers off the machine.
+///
+/// When running under a known emulator, this first attempts to enter ACPI S5. Under QEMU, it then
+/// falls back to the `isa-debug-exit` device (which reports an exit status of 1). If neither is
+/// available, the current core is halted.
+pub fn shutdown() -> ! {
+    unsafe {
+        cli();
+    }
+
+    let vendor = hypervisor_vendor();
+
+    let pm1a_s5 = vendor.and_then(|vendor| {
+        EMULATOR_PM1A_S5
+            .iter()
+            .find(|&&(emulator, _)| *emulator == vendor)
+            .map(|&(_, ports)| ports)
+    });
+
+    for &(port, value) in pm1a_s5.unwrap_or_default() {
+        // Safety: the port belongs to the detected emulator's PM1a control block, and we're
+        // shutting down, so nothing else is relying on the state of the machine.
+        unsafe {
+            outw(port, value);
+        }
+    }
+
+    if is_qemu(vendor) {
+        // Safety: we've just checked that we're running under QEMU.
+        unsafe {
+            debug_exit(0);
+        }
+    }
+
+    halt();
+}
+
+/// Terminates QEMU with exit status `(code << 1) | 1` via the `isa-debug-exit` device.
+///
+/// If we are not running under QEMU or the device is not present, this shuts the machine down with
+/// [`shutdown`] instead.
+pub fn exit_qemu(code: u8) -> ! {
+    if is_qemu(hypervisor_vendor()) {
+        // Safety: we've just checked that we're running under QEMU.
+        unsafe {
+            debug_exit(code);
+        }
+    }
+
+    shutdown();
+}
+
+/// Resets the machine.
+///
+/// This first pulses the CPU reset line through the keyboard controller, falling back to triple
+/// faulting the current core if that has no effect.
+pub fn reboot() -> ! {
+    unsafe {
+        cli();
+    }
+
+    // Give the keyboard controller a chance to drain its input buffer, but don't wait forever in
+    // case it doesn't exist.
+    for _ in 0..KBC_READY_SPINS {
+        // Safety: reading the status register has no side effects.
+        if unsafe { inb(KBC_STATUS_PORT) } & KBC_STATUS_INPUT_FULL == 0 {
+            break;
+        }
+        hint::spin_loop();
+    }
+
+    // Safety: we're resetting, so nothing else is relying on the state of the machine.
+    unsafe {
+        outb(KBC_COMMAND_PORT, KBC_CMD_PULSE_RESET);
+    }
+
+    // Safety: with an empty IDT, the breakpoint exception will escalate to a double fault and then
+    // to a triple fault, resetting the core.
+    unsafe {
+        lidt(&DescriptorRegister { limit: 0, ptr: 0 });
+        asm!("int3", options(nomem, nostack));
+    }
+
+    halt();
+}
+
+/// Returns the vendor signature of the hypervisor we are running under, if any.
+fn hypervisor_vendor() -> Option<[u8; 12]> {
+    if cpuid(CPUID_FEATURE_LEAF).ecx & CPUID_FEATURE_ECX_HYPERVISOR == 0 {
+        return None;
+    }
+
+    let leaf = cpuid(CPUID_HYPERVISOR_LEAF);
+    let mut vendor = [0; 12];
+    vendor[..4].copy_from_slice(&leaf.ebx.to_le_bytes());
+    vendor[4..8].copy_from_slice(&leaf.ecx.to_le_bytes());
+    vendor[8..].copy_from_slice(&leaf.edx.to_le_bytes());
+    Some(vendor)
+}
+
+/// Returns whether `vendor` (as returned by [`hypervisor_vendor`]) identifies QEMU.
+fn is_qemu(vendor: Option<[u8; 12]>) -> bool {
+    vendor.is_some_and(|vendor| QEMU_VENDORS.contains(&&vendor))
+}
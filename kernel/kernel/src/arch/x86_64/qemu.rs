@@ -0,0 +1,22 @@
+use super::x64_cpu::outb;
+
+/// The IO port at which QEMU's `isa-debug-exit` device is expected to be mapped.
+///
+/// This must match the `iobase` passed to QEMU by the host tools.
+pub const DEBUG_EXIT_PORT: u16 = 0xf4;
+
+/// Terminates QEMU with exit status `(code << 1) | 1` via the `isa-debug-exit` device.
+///
+/// If the device is not present, this function has no effect and returns normally.
+///
+/// # Safety
+///
+/// The caller must ensure that the kernel is running under QEMU, as [`DEBUG_EXIT_PORT`] may belong
+/// to an unrelated device on other machines.
+pub unsafe fn debug_exit(code: u8) {
+    // Safety: the caller guarantees that we are running under QEMU, where the port is either unused
+    // or belongs to `isa-debug-exit`, so the write has no effect other than terminating QEMU.
+    unsafe {
+        outb(DEBUG_EXIT_PORT, code);
+    }
+}
@@ -4,80 +4,152 @@ use bitflags::bitflags;
 
 use crate::bootparse::CommandLine;
 
+use super::debugcon::{DebugconPort, DEBUGCON_PORT};
 use super::x64_cpu::{inb, outb};
 
 pub struct Console {
-    serial: Serial,
+    sink: ConsoleSink,
+}
+
+enum ConsoleSink {
+    Serial(SerialPort),
+    Debugcon(DebugconPort),
 }
 
 impl Console {
-    /// Creates a new serial console based on parameters set in the provided command line.
+    /// Creates a new console based on parameters set in the provided command line.
     ///
-    /// If the command line does not specify a serial console at all, `None` is returned.
+    /// `x86.debugcon[=port]` selects a debug console at the specified (hex) IO port, defaulting to
+    /// [`DEBUGCON_PORT`]; otherwise, `x86.serial=port` selects a serial console. If the command
+    /// line does not specify a console at all, `None` is returned.
     ///
     /// # Safety
     ///
     /// * Callers should ensure that at most a single instance of `Console` is in use at a given
     ///   time, as it provides (unsynchronized) direct access to the hardware.
     pub unsafe fn new(cmdline: CommandLine<'_>) -> Option<Self> {
+        if let Some(port_str) = cmdline.get_arg_str_value("x86.debugcon") {
+            let port = if port_str.is_empty() {
+                DEBUGCON_PORT
+            } else {
+                u16::from_str_radix(port_str, 16).ok()?
+            };
+
+            let port = unsafe { DebugconPort::new(port) };
+            return Some(Self {
+                sink: ConsoleSink::Debugcon(port),
+            });
+        }
+
         let base_port_str = cmdline.get_arg_str_value("x86.serial")?;
         let base_port = u16::from_str_radix(base_port_str, 16).ok()?;
 
-        let serial = unsafe { Serial::new(base_port, 115200) };
+        let port = unsafe { SerialPort::new(base_port) };
 
-        Some(Self { serial })
+        Some(Self {
+            sink: ConsoleSink::Serial(port),
+        })
     }
 
-    pub fn write(&mut self, s: &str) {
-        for &byte in s.as_bytes() {
-            if byte == b'\n' {
-                self.serial.write_byte(b'\r');
+    /// Creates a second console driving the same hardware as `self`, without reinitializing it.
+    ///
+    /// # Safety
+    ///
+    /// * The caller must accept that output written through the two consoles may be arbitrarily
+    ///   interleaved; this should only be used for emergency output when `self` is unavailable.
+    pub unsafe fn alias(&self) -> Self {
+        let sink = match &self.sink {
+            ConsoleSink::Serial(port) => ConsoleSink::Serial(SerialPort {
+                base_port: port.base_port,
+            }),
+            ConsoleSink::Debugcon(port) => {
+                ConsoleSink::Debugcon(unsafe { DebugconPort::new(port.port()) })
             }
-            self.serial.write_byte(byte);
-        }
+        };
+
+        Self { sink }
     }
-}
 
-impl fmt::Write for Console {
-    fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.write(s);
-        Ok(())
+    /// Writes raw bytes to the console, without any newline translation.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        match &mut self.sink {
+            ConsoleSink::Serial(port) => port.write_bytes(bytes),
+            ConsoleSink::Debugcon(port) => port.write_bytes(bytes),
+        }
     }
 }
 
-pub struct Serial {
+/// The base port of the first legacy serial port (COM1).
+pub const EARLY_SERIAL_PORT: u16 = 0x3f8;
+
+/// A polled 16550-compatible UART.
+pub struct SerialPort {
     base_port: u16,
 }
 
-impl Serial {
+impl SerialPort {
+    /// Initializes the UART at `base_port` for 8N1 output at 115200 baud, with FIFOs enabled and
+    /// interrupts disabled.
+    ///
     /// # Safety
     ///
     /// * `base_port` must indicate an IO port mapping an actual UART controller.
-    /// * The specified baud rate must be a valid value supported by the hardware.
-    /// * Callers should ensure that at most a single instance of `Serial` is in use for a given
+    /// * Callers should ensure that at most a single instance of `SerialPort` is in use for a given
     ///   base port, as this struct enables unsynchronized access to the hardware.
-    pub unsafe fn new(base_port: u16, baud: u32) -> Self {
-        let mut serial = Self { base_port };
-        serial.set_baud(baud);
-        serial.set_line_control(LineControlFlags::WORD_LENGTH_8);
-        serial.set_fifo_control(0);
-        unsafe { serial.set_interrupt_enable(0) };
-        serial.set_modem_control(
+    pub unsafe fn new(base_port: u16) -> Self {
+        let mut port = Self { base_port };
+        port.set_baud(DEFAULT_BAUD);
+        port.set_line_control(LineControlFlags::WORD_LENGTH_8);
+        port.set_fifo_control(
+            FifoControlFlags::ENABLE | FifoControlFlags::CLEAR_RX | FifoControlFlags::CLEAR_TX,
+        );
+        unsafe { port.set_interrupt_enable(0) };
+        port.set_modem_control(
             ModemControlFlags::DATA_TERMINAL_READY | ModemControlFlags::REQUEST_TO_SEND,
         );
-        serial
+        port
     }
 
-    pub fn write_byte(&mut self, byte: u8) {
-        while !self.get_line_status().contains(LineStatus::EMPTY_THR) {
-            hint::spin_loop();
+    /// Writes `s` to the port, translating every `\n` to `\r\n`.
+    pub fn write(&mut self, s: &str) {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
         }
+    }
+
+    /// Writes raw bytes to the port, filling the transmit FIFO in bursts.
+    ///
+    /// This polls the line status once per FIFO-sized chunk instead of once per byte.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(FIFO_SIZE) {
+            self.wait_thr_empty();
+            for &byte in chunk {
+                unsafe {
+                    self.write_reg(THR_OFF, byte);
+                }
+            }
+        }
+    }
+
+    /// Writes a single raw byte to the port, waiting for the transmit holding register to drain
+    /// first.
+    pub fn write_byte(&mut self, byte: u8) {
+        self.wait_thr_empty();
 
         unsafe {
             self.write_reg(THR_OFF, byte);
         }
     }
 
+    fn wait_thr_empty(&mut self) {
+        while !self.get_line_status().contains(LineStatus::EMPTY_THR) {
+            hint::spin_loop();
+        }
+    }
+
     fn set_baud(&mut self, baud: u32) {
         let divisor = (115200 / baud) as u16;
 
@@ -91,8 +163,8 @@ impl Serial {
         self.set_line_control(LineControlFlags::empty());
     }
 
-    fn set_fifo_control(&mut self, val: u8) {
-        unsafe { self.write_reg(FCR_OFF, val) };
+    fn set_fifo_control(&mut self, flags: FifoControlFlags) {
+        unsafe { self.write_reg(FCR_OFF, flags.bits()) };
     }
 
     unsafe fn set_interrupt_enable(&mut self, enable: u8) {
@@ -120,6 +192,13 @@ impl Serial {
     }
 }
 
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s);
+        Ok(())
+    }
+}
+
 bitflags! {
     struct LineControlFlags: u8 {
         const WORD_LENGTH_MASK = 0b11;
@@ -136,6 +215,14 @@ bitflags! {
     }
 }
 
+bitflags! {
+    struct FifoControlFlags: u8 {
+        const ENABLE = 1 << 0;
+        const CLEAR_RX = 1 << 1;
+        const CLEAR_TX = 1 << 2;
+    }
+}
+
 bitflags! {
     struct ModemControlFlags: u8 {
         const DATA_TERMINAL_READY = 1 << 0;
@@ -156,6 +243,9 @@ bitflags! {
     }
 }
 
+const DEFAULT_BAUD: u32 = 115200;
+const FIFO_SIZE: usize = 16;
+
 const IER_OFF: u16 = 1;
 const FCR_OFF: u16 = 2;
 
@@ -167,3 +257,32 @@ const DIVISOR_HIGH_OFF: u16 = 1;
 const LCR_OFF: u16 = 3;
 const MCR_OFF: u16 = 4;
 const LSR_OFF: u16 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugcon_port(cmdline: &[u8]) -> Option<u16> {
+        // Safety: the console is only created when it selects a debug console, which has no side
+        // effects other than producing output.
+        let console = unsafe { Console::new(CommandLine::new(cmdline)) }?;
+        match console.sink {
+            ConsoleSink::Debugcon(port) => Some(port.port()),
+            ConsoleSink::Serial(_) => None,
+        }
+    }
+
+    #[test_case]
+    fn debugcon_selected_from_command_line() {
+        assert_eq!(
+            debugcon_port(b"x86.serial=3f8 x86.debugcon"),
+            Some(DEBUGCON_PORT)
+        );
+        assert_eq!(debugcon_port(b"x86.debugcon=402"), Some(0x402));
+        assert_eq!(debugcon_port(b"x86.debugcon=xyz"), None);
+
+        let mut console =
+            unsafe { Console::new(CommandLine::new(b"x86.debugcon")) }.expect("no console");
+        console.write_bytes(b"debugcon test output\r\n");
+    }
+}
@@ -1,13 +1,11 @@
 use core::arch::asm;
+use core::arch::x86_64::{CpuidResult, __cpuid, _rdtsc};
 
 use bitflags::bitflags;
 
 use crate::mm::types::VirtAddr;
 
-const IA32_PAT: u32 = 0x277;
-const IA32_MTRR_DEF_TYPE: u32 = 0x2ff;
-const IA32_GS_BASE: u32 = 0xc0000101;
-const IA32_EFER: u32 = 0xc0000080;
+use super::msr::IA32_GS_BASE;
 
 bitflags! {
     #[derive(Debug, Clone, Copy)]
@@ -97,24 +95,6 @@ bitflags! {
     }
 }
 
-bitflags! {
-    #[derive(Debug, Clone, Copy)]
-    #[repr(transparent)]
-    pub struct Ia32Efer: u64 {
-        /// Syscall enable
-        const SCE = 1 << 0;
-
-        /// Long mode enable
-        const LME = 1 << 8;
-
-        /// Long mode active
-        const LMA = 1 << 10;
-
-        /// NX bit enable
-        const NXE = 1 << 11;
-    }
-}
-
 #[repr(C, packed(2))]
 pub struct DescriptorRegister {
     pub limit: u16,
@@ -137,6 +117,13 @@ pub unsafe fn outb(port: u16, val: u8) {
     }
 }
 
+#[inline]
+pub unsafe fn outw(port: u16, val: u16) {
+    unsafe {
+        asm!("out dx, ax", in("dx") port, in("ax") val, options(nostack));
+    }
+}
+
 #[inline]
 pub unsafe fn cli() {
     unsafe {
@@ -158,6 +145,16 @@ pub fn hlt() {
     }
 }
 
+#[inline]
+pub fn cpuid(leaf: u32) -> CpuidResult {
+    unsafe { __cpuid(leaf) }
+}
+
+#[inline]
+pub fn rdtsc() -> u64 {
+    unsafe { _rdtsc() }
+}
+
 #[inline]
 pub fn wbinvd() {
     unsafe {
@@ -261,42 +258,11 @@ pub fn read_cr2() -> VirtAddr {
     VirtAddr::new(cr2 as usize)
 }
 
-#[inline]
-pub fn read_ia32_efer() -> Ia32Efer {
-    unsafe { Ia32Efer::from_bits_retain(rdmsr(IA32_EFER)) }
-}
-
-#[inline]
-pub unsafe fn write_ia32_efer(ia32_efer: Ia32Efer) {
-    unsafe {
-        wrmsr(IA32_EFER, ia32_efer.bits());
-    }
-}
-
-#[inline]
-pub fn read_mtrr_def_type() -> u64 {
-    unsafe { rdmsr(IA32_MTRR_DEF_TYPE) }
-}
-
-#[inline]
-pub unsafe fn write_mtrr_def_type(def_type: u64) {
-    unsafe {
-        wrmsr(IA32_MTRR_DEF_TYPE, def_type);
-    }
-}
-
-#[inline]
-pub unsafe fn write_pat(pat: u64) {
-    unsafe {
-        wrmsr(IA32_PAT, pat);
-    }
-}
-
 #[inline]
 pub unsafe fn wrgsbase(base: VirtAddr) {
     // TODO: consider using the `wrgsbase` instruction when available
     unsafe {
-        wrmsr(IA32_GS_BASE, base.as_u64());
+        IA32_GS_BASE.write(base);
     }
 }
 
@@ -326,22 +292,3 @@ pub unsafe fn xadd_gs_dword<const OFF: usize>(addend: u32) -> u32 {
     }
     retval
 }
-
-#[inline]
-unsafe fn rdmsr(num: u32) -> u64 {
-    let eax: u32;
-    let edx: u32;
-
-    unsafe {
-        asm!("rdmsr", in("ecx") num, out("eax") eax, out("edx") edx, options(nostack));
-    }
-
-    ((edx as u64) << 32) | (eax as u64)
-}
-
-#[inline]
-unsafe fn wrmsr(num: u32, val: u64) {
-    unsafe {
-        asm!("wrmsr", in("ecx") num, in("eax") val as u32, in("edx") (val >> 32) as u32, options(nostack));
-    }
-}
@@ -1,13 +1,15 @@
 use core::str::{self, Utf8Chunks};
 use core::{fmt, slice};
 
-use bootinfo::item::{FramebufferInfo, MemoryRange};
-use bootinfo::view::View;
+use bootinfo::item::{FramebufferInfo, InitrdInfo, KernelImageInfo, MemoryRange};
+use bootinfo::view::{ItemView, View};
 use bootinfo::ItemKind;
 use itertools::Itertools;
+use log::{debug, trace};
 
 use crate::mm::physmap::paddr_to_physmap;
 use crate::mm::types::PhysAddr;
+use crate::mm::utils::display_hexdump;
 
 /// A parsed command-line argument, with its name and value.
 #[derive(Clone, Copy)]
@@ -38,8 +40,14 @@ impl<'a> CommandLineArg<'a> {
 impl fmt::Display for CommandLineArg<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         display_utf8_lossy(f, self.name)?;
-        write!(f, "=")?;
-        display_utf8_lossy(f, self.value)
+
+        // Flags without values are displayed as just their name.
+        if !self.value.is_empty() {
+            write!(f, "=")?;
+            display_utf8_lossy(f, self.value)?;
+        }
+
+        Ok(())
     }
 }
 
@@ -92,9 +100,12 @@ impl fmt::Display for CommandLine<'_> {
 
 /// Encapsulates data from a parsed bootinfo view created by the loader.
 pub struct BootinfoData<'a> {
+    view: View<'a>,
     memory_map: &'a [MemoryRange],
     efi_system_table: Option<PhysAddr>,
     framebuffer_info: Option<&'a FramebufferInfo>,
+    kernel_image: Option<&'a KernelImageInfo>,
+    initrd: Option<&'a InitrdInfo>,
     command_line: CommandLine<'a>,
 }
 
@@ -119,6 +130,8 @@ impl<'a> BootinfoData<'a> {
         let mut memory_map = None;
         let mut efi_system_table = None;
         let mut framebuffer_info = None;
+        let mut kernel_image = None;
+        let mut initrd = None;
         let mut command_line = None;
 
         let view = View::new(buffer).expect("invalid bootinfo");
@@ -138,14 +151,23 @@ impl<'a> BootinfoData<'a> {
                 ItemKind::COMMAND_LINE => {
                     command_line = unsafe { item.get_slice() }.ok();
                 }
+                ItemKind::KERNEL_IMAGE => {
+                    kernel_image = unsafe { item.get() }.ok();
+                }
+                ItemKind::INITRD => {
+                    initrd = unsafe { item.get() }.ok();
+                }
                 _ => {}
             }
         }
 
         Self {
+            view,
             memory_map: memory_map.expect("no memory map in bootinfo"),
             efi_system_table,
             framebuffer_info,
+            kernel_image,
+            initrd,
             command_line: CommandLine::new(command_line.unwrap_or(b"")),
         }
     }
@@ -165,10 +187,59 @@ impl<'a> BootinfoData<'a> {
         self.framebuffer_info
     }
 
+    /// Returns the information about the loaded kernel image provided in the bootinfo, if present.
+    pub fn kernel_image(&self) -> Option<&KernelImageInfo> {
+        self.kernel_image
+    }
+
+    /// Returns the location of the initial ramdisk provided in the bootinfo, if present.
+    pub fn initrd(&self) -> Option<&InitrdInfo> {
+        self.initrd
+    }
+
     /// Returns the kernel command line provided in the bootinfo.
     pub fn command_line(&self) -> CommandLine<'_> {
         self.command_line
     }
+
+    /// Returns an iterator over all items in the bootinfo, including those of kinds not recognized
+    /// by the kernel.
+    pub fn items(&self) -> impl Iterator<Item = ItemView<'a>> + Clone {
+        self.view.items()
+    }
+
+    /// Returns an iterator over all items in the bootinfo whose kinds are not recognized by the
+    /// kernel.
+    pub fn unknown_items(&self) -> impl Iterator<Item = ItemView<'a>> + Clone {
+        self.items().filter(|item| !is_known_item_kind(item.kind()))
+    }
+
+    /// Logs the kind and payload length of every unrecognized item in the bootinfo.
+    ///
+    /// Bootinfo parsing happens before logging is available, so this should be called separately
+    /// once logging has been initialized.
+    pub fn log_unknown_items(&self) {
+        for item in self.unknown_items() {
+            debug!(
+                "unknown bootinfo item kind {}, len {}",
+                item.kind().to_raw(),
+                item.payload().len()
+            );
+            trace!("{}", display_hexdump(item.payload()));
+        }
+    }
+}
+
+fn is_known_item_kind(kind: ItemKind) -> bool {
+    matches!(
+        kind,
+        ItemKind::MEMORY_MAP
+            | ItemKind::EFI_SYSTEM_TABLE
+            | ItemKind::FRAMEBUFFER
+            | ItemKind::COMMAND_LINE
+            | ItemKind::KERNEL_IMAGE
+            | ItemKind::INITRD
+    )
 }
 
 fn display_utf8_lossy(f: &mut fmt::Formatter<'_>, buf: &[u8]) -> fmt::Result {
@@ -181,3 +252,185 @@ fn display_utf8_lossy(f: &mut fmt::Formatter<'_>, buf: &[u8]) -> fmt::Result {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use alloc::{format, vec};
+    use core::mem;
+
+    use bootinfo::builder::Builder;
+    use bootinfo::item::{MemoryKind, PixelFormat};
+    use bootinfo::{ITEM_ALIGN, MAX_ITEM_ALIGN};
+
+    use super::*;
+
+    /// The `payload_align_log2` field of a packed item header with the default item alignment.
+    const ITEM_ALIGN_LOG2: u64 = (ITEM_ALIGN.trailing_zeros() as u64) << 32;
+
+    /// Returns the first word of a packed header for an item of kind `kind` with the default item
+    /// alignment.
+    fn item_header(kind: ItemKind) -> u64 {
+        u64::from(kind.to_raw()) | ITEM_ALIGN_LOG2
+    }
+
+    /// Lays out a bootinfo structure containing an empty memory map, followed by `items` and an
+    /// empty command line.
+    ///
+    /// Item headers are `(kind, payload_align_log2)` pairs of `u32`s followed by a `u64`
+    /// `payload_len`, packed into `u64` words here to get the required alignment.
+    fn bootinfo_words(items: &[u64]) -> Vec<u64> {
+        let mut words = vec![item_header(ItemKind::MEMORY_MAP), 0];
+        words.extend_from_slice(items);
+        words.extend_from_slice(&[item_header(ItemKind::COMMAND_LINE), 0]);
+        words
+    }
+
+    fn as_bytes(words: &[u64]) -> &[u8] {
+        unsafe { slice::from_raw_parts(words.as_ptr().cast::<u8>(), mem::size_of_val(words)) }
+    }
+
+    #[test_case]
+    fn command_line_display() {
+        let command_line = CommandLine::new(b"  x86.serial=3f8 kvm\tloglevel=debug  ");
+        assert_eq!(
+            format!("{command_line}"),
+            "x86.serial=3f8 kvm loglevel=debug"
+        );
+
+        assert_eq!(format!("{}", CommandLineArg::parse(b"kvm")), "kvm");
+        assert_eq!(format!("{}", CommandLineArg::parse(b"a=b=c")), "a=b=c");
+    }
+
+    #[test_case]
+    fn bootinfo_unknown_items() {
+        // Unknown item with a 4-byte payload, padded to 8 bytes
+        let words = bootinfo_words(&[item_header(ItemKind::from_raw(0x99)), 4, 0]);
+
+        let bootinfo = BootinfoData::parse(as_bytes(&words));
+        assert_eq!(bootinfo.items().count(), 3);
+        assert!(bootinfo.memory_map().is_empty());
+
+        let mut unknown = bootinfo.unknown_items();
+        let item = unknown.next().unwrap();
+        assert_eq!(item.kind().to_raw(), 0x99);
+        assert_eq!(item.payload().len(), 4);
+        assert!(unknown.next().is_none());
+    }
+
+    #[test_case]
+    fn bootinfo_kernel_image_item() {
+        let words = bootinfo_words(&[
+            item_header(ItemKind::KERNEL_IMAGE),
+            mem::size_of::<KernelImageInfo>() as u64,
+            0x20_0000,
+            0x3_0000,
+            0x20_1000,
+            0xffff_ffff_8000_0000,
+        ]);
+
+        let bootinfo = BootinfoData::parse(as_bytes(&words));
+        assert_eq!(bootinfo.unknown_items().count(), 0);
+
+        let kernel_image = bootinfo.kernel_image().unwrap();
+        assert_eq!(kernel_image.phys_base, 0x20_0000);
+        assert_eq!(kernel_image.byte_size, 0x3_0000);
+        assert_eq!(kernel_image.entry, 0x20_1000);
+        assert_eq!(kernel_image.virt_base, 0xffff_ffff_8000_0000);
+        assert_eq!(
+            format!("{kernel_image}"),
+            "phys range 0x200000-0x230000, entry 0x201000, virt base 0xffffffff80000000"
+        );
+    }
+
+    #[test_case]
+    fn bootinfo_initrd_item() {
+        let words = bootinfo_words(&[
+            item_header(ItemKind::INITRD),
+            mem::size_of::<InitrdInfo>() as u64,
+            0x80_0000,
+            0x1234,
+        ]);
+
+        let bootinfo = BootinfoData::parse(as_bytes(&words));
+        assert_eq!(bootinfo.unknown_items().count(), 0);
+
+        let initrd = bootinfo.initrd().unwrap();
+        assert_eq!(initrd.paddr, 0x80_0000);
+        assert_eq!(initrd.byte_size, 0x1234);
+        assert_eq!(format!("{initrd}"), "phys range 0x800000-0x801234");
+    }
+
+    #[test_case]
+    fn bootinfo_large_aligned_item_round_trip() {
+        #[repr(C, align(4096))]
+        struct Page([u8; 4096]);
+
+        const PAYLOAD_LEN: usize = 0x4_0000 + 5;
+
+        let mut pages: Vec<Page> = (0..(PAYLOAD_LEN / 4096 + 4))
+            .map(|_| Page([0; 4096]))
+            .collect();
+        let buffer = unsafe {
+            slice::from_raw_parts_mut(
+                pages.as_mut_ptr().cast::<u8>(),
+                pages.len() * mem::size_of::<Page>(),
+            )
+        };
+
+        let payload: Vec<u8> = (0..PAYLOAD_LEN).map(|i| (i % 251) as u8).collect();
+
+        let mut builder = Builder::new(buffer.into()).unwrap();
+        builder
+            .append_slice::<MemoryRange>(ItemKind::MEMORY_MAP, &[])
+            .unwrap();
+        builder
+            .append_with(
+                ItemKind::from_raw(0x99),
+                PAYLOAD_LEN,
+                MAX_ITEM_ALIGN,
+                |buf| buf.copy_from_slice(&payload),
+            )
+            .unwrap();
+        builder
+            .append_slice::<u8>(ItemKind::COMMAND_LINE, &[])
+            .unwrap();
+        assert!(matches!(
+            builder.append_slice(ItemKind::from_raw(0x98), &[0u8; 0x10_0000]),
+            Err(bootinfo::Error::BadSize)
+        ));
+        let buffer = builder.finish();
+
+        let bootinfo = BootinfoData::parse(buffer);
+        assert_eq!(bootinfo.items().count(), 3);
+
+        let item = bootinfo.unknown_items().next().unwrap();
+        assert_eq!(item.kind().to_raw(), 0x99);
+        assert_eq!(item.payload().as_ptr() as usize % MAX_ITEM_ALIGN, 0);
+        assert_eq!(item.payload(), &payload[..]);
+    }
+
+    #[test_case]
+    fn bootinfo_item_formatting() {
+        let range = MemoryRange {
+            start_page: 0x100,
+            page_count: 0x20,
+            kind: MemoryKind::FIRMWARE_RUNIME,
+        };
+        assert_eq!(format!("{range}"), "pages 0x100-0x120: firmware (runtime)");
+        assert_eq!(format!("{}", MemoryKind::from_raw(0x55)), "other");
+
+        let framebuffer = FramebufferInfo {
+            paddr: 0x8000_0000,
+            byte_size: 0x1000,
+            pixel_width: 32,
+            pixel_height: 32,
+            pixel_stride: 32,
+            pixel_format: PixelFormat::BGR,
+        };
+        assert_eq!(
+            format!("{framebuffer}"),
+            "phys range 0x80000000-0x80001000, dimensions 32x32, stride 32, format BGR"
+        );
+    }
+}
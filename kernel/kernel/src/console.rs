@@ -1,8 +1,17 @@
-use core::fmt::{Arguments, Write};
+use core::fmt::{self, Arguments, Write};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
-use crate::arch::serial::Console;
+use arrayvec::ArrayVec;
+use spin_once::Once;
+
+use crate::arch::serial::{Console, SerialPort, EARLY_SERIAL_PORT};
 use crate::bootparse::CommandLine;
-use crate::sync::SpinLock;
+use crate::fbcon::FramebufferConsole;
+use crate::mp::current_cpu_id;
+use crate::sync::irq::{self, IrqDisabled};
+use crate::sync::lockrank::RANK_CONSOLE;
+use crate::sync::{SpinLock, SpinLockGuard};
 
 macro_rules! println {
     () => {
@@ -14,21 +23,267 @@ macro_rules! println {
     };
 }
 
-static CONSOLE: SpinLock<Option<Console>> = SpinLock::new(None);
+macro_rules! force_println {
+    () => {
+        force_println!("")
+    };
+
+    ($($args:tt)*) => {
+        $crate::console::force_writeln_fmt(format_args!($($args)*))
+    };
+}
+
+const LINE_BUFFER_SIZE: usize = 128;
+
+/// The number of times forced output spins on a console lock held by another core before giving up
+/// and bypassing it.
+const FORCE_SPIN_BUDGET: usize = 1 << 20;
+
+const NO_OWNER: u32 = u32::MAX;
+
+static CONSOLE: SpinLock<Option<BufferedConsole>> = SpinLock::new_ranked(None, RANK_CONSOLE);
+/// The CPU number of the core currently holding `CONSOLE`, or `NO_OWNER`.
+static CONSOLE_OWNER: AtomicU32 = AtomicU32::new(NO_OWNER);
+/// An alias of the console hardware, used by forced output when `CONSOLE` is unavailable.
+static EMERGENCY_CONSOLE: Once<Console> = Once::new();
+static CONSOLE_INITIALIZED: AtomicBool = AtomicBool::new(false);
+static FRAMEBUFFER_CONSOLE: SpinLock<Option<FramebufferConsole<'static>>> =
+    SpinLock::new_ranked(None, RANK_CONSOLE);
 
 pub fn init(cmdline: CommandLine<'_>) {
     CONSOLE.with(|console, _| {
         assert!(console.is_none());
         unsafe {
-            *console = Console::new(cmdline);
+            *console = Console::new(cmdline).map(BufferedConsole::new);
         }
+
+        if let Some(console) = console {
+            // Safety: the alias is only used when the real console can't be locked, in which case
+            // interleaved output is preferable to no output at all.
+            EMERGENCY_CONSOLE.init(unsafe { console.console.alias() });
+        }
+    });
+    CONSOLE_INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Returns `true` if [`init`] has been called, meaning that [`println!`] output is routed to the
+/// configured console.
+pub fn is_initialized() -> bool {
+    CONSOLE_INITIALIZED.load(Ordering::Acquire)
+}
+
+/// Mirrors all subsequent [`println!`] output to `fb_console`.
+///
+/// # Panics
+///
+/// Panics if a framebuffer console has already been attached.
+pub fn attach_framebuffer(fb_console: FramebufferConsole<'static>) {
+    FRAMEBUFFER_CONSOLE.with(|console, _| {
+        assert!(console.is_none(), "framebuffer console already attached");
+        *console = Some(fb_console);
     });
 }
 
 pub fn writeln_fmt(args: Arguments<'_>) {
-    CONSOLE.with(|console, _| {
+    irq::disable_with(|irq_disabled| {
+        if let Some(console) = &mut *lock_console(irq_disabled) {
+            let _ = writeln!(console, "{args}");
+
+            // Make sure nothing lingers in the buffer once we release the lock, so that output is
+            // never lost if we panic before the next write.
+            console.flush();
+        }
+    });
+
+    FRAMEBUFFER_CONSOLE.with(|console, _| {
+        if let Some(console) = console {
+            let _ = writeln!(console, "{args}");
+            console.flush();
+        }
+    });
+}
+
+/// Writes `args` to the console even if it is currently locked, for use by panic handlers and other
+/// diagnostic paths that may have interrupted a console write.
+///
+/// If the console lock is held by the current core, or is held by another core for too long, the
+/// lock is bypassed and the line is written directly to the hardware, possibly interleaving with
+/// other output. Before the console has been initialized, this falls back to early output.
+pub fn force_writeln_fmt(args: Arguments<'_>) {
+    force_write(args);
+}
+
+/// The way in which [`force_write`] produced its output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ForcedWrite {
+    Early,
+    Locked,
+    Bypassed,
+}
+
+fn force_write(args: Arguments<'_>) -> ForcedWrite {
+    if !is_initialized() {
+        early_writeln_fmt(args);
+        return ForcedWrite::Early;
+    }
+
+    let how = irq::disable_with(|irq_disabled| {
+        let cpu_num = current_cpu_id(irq_disabled.resched_disabled());
+
+        // Never spin on a lock we're holding ourselves: we must have interrupted a write on this
+        // core, which will never complete until we return.
+        let held_here = CONSOLE_OWNER.load(Ordering::Relaxed) == cpu_num;
+        let guard = (!held_here)
+            .then(|| CONSOLE.try_lock_spinning(irq_disabled, FORCE_SPIN_BUDGET))
+            .flatten();
+
+        match guard {
+            Some(mut console) => {
+                if let Some(console) = &mut *console {
+                    let _ = writeln!(console, "{args}");
+                    console.flush();
+                }
+                ForcedWrite::Locked
+            }
+            None => {
+                if let Some(console) = EMERGENCY_CONSOLE.get() {
+                    // Safety: we accept interleaving with the output of the current lock holder.
+                    let mut console = BufferedConsole::new(unsafe { console.alias() });
+                    let _ = writeln!(console, "{args}");
+                    console.flush();
+                }
+                ForcedWrite::Bypassed
+            }
+        }
+    });
+
+    // The framebuffer console holds more state, so never bypass its lock.
+    FRAMEBUFFER_CONSOLE.with_timeout(0, |console, _| {
         if let Some(console) = console {
             let _ = writeln!(console, "{args}");
+            console.flush();
         }
-    })
+    });
+
+    how
+}
+
+/// Locks `CONSOLE`, recording the current core as its owner until the returned guard is dropped.
+fn lock_console(irq_disabled: &IrqDisabled) -> OwnedConsole<'_> {
+    let guard = CONSOLE.lock(irq_disabled);
+    CONSOLE_OWNER.store(
+        current_cpu_id(irq_disabled.resched_disabled()),
+        Ordering::Relaxed,
+    );
+    OwnedConsole(guard)
+}
+
+struct OwnedConsole<'a>(SpinLockGuard<'a, Option<BufferedConsole>>);
+
+impl Drop for OwnedConsole<'_> {
+    fn drop(&mut self) {
+        CONSOLE_OWNER.store(NO_OWNER, Ordering::Relaxed);
+    }
+}
+
+impl Deref for OwnedConsole<'_> {
+    type Target = Option<BufferedConsole>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for OwnedConsole<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Wraps a [`Console`], accumulating output into lines to reduce per-write hardware overhead.
+///
+/// Newlines are translated to `\r\n` and trigger a flush, as does filling the buffer.
+struct BufferedConsole {
+    console: Console,
+    buf: ArrayVec<u8, LINE_BUFFER_SIZE>,
+}
+
+impl BufferedConsole {
+    fn new(console: Console) -> Self {
+        Self {
+            console,
+            buf: ArrayVec::new(),
+        }
+    }
+
+    fn write(&mut self, s: &str) {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.push(b'\r');
+                self.push(b'\n');
+                self.flush();
+            } else {
+                self.push(byte);
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.buf.is_full() {
+            self.flush();
+        }
+        self.buf.push(byte);
+    }
+
+    fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            self.console.write_bytes(&self.buf);
+            self.buf.clear();
+        }
+    }
+}
+
+impl fmt::Write for BufferedConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s);
+        Ok(())
+    }
+}
+
+/// Writes `args` directly to the first legacy serial port, bypassing the console entirely.
+///
+/// This should only be used for reporting errors that occur before the console has been
+/// initialized, when the system is still single-threaded; later diagnostics that must not take the
+/// console lock should use [`force_writeln_fmt`] instead. In particular, this does not touch any
+/// per-CPU state or locks, so it can be called before early processor initialization.
+pub fn early_writeln_fmt(args: Arguments<'_>) {
+    // Safety: early output is only used before the console is up, while nothing else is accessing
+    // the serial port.
+    let mut port = unsafe { SerialPort::new(EARLY_SERIAL_PORT) };
+    let _ = writeln!(port, "{args}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn forced_output_bypasses_lock_held_here() {
+        assert_eq!(
+            force_write(format_args!("forced output test: unlocked")),
+            ForcedWrite::Locked
+        );
+
+        irq::disable_with(|irq_disabled| {
+            let _console = lock_console(irq_disabled);
+
+            // This would deadlock if we tried to take the lock.
+            assert_eq!(
+                force_write(format_args!("forced output test: locked")),
+                ForcedWrite::Bypassed
+            );
+        });
+
+        assert_eq!(CONSOLE_OWNER.load(Ordering::Relaxed), NO_OWNER);
+    }
 }
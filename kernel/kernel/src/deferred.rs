@@ -0,0 +1,90 @@
+//! Deferred work ("bottom halves") for interrupt handlers.
+//!
+//! Interrupt handlers should do as little as possible while interrupts are disabled. Any remaining
+//! work can be queued with [`defer`], and will be run on the same core on the way out of the next
+//! IRQ that returns to a context with interrupts enabled, with interrupts enabled again.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::err::{Error, Result};
+use crate::mp::current_percpu;
+use crate::sync::irq::{self, IrqDisabled};
+use crate::sync::resched::ReschedDisabled;
+use crate::sync::RingBuffer;
+
+/// The maximum number of work items that can be pending on a single core.
+pub const DEFERRED_QUEUE_CAPACITY: usize = 32;
+
+/// Per-CPU queue of pending deferred work.
+pub struct DeferredQueue {
+    work: RingBuffer<fn(), DEFERRED_QUEUE_CAPACITY>,
+    draining: AtomicBool,
+}
+
+impl DeferredQueue {
+    pub const fn new() -> Self {
+        Self {
+            work: RingBuffer::new(),
+            draining: AtomicBool::new(false),
+        }
+    }
+}
+
+/// Queues `work` to be run on the current core once IRQ handling completes.
+///
+/// Work items run in the order in which they were queued, with interrupts enabled.
+///
+/// # Errors
+///
+/// * `OUT_OF_RESOURCES` - The current core's queue already holds [`DEFERRED_QUEUE_CAPACITY`] items.
+pub fn defer(irq_disabled: &IrqDisabled, work: fn()) -> Result<()> {
+    let percpu = current_percpu(irq_disabled.resched_disabled());
+    percpu.debug_assert_owned();
+
+    percpu
+        .deferred
+        .work
+        .try_push(work)
+        .map_err(|_| Error::OUT_OF_RESOURCES)
+}
+
+/// Runs all work queued on the current core, temporarily enabling interrupts while doing so.
+///
+/// Work queued by interrupts taken while the queue is being drained is run as part of the same
+/// call. If this function is re-entered by such a nested interrupt, it returns immediately and
+/// leaves the work to the outer invocation.
+///
+/// This should be called by the architecture-specific IRQ return path. Exceptions and system calls
+/// should not run it, as they are synchronous with the code that triggered them and should not be
+/// delayed by unrelated work.
+///
+/// # Safety
+///
+/// * Interrupts must be disabled on entry, and will be disabled again on return
+/// * The interrupted context must have been running with interrupts and rescheduling enabled, so
+///   that it is safe to re-enable interrupts here
+pub unsafe fn run_pending() {
+    // Safety: we are running on the interrupt return path, so nothing can reschedule us.
+    let resched_disabled = unsafe { ReschedDisabled::new_unchecked() };
+    let queue = &current_percpu(&resched_disabled).deferred;
+
+    if queue.draining.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    // Check for more work with interrupts disabled, so that anything queued by an interrupt taken
+    // after the final check is guaranteed to be run by that interrupt's own return path.
+    while let Some(work) = queue.work.pop() {
+        // Safety: the caller guarantees that the interrupted context was running with interrupts
+        // enabled.
+        unsafe {
+            irq::enable();
+        }
+
+        work();
+
+        irq::disable();
+    }
+
+    queue.draining.store(false, Ordering::Relaxed);
+}
@@ -1,4 +1,5 @@
 use core::alloc::AllocError;
+use core::fmt;
 
 use alloc::collections::TryReserveError;
 use struct_enum::struct_enum;
@@ -12,6 +13,34 @@ struct_enum! {
         RESOURCE_OVERLAP = 5;
         OUT_OF_RESOURCES = 6;
         NO_PERMS = 7;
+        QUOTA_EXCEEDED = 8;
+    }
+}
+
+impl Error {
+    /// Returns a stable, human-readable description of this error, or `None` if it is not one of
+    /// the known error codes.
+    pub const fn description(self) -> Option<&'static str> {
+        match self {
+            Self::INVALID_ARGUMENT => Some("invalid argument"),
+            Self::INVALID_STATE => Some("invalid state"),
+            Self::BAD_ADDRESS => Some("bad address"),
+            Self::OUT_OF_MEMORY => Some("out of memory"),
+            Self::RESOURCE_OVERLAP => Some("resource overlap"),
+            Self::OUT_OF_RESOURCES => Some("out of resources"),
+            Self::NO_PERMS => Some("permission denied"),
+            Self::QUOTA_EXCEEDED => Some("quota exceeded"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            Some(desc) => f.write_str(desc),
+            None => write!(f, "unknown error {}", self.to_raw()),
+        }
     }
 }
 
@@ -28,3 +57,31 @@ impl From<TryReserveError> for Error {
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test_case]
+    fn errors_display_stable_strings() {
+        let expected = [
+            (Error::INVALID_ARGUMENT, "invalid argument"),
+            (Error::INVALID_STATE, "invalid state"),
+            (Error::BAD_ADDRESS, "bad address"),
+            (Error::OUT_OF_MEMORY, "out of memory"),
+            (Error::RESOURCE_OVERLAP, "resource overlap"),
+            (Error::OUT_OF_RESOURCES, "out of resources"),
+            (Error::NO_PERMS, "permission denied"),
+            (Error::QUOTA_EXCEEDED, "quota exceeded"),
+        ];
+
+        assert_eq!(expected.len(), Error::all_values().len());
+        for (err, desc) in expected {
+            assert_eq!(format!("{err}"), desc);
+        }
+
+        assert_eq!(format!("{}", Error::from_raw(1234)), "unknown error 1234");
+    }
+}
@@ -0,0 +1,578 @@
+//! A simple text console rendered to a linear framebuffer.
+
+use core::ops::Range;
+use core::{cmp, fmt, mem, slice};
+
+use bootinfo::item::{FramebufferInfo, PixelFormat};
+
+use crate::err::{Error, Result};
+use crate::mm::pmm::FrameBoxSlice;
+use crate::mm::utils::to_page_count;
+
+use self::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+mod font;
+
+const TAB_WIDTH: usize = 8;
+
+/// An RGB color, to be encoded according to the framebuffer's pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const LIGHT_GRAY: Self = Self::new(0xaa, 0xaa, 0xaa);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    fn encode(self, format: PixelFormat) -> u32 {
+        let (r, g, b) = (self.r as u32, self.g as u32, self.b as u32);
+        if format == PixelFormat::BGR {
+            b | (g << 8) | (r << 16)
+        } else {
+            r | (g << 8) | (b << 16)
+        }
+    }
+}
+
+/// A linear framebuffer with bounds-checked drawing operations.
+///
+/// Coordinates are in pixels, with the origin at the top-left corner. Only the visible area is ever
+/// written; any padding at the end of each line (up to the stride) is left untouched.
+pub struct Framebuffer<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// Creates a framebuffer drawing into `pixels`, whose layout is described by `info`.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - The pixel format is unsupported, or `pixels` is too small for the
+    ///                        specified geometry.
+    pub fn new(pixels: &'a mut [u32], info: &FramebufferInfo) -> Result<Self> {
+        let format = info.pixel_format;
+        if format != PixelFormat::RGB && format != PixelFormat::BGR {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let width = info.pixel_width as usize;
+        let height = info.pixel_height as usize;
+        let stride = info.pixel_stride as usize;
+
+        let len = stride.checked_mul(height).ok_or(Error::INVALID_ARGUMENT)?;
+        if width > stride || pixels.len() < len {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        Ok(Self {
+            pixels,
+            width,
+            height,
+            stride,
+            format,
+        })
+    }
+
+    /// Returns the width of the visible area, in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the visible area, in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Sets the pixel at (`x`, `y`) to `color`.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - The pixel lies outside the visible area.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) -> Result<()> {
+        if x >= self.width || y >= self.height {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let color = color.encode(self.format);
+        self.pixels[y * self.stride + x] = color;
+        Ok(())
+    }
+
+    /// Fills the `width` by `height` rectangle whose top-left corner is at (`x`, `y`) with
+    /// `color`.
+    ///
+    /// The rectangle is clipped to the visible area, so any parts of it lying off-screen are
+    /// ignored.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        let x_end = x.saturating_add(width).min(self.width);
+        let y_end = y.saturating_add(height).min(self.height);
+
+        if x >= x_end {
+            return;
+        }
+
+        let color = color.encode(self.format);
+        for line_y in y..y_end {
+            let start = line_y * self.stride;
+            self.pixels[start + x..start + x_end].fill(color);
+        }
+    }
+
+    /// Fills the entire visible area with `color`.
+    pub fn clear(&mut self, color: Color) {
+        self.fill_rect(0, 0, self.width, self.height, color);
+    }
+}
+
+/// A text console drawing fixed-size glyphs into a framebuffer.
+///
+/// Text is laid out on a grid of character cells, wrapping to the next line when the cursor passes
+/// the right edge of the screen. When the cursor moves past the last line, the contents of the
+/// screen are scrolled up by a single line.
+///
+/// By default, drawing happens directly in the framebuffer. Once
+/// [`enable_back_buffer`](Self::enable_back_buffer) has been called, drawing happens in a back
+/// buffer in normal memory instead, and changes only become visible when [`flush`](Self::flush) is
+/// called.
+pub struct FramebufferConsole<'a> {
+    fb: Framebuffer<'a>,
+    back: Option<BackBuffer>,
+    dirty_rows: Range<usize>,
+    cols: usize,
+    rows: usize,
+    cursor_col: usize,
+    cursor_row: usize,
+    fg: u32,
+    bg: u32,
+}
+
+impl<'a> FramebufferConsole<'a> {
+    /// Creates a new console drawing into `pixels`, whose layout is described by `info`, and clears
+    /// the screen.
+    ///
+    /// Any pixels not covered by a full character cell are left untouched.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - The pixel format is unsupported, `pixels` is too small for the
+    ///                        specified geometry, or the screen cannot fit a single character.
+    pub fn new(pixels: &'a mut [u32], info: &FramebufferInfo) -> Result<Self> {
+        let fb = Framebuffer::new(pixels, info)?;
+
+        let cols = fb.width() / GLYPH_WIDTH;
+        let rows = fb.height() / GLYPH_HEIGHT;
+
+        if cols == 0 || rows == 0 {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let mut console = Self {
+            fb,
+            back: None,
+            dirty_rows: 0..0,
+            cols,
+            rows,
+            cursor_col: 0,
+            cursor_row: 0,
+            fg: 0,
+            bg: 0,
+        };
+
+        console.set_colors(Color::LIGHT_GRAY, Color::BLACK);
+        console.clear();
+        Ok(console)
+    }
+
+    /// Returns the number of character columns on the screen.
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns the number of character rows on the screen.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the current cursor position, as a `(col, row)` pair.
+    #[cfg(test)]
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_col, self.cursor_row)
+    }
+
+    /// Switches the console to drawing into a newly-allocated back buffer, which is copied to the
+    /// framebuffer on [`flush`](Self::flush).
+    ///
+    /// Scrolling the back buffer never needs to read from the framebuffer, which is typically
+    /// mapped write-combining and very slow to read. Flushing writes out whole lines sequentially,
+    /// which is the access pattern write-combining is designed for.
+    ///
+    /// Does nothing if a back buffer is already in use.
+    ///
+    /// # Errors
+    ///
+    /// * `OUT_OF_MEMORY` - The back buffer could not be allocated.
+    pub fn enable_back_buffer(&mut self) -> Result<()> {
+        if self.back.is_some() {
+            return Ok(());
+        }
+
+        // Anything drawn so far went straight to the framebuffer and is already visible.
+        self.dirty_rows = 0..0;
+
+        let len = self.text_pixel_count();
+        let mut back = BackBuffer::new(len)?;
+        back.pixels_mut().copy_from_slice(&self.fb.pixels[..len]);
+        self.back = Some(back);
+
+        Ok(())
+    }
+
+    /// Copies any rows changed since the last flush from the back buffer to the framebuffer.
+    ///
+    /// This does nothing if no back buffer is in use, as all drawing is then immediately visible.
+    pub fn flush(&mut self) {
+        let Some(back) = &mut self.back else {
+            return;
+        };
+
+        let width = self.cols * GLYPH_WIDTH;
+        let back_pixels = back.pixels_mut();
+
+        for y in self.dirty_rows.start * GLYPH_HEIGHT..self.dirty_rows.end * GLYPH_HEIGHT {
+            let line = y * self.fb.stride..y * self.fb.stride + width;
+            self.fb.pixels[line.clone()].copy_from_slice(&back_pixels[line]);
+        }
+
+        self.dirty_rows = 0..0;
+    }
+
+    /// Sets the colors used for subsequently written text.
+    pub fn set_colors(&mut self, fg: Color, bg: Color) {
+        self.fg = fg.encode(self.fb.format);
+        self.bg = bg.encode(self.fb.format);
+    }
+
+    /// Clears the screen to the background color and moves the cursor to the top-left corner.
+    pub fn clear(&mut self) {
+        for row in 0..self.rows {
+            self.clear_row(row);
+        }
+
+        self.cursor_col = 0;
+        self.cursor_row = 0;
+    }
+
+    /// Writes `s` at the current cursor position.
+    ///
+    /// `\n`, `\r` and `\t` are interpreted as control characters; any other characters not
+    /// supported by the font are rendered as `?`.
+    pub fn write(&mut self, s: &str) {
+        for c in s.chars() {
+            self.put_char(c);
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        match c {
+            '\n' => self.newline(),
+            '\r' => self.cursor_col = 0,
+            '\t' => {
+                let next_stop = (self.cursor_col / TAB_WIDTH + 1) * TAB_WIDTH;
+                while self.cursor_col < next_stop.min(self.cols) {
+                    self.put_char(' ');
+                }
+            }
+            c => {
+                if self.cursor_col == self.cols {
+                    self.newline();
+                }
+
+                self.draw_glyph(self.cursor_col, self.cursor_row, c);
+                self.cursor_col += 1;
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.cursor_col = 0;
+
+        if self.cursor_row + 1 < self.rows {
+            self.cursor_row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn scroll(&mut self) {
+        let line_pixels = GLYPH_HEIGHT * self.fb.stride;
+        let text_pixels = self.text_pixel_count();
+
+        self.target().copy_within(line_pixels..text_pixels, 0);
+        self.mark_dirty(0..self.rows);
+        self.clear_row(self.rows - 1);
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let width = self.cols * GLYPH_WIDTH;
+        let stride = self.fb.stride;
+        let bg = self.bg;
+
+        self.mark_dirty(row..row + 1);
+        let target = self.target();
+
+        for y in row * GLYPH_HEIGHT..(row + 1) * GLYPH_HEIGHT {
+            let start = y * stride;
+            target[start..start + width].fill(bg);
+        }
+    }
+
+    fn draw_glyph(&mut self, col: usize, row: usize, c: char) {
+        let glyph = glyph(c);
+        let stride = self.fb.stride;
+        let (fg, bg) = (self.fg, self.bg);
+
+        self.mark_dirty(row..row + 1);
+        let target = self.target();
+
+        for (glyph_y, &bits) in glyph.iter().enumerate() {
+            let start = (row * GLYPH_HEIGHT + glyph_y) * stride + col * GLYPH_WIDTH;
+            let line = &mut target[start..start + GLYPH_WIDTH];
+
+            for (glyph_x, pixel) in line.iter_mut().enumerate() {
+                *pixel = if bits & (1 << glyph_x) != 0 { fg } else { bg };
+            }
+        }
+    }
+
+    /// Returns the buffer that should currently be drawn into.
+    fn target(&mut self) -> &mut [u32] {
+        match &mut self.back {
+            Some(back) => back.pixels_mut(),
+            None => self.fb.pixels,
+        }
+    }
+
+    fn mark_dirty(&mut self, rows: Range<usize>) {
+        self.dirty_rows = if self.dirty_rows.is_empty() {
+            rows
+        } else {
+            cmp::min(self.dirty_rows.start, rows.start)..cmp::max(self.dirty_rows.end, rows.end)
+        };
+    }
+
+    /// Returns the number of pixels (including padding) spanned by full rows of text.
+    fn text_pixel_count(&self) -> usize {
+        self.rows * GLYPH_HEIGHT * self.fb.stride
+    }
+}
+
+/// A back buffer for a [`FramebufferConsole`], allocated directly from the PMM.
+struct BackBuffer {
+    frames: FrameBoxSlice,
+    len: usize,
+}
+
+impl BackBuffer {
+    fn new(len: usize) -> Result<Self> {
+        let frames = FrameBoxSlice::new(to_page_count(len * mem::size_of::<u32>()))?;
+        Ok(Self { frames, len })
+    }
+
+    fn pixels_mut(&mut self) -> &mut [u32] {
+        let bytes = &mut self.frames[..];
+
+        // Safety: the frames are page-aligned, cover at least `len` pixels, and are zero-initialized
+        // (which is a valid `u32`). The returned slice borrows `self` mutably, so it cannot alias
+        // any other access to the frames.
+        unsafe { slice::from_raw_parts_mut(bytes.as_mut_ptr().cast(), self.len) }
+    }
+}
+
+impl fmt::Write for FramebufferConsole<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    const PADDING: u32 = 0xdeadbeef;
+
+    fn test_info(cols: usize, rows: usize, stride: usize, format: PixelFormat) -> FramebufferInfo {
+        FramebufferInfo {
+            paddr: 0,
+            byte_size: stride * rows * GLYPH_HEIGHT * 4,
+            pixel_width: (cols * GLYPH_WIDTH) as u32,
+            pixel_height: (rows * GLYPH_HEIGHT) as u32,
+            pixel_stride: stride as u32,
+            pixel_format: format,
+        }
+    }
+
+    fn cell_matches(pixels: &[u32], stride: usize, col: usize, row: usize, c: char) -> bool {
+        let fg = Color::LIGHT_GRAY.encode(PixelFormat::RGB);
+        let bg = Color::BLACK.encode(PixelFormat::RGB);
+
+        glyph(c).iter().enumerate().all(|(glyph_y, &bits)| {
+            (0..GLYPH_WIDTH).all(|glyph_x| {
+                let pixel =
+                    pixels[(row * GLYPH_HEIGHT + glyph_y) * stride + col * GLYPH_WIDTH + glyph_x];
+                let expected = if bits & (1 << glyph_x) != 0 { fg } else { bg };
+                pixel == expected
+            })
+        })
+    }
+
+    #[test_case]
+    fn fbcon_wraps_and_scrolls() {
+        let stride = 2 * GLYPH_WIDTH + 3;
+        let info = test_info(2, 2, stride, PixelFormat::RGB);
+        let mut pixels = vec![PADDING; stride * 2 * GLYPH_HEIGHT];
+
+        let mut console = FramebufferConsole::new(&mut pixels, &info).unwrap();
+        assert_eq!((console.cols(), console.rows()), (2, 2));
+
+        console.write("abcd");
+        assert_eq!(console.cursor(), (2, 1));
+
+        // Wrapping past the bottom-right corner should scroll the screen.
+        console.write("e");
+        assert_eq!(console.cursor(), (1, 1));
+
+        drop(console);
+
+        assert!(cell_matches(&pixels, stride, 0, 0, 'c'));
+        assert!(cell_matches(&pixels, stride, 1, 0, 'd'));
+        assert!(cell_matches(&pixels, stride, 0, 1, 'e'));
+        assert!(cell_matches(&pixels, stride, 1, 1, ' '));
+
+        // Padding beyond the visible width must never be touched.
+        for y in 0..2 * GLYPH_HEIGHT {
+            for x in 2 * GLYPH_WIDTH..stride {
+                assert_eq!(pixels[y * stride + x], PADDING);
+            }
+        }
+    }
+
+    #[test_case]
+    fn fbcon_back_buffer_flushes_dirty_rows() {
+        let stride = 2 * GLYPH_WIDTH;
+        let info = test_info(2, 2, stride, PixelFormat::RGB);
+        let mut pixels = vec![PADDING; stride * 2 * GLYPH_HEIGHT];
+
+        let mut console = FramebufferConsole::new(&mut pixels, &info).unwrap();
+        console.enable_back_buffer().unwrap();
+
+        // Drawing should not touch the framebuffer until flushed.
+        console.write("ab");
+        assert!(cell_matches(console.fb.pixels, stride, 0, 0, ' '));
+
+        // Only the dirty first row should be copied out; the second row keeps whatever it held.
+        let second_row = GLYPH_HEIGHT * stride;
+        console.fb.pixels[second_row..].fill(PADDING);
+        console.flush();
+        assert!(cell_matches(console.fb.pixels, stride, 0, 0, 'a'));
+        assert!(cell_matches(console.fb.pixels, stride, 1, 0, 'b'));
+        assert!(console.fb.pixels[second_row..]
+            .iter()
+            .all(|&p| p == PADDING));
+
+        // Scrolling dirties the whole screen.
+        console.write("\ncd\ne");
+        console.flush();
+        assert!(cell_matches(console.fb.pixels, stride, 0, 0, 'c'));
+        assert!(cell_matches(console.fb.pixels, stride, 1, 0, 'd'));
+        assert!(cell_matches(console.fb.pixels, stride, 0, 1, 'e'));
+        assert!(cell_matches(console.fb.pixels, stride, 1, 1, ' '));
+    }
+
+    #[test_case]
+    fn framebuffer_drawing_is_bounds_checked() {
+        let (width, height, stride) = (5, 3, 8);
+        let info = FramebufferInfo {
+            paddr: 0,
+            byte_size: stride * height * 4,
+            pixel_width: width as u32,
+            pixel_height: height as u32,
+            pixel_stride: stride as u32,
+            pixel_format: PixelFormat::BGR,
+        };
+
+        // Leave some slack past the end of the framebuffer to catch overruns.
+        let mut pixels = vec![PADDING; stride * height + stride];
+        let mut fb = Framebuffer::new(&mut pixels[..stride * height], &info).unwrap();
+
+        let color = Color::new(0x11, 0x22, 0x33);
+        let encoded = color.encode(PixelFormat::BGR);
+
+        fb.set_pixel(4, 2, color).unwrap();
+        assert_eq!(fb.set_pixel(5, 0, color), Err(Error::INVALID_ARGUMENT));
+        assert_eq!(fb.set_pixel(0, 3, color), Err(Error::INVALID_ARGUMENT));
+        assert_eq!(
+            fb.set_pixel(usize::MAX, 0, color),
+            Err(Error::INVALID_ARGUMENT)
+        );
+
+        // Partially off-screen, and overflowing the coordinate space.
+        fb.fill_rect(3, 1, usize::MAX, usize::MAX, color);
+        fb.fill_rect(width, 0, 2, 2, Color::BLACK);
+        fb.fill_rect(0, height, 2, 2, Color::BLACK);
+
+        let drawn = |x: usize, y: usize| (x == 4 && y == 2) || (x >= 3 && y >= 1);
+        for y in 0..height + 1 {
+            for x in 0..stride {
+                let expected = if x < width && y < height && drawn(x, y) {
+                    encoded
+                } else {
+                    PADDING
+                };
+                assert_eq!(pixels[y * stride + x], expected, "pixel ({x}, {y})");
+            }
+        }
+
+        let mut fb = Framebuffer::new(&mut pixels[..stride * height], &info).unwrap();
+        fb.clear(Color::BLACK);
+        for (i, &pixel) in pixels.iter().enumerate() {
+            let (x, y) = (i % stride, i / stride);
+            let expected = if x < width && y < height { 0 } else { PADDING };
+            assert_eq!(pixel, expected, "pixel ({x}, {y})");
+        }
+
+        let short_info = FramebufferInfo {
+            pixel_stride: (width - 1) as u32,
+            ..info
+        };
+        assert!(Framebuffer::new(&mut pixels, &short_info).is_err());
+        assert!(Framebuffer::new(&mut pixels[..stride * height - 1], &info).is_err());
+    }
+
+    #[test_case]
+    fn fbcon_encodes_pixel_format() {
+        let color = Color::new(0x11, 0x22, 0x33);
+        assert_eq!(color.encode(PixelFormat::RGB), 0x332211);
+        assert_eq!(color.encode(PixelFormat::BGR), 0x112233);
+
+        let info = test_info(1, 1, GLYPH_WIDTH, PixelFormat::from_raw(5));
+        let mut pixels = vec![0; GLYPH_WIDTH * GLYPH_HEIGHT];
+        assert_eq!(
+            FramebufferConsole::new(&mut pixels, &info).err(),
+            Some(Error::INVALID_ARGUMENT)
+        );
+    }
+}
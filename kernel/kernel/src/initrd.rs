@@ -0,0 +1,68 @@
+use core::{mem, slice};
+
+use bootinfo::item::InitrdInfo;
+use log::info;
+use spin_once::Once;
+
+use crate::err::{Error, Result};
+use crate::mm::kmap::kmap;
+use crate::mm::types::{CacheMode, PhysAddr, Protection};
+use crate::mm::utils::{display_byte_size, to_page_count};
+use crate::mm::vm::object::PhysVmObject;
+
+static INITRD: Once<&'static [u8]> = Once::new();
+
+/// Maps the initial ramdisk described by `info` into the kernel address space, making it available
+/// through [`get`].
+///
+/// The mapping is never torn down, as the ramdisk is expected to remain in use for the lifetime of
+/// the system.
+///
+/// # Errors
+///
+/// * `INVALID_STATE` - The ramdisk has already been mapped.
+/// * Any errors returned while creating the mapping.
+///
+/// # Safety
+///
+/// * `info` must describe a range of normal memory that has been set aside for the ramdisk, and is
+///   never handed out by the frame allocator
+pub unsafe fn init(info: &InitrdInfo) -> Result<()> {
+    let base = PhysAddr::new(info.paddr);
+
+    // Safety: function contract
+    let object = unsafe {
+        PhysVmObject::new_named(
+            "initrd",
+            base.containing_frame(),
+            to_page_count(base.frame_offset() + info.byte_size),
+            CacheMode::Cached,
+        )?
+    };
+    let mapping = kmap(object, Protection::READ)?;
+
+    // Safety: the mapping covers the entire ramdisk, and is never unmapped.
+    let initrd = unsafe {
+        slice::from_raw_parts(
+            (mapping.addr() + base.frame_offset()).as_ptr(),
+            info.byte_size,
+        )
+    };
+
+    INITRD.set(initrd).map_err(|_| Error::INVALID_STATE)?;
+    mem::forget(mapping);
+
+    info!(
+        "initrd: {} at {}, mapped at {:p}",
+        display_byte_size(info.byte_size),
+        base,
+        initrd.as_ptr()
+    );
+
+    Ok(())
+}
+
+/// Returns the contents of the initial ramdisk, if one was provided by the loader.
+pub fn get() -> Option<&'static [u8]> {
+    INITRD.get().copied()
+}
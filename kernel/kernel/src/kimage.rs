@@ -1,4 +1,8 @@
+use bootinfo::item::KernelImageInfo;
+use log::warn;
+
 use crate::mm::types::{PhysAddr, PhysFrameNum, VirtAddr, VirtPageNum};
+use crate::mm::utils::to_page_count;
 
 static mut KERNEL_PHYS: PhysFrameNum = PhysFrameNum::new(0);
 
@@ -28,6 +32,31 @@ pub unsafe fn init(kernel_paddr: PhysAddr) {
     }
 }
 
+/// Cross-checks the kernel image information reported by the loader against the physical base
+/// passed to [`init`] and the extent of the image.
+///
+/// A size mismatch is only logged, as the loader's view of the image is derived from its program
+/// headers rather than the linker-provided symbols used by the kernel.
+///
+/// # Panics
+///
+/// Panics if the loader reports a different physical base than the one passed to [`init`].
+pub fn check_loader_info(info: &KernelImageInfo) {
+    assert_eq!(
+        PhysAddr::new(info.phys_base),
+        phys_base().addr(),
+        "kernel physical base does not match loader"
+    );
+
+    let loader_pages = to_page_count(info.byte_size);
+    if loader_pages != total_pages() {
+        warn!(
+            "loader reports kernel image of {loader_pages} pages, expected {}",
+            total_pages()
+        );
+    }
+}
+
 pub fn phys_base() -> PhysFrameNum {
     // Safety: no one else should be mutating `KERNEL_PHYS` at this point.
     unsafe { KERNEL_PHYS }
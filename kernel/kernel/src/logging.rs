@@ -1,7 +1,44 @@
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
 use log::{LevelFilter, Log, Metadata, Record};
 
+use crate::arch::cpu::read_timestamp;
 use crate::bootparse::CommandLine;
 
+/// Invokes the logging macro `$log` (such as `debug!`), unless this call site has already logged
+/// [`RATE_LIMIT_BURST`] messages in the current [`RATE_LIMIT_WINDOW`].
+///
+/// The number of messages dropped in the meantime is reported at the same level before the next
+/// message that gets through.
+///
+/// ```ignore
+/// rate_limited!(debug!("got IRQ {}", vector));
+/// ```
+macro_rules! rate_limited {
+    ($log:ident!($($args:tt)+)) => {{
+        static LIMIT: $crate::logging::RateLimit = $crate::logging::RateLimit::new(
+            $crate::logging::RATE_LIMIT_BURST,
+            $crate::logging::RATE_LIMIT_WINDOW,
+        );
+
+        if let Some(suppressed) = LIMIT.check() {
+            if suppressed != 0 {
+                $log!("(suppressed {suppressed} messages)");
+            }
+            $log!($($args)+);
+        }
+    }};
+}
+
+/// The number of messages a rate-limited call site may log per window.
+pub const RATE_LIMIT_BURST: usize = 10;
+
+/// The length of a rate-limiting window, in timestamp counter ticks.
+///
+/// The timestamp counter is not calibrated, so this is only approximate: it corresponds to roughly
+/// a second on a 2GHz core.
+pub const RATE_LIMIT_WINDOW: u64 = 1 << 31;
+
 pub fn init(cmdline: CommandLine<'_>) {
     log::set_logger(&LOGGER).expect("logging already initialized");
 
@@ -29,6 +66,61 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
+/// Tracks how many events have occurred in the current time window, allowing at most `burst` of them
+/// per window.
+pub struct RateLimit {
+    burst: usize,
+    window: u64,
+    window_start: AtomicU64,
+    count: AtomicUsize,
+    suppressed: AtomicUsize,
+}
+
+impl RateLimit {
+    /// Creates a new limit allowing `burst` events in every window of `window` timestamp counter
+    /// ticks.
+    pub const fn new(burst: usize, window: u64) -> Self {
+        Self {
+            burst,
+            window,
+            window_start: AtomicU64::new(0),
+            count: AtomicUsize::new(0),
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Records an event, returning `None` if it should be suppressed.
+    ///
+    /// If the event is allowed, returns the number of events suppressed since the last one that was
+    /// allowed.
+    pub fn check(&self) -> Option<usize> {
+        self.check_at(read_timestamp())
+    }
+
+    /// Records an event occurring at timestamp `now`, as described in [`check`](Self::check).
+    fn check_at(&self, now: u64) -> Option<usize> {
+        let window_start = self.window_start.load(Ordering::Relaxed);
+
+        if now.wrapping_sub(window_start) >= self.window
+            && self
+                .window_start
+                .compare_exchange(window_start, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            // We're the first event in a new window.
+            self.count.store(1, Ordering::Relaxed);
+            return Some(self.suppressed.swap(0, Ordering::Relaxed));
+        }
+
+        if self.count.fetch_add(1, Ordering::Relaxed) < self.burst {
+            Some(self.suppressed.swap(0, Ordering::Relaxed))
+        } else {
+            self.suppressed.fetch_add(1, Ordering::Relaxed);
+            None
+        }
+    }
+}
+
 fn get_log_level(cmdline: CommandLine<'_>) -> Option<LevelFilter> {
     let level_str = cmdline.get_arg_str_value("loglevel")?;
     parse_log_level(level_str)
@@ -45,3 +137,34 @@ fn parse_log_level(level_str: &str) -> Option<LevelFilter> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn rate_limit_throttles_tight_loop() {
+        let limit = RateLimit::new(3, u64::MAX);
+        let allowed = (0..100).filter(|_| limit.check().is_some()).count();
+        assert_eq!(allowed, 3);
+    }
+
+    #[test_case]
+    fn rate_limit_reports_suppressed_in_next_window() {
+        let limit = RateLimit::new(2, 100);
+
+        assert_eq!(limit.check_at(1000), Some(0));
+        assert_eq!(limit.check_at(1010), Some(0));
+        for now in 1020..1025 {
+            assert_eq!(limit.check_at(now), None);
+        }
+
+        // Still within the first window.
+        assert_eq!(limit.check_at(1099), None);
+
+        assert_eq!(limit.check_at(1100), Some(6));
+        assert_eq!(limit.check_at(1150), Some(0));
+        assert_eq!(limit.check_at(1199), None);
+        assert_eq!(limit.check_at(1300), Some(1));
+    }
+}
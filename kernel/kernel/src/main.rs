@@ -3,6 +3,9 @@
 #![feature(asm_const)]
 #![feature(panic_info_message)]
 #![feature(utf8_chunks)]
+#![feature(custom_test_frameworks)]
+#![test_runner(crate::testing::run_tests)]
+#![reexport_test_harness_main = "test_main"]
 #![warn(rust_2018_idioms)]
 #![deny(unsafe_op_in_unsafe_fn)]
 #![no_std]
@@ -10,29 +13,45 @@
 
 extern crate alloc;
 
+use alloc::sync::Arc;
 use core::{mem, slice};
 
-use log::{debug, info};
+use log::{debug, info, trace, warn};
 
+use crate::arch::power;
 use crate::bootparse::BootinfoData;
+use crate::err::{Error, Result};
+use crate::fbcon::FramebufferConsole;
 use crate::mm::kmap::iomap;
-use crate::mm::types::{CacheMode, PhysAddr, Protection};
+use crate::mm::types::{AccessMode, CacheMode, PhysAddr, PhysFrameNum, Protection, VirtAddr};
+use crate::mm::utils::display_hexdump;
+use crate::mm::vm::aspace::MapBase;
+use crate::mm::vm::object::{CommitType, VmObject};
 use crate::sched::Thread;
 use crate::sync::irq::{self, IrqDisabled};
 
 #[macro_use]
 mod console;
+#[macro_use]
+mod logging;
 
 mod arch;
 mod bootparse;
+mod deferred;
 mod err;
+mod fbcon;
+mod initrd;
 mod kimage;
-mod logging;
 mod mm;
 mod mp;
+mod names;
 mod panic;
 mod sched;
 mod sync;
+mod syscall;
+
+#[cfg(test)]
+mod testing;
 
 /// The main architecture-agnostic entry point.
 ///
@@ -89,18 +108,32 @@ unsafe extern "C" fn kernel_main(
         kimage::virt_end().addr()
     );
 
+    match bootinfo.kernel_image() {
+        Some(kernel_image) => kimage::check_loader_info(kernel_image),
+        None => warn!("no kernel image information in bootinfo"),
+    }
+
     debug!("bootinfo at {}, size {:#x}", bootinfo_paddr, bootinfo_size);
 
     info!("kernel command line: {}", bootinfo.command_line());
+    bootinfo.log_unknown_items();
 
     info!("initializing memory manager");
     unsafe {
-        mm::init_late(mm_init_ctx, &bootinfo, &irq_disabled);
+        mm::init_late(mm_init_ctx, &bootinfo);
     }
     info!("memory manager initialized");
 
     mm::pmm::dump_usage();
 
+    if let Some(initrd_info) = bootinfo.initrd() {
+        debug!("initrd: {initrd_info}");
+
+        // Safety: the initrd range was reserved during memory manager initialization.
+        unsafe { initrd::init(initrd_info) }.expect("failed to map initrd");
+    }
+
+    sched::init();
     Thread::spawn("bootstrap", move || bootstrap(&bootinfo), None)
         .expect("failed to create bootstrap thread");
     unsafe { sched::start() };
@@ -110,6 +143,9 @@ fn bootstrap(bootinfo: &BootinfoData<'_>) {
     info!("in bootstrap thread");
     assert!(irq::enabled());
 
+    #[cfg(test)]
+    test_main();
+
     if let Some(efi_system_table) = bootinfo.efi_system_table() {
         debug!("EFI system table: {}", efi_system_table);
     }
@@ -117,14 +153,7 @@ fn bootstrap(bootinfo: &BootinfoData<'_>) {
     if let Some(framebuffer_info) = bootinfo.framebuffer_info() {
         let framebuffer_paddr = PhysAddr::new(framebuffer_info.paddr);
 
-        debug!(
-            "framebuffer: phys range {}-{}, dimensions {}x{}, format {:?}",
-            framebuffer_paddr,
-            framebuffer_paddr + framebuffer_info.byte_size,
-            framebuffer_info.pixel_width,
-            framebuffer_info.pixel_height,
-            framebuffer_info.pixel_format
-        );
+        debug!("framebuffer: {framebuffer_info}");
 
         let framebuffer_mapping = unsafe {
             iomap(
@@ -138,22 +167,40 @@ fn bootstrap(bootinfo: &BootinfoData<'_>) {
 
         debug!("framebuffer mapped at {}", framebuffer_mapping.addr());
 
-        let framebuffer_slice: &mut [u32] = unsafe {
+        // Safety: the framebuffer is never unmapped, so the slice remains valid forever.
+        let framebuffer_slice: &'static mut [u32] = unsafe {
             slice::from_raw_parts_mut(
                 framebuffer_mapping.addr().as_mut_ptr(),
                 framebuffer_info.byte_size / mem::size_of::<u32>(),
             )
         };
+        mem::forget(framebuffer_mapping);
 
-        debug!("writing to framebuffer");
+        match FramebufferConsole::new(framebuffer_slice, framebuffer_info) {
+            Ok(mut fb_console) => {
+                if let Err(err) = fb_console.enable_back_buffer() {
+                    warn!("failed to allocate framebuffer back buffer: {err:?}");
+                }
 
-        for row in 0..framebuffer_info.pixel_height {
-            for col in 0..framebuffer_info.pixel_width {
-                framebuffer_slice[(row * framebuffer_info.pixel_stride + col) as usize] = 0xff;
+                debug!(
+                    "framebuffer console: {}x{} characters",
+                    fb_console.cols(),
+                    fb_console.rows()
+                );
+                console::attach_framebuffer(fb_console);
             }
+            Err(err) => warn!("failed to create framebuffer console: {err:?}"),
         }
     }
 
+    mm::vm::kernel_aspace().dump();
+
+    if let Some(initrd) = initrd::get() {
+        // The first few rows are enough to tell whether the loader picked up the right image.
+        let header = &initrd[..initrd.len().min(0x40)];
+        trace!("initrd header:\n{}", display_hexdump(header));
+    }
+
     if bootinfo
         .command_line()
         .get_arg_value("stackoverflow")
@@ -162,6 +209,35 @@ fn bootstrap(bootinfo: &BootinfoData<'_>) {
         info!("triggering kernel stack overflow");
         stack_overflow();
     }
+
+    if bootinfo
+        .command_line()
+        .get_arg_value("nestedfault")
+        .is_some()
+    {
+        info!("triggering nested page fault");
+        nested_page_fault();
+    }
+
+    if bootinfo.command_line().get_arg_value("nmidump").is_some() {
+        info!("dumping cpu state");
+        mp::broadcast_nmi_dump();
+    }
+
+    if let Some(code) = bootinfo.command_line().get_arg_str_value("qemu.exit") {
+        match code.parse() {
+            Ok(code) => {
+                info!("exiting QEMU with code {code}");
+                power::exit_qemu(code);
+            }
+            Err(_) => warn!("invalid QEMU exit code '{code}'"),
+        }
+    }
+
+    if bootinfo.command_line().get_arg_value("reboot").is_some() {
+        info!("rebooting");
+        power::reboot();
+    }
 }
 
 #[inline(never)]
@@ -169,3 +245,47 @@ fn stack_overflow() {
     let big = [0u8; 0x8000];
     core::hint::black_box(&big);
 }
+
+fn nested_page_fault() {
+    /// An object that faults on an unmapped address whenever a page is requested from it.
+    struct FaultingVmObject;
+
+    unsafe impl VmObject for FaultingVmObject {
+        fn page_count(&self) -> usize {
+            1
+        }
+
+        fn provide_page(&self, _offset: usize, _commit_type: CommitType) -> Result<PhysFrameNum> {
+            let bad_ptr: *const u8 = VirtAddr::new(0x1000).as_ptr();
+
+            // Safety: none; this intentionally faults from within the page fault handler.
+            unsafe { bad_ptr.read_volatile() };
+            Err(Error::BAD_ADDRESS)
+        }
+    }
+
+    let aspace =
+        mm::vm::make_low_addr_space(AccessMode::Kernel).expect("failed to create address space");
+    let object: Arc<dyn VmObject> = Arc::new(FaultingVmObject);
+    let mapping = aspace
+        .map(
+            aspace.root_slice(),
+            MapBase::any(),
+            1,
+            0,
+            object,
+            Protection::READ,
+        )
+        .expect("failed to map faulting object");
+
+    let addr = mapping.start().addr();
+    Thread::spawn(
+        "nestedfault",
+        move || {
+            // Safety: none; this intentionally faults on the mapping.
+            unsafe { core::hint::black_box(addr.as_ptr::<u8>().read_volatile()) };
+        },
+        Some(aspace),
+    )
+    .expect("failed to spawn faulting thread");
+}
@@ -10,4 +10,59 @@ mod early;
 mod init;
 mod pt;
 
+use crate::sched::Thread;
+
+use self::types::{PhysAddr, VirtAddr};
+
 pub use init::{init_early, init_late};
+
+/// Translates `addr` to the physical address it is currently mapped to.
+///
+/// Low addresses are translated in the address space of the current thread, and all other
+/// addresses are translated in the kernel address space. Returns `None` if `addr` is not currently
+/// mapped (or is a low address and the current thread has no address space).
+///
+/// # Panics
+///
+/// Panics if the kernel address space has not yet been initialized.
+pub fn virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
+    let pfn = if vm::is_low_addr(addr) {
+        let current_thread = Thread::current()?;
+        current_thread
+            .addr_space()?
+            .translate(addr.containing_page())
+    } else {
+        vm::kernel_aspace().translate(addr.containing_page())
+    }?;
+
+    Some(pfn.addr() + addr.page_offset())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kimage;
+
+    use super::physmap::pfn_to_physmap;
+    use super::types::PhysFrameNum;
+    use super::*;
+
+    #[test_case]
+    fn translates_kernel_mappings() {
+        static MARKER: u64 = 0;
+
+        let marker_addr = VirtAddr::from_ptr(&MARKER);
+        let marker_offset = marker_addr - kimage::virt_base().addr();
+        assert_eq!(
+            virt_to_phys(marker_addr),
+            Some(kimage::phys_base().addr() + marker_offset)
+        );
+
+        // The physmap is mapped with large pages where possible, so this exercises offsets within
+        // them as well.
+        let pfn = PhysFrameNum::new(0x1234);
+        assert_eq!(
+            virt_to_phys(pfn_to_physmap(pfn).addr() + 0x56),
+            Some(pfn.addr() + 0x56)
+        );
+    }
+}
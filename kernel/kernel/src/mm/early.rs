@@ -1,6 +1,8 @@
 use core::alloc::Layout;
+use core::fmt;
 use core::marker::PhantomData;
 use core::ops::Range;
+use core::result;
 
 use arrayvec::ArrayVec;
 use spin_once::TakeOnce;
@@ -41,14 +43,61 @@ impl BootHeap {
         self.base..self.cur
     }
 
+    /// Returns the number of bytes left in the heap, ignoring any alignment requirements.
+    pub fn remaining(&self) -> usize {
+        self.end - self.cur
+    }
+
+    /// Allocates a physical range satisfying `layout`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not enough space left in the heap, reporting the requested layout and
+    /// the remaining space.
     pub fn alloc_phys(&mut self, layout: Layout) -> PhysAddr {
+        match self.try_alloc_phys(layout) {
+            Ok(base) => base,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Allocates a physical range satisfying `layout`, failing if there is not enough space left
+    /// in the heap.
+    pub fn try_alloc_phys(
+        &mut self,
+        layout: Layout,
+    ) -> result::Result<PhysAddr, BootHeapExhausted> {
         let base = self.cur.align_up(layout.align());
         if base > self.end || layout.size() > self.end - base {
-            panic!("bootheap exhausted");
+            return Err(BootHeapExhausted {
+                layout,
+                remaining: self.remaining(),
+            });
         }
 
         self.cur = base + layout.size();
-        base
+        Ok(base)
+    }
+}
+
+/// Error returned when a [`BootHeap`] allocation cannot be satisfied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootHeapExhausted {
+    /// The layout of the failed allocation.
+    pub layout: Layout,
+    /// The number of bytes left in the heap at the time of the allocation.
+    pub remaining: usize,
+}
+
+impl fmt::Display for BootHeapExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "bootheap exhausted: requested {} bytes aligned to {}, {} bytes remaining",
+            self.layout.size(),
+            self.layout.align(),
+            self.remaining
+        )
     }
 }
 
@@ -193,3 +242,44 @@ impl TranslatePhys for KernelPfnTranslator {
         kimage::vpn_from_kernel_pfn(phys)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test_case]
+    fn bootheap_exhaustion_reports_sizes() {
+        // The heap never touches the memory it hands out, so any range will do here.
+        let mut heap = BootHeap::new(PhysAddr::new(0x10008)..PhysAddr::new(0x11000));
+        assert_eq!(heap.remaining(), 0xff8);
+
+        assert_eq!(
+            heap.try_alloc_phys(Layout::from_size_align(0x100, 0x10).unwrap()),
+            Ok(PhysAddr::new(0x10010))
+        );
+        assert_eq!(heap.remaining(), 0xef0);
+
+        let layout = Layout::new::<PageTableSpace>();
+        let err = heap.try_alloc_phys(layout).unwrap_err();
+        assert_eq!(
+            err,
+            BootHeapExhausted {
+                layout,
+                remaining: 0xef0
+            }
+        );
+        assert_eq!(
+            format!("{err}"),
+            "bootheap exhausted: requested 4096 bytes aligned to 4096, 3824 bytes remaining"
+        );
+
+        // Failed allocations should leave the heap untouched.
+        assert_eq!(heap.remaining(), 0xef0);
+        assert_eq!(
+            heap.used_range(),
+            PhysAddr::new(0x10008)..PhysAddr::new(0x10110)
+        );
+    }
+}
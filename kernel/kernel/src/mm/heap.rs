@@ -10,8 +10,9 @@ use num_utils::{align_down, align_up, log2_ceil};
 use super::physmap::{pfn_to_physmap, physmap_to_pfn};
 use super::pmm;
 use super::types::VirtAddr;
-use super::utils::to_page_count;
+use super::utils::{to_page_count, FailureInjector};
 use crate::arch::mmu::PAGE_SIZE;
+use crate::sync::lockrank::RANK_HEAP;
 use crate::sync::SpinLock;
 
 #[global_allocator]
@@ -52,12 +53,35 @@ unsafe impl GlobalAlloc for KernelHeapAlloc {
 #[derive(Debug, Clone, Copy)]
 pub struct HeapAllocError;
 
+/// Whether heap allocations can be made to fail on demand with [`fail_nth_allocation`].
+const ALLOC_FAILURE_INJECTION: bool = cfg!(any(test, feature = "alloc-failure-injection"));
+
+static FAILURE_INJECTOR: FailureInjector = FailureInjector::new();
+
 pub fn allocate(layout: Layout) -> Result<NonNull<[u8]>, HeapAllocError> {
-    ALLOCATOR.allocate(get_effective_size(layout))
+    if ALLOC_FAILURE_INJECTION && FAILURE_INJECTOR.should_fail() {
+        return Err(HeapAllocError);
+    }
+
+    ALLOCATOR.allocate(get_effective_size(layout), layout.align())
+}
+
+/// Causes the `n`th subsequent heap allocation (counting from 0) to fail, replacing any previously
+/// requested failure.
+#[cfg(any(test, feature = "alloc-failure-injection"))]
+pub fn fail_nth_allocation(n: usize) {
+    FAILURE_INJECTOR.fail_nth(n);
+}
+
+/// Cancels any heap allocation failure requested with [`fail_nth_allocation`] that has not yet
+/// taken place.
+#[cfg(any(test, feature = "alloc-failure-injection"))]
+pub fn clear_allocation_failure() {
+    FAILURE_INJECTOR.disarm();
 }
 
 pub unsafe fn deallocate(ptr: NonNull<u8>, layout: Layout) {
-    unsafe { ALLOCATOR.deallocate(ptr, get_effective_size(layout)) }
+    unsafe { ALLOCATOR.deallocate(ptr, get_effective_size(layout), layout.align()) }
 }
 
 pub unsafe fn resize(
@@ -68,13 +92,23 @@ pub unsafe fn resize(
     let old_effective_size = get_effective_size(old_layout);
     let new_effective_size = get_effective_size(new_layout);
 
-    let old_usable_size = ALLOCATOR.usable_size(old_effective_size);
-    let new_usable_size = ALLOCATOR.usable_size(new_effective_size);
+    let old_usable_size = ALLOCATOR.usable_size(old_effective_size, old_layout.align());
+    let new_usable_size = ALLOCATOR.usable_size(new_effective_size, new_layout.align());
 
     if old_usable_size == new_usable_size {
         Ok(NonNull::slice_from_raw_parts(ptr, old_usable_size))
+    } else if let Some(new_ptr) = unsafe {
+        ALLOCATOR.resize_pages_in_place(
+            ptr,
+            old_effective_size,
+            old_layout.align(),
+            new_effective_size,
+            new_layout.align(),
+        )
+    } {
+        Ok(new_ptr)
     } else {
-        let new_ptr = ALLOCATOR.allocate(new_effective_size)?;
+        let new_ptr = ALLOCATOR.allocate(new_effective_size, new_layout.align())?;
         let copy_size = cmp::min(old_layout.size(), new_layout.size());
 
         unsafe {
@@ -82,7 +116,7 @@ pub unsafe fn resize(
                 .as_ptr()
                 .cast::<u8>()
                 .copy_from_nonoverlapping(ptr.as_ptr(), copy_size);
-            ALLOCATOR.deallocate(ptr, old_effective_size);
+            ALLOCATOR.deallocate(ptr, old_effective_size, old_layout.align());
         }
 
         Ok(new_ptr)
@@ -98,6 +132,8 @@ fn get_effective_size(layout: Layout) -> usize {
 // the number; in other words, rounding a number up to its size class must not decrease its trailing
 // zero count. We ensure this by never adding a power of 2 to a size class not already divisible by
 // that power, which would cause us to "skip" a size class that was more strictly aligned.
+// Requests whose alignment still exceeds that of their size class are served directly by the PMM
+// as a safety net; see `Allocator::get_size_class`.
 static ALLOCATOR: Allocator<25> = Allocator::new([
     // For small marker objects like `QCellOwner`
     SizeClass::new(2, 0),
@@ -142,14 +178,19 @@ impl<const N: usize> Allocator<N> {
         Self { size_classes }
     }
 
-    fn allocate(&self, effective_size: usize) -> Result<NonNull<[u8]>, HeapAllocError> {
-        match self.get_size_class(effective_size) {
+    fn allocate(
+        &self,
+        effective_size: usize,
+        align: usize,
+    ) -> Result<NonNull<[u8]>, HeapAllocError> {
+        match self.get_size_class(effective_size, align) {
             Some(size_class) => {
                 let ptr = size_class.allocate()?;
                 Ok(NonNull::slice_from_raw_parts(ptr, size_class.size()))
             }
             None => {
-                // Request too large for the slab allocator, get pages directly from the PMM
+                // Request too large or over-aligned for the slab allocator, get pages directly from
+                // the PMM
                 let order = raw_page_order(effective_size);
                 let ptr = alloc_virt_pages(order).ok_or(HeapAllocError)?;
                 Ok(NonNull::slice_from_raw_parts(ptr, PAGE_SIZE << order))
@@ -157,8 +198,8 @@ impl<const N: usize> Allocator<N> {
         }
     }
 
-    unsafe fn deallocate(&self, ptr: NonNull<u8>, effective_size: usize) {
-        match self.get_size_class(effective_size) {
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, effective_size: usize, align: usize) {
+        match self.get_size_class(effective_size, align) {
             Some(size_class) => unsafe {
                 size_class.deallocate(ptr);
             },
@@ -171,20 +212,64 @@ impl<const N: usize> Allocator<N> {
         }
     }
 
-    fn usable_size(&self, effective_size: usize) -> usize {
-        match self.get_size_class(effective_size) {
+    /// Attempts to resize the page-backed allocation at `ptr` without moving it, returning the
+    /// resized allocation on success.
+    ///
+    /// This only succeeds if both the old and new sizes are served directly by the PMM, and (when
+    /// growing) the pages following the allocation are free.
+    unsafe fn resize_pages_in_place(
+        &self,
+        ptr: NonNull<u8>,
+        old_effective_size: usize,
+        old_align: usize,
+        new_effective_size: usize,
+        new_align: usize,
+    ) -> Option<NonNull<[u8]>> {
+        if self.get_size_class(old_effective_size, old_align).is_some()
+            || self.get_size_class(new_effective_size, new_align).is_some()
+        {
+            return None;
+        }
+
+        let old_order = raw_page_order(old_effective_size);
+        let new_order = raw_page_order(new_effective_size);
+        let pfn = physmap_to_pfn(VirtAddr::from_ptr(ptr.as_ptr()).containing_page());
+
+        if new_order > old_order {
+            // Safety: the caller guarantees that `ptr` is a live allocation of the old size.
+            if !unsafe { pmm::try_grow_in_place(pfn, old_order, new_order) } {
+                return None;
+            }
+        } else {
+            // Safety: as above, and the caller will not access the trailing pages once the
+            // allocation has been shrunk.
+            unsafe { pmm::shrink_in_place(pfn, old_order, new_order) };
+        }
+
+        Some(NonNull::slice_from_raw_parts(ptr, PAGE_SIZE << new_order))
+    }
+
+    fn usable_size(&self, effective_size: usize, align: usize) -> usize {
+        match self.get_size_class(effective_size, align) {
             Some(size_class) => size_class.size(),
             None => PAGE_SIZE << raw_page_order(effective_size),
         }
     }
 
-    fn get_size_class(&self, effective_size: usize) -> Option<&SizeClass> {
+    /// Returns the size class that should be used to serve allocations of `effective_size` bytes
+    /// aligned to `align`, or `None` if they should be served directly by the PMM.
+    ///
+    /// Page allocations are always page-aligned, so over-aligned requests that no size class can
+    /// satisfy are routed there.
+    fn get_size_class(&self, effective_size: usize, align: usize) -> Option<&SizeClass> {
         let i = self
             .size_classes
             .binary_search_by_key(&effective_size, |size_class| size_class.size())
             .unwrap_or_else(|i| i);
 
-        self.size_classes.get(i)
+        self.size_classes
+            .get(i)
+            .filter(|size_class| size_class.align() >= align)
     }
 }
 
@@ -209,9 +294,12 @@ impl SizeClass {
     const fn new(size: usize, slab_order: usize) -> Self {
         Self {
             meta: SizeClassMeta::new(size, slab_order),
-            inner: SpinLock::new(SizeClassInner {
-                partial_slabs: LinkedList::new(SlabAdapter::NEW),
-            }),
+            inner: SpinLock::new_ranked(
+                SizeClassInner {
+                    partial_slabs: LinkedList::new(SlabAdapter::NEW),
+                },
+                RANK_HEAP,
+            ),
         }
     }
 
@@ -219,6 +307,14 @@ impl SizeClass {
         self.meta.size
     }
 
+    /// Returns the alignment guaranteed for all objects in this size class.
+    fn align(&self) -> usize {
+        // Slabs are aligned to their size and objects are packed at the end of the slab, so every
+        // object is aligned to the largest power of 2 dividing both sizes.
+        let slab_size = PAGE_SIZE << self.meta.slab_order;
+        1 << cmp::min(self.size().trailing_zeros(), slab_size.trailing_zeros())
+    }
+
     fn allocate(&self) -> Result<NonNull<u8>, HeapAllocError> {
         self.inner.with(|inner, _| inner.allocate(&self.meta))
     }
@@ -397,3 +493,62 @@ unsafe fn free_virt_pages(ptr: NonNull<u8>, order: usize) {
         pmm::deallocate(physmap_to_pfn(vaddr.containing_page()), order);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test_case]
+    fn small_box_roundtrip() {
+        let b = Box::new(0x1234u64);
+        assert_eq!(*b, 0x1234);
+    }
+
+    #[test_case]
+    fn vec_growth_preserves_contents() {
+        let mut v = Vec::new();
+        for i in 0..5000u32 {
+            v.push(i);
+        }
+        assert!(v.iter().copied().eq(0..5000));
+    }
+
+    #[test_case]
+    fn over_aligned_small_allocations() {
+        for (size, align) in [(32, 256), (64, PAGE_SIZE), (48, 32)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let ptr = allocate(layout).unwrap();
+            assert!(ptr.len() >= size);
+            assert_eq!(ptr.as_ptr().cast::<u8>() as usize % align, 0);
+            unsafe { deallocate(ptr.cast(), layout) };
+        }
+    }
+
+    #[test_case]
+    fn page_allocations_resize_in_place() {
+        let small = Layout::from_size_align(PAGE_SIZE, 8).unwrap();
+        let large = Layout::from_size_align(2 * PAGE_SIZE, 8).unwrap();
+
+        let ptr = allocate(large).unwrap().cast::<u8>();
+        unsafe { ptr.as_ptr().write_bytes(0x5a, PAGE_SIZE) };
+
+        // Shrinking always happens in place, and frees the trailing page.
+        let shrunk = unsafe { resize(ptr, large, small) }.unwrap();
+        assert_eq!(shrunk.cast::<u8>(), ptr);
+        assert_eq!(shrunk.len(), PAGE_SIZE);
+
+        // The page we just freed is still available, so we should be able to grow back into it.
+        let grown = unsafe { resize(ptr, small, large) }.unwrap();
+        assert_eq!(grown.cast::<u8>(), ptr);
+        assert_eq!(grown.len(), 2 * PAGE_SIZE);
+
+        let contents = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), PAGE_SIZE) };
+        assert!(contents.iter().all(|&b| b == 0x5a));
+
+        unsafe { deallocate(ptr, large) };
+        pmm::check_invariants();
+    }
+}
@@ -4,7 +4,7 @@ use core::{cmp, slice};
 use arrayvec::ArrayVec;
 use log::{debug, info, trace};
 
-use bootinfo::item::{MemoryKind, MemoryRange};
+use bootinfo::item::{InitrdInfo, MemoryKind, MemoryRange};
 use bootinfo::view::View;
 use bootinfo::ItemKind;
 
@@ -68,9 +68,13 @@ pub unsafe fn init_early(
     let bootinfo_view = View::new(bootinfo_slice).expect("bad bootinfo");
     let mem_map = get_mem_map(bootinfo_view);
 
+    let bootinfo_end = bootinfo_paddr
+        .checked_add(bootinfo_size)
+        .expect("bootinfo extends past end of physical address space");
     let bootinfo_frame_range =
-        bootinfo_paddr.containing_frame()..(bootinfo_paddr + bootinfo_size).containing_tail_frame();
-    let reserved_ranges = gather_reserved_ranges(bootinfo_frame_range);
+        bootinfo_paddr.containing_frame()..bootinfo_end.containing_tail_frame();
+    let initrd_frame_range = get_initrd_frame_range(bootinfo_view);
+    let reserved_ranges = gather_reserved_ranges(bootinfo_frame_range, initrd_frame_range);
 
     let bootheap_range = largest_early_usable_range(mem_map, &reserved_ranges);
     let bootheap_pages = bootheap_range.end - bootheap_range.start;
@@ -110,11 +114,7 @@ pub unsafe fn init_early(
 /// # Panics
 ///
 /// Panics if this function is called more than once.
-pub unsafe fn init_late(
-    context: InitContext,
-    bootinfo: &BootinfoData<'_>,
-    irq_disabled: &IrqDisabled,
-) {
+pub unsafe fn init_late(context: InitContext, bootinfo: &BootinfoData<'_>) {
     let InitContext {
         mut bootheap,
         mut reserved_ranges,
@@ -138,15 +138,19 @@ pub unsafe fn init_late(
     let mut added_free_pages = 0;
 
     unsafe {
-        pmm::init(max_pfn, &mut bootheap, irq_disabled);
+        pmm::init(max_pfn, &mut bootheap);
 
         reserve_bootheap(&mut reserved_ranges, bootheap);
         iter_early_usable_ranges(mem_map, &reserved_ranges, |start, end| {
-            pmm::add_free_range(start, end, irq_disabled);
+            pmm::add_free_range(start, end);
             added_free_pages += end - start;
         })
     }
 
+    if cfg!(debug_assertions) {
+        pmm::check_invariants();
+    }
+
     debug!(
         "initialized PMM with {} free pages ({})",
         added_free_pages,
@@ -166,11 +170,30 @@ fn get_mem_map(bootinfo: View<'_>) -> &[MemoryRange] {
     unsafe { mem_map_item.get_slice() }.expect("invalid bootinfo memory map")
 }
 
+fn get_initrd_frame_range(bootinfo: View<'_>) -> Option<Range<PhysFrameNum>> {
+    let initrd_item = bootinfo
+        .items()
+        .find(|item| item.kind() == ItemKind::INITRD)?;
+
+    // Safety: we trust the bootinfo
+    let initrd: &InitrdInfo = unsafe { initrd_item.get() }.ok()?;
+
+    let start = PhysAddr::new(initrd.paddr);
+    let end = start
+        .checked_add(initrd.byte_size)
+        .expect("initrd extends past end of physical address space");
+    Some(start.containing_frame()..end.containing_tail_frame())
+}
+
 type ReservedRanges = ArrayVec<Range<PhysFrameNum>, 5>;
 
-fn gather_reserved_ranges(bootinfo_range: Range<PhysFrameNum>) -> ReservedRanges {
+fn gather_reserved_ranges(
+    bootinfo_range: Range<PhysFrameNum>,
+    initrd_range: Option<Range<PhysFrameNum>>,
+) -> ReservedRanges {
     let mut ret = ReservedRanges::new();
     ret.extend([kimage::phys_base()..kimage::phys_end(), bootinfo_range]);
+    ret.extend(initrd_range);
     ret.extend(arch::mm::RESERVED_RANGES);
     sort_reserved_ranges(&mut ret);
     ret
@@ -263,20 +286,10 @@ fn print_mem_info(mem_map: &[MemoryRange]) {
 }
 
 fn display_range(range: &MemoryRange) {
-    let kind = match range.kind {
-        MemoryKind::RESERVED => "reserved",
-        MemoryKind::USABLE => "usable",
-        MemoryKind::FIRMWARE_BOOT => "firmware (boot)",
-        MemoryKind::FIRMWARE_RUNIME => "firmware (runtime)",
-        MemoryKind::ACPI_TABLES => "ACPI tables",
-        MemoryKind::UNUSABLE => "unusable",
-        _ => "other",
-    };
-
     trace!(
         "{:#012x}-{:#012x}: {}",
         range.start_page * PAGE_SIZE,
         (range.start_page + range.page_count) * PAGE_SIZE,
-        kind
+        range.kind
     );
 }
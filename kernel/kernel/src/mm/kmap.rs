@@ -6,7 +6,7 @@ use crate::err::Result;
 use super::types::{CacheMode, PhysAddr, Protection, VirtAddr};
 use super::utils::to_page_count;
 use super::vm;
-use super::vm::aspace::{MapBase, MappingHandle, SliceHandle};
+use super::vm::aspace::{MapBase, MappingHandle, ReservationHandle, SliceHandle};
 use super::vm::object::{EagerVmObject, PhysVmObject, VmObject};
 
 /// An owned pointer to a mapping of a VM object into the kernel address space.
@@ -23,7 +23,7 @@ impl Drop for KernelMapping {
     fn drop(&mut self) {
         // Safety: we have unique ownership of the mapping.
         unsafe {
-            vm::get_kernel_addr_space()
+            vm::kernel_aspace()
                 .unmap(&self.0)
                 .expect("kernel mapping already detached");
         }
@@ -51,13 +51,14 @@ const STACK_PAGES: usize = STACK_SIZE / PAGE_SIZE;
 
 pub struct KernelStack {
     slice: SliceHandle,
+    guard: ReservationHandle,
 }
 
 impl KernelStack {
     pub fn new() -> Result<Self> {
-        let kernel_aspace = vm::get_kernel_addr_space();
+        let kernel_aspace = vm::kernel_aspace();
 
-        let stack_obj = EagerVmObject::new(STACK_PAGES)?;
+        let stack_obj = EagerVmObject::new_named("kernel stack", STACK_PAGES)?;
         let slice = kernel_aspace.create_subslice(
             kernel_aspace.root_slice(),
             "kernel stack",
@@ -65,12 +66,15 @@ impl KernelStack {
             STACK_PAGES + 1,
         )?;
 
-        let stack = KernelStack { slice };
+        // Reserve a guard page at the bottom of the stack, so that overflows fault instead of running
+        // into whatever might otherwise be placed there.
+        let guard = kernel_aspace.reserve(&slice, MapBase::Fixed(slice.start()), 1)?;
+
+        let stack = KernelStack { slice, guard };
 
-        // Leave a guard page at the bottom of the stack.
         kernel_aspace.map_committed(
             &stack.slice,
-            MapBase::Fixed(stack.slice.start() + 1),
+            MapBase::Fixed(stack.guard.end()),
             STACK_PAGES,
             0,
             stack_obj,
@@ -89,16 +93,20 @@ impl KernelStack {
     }
 
     pub fn guard_page_contains(&self, addr: VirtAddr) -> bool {
-        let bottom = self.slice.start();
-        (bottom.addr()..(bottom + 1).addr()).contains(&addr)
+        (self.guard.start().addr()..self.guard.end().addr()).contains(&addr)
     }
 }
 
 impl Drop for KernelStack {
     fn drop(&mut self) {
-        // Safety: we have unique ownership of the stack slice.
+        let kernel_aspace = vm::kernel_aspace();
+
+        // Safety: we have unique ownership of the stack slice and its guard reservation.
         unsafe {
-            vm::get_kernel_addr_space()
+            kernel_aspace
+                .unmap(&self.guard)
+                .expect("kernel stack guard already unmapped");
+            kernel_aspace
                 .unmap_slice(&self.slice)
                 .expect("failed to unmap kernel stack");
         }
@@ -109,7 +117,7 @@ impl Drop for KernelStack {
 pub fn kmap(object: Arc<dyn VmObject>, prot: Protection) -> Result<KernelMapping> {
     let page_count = object.page_count();
 
-    let kernel_aspace = vm::get_kernel_addr_space();
+    let kernel_aspace = vm::kernel_aspace();
     let mapping = kernel_aspace.map_committed(
         kernel_aspace.root_slice(),
         MapBase::any(),
@@ -139,7 +147,8 @@ pub unsafe fn iomap(
     let page_offset = base.frame_offset();
 
     // Safety: function contract
-    let object = unsafe { PhysVmObject::new(base_pfn, to_page_count(len), cache_mode)? };
+    let object =
+        unsafe { PhysVmObject::new_named("iomap", base_pfn, to_page_count(len), cache_mode)? };
     let mapping = kmap(object, prot)?;
 
     Ok(IoMapping {
@@ -148,3 +157,27 @@ pub unsafe fn iomap(
         len,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mm::pmm::FrameBox;
+
+    #[test_case]
+    fn iomap_read_only_uncached() {
+        let frame = FrameBox::<0>::new().unwrap();
+        let base = frame.pfn().addr() + 0x10;
+
+        // Safety: the frame is owned by us and is never accessed through the mapping.
+        let mapping = unsafe { iomap(base, 0x20, Protection::READ, CacheMode::Uncached) }.unwrap();
+
+        assert_eq!(mapping.len(), 0x20);
+        assert_eq!(mapping.addr().page_offset(), 0x10);
+        assert_eq!(
+            vm::kernel_aspace().translate(mapping.addr().containing_page()),
+            Some(frame.pfn())
+        );
+
+        drop(mapping);
+    }
+}
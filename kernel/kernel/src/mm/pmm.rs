@@ -1,20 +1,23 @@
 use core::alloc::Layout;
-use core::{array, cmp, ptr, slice};
+use core::marker::PhantomData;
+use core::mem::{ManuallyDrop, MaybeUninit};
+use core::ops::{Deref, DerefMut};
+use core::{array, cmp, fmt, mem, ptr, slice};
 
 use intrusive_collections::{intrusive_adapter, LinkedList, LinkedListLink, UnsafeRef};
 use itertools::Itertools;
 use log::{debug, trace};
 
 use bitmap::BorrowedBitmapMut;
-use num_utils::{div_ceil, log2};
+use num_utils::{div_ceil, log2, log2_ceil};
 
 use crate::arch::mmu::PAGE_SIZE;
 use crate::err::{Error, Result};
 use crate::mm::physmap::{paddr_to_physmap, physmap_to_pfn};
 use crate::mm::types::PhysFrameNum;
-use crate::mm::utils::display_byte_size;
-use crate::sync::irq::{self, IrqDisabled};
-use crate::sync::SpinLock;
+use crate::mm::utils::{display_byte_size, FailureInjector};
+use crate::sync::lockrank::RANK_PMM;
+use crate::sync::{Counter, SpinLockIrq};
 
 use super::early::BootHeap;
 use super::physmap::pfn_to_physmap;
@@ -22,7 +25,29 @@ use super::types::VirtAddr;
 
 const ORDER_COUNT: usize = 16;
 
-static PHYS_MANAGER: SpinLock<Option<PhysManager>> = SpinLock::new(None);
+/// Whether free lists should be kept sorted by PFN, so that [`allocate`] always returns the lowest
+/// available block.
+///
+/// This makes allocation deterministic for a given set of free blocks at the cost of linear-time
+/// frees, and is intended only for testing and debugging.
+const ORDERED_FREE_LISTS: bool = cfg!(any(test, feature = "pmm-ordered-free-lists"));
+
+/// Whether allocations can be made to fail on demand with [`fail_nth_allocation`].
+const ALLOC_FAILURE_INJECTION: bool = cfg!(any(test, feature = "alloc-failure-injection"));
+
+/// Whether the frames passed to [`add_free_range`] should be recorded, so that overlapping
+/// additions (which would otherwise silently corrupt the allocator) are caught.
+const TRACK_ADDED_RANGES: bool = cfg!(debug_assertions);
+
+static FAILURE_INJECTOR: FailureInjector = FailureInjector::new();
+
+/// The number of successful allocations made from the PMM.
+static ALLOCATION_COUNT: Counter = Counter::new();
+
+/// The number of allocations returned to the PMM.
+static FREE_COUNT: Counter = Counter::new();
+
+static PHYS_MANAGER: SpinLockIrq<Option<PhysManager>> = SpinLockIrq::new_ranked(None, RANK_PMM);
 
 pub struct FrameBox<const ORDER: usize = 0>(PhysFrameNum);
 
@@ -35,6 +60,26 @@ impl<const ORDER: usize> FrameBox<ORDER> {
     pub fn pfn(&self) -> PhysFrameNum {
         self.0
     }
+
+    /// Consumes the box, returning the PFN of the block without freeing it.
+    ///
+    /// The caller becomes responsible for the block, and should eventually free it by passing the
+    /// PFN back to [`FrameBox::from_pfn`] (or directly to [`deallocate`] with `ORDER`).
+    pub fn into_pfn(self) -> PhysFrameNum {
+        let pfn = self.0;
+        mem::forget(self);
+        pfn
+    }
+
+    /// Reconstructs a box from a PFN previously returned by [`FrameBox::into_pfn`].
+    ///
+    /// # Safety
+    ///
+    /// `pfn` must have been returned by a call to `into_pfn` on a box with the same `ORDER`, and
+    /// must not have been passed to `from_pfn` (or freed) since.
+    pub unsafe fn from_pfn(pfn: PhysFrameNum) -> Self {
+        Self(pfn)
+    }
 }
 
 impl<const ORDER: usize> Drop for FrameBox<ORDER> {
@@ -43,6 +88,210 @@ impl<const ORDER: usize> Drop for FrameBox<ORDER> {
     }
 }
 
+/// An owned value of type `T`, stored in a dedicated physical frame and accessed through the
+/// physmap.
+///
+/// `T` must fit in (and be no more aligned than) a single page; this is checked at compile time.
+pub struct PhysBox<T> {
+    frame: FrameBox,
+    _marker: PhantomData<T>,
+}
+
+impl<T> PhysBox<T> {
+    const LAYOUT_CHECK: () = assert!(
+        mem::size_of::<T>() <= PAGE_SIZE && mem::align_of::<T>() <= PAGE_SIZE,
+        "type does not fit in a single frame"
+    );
+
+    /// Allocates a frame and moves `value` into it.
+    ///
+    /// # Errors
+    ///
+    /// * `OUT_OF_MEMORY` - No free frames are available.
+    pub fn new(value: T) -> Result<Self> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::LAYOUT_CHECK;
+
+        let frame = FrameBox::new()?;
+        let phys_box = Self {
+            frame,
+            _marker: PhantomData,
+        };
+
+        // Safety: we own the frame, which is large and aligned enough to hold a `T`.
+        unsafe { phys_box.ptr().write(value) };
+        Ok(phys_box)
+    }
+
+    pub fn pfn(&self) -> PhysFrameNum {
+        self.frame.pfn()
+    }
+
+    /// Consumes the box, returning the PFN of its frame without dropping the contained value or
+    /// freeing the frame.
+    ///
+    /// The caller becomes responsible for both, and should eventually reclaim them by passing the
+    /// PFN back to [`PhysBox::from_pfn`].
+    pub fn into_pfn(self) -> PhysFrameNum {
+        let this = ManuallyDrop::new(self);
+        // Safety: `this` is never used or dropped again, so the frame is moved out exactly once.
+        unsafe { ptr::read(&this.frame) }.into_pfn()
+    }
+
+    /// Reconstructs a box from a PFN previously returned by [`PhysBox::into_pfn`].
+    ///
+    /// # Safety
+    ///
+    /// `pfn` must have been returned by a call to `into_pfn` on a `PhysBox<T>` with the same `T`,
+    /// and must not have been passed to `from_pfn` since.
+    pub unsafe fn from_pfn(pfn: PhysFrameNum) -> Self {
+        Self {
+            // Safety: guaranteed by the caller.
+            frame: unsafe { FrameBox::from_pfn(pfn) },
+            _marker: PhantomData,
+        }
+    }
+
+    fn ptr(&self) -> *mut T {
+        pfn_to_physmap(self.pfn()).addr().as_mut_ptr()
+    }
+}
+
+impl<T> Deref for PhysBox<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: the value was initialized on construction and is owned by us.
+        unsafe { &*self.ptr() }
+    }
+}
+
+impl<T> DerefMut for PhysBox<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: as above, and `&mut self` guarantees exclusive access.
+        unsafe { &mut *self.ptr() }
+    }
+}
+
+impl<T> Drop for PhysBox<T> {
+    fn drop(&mut self) {
+        // Safety: the value is still initialized, and will not be accessed again. The frame itself
+        // is freed when `self.frame` is dropped.
+        unsafe { ptr::drop_in_place(self.ptr()) }
+    }
+}
+
+/// An owned range of physically contiguous frames, accessible as a zero-initialized byte slice
+/// through the physmap.
+///
+/// Unlike [`FrameBox`], the number of frames need not be a power of two.
+pub struct FrameBoxSlice {
+    base: PhysFrameNum,
+    page_count: usize,
+}
+
+impl FrameBoxSlice {
+    /// Allocates `page_count` physically contiguous, zeroed frames.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - `page_count` is 0.
+    /// * `OUT_OF_MEMORY` - Not enough contiguous memory is available.
+    pub fn new(page_count: usize) -> Result<Self> {
+        if page_count == 0 {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let base = allocate_contiguous(page_count).ok_or(Error::OUT_OF_MEMORY)?;
+        let mut frames = Self { base, page_count };
+        frames.fill(0);
+        Ok(frames)
+    }
+
+    fn ptr(&self) -> *mut u8 {
+        pfn_to_physmap(self.base).addr().as_mut_ptr()
+    }
+}
+
+impl Deref for FrameBoxSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // Safety: we own the frames, which are covered by the physmap and were initialized on
+        // allocation.
+        unsafe { slice::from_raw_parts(self.ptr(), self.page_count * PAGE_SIZE) }
+    }
+}
+
+impl DerefMut for FrameBoxSlice {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // Safety: as above, and `&mut self` guarantees exclusive access.
+        unsafe { slice::from_raw_parts_mut(self.ptr(), self.page_count * PAGE_SIZE) }
+    }
+}
+
+impl Drop for FrameBoxSlice {
+    fn drop(&mut self) {
+        unsafe { deallocate_contiguous(self.base, self.page_count) }
+    }
+}
+
+/// An owned, uninitialized block of `2 ** order` physically contiguous pages, accessible as a byte
+/// slice through the physmap.
+///
+/// This is intended for transient scratch buffers; the pages are returned to the PMM on drop.
+pub struct ScratchPages {
+    base: PhysFrameNum,
+    order: usize,
+}
+
+impl ScratchPages {
+    /// Allocates a block of `2 ** order` pages, without initializing its contents.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - `order` exceeds the largest order supported by the PMM.
+    /// * `OUT_OF_MEMORY` - No free block of the requested order is available.
+    pub fn new(order: usize) -> Result<Self> {
+        if order >= ORDER_COUNT {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let base = allocate(order).ok_or(Error::OUT_OF_MEMORY)?;
+        Ok(Self { base, order })
+    }
+
+    pub fn base(&self) -> PhysFrameNum {
+        self.base
+    }
+
+    fn ptr(&self) -> *mut MaybeUninit<u8> {
+        pfn_to_physmap(self.base).addr().as_mut_ptr()
+    }
+}
+
+impl Deref for ScratchPages {
+    type Target = [MaybeUninit<u8>];
+
+    fn deref(&self) -> &[MaybeUninit<u8>] {
+        // Safety: we own the block, which is covered by the physmap.
+        unsafe { slice::from_raw_parts(self.ptr(), PAGE_SIZE << self.order) }
+    }
+}
+
+impl DerefMut for ScratchPages {
+    fn deref_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        // Safety: as above, and `&mut self` guarantees exclusive access.
+        unsafe { slice::from_raw_parts_mut(self.ptr(), PAGE_SIZE << self.order) }
+    }
+}
+
+impl Drop for ScratchPages {
+    fn drop(&mut self) {
+        unsafe { deallocate(self.base, self.order) }
+    }
+}
+
 /// Initializes the physical memory manager (PMM) with space for tracking physical frames up to
 /// `max_pfn`.
 ///
@@ -55,8 +304,8 @@ impl<const ORDER: usize> Drop for FrameBox<ORDER> {
 /// # Panics
 ///
 /// Panics if this function is called more than once.
-pub unsafe fn init(max_pfn: PhysFrameNum, bootheap: &mut BootHeap, irq_disabled: &IrqDisabled) {
-    let mut manager_ref = PHYS_MANAGER.lock(irq_disabled);
+pub unsafe fn init(max_pfn: PhysFrameNum, bootheap: &mut BootHeap) {
+    let mut manager_ref = PHYS_MANAGER.lock();
     assert!(manager_ref.is_none(), "pmm already initialized");
     debug!("reserving bitmaps up to frame {}", max_pfn);
     let manager = PhysManager::new(max_pfn, bootheap);
@@ -66,7 +315,33 @@ pub unsafe fn init(max_pfn: PhysFrameNum, bootheap: &mut BootHeap, irq_disabled:
 /// Allocates a block of physical pages of size and alignment `2 ** order`, returning the base
 /// of the allocated block, or `None` if not enough memory is available.
 pub fn allocate(order: usize) -> Option<PhysFrameNum> {
-    with(|pmm| pmm.allocate(order))
+    if ALLOC_FAILURE_INJECTION && FAILURE_INJECTOR.should_fail() {
+        return None;
+    }
+
+    let pfn = with(|pmm| pmm.allocate(order))?;
+    ALLOCATION_COUNT.inc();
+    Some(pfn)
+}
+
+/// Causes the `n`th subsequent call (counting from 0) to [`allocate`] or [`allocate_contiguous`] to
+/// fail, replacing any previously requested failure.
+#[cfg(any(test, feature = "alloc-failure-injection"))]
+pub fn fail_nth_allocation(n: usize) {
+    FAILURE_INJECTOR.fail_nth(n);
+}
+
+/// Cancels any allocation failure requested with [`fail_nth_allocation`] that has not yet taken
+/// place.
+#[cfg(any(test, feature = "alloc-failure-injection"))]
+pub fn clear_allocation_failure() {
+    FAILURE_INJECTOR.disarm();
+}
+
+/// Returns the number of physical pages currently free.
+#[cfg(test)]
+pub fn free_page_count() -> usize {
+    with(|pmm| pmm.free_pages())
 }
 
 /// Frees a block of physical pages previously allocated by [`allocate`].
@@ -76,7 +351,68 @@ pub fn allocate(order: usize) -> Option<PhysFrameNum> {
 /// * `pfn` must have been obtained by a previous successfull call to [`allocate`] with `order`
 /// * The pages should no longer be accessed after this function returns
 pub unsafe fn deallocate(pfn: PhysFrameNum, order: usize) {
-    with(|pmm| unsafe { pmm.deallocate(pfn, order) })
+    with(|pmm| unsafe { pmm.deallocate(pfn, order) });
+    FREE_COUNT.inc();
+}
+
+/// Attempts to grow the block of order `order` at `pfn` to order `new_order` without moving it, by
+/// claiming the free buddies following it.
+///
+/// Returns `true` if the block was grown, in which case it must subsequently be freed with
+/// `new_order`. If any of the required buddies is not free (or `pfn` is not suitably aligned), the
+/// block is left untouched and `false` is returned.
+///
+/// # Safety
+///
+/// * `pfn` must have been obtained by a previous successful call to [`allocate`] with `order`
+/// * `new_order` must be at least `order`
+pub unsafe fn try_grow_in_place(pfn: PhysFrameNum, order: usize, new_order: usize) -> bool {
+    with(|pmm| unsafe { pmm.try_grow_in_place(pfn, order, new_order) })
+}
+
+/// Shrinks the block of order `order` at `pfn` to order `new_order` without moving it, freeing the
+/// pages following the shrunk block.
+///
+/// When this function returns, the block must subsequently be freed with `new_order`.
+///
+/// # Safety
+///
+/// * `pfn` must have been obtained by a previous successful call to [`allocate`] with `order`
+/// * `new_order` must be at most `order`
+/// * The freed pages should no longer be accessed after this function returns
+pub unsafe fn shrink_in_place(pfn: PhysFrameNum, order: usize, new_order: usize) {
+    with(|pmm| unsafe { pmm.shrink_in_place(pfn, order, new_order) })
+}
+
+/// Allocates `page_count` physically contiguous pages, returning the base of the allocated range,
+/// or `None` if not enough contiguous memory is available.
+///
+/// Unlike [`allocate`], the size of the range need not be a power of two. The base of the range
+/// is aligned to the largest power of two not exceeding `page_count`.
+pub fn allocate_contiguous(page_count: usize) -> Option<PhysFrameNum> {
+    if page_count == 0 {
+        return None;
+    }
+
+    if ALLOC_FAILURE_INJECTION && FAILURE_INJECTOR.should_fail() {
+        return None;
+    }
+
+    let base = with(|pmm| pmm.allocate_contiguous(page_count))?;
+    ALLOCATION_COUNT.inc();
+    Some(base)
+}
+
+/// Frees a range of pages previously allocated by [`allocate_contiguous`].
+///
+/// # Safety
+///
+/// * `base` must have been obtained by a previous successful call to [`allocate_contiguous`] with
+///   `page_count`
+/// * The pages should no longer be accessed after this function returns
+pub unsafe fn deallocate_contiguous(base: PhysFrameNum, page_count: usize) {
+    with(|pmm| unsafe { pmm.free_range(base, base + page_count) });
+    FREE_COUNT.inc();
 }
 
 /// Marks the range `start..end` as free in the PMM.
@@ -86,31 +422,51 @@ pub unsafe fn deallocate(pfn: PhysFrameNum, order: usize) {
 /// The reported range should contain free memory that can safely be repurposed, and should not
 /// overlap any ranges added to the PMM by previous calls to `add_free_range`. The range should also
 /// be present in the physmap.
-pub unsafe fn add_free_range(start: PhysFrameNum, end: PhysFrameNum, irq_disabled: &IrqDisabled) {
+///
+/// # Panics
+///
+/// Panics if the range extends past the `max_pfn` passed to [`init`]. In debug builds, also panics
+/// if the range overlaps a previously-added range.
+pub unsafe fn add_free_range(start: PhysFrameNum, end: PhysFrameNum) {
     trace!("adding free range {}-{}", start, end);
-    with_noirq(irq_disabled, |pmm| unsafe {
-        pmm.add_free_range(start, end)
-    })
+    with(|pmm| unsafe { pmm.add_free_range(start, end) })
 }
 
 pub fn dump_usage() {
     with(|pmm| pmm.dump_usage());
+    debug!(
+        "{} allocations, {} frees",
+        ALLOCATION_COUNT.sum(),
+        FREE_COUNT.sum()
+    );
 }
 
-fn with_noirq<R>(irq_disabled: &IrqDisabled, f: impl FnOnce(&mut PhysManager) -> R) -> R {
-    f(PHYS_MANAGER
-        .lock(irq_disabled)
-        .as_mut()
-        .expect("pmm not initialized"))
+/// Walks the PMM's internal data structures and verifies the buddy allocator invariants.
+///
+/// This is expensive (quadratic in the number of free blocks) and intended for use in debug builds
+/// only.
+///
+/// # Panics
+///
+/// Panics if an inconsistency is detected.
+pub fn check_invariants() {
+    with(|pmm| {
+        if let Err(violation) = pmm.check_invariants() {
+            panic!("pmm invariant violated: {violation}");
+        }
+    });
 }
 
 fn with<R>(f: impl FnOnce(&mut PhysManager) -> R) -> R {
-    irq::disable_with(|irq_disabled| with_noirq(irq_disabled, f))
+    f(PHYS_MANAGER.lock().as_mut().expect("pmm not initialized"))
 }
 
 struct PhysManager {
     total_pages: usize,
+    max_pfn: PhysFrameNum,
     levels: [BuddyLevel; ORDER_COUNT],
+    /// Frames added by [`add_free_range`], present only if `TRACK_ADDED_RANGES` is set.
+    added_frames: Option<BorrowedBitmapMut<'static>>,
 }
 
 impl PhysManager {
@@ -118,28 +474,22 @@ impl PhysManager {
         let levels = array::from_fn(|order| {
             // Note: the bitmap in each level tracks *pairs* of blocks on that level
             let splitmap_bits = div_ceil(max_pfn.as_usize(), 1 << (order + 1));
-            let splitmap_bytes = bitmap::bytes_required(splitmap_bits);
-
-            let splitmap_ptr: *mut u8 = paddr_to_physmap(bootheap.alloc_phys(
-                Layout::from_size_align(splitmap_bytes, 1).expect("buddy bitmap too large"),
-            ))
-            .as_mut_ptr();
-
-            let splitmap_slice = unsafe {
-                ptr::write_bytes(splitmap_ptr, 0, splitmap_bytes);
-                slice::from_raw_parts_mut(splitmap_ptr, splitmap_bytes)
-            };
 
             BuddyLevel {
                 free_list: LinkedList::new(FreePageAdapter::new()),
                 free_blocks: 0,
-                splitmap: BorrowedBitmapMut::new(splitmap_slice),
+                splitmap: alloc_zeroed_bitmap(bootheap, splitmap_bits),
             }
         });
 
+        let added_frames =
+            TRACK_ADDED_RANGES.then(|| alloc_zeroed_bitmap(bootheap, max_pfn.as_usize()));
+
         Self {
             total_pages: 0,
+            max_pfn,
             levels,
+            added_frames,
         }
     }
 
@@ -174,7 +524,7 @@ impl PhysManager {
     }
 
     unsafe fn deallocate(&mut self, mut pfn: PhysFrameNum, mut order: usize) {
-        assert!(pfn.as_usize() & ((1 << order) - 1) == 0);
+        assert!(pfn.is_aligned_to_order(order));
 
         while order < ORDER_COUNT - 1 {
             self.toggle_parent_split(pfn, order);
@@ -200,6 +550,49 @@ impl PhysManager {
         }
     }
 
+    unsafe fn try_grow_in_place(
+        &mut self,
+        pfn: PhysFrameNum,
+        order: usize,
+        new_order: usize,
+    ) -> bool {
+        assert!(order <= new_order);
+
+        if new_order >= ORDER_COUNT || !pfn.is_aligned_to_order(new_order) {
+            return false;
+        }
+
+        // Since our block is allocated, its parent's split bit is set exactly when the buddy is
+        // free as a whole. Check every level before modifying anything, so that we don't have to
+        // roll back partial progress.
+        if !(order..new_order).all(|cur_order| self.is_parent_split(pfn, cur_order)) {
+            return false;
+        }
+
+        for cur_order in order..new_order {
+            unsafe {
+                self.levels[cur_order].remove_free(buddy_of(pfn, cur_order));
+            }
+            // Note: this will always clear the bit, as both halves are now in use.
+            self.toggle_parent_split(pfn, cur_order);
+        }
+
+        true
+    }
+
+    unsafe fn shrink_in_place(&mut self, pfn: PhysFrameNum, order: usize, new_order: usize) {
+        assert!(new_order <= order);
+
+        // This mirrors the splitting performed in `allocate`.
+        for cur_order in (new_order..order).rev() {
+            // Note: this will always set the bit, as we started with an allocated (unsplit) block.
+            self.toggle_parent_split(pfn, cur_order);
+            unsafe {
+                self.levels[cur_order].push_free(buddy_of(pfn, cur_order));
+            }
+        }
+    }
+
     fn dump_usage(&self) {
         let free_pages = self.free_pages();
         let used_pages = self.total_pages - free_pages;
@@ -226,9 +619,61 @@ impl PhysManager {
         );
     }
 
-    unsafe fn add_free_range(&mut self, mut start: PhysFrameNum, end: PhysFrameNum) {
-        let size = end - start;
+    fn allocate_contiguous(&mut self, page_count: usize) -> Option<PhysFrameNum> {
+        let order = log2_ceil(page_count);
+        let base = self.allocate(order)?;
+
+        // Return any excess pages at the end of the block.
+        unsafe {
+            self.free_range(base + page_count, base + (1 << order));
+        }
+
+        Some(base)
+    }
+
+    unsafe fn add_free_range(&mut self, start: PhysFrameNum, end: PhysFrameNum) {
+        if let Err(err) = self.track_added_range(start, end) {
+            panic!("bad free range {start}-{end}: {err}");
+        }
+
+        unsafe {
+            self.free_range(start, end);
+        }
+        self.total_pages += end - start;
+    }
+
+    /// Checks that `start..end` can be added to the PMM and records it as added.
+    ///
+    /// Nothing is recorded if the range is rejected.
+    fn track_added_range(
+        &mut self,
+        start: PhysFrameNum,
+        end: PhysFrameNum,
+    ) -> core::result::Result<(), BadFreeRange> {
+        if end > self.max_pfn {
+            return Err(BadFreeRange::OutOfRange {
+                max_pfn: self.max_pfn,
+            });
+        }
+
+        if let Some(added_frames) = &mut self.added_frames {
+            let range = start.as_usize()..end.as_usize();
+
+            if let Some(pfn) = range.clone().find(|&pfn| added_frames.get(pfn)) {
+                return Err(BadFreeRange::Overlap {
+                    pfn: PhysFrameNum::new(pfn),
+                });
+            }
+
+            for pfn in range {
+                added_frames.set(pfn);
+            }
+        }
 
+        Ok(())
+    }
+
+    unsafe fn free_range(&mut self, mut start: PhysFrameNum, end: PhysFrameNum) {
         while start < end {
             let remaining_order = log2(end - start);
             let alignment_order = start.as_usize().trailing_zeros() as usize;
@@ -242,8 +687,61 @@ impl PhysManager {
 
             start += 1 << order;
         }
+    }
+
+    fn check_invariants(&self) -> core::result::Result<(), InvariantViolation> {
+        let mut counted_pages = 0;
+
+        for (order, level) in self.levels.iter().enumerate() {
+            let mut counted_blocks = 0;
+
+            for pfn in level.iter_free() {
+                counted_blocks += 1;
+
+                if !pfn.is_aligned_to_order(order) {
+                    return Err(InvariantViolation::Misaligned { pfn, order });
+                }
+
+                // A free block whose buddy is allocated (or split) always has its parent's split bit
+                // set; if the buddy were free as well, the two would have been merged.
+                if order < ORDER_COUNT - 1 && !self.is_parent_split(pfn, order) {
+                    return Err(InvariantViolation::ParentNotSplit { pfn, order });
+                }
+
+                for ancestor_order in order + 1..ORDER_COUNT {
+                    let ancestor = pfn.align_down_order(ancestor_order);
+                    if self.levels[ancestor_order]
+                        .iter_free()
+                        .any(|free| free == ancestor)
+                    {
+                        return Err(InvariantViolation::Overlap {
+                            pfn,
+                            order,
+                            ancestor_order,
+                        });
+                    }
+                }
+            }
+
+            if counted_blocks != level.free_blocks {
+                return Err(InvariantViolation::FreeCountMismatch {
+                    order,
+                    counted: counted_blocks,
+                    recorded: level.free_blocks,
+                });
+            }
+
+            counted_pages += counted_blocks << order;
+        }
+
+        if counted_pages > self.total_pages {
+            return Err(InvariantViolation::TooManyFreePages {
+                free: counted_pages,
+                total: self.total_pages,
+            });
+        }
 
-        self.total_pages += size;
+        Ok(())
     }
 
     fn free_pages(&self) -> usize {
@@ -265,6 +763,94 @@ impl PhysManager {
     }
 }
 
+enum BadFreeRange {
+    OutOfRange { max_pfn: PhysFrameNum },
+    Overlap { pfn: PhysFrameNum },
+}
+
+impl fmt::Display for BadFreeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::OutOfRange { max_pfn } => write!(f, "range extends past maximum frame {max_pfn}"),
+            Self::Overlap { pfn } => write!(f, "frame {pfn} already added"),
+        }
+    }
+}
+
+enum InvariantViolation {
+    Misaligned {
+        pfn: PhysFrameNum,
+        order: usize,
+    },
+    ParentNotSplit {
+        pfn: PhysFrameNum,
+        order: usize,
+    },
+    Overlap {
+        pfn: PhysFrameNum,
+        order: usize,
+        ancestor_order: usize,
+    },
+    FreeCountMismatch {
+        order: usize,
+        counted: usize,
+        recorded: usize,
+    },
+    TooManyFreePages {
+        free: usize,
+        total: usize,
+    },
+}
+
+impl fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Misaligned { pfn, order } => {
+                write!(f, "free block {pfn} misaligned for order {order}")
+            }
+            Self::ParentNotSplit { pfn, order } => {
+                write!(f, "free block {pfn} (order {order}) has unsplit parent")
+            }
+            Self::Overlap {
+                pfn,
+                order,
+                ancestor_order,
+            } => write!(
+                f,
+                "free block {pfn} (order {order}) overlaps free block of order {ancestor_order}"
+            ),
+            Self::FreeCountMismatch {
+                order,
+                counted,
+                recorded,
+            } => write!(
+                f,
+                "order {order} has {counted} free blocks, but {recorded} were recorded"
+            ),
+            Self::TooManyFreePages { free, total } => {
+                write!(f, "{free} free pages exceed total of {total}")
+            }
+        }
+    }
+}
+
+fn alloc_zeroed_bitmap(bootheap: &mut BootHeap, bits: usize) -> BorrowedBitmapMut<'static> {
+    let bytes = bitmap::bytes_required(bits);
+
+    let ptr: *mut u8 = paddr_to_physmap(
+        bootheap.alloc_phys(Layout::from_size_align(bytes, 1).expect("pmm bitmap too large")),
+    )
+    .as_mut_ptr();
+
+    // Safety: the bootheap has handed us exclusive ownership of this memory forever.
+    let slice = unsafe {
+        ptr::write_bytes(ptr, 0, bytes);
+        slice::from_raw_parts_mut(ptr, bytes)
+    };
+
+    BorrowedBitmapMut::new(slice)
+}
+
 fn splitmap_index(pfn: PhysFrameNum, order: usize) -> usize {
     // Note: we take `order + 1` as every splitmap bit tracks *pairs* of blocks of the given order
     pfn.as_usize() >> (order + 1)
@@ -308,7 +894,21 @@ impl BuddyLevel {
             UnsafeRef::from_raw(ptr)
         };
 
-        self.free_list.push_front(link);
+        if ORDERED_FREE_LISTS {
+            let mut cursor = self.free_list.front_mut();
+            while let Some(page) = cursor.get() {
+                if pfn_from_free_link(page) > pfn {
+                    break;
+                }
+                cursor.move_next();
+            }
+            // Note: if we've hit the end of the list, the cursor will be on the null object and
+            // this will append the page to the back.
+            cursor.insert_before(link);
+        } else {
+            self.free_list.push_front(link);
+        }
+
         self.free_blocks += 1;
     }
 
@@ -326,6 +926,10 @@ impl BuddyLevel {
         self.free_blocks -= 1;
         Some(pfn_from_free_link(UnsafeRef::into_raw(link)))
     }
+
+    fn iter_free(&self) -> impl Iterator<Item = PhysFrameNum> + '_ {
+        self.free_list.iter().map(|page| pfn_from_free_link(page))
+    }
 }
 
 fn free_link_from_pfn(pfn: PhysFrameNum) -> *mut FreePage {
@@ -337,3 +941,189 @@ fn pfn_from_free_link(link: *const FreePage) -> PhysFrameNum {
     assert_eq!(vaddr.page_offset(), 0);
     physmap_to_pfn(vaddr.containing_page())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn allocate_returns_aligned_blocks() {
+        for order in 0..4 {
+            let pfn = allocate(order).expect("out of memory");
+            assert!(pfn.is_aligned_to_order(order));
+            unsafe { deallocate(pfn, order) };
+        }
+    }
+
+    #[test_case]
+    fn deallocate_restores_free_pages() {
+        let before = free_page_count();
+        let pfn = allocate(2).expect("out of memory");
+        assert_eq!(free_page_count(), before - 4);
+        unsafe { deallocate(pfn, 2) };
+        assert_eq!(free_page_count(), before);
+    }
+
+    #[test_case]
+    fn add_free_range_rejects_bad_ranges() {
+        let frame = FrameBox::<0>::new().expect("out of memory");
+        let pfn = frame.pfn();
+
+        with(|pmm| {
+            let max_pfn = pmm.max_pfn;
+            assert!(matches!(
+                pmm.track_added_range(max_pfn - 1, max_pfn + 1),
+                Err(BadFreeRange::OutOfRange { .. })
+            ));
+
+            // Every allocated frame was originally added as part of some free range.
+            if TRACK_ADDED_RANGES {
+                assert!(matches!(
+                    pmm.track_added_range(pfn, pfn + 1),
+                    Err(BadFreeRange::Overlap { pfn: found }) if found == pfn
+                ));
+            }
+        });
+    }
+
+    #[test_case]
+    fn check_invariants_detects_corrupt_splitmap() {
+        with(|pmm| {
+            assert!(pmm.check_invariants().is_ok());
+
+            let (order, pfn) = (0..ORDER_COUNT - 1)
+                .find_map(|order| Some((order, pmm.levels[order].iter_free().next()?)))
+                .expect("no free blocks");
+
+            pmm.toggle_parent_split(pfn, order);
+            assert!(matches!(
+                pmm.check_invariants(),
+                Err(InvariantViolation::ParentNotSplit { .. })
+            ));
+            pmm.toggle_parent_split(pfn, order);
+
+            assert!(pmm.check_invariants().is_ok());
+        });
+    }
+
+    #[test_case]
+    fn frame_box_slice_spans_pages() {
+        let mut frames = FrameBoxSlice::new(3).unwrap();
+        assert_eq!(frames.len(), 3 * PAGE_SIZE);
+        assert!(frames.iter().all(|&b| b == 0));
+
+        for (i, byte) in frames.iter_mut().enumerate() {
+            *byte = (i % 251) as u8;
+        }
+
+        for off in [PAGE_SIZE - 1, PAGE_SIZE, 2 * PAGE_SIZE - 1, 2 * PAGE_SIZE] {
+            assert_eq!(frames[off], (off % 251) as u8);
+        }
+
+        assert_eq!(FrameBoxSlice::new(0).err(), Some(Error::INVALID_ARGUMENT));
+    }
+
+    #[test_case]
+    fn phys_box_stores_value() {
+        struct Data {
+            counter: u64,
+            bytes: [u8; 100],
+        }
+
+        let mut data = PhysBox::new(Data {
+            counter: 5,
+            bytes: [1; 100],
+        })
+        .unwrap();
+
+        data.counter += 1;
+        data.bytes[99] = 7;
+
+        let ptr: *const Data = &*data;
+        assert_eq!(VirtAddr::from_ptr(ptr), pfn_to_physmap(data.pfn()).addr());
+        assert_eq!(data.counter, 6);
+        assert_eq!(data.bytes[0], 1);
+        assert_eq!(data.bytes[99], 7);
+    }
+
+    #[test_case]
+    fn frame_box_pfn_round_trip() {
+        let before = free_page_count();
+
+        let pfn = FrameBox::<1>::new().unwrap().into_pfn();
+        assert_eq!(free_page_count(), before - 2);
+
+        let frame = unsafe { FrameBox::<1>::from_pfn(pfn) };
+        assert_eq!(frame.pfn(), pfn);
+        drop(frame);
+        assert_eq!(free_page_count(), before);
+
+        let pfn = PhysBox::new(0x1234u64).unwrap().into_pfn();
+        assert_eq!(free_page_count(), before - 1);
+
+        let phys_box = unsafe { PhysBox::<u64>::from_pfn(pfn) };
+        assert_eq!(*phys_box, 0x1234);
+        drop(phys_box);
+        assert_eq!(free_page_count(), before);
+    }
+
+    #[test_case]
+    fn scratch_pages_freed_on_drop() {
+        let before = free_page_count();
+
+        {
+            let mut scratch = ScratchPages::new(1).unwrap();
+            assert_eq!(scratch.len(), 2 * PAGE_SIZE);
+            assert_eq!(scratch.base().as_usize() & 1, 0);
+            assert_eq!(free_page_count(), before - 2);
+
+            for (i, byte) in scratch.iter_mut().enumerate() {
+                byte.write(i as u8);
+            }
+
+            // Safety: every byte was initialized above.
+            let last = unsafe { scratch[2 * PAGE_SIZE - 1].assume_init() };
+            assert_eq!(last, (2 * PAGE_SIZE - 1) as u8);
+        }
+
+        assert_eq!(free_page_count(), before);
+        assert_eq!(
+            ScratchPages::new(ORDER_COUNT).err(),
+            Some(Error::INVALID_ARGUMENT)
+        );
+    }
+
+    #[test_case]
+    fn ordered_free_lists_are_deterministic() {
+        fn lowest_free_block(order: usize) -> Option<PhysFrameNum> {
+            with(|pmm| (order..ORDER_COUNT).find_map(|order| pmm.levels[order].iter_free().next()))
+        }
+
+        fn allocate_blocks() -> [PhysFrameNum; 4] {
+            array::from_fn(|_| {
+                let expected = lowest_free_block(0).expect("out of memory");
+                let pfn = allocate(0).expect("out of memory");
+                assert_eq!(pfn, expected);
+                pfn
+            })
+        }
+
+        with(|pmm| {
+            for level in &pmm.levels {
+                assert!(level.iter_free().tuple_windows().all(|(a, b)| a < b));
+            }
+        });
+
+        let first = allocate_blocks();
+        for i in [2, 0, 3, 1] {
+            unsafe { deallocate(first[i], 0) };
+        }
+
+        let second = allocate_blocks();
+        assert_eq!(first, second);
+
+        for pfn in second {
+            unsafe { deallocate(pfn, 0) };
+        }
+    }
+}
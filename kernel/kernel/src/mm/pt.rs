@@ -2,15 +2,21 @@
 //!
 //! This module should generally not be used directly; it is used by early initialization code and
 //! by the VM subsystem to implement address spaces.
+//!
+//! Besides empty and present entries, leaf page tables may contain "special" entries: non-present
+//! entries carrying an architecture-independent tag (see [`mmu::make_special_pte`]). These never
+//! translate addresses, but are treated as occupied slots: they are not overwritten by
+//! [`PageTable::map`] or [`PageTable::map_replace`], keep otherwise-empty tables from being
+//! culled, and are cleared only by [`PageTable::unmap`].
 
 use core::{cmp, result};
 
 use log::trace;
 
 use crate::arch::mmu::{
-    self, get_pte_frame, make_empty_pte, make_intermediate_pte, make_terminal_pte, pte_is_present,
-    pte_is_terminal, update_pte_perms, PageTableEntry, PT_ENTRY_COUNT, PT_LEVEL_COUNT,
-    PT_LEVEL_SHIFT,
+    self, get_pte_frame, make_empty_pte, make_intermediate_pte, make_special_pte,
+    make_terminal_pte, pte_is_present, pte_is_terminal, pte_special_tag, update_pte_perms,
+    PageTableEntry, PT_ENTRY_COUNT, PT_LEVEL_COUNT, PT_LEVEL_SHIFT,
 };
 use crate::err::{Error, Result};
 
@@ -156,7 +162,7 @@ impl<T: TranslatePhys> PageTable<T> {
     /// `phys_base`, with permissions `perms` and cache mode `cache_mode`.
     ///
     /// This function does not support overwriting existing mappings, and will fail if it encounters
-    /// a page that is already mapped.
+    /// a page that is already mapped. Use [`map_replace`](Self::map_replace) to remap pages.
     ///
     /// When this function returns, `pointer` will point past the last page mapped successfully. On
     /// success, this will always be the last page, but if the function returns early due to an
@@ -165,7 +171,7 @@ impl<T: TranslatePhys> PageTable<T> {
     /// # Errors
     ///
     /// * `OUT_OF_MEMORY` - A page table allocation failed.
-    /// * `RESOURCE_OVERLAP` - A page in the range was already mapped.
+    /// * `RESOURCE_OVERLAP` - A page in the range was already mapped or held a special entry.
     ///
     /// # Safety
     ///
@@ -192,6 +198,8 @@ impl<T: TranslatePhys> PageTable<T> {
 
         self.inner.map(
             alloc,
+            &mut NoopGather,
+            false,
             pointer,
             self.root,
             PT_LEVEL_COUNT - 1,
@@ -201,10 +209,137 @@ impl<T: TranslatePhys> PageTable<T> {
         )
     }
 
+    /// Maps the virtual page range spanned by `pointer` to a contiguous physical range starting at
+    /// `phys_base`, with permissions `perms` and cache mode `cache_mode`, overwriting any existing
+    /// mappings in the range and reporting the virtual pages whose mappings were replaced to
+    /// `gather`.
+    ///
+    /// Only terminal entries are ever overwritten: parts of the range already covered by page tables
+    /// are mapped with smaller pages inside those tables, rather than replacing the tables with
+    /// large pages. This function currently cannot split large pages, and will return an error if
+    /// the range partially intersects one. Special entries are never overwritten.
+    ///
+    /// When this function returns, `pointer` will point past the last page mapped successfully. On
+    /// success, this will always be the last page, but if the function returns early due to an
+    /// error, the reported progress can be used to take appropriate action.
+    ///
+    /// # Errors
+    ///
+    /// * `OUT_OF_MEMORY` - A page table allocation failed.
+    /// * `RESOURCE_OVERLAP` - A page in the range held a special entry, or the range partially
+    ///                        intersected a large page.
+    ///
+    /// # Safety
+    ///
+    /// * The page table must not be accessed concurrently by other cores/interrupts during the
+    ///   mapping
+    /// * The provided allocator must return physical frames usable as page tables
+    /// * `cache_mode` must be a cache mode that can safely be applied to the provided pages,
+    ///   respecting any platform limitations
+    /// * Any cores on which the page table is active must be able to tolerate accesses to replaced
+    ///   pages reaching either the old or the new frame until the TLB is flushed
+    /// * Any pages reported to `gather` must be flushed from the TLB before the new mappings can be
+    ///   relied on.
+    pub unsafe fn map_replace(
+        &mut self,
+        alloc: &mut impl PageTableAlloc,
+        gather: &mut impl GatherInvalidations,
+        pointer: &mut MappingPointer,
+        phys_base: PhysFrameNum,
+        perms: PageTablePerms,
+        cache_mode: CacheMode,
+    ) -> Result<()> {
+        trace!(
+            "remapping pages {}-{} to {}-{} as {perms:?}, cache mode {cache_mode:?}",
+            pointer.virt(),
+            pointer.virt() + pointer.remaining_pages(),
+            phys_base,
+            phys_base + pointer.remaining_pages()
+        );
+
+        self.inner.map(
+            alloc,
+            gather,
+            true,
+            pointer,
+            self.root,
+            PT_LEVEL_COUNT - 1,
+            phys_base,
+            perms,
+            cache_mode,
+        )
+    }
+
+    /// Installs a special entry carrying `tag` at `vpn`, allocating intermediate tables as
+    /// necessary.
+    ///
+    /// `tag` must fit in [`mmu::SPECIAL_PTE_TAG_BITS`] bits.
+    ///
+    /// # Errors
+    ///
+    /// * `OUT_OF_MEMORY` - A page table allocation failed.
+    /// * `RESOURCE_OVERLAP` - `vpn` is already mapped or holds a special entry.
+    ///
+    /// # Safety
+    ///
+    /// * The page table must not be accessed concurrently by other cores/interrupts during the
+    ///   operation
+    /// * The provided allocator must return physical frames usable as page tables
+    pub unsafe fn set_special(
+        &mut self,
+        alloc: &mut impl PageTableAlloc,
+        vpn: VirtPageNum,
+        tag: u64,
+    ) -> Result<()> {
+        trace!("installing special entry {tag:#x} at page {vpn}");
+
+        let mut table = self.root;
+        for level in (1..PT_LEVEL_COUNT).rev() {
+            table = self
+                .inner
+                .next_table_or_create(alloc, table, vpn.pt_index(level), level)?;
+        }
+
+        let index = vpn.pt_index(0);
+        if !self.inner.slot_is_free(table, index, 0) {
+            return Err(Error::RESOURCE_OVERLAP);
+        }
+
+        self.inner.set(table, index, make_special_pte(tag));
+        Ok(())
+    }
+
+    /// Returns the tag of the special entry at `vpn`, if there is one.
+    pub fn special_tag(&self, vpn: VirtPageNum) -> Option<u64> {
+        let mut table = self.root;
+        for level in (1..PT_LEVEL_COUNT).rev() {
+            table = self
+                .inner
+                .next_table(table, vpn.pt_index(level), level)
+                .ok()?;
+        }
+
+        pte_special_tag(self.inner.get(table, vpn.pt_index(0)), 0)
+    }
+
+    /// Checks whether `vpn` is currently mapped by a page of any size.
+    pub fn is_mapped(&self, vpn: VirtPageNum) -> bool {
+        self.query(vpn).is_some()
+    }
+
+    /// Returns the physical frame currently mapped at `vpn`, if any.
+    ///
+    /// If `vpn` is covered by a large page, the returned frame is the one within the large page
+    /// corresponding to `vpn`.
+    pub fn query(&self, vpn: VirtPageNum) -> Option<PhysFrameNum> {
+        self.inner.query(vpn, self.root, PT_LEVEL_COUNT - 1)
+    }
+
     /// Unmaps any pages in the range covered by `pointer`, reporting any virtual pages that need
     /// TLB invalidation to `gather`.
     ///
-    /// This function will skip any unmapped "holes" encountered in the range.
+    /// This function will skip any unmapped "holes" encountered in the range. Special entries in
+    /// the range are cleared, but are not counted as mapped pages.
     ///
     /// This function currently cannot split large pages, and will return an error if the range
     /// partially intersects one.
@@ -213,6 +348,8 @@ impl<T: TranslatePhys> PageTable<T> {
     /// On success, this will always be the last page, but if the function returns early due to an
     /// error, the reported progress can be used to take appropriate action.
     ///
+    /// On success, returns the number of pages in the range that were actually mapped.
+    ///
     /// # Errors
     ///
     /// * `RESOURCE_OVERLAP` - The unmapping range partially intersected a large page.
@@ -229,25 +366,34 @@ impl<T: TranslatePhys> PageTable<T> {
         &mut self,
         gather: &mut impl GatherInvalidations,
         pointer: &mut MappingPointer,
-    ) -> Result<()> {
+    ) -> Result<usize> {
         trace!(
             "unmapping page range {}-{}",
             pointer.virt(),
             pointer.virt() + pointer.remaining_pages()
         );
+
+        let mut unmapped_pages = 0;
         self.inner.walk_update(
             gather,
             pointer,
-            &mut |_vpn, _pte, _level| make_empty_pte(),
+            &mut |_vpn, pte, level| {
+                if pte_is_present(pte, level) {
+                    unmapped_pages += level_page_count(level);
+                }
+                make_empty_pte()
+            },
             self.root,
             PT_LEVEL_COUNT - 1,
-        )
+        )?;
+
+        Ok(unmapped_pages)
     }
 
     /// Updates the protection permissions of all pages in the range covered by `pointer`, reporting
     /// any virtual pages that need TLB invalidation to `gather`.
     ///
-    /// This function will skip any "holes" encountered in the range.
+    /// This function will skip any "holes" or special entries encountered in the range.
     ///
     /// This function currently cannot split large pages, and will return an error if the range
     /// partially intersects one.
@@ -283,7 +429,13 @@ impl<T: TranslatePhys> PageTable<T> {
         self.inner.walk_update(
             gather,
             pointer,
-            &mut |_vpn, pte, level| update_pte_perms(pte, level, perms),
+            &mut |_vpn, pte, level| {
+                if pte_is_present(pte, level) {
+                    update_pte_perms(pte, level, perms)
+                } else {
+                    pte
+                }
+            },
             self.root,
             PT_LEVEL_COUNT - 1,
         )
@@ -337,6 +489,8 @@ impl<T: TranslatePhys> PageTableInner<T> {
     fn map(
         &mut self,
         alloc: &mut impl PageTableAlloc,
+        gather: &mut impl GatherInvalidations,
+        replace: bool,
         pointer: &mut MappingPointer,
         table: PhysFrameNum,
         level: usize,
@@ -345,13 +499,22 @@ impl<T: TranslatePhys> PageTableInner<T> {
         cache_mode: CacheMode,
     ) -> Result<()> {
         walk_level(level, pointer, |pointer| {
-            if mmu::supports_page_size(level) && can_use_level_page(level, pointer, phys_base) {
-                self.map_terminal(pointer, table, level, phys_base, perms, cache_mode)?;
+            let index = pointer.virt().pt_index(level);
+
+            // When replacing, existing tables are descended into instead of being overwritten.
+            if mmu::supports_page_size(level)
+                && can_use_level_page(level, pointer, phys_base)
+                && !(replace && level > 0 && self.next_table(table, index, level).is_ok())
+            {
+                self.map_terminal(
+                    gather, replace, pointer, table, level, phys_base, perms, cache_mode,
+                )?;
             } else {
-                let next =
-                    self.next_table_or_create(alloc, table, pointer.virt().pt_index(level), level)?;
+                let next = self.next_table_or_create(alloc, table, index, level)?;
                 self.map(
                     alloc,
+                    gather,
+                    replace,
                     pointer,
                     next,
                     level - 1,
@@ -365,6 +528,26 @@ impl<T: TranslatePhys> PageTableInner<T> {
         })
     }
 
+    fn query(&self, vpn: VirtPageNum, table: PhysFrameNum, level: usize) -> Option<PhysFrameNum> {
+        let index = vpn.pt_index(level);
+
+        if level == 0 {
+            let pte = self.get(table, index);
+            return pte_is_present(pte, level).then(|| get_pte_frame(pte, level));
+        }
+
+        match self.next_table(table, index, level) {
+            Ok(next) => self.query(vpn, next, level - 1),
+            Err(NextTableError::TerminalEntry(pte)) => {
+                // Select the correct frame within the large page.
+                let offset_mask = (1 << (PT_LEVEL_SHIFT * level)) - 1;
+                let base = get_pte_frame(pte, level).as_usize() & !offset_mask;
+                Some(PhysFrameNum::new(base | (vpn.as_usize() & offset_mask)))
+            }
+            Err(NextTableError::NotPresent) => None,
+        }
+    }
+
     fn walk_update(
         &mut self,
         gather: &mut impl GatherInvalidations,
@@ -480,8 +663,11 @@ impl<T: TranslatePhys> PageTableInner<T> {
         Ok(get_pte_frame(pte, level))
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn map_terminal(
         &mut self,
+        gather: &mut impl GatherInvalidations,
+        replace: bool,
         pointer: &mut MappingPointer,
         table: PhysFrameNum,
         level: usize,
@@ -490,8 +676,11 @@ impl<T: TranslatePhys> PageTableInner<T> {
         cache_mode: CacheMode,
     ) -> Result<()> {
         let index = pointer.virt().pt_index(level);
+        let pte = self.get(table, index);
 
-        if pte_is_present(self.get(table, index), level) {
+        if replace && pte_is_present(pte, level) && pte_is_terminal(pte, level) {
+            gather.add_tlb_flush(pointer.virt());
+        } else if !self.slot_is_free(table, index, level) {
             return Err(Error::RESOURCE_OVERLAP);
         }
 
@@ -522,7 +711,12 @@ impl<T: TranslatePhys> PageTableInner<T> {
     }
 
     fn table_is_empty(&self, table: PhysFrameNum, level: usize) -> bool {
-        (0..PT_ENTRY_COUNT).all(|i| !pte_is_present(self.get(table, i), level))
+        (0..PT_ENTRY_COUNT).all(|i| self.slot_is_free(table, i, level))
+    }
+
+    fn slot_is_free(&self, table: PhysFrameNum, index: usize, level: usize) -> bool {
+        let pte = self.get(table, index);
+        !pte_is_present(pte, level) && pte_special_tag(pte, level).is_none()
     }
 
     fn get(&self, table: PhysFrameNum, index: usize) -> PageTableEntry {
@@ -595,3 +789,128 @@ fn level_page_count(level: usize) -> usize {
 fn level_page_mask(level: usize) -> usize {
     level_page_count(level) - 1
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::mm::physmap::PhysmapPfnTranslator;
+    use crate::mm::pmm;
+
+    struct TestAlloc;
+
+    impl PageTableAlloc for TestAlloc {
+        fn allocate(&mut self) -> Result<PhysFrameNum> {
+            pmm::allocate(0).ok_or(Error::OUT_OF_MEMORY)
+        }
+    }
+
+    struct TestCull;
+
+    impl CullPageTables for TestCull {
+        fn can_cull(&self, _pt: PhysFrameNum, _level: usize) -> bool {
+            true
+        }
+
+        fn cull(&mut self, pt: PhysFrameNum, _level: usize) {
+            unsafe { pmm::deallocate(pt, 0) }
+        }
+    }
+
+    #[derive(Default)]
+    struct TestGather(Vec<VirtPageNum>);
+
+    impl GatherInvalidations for TestGather {
+        fn add_tlb_flush(&mut self, vpn: VirtPageNum) {
+            self.0.push(vpn);
+        }
+    }
+
+    #[test_case]
+    fn map_replace_remaps_pages() {
+        let root = TestAlloc.allocate().unwrap();
+
+        // Safety: the table is freshly allocated and never activated, so the frames it maps are
+        // never accessed.
+        let mut pt = unsafe { PageTable::new(root, PhysmapPfnTranslator) };
+        pt.inner.clear_table(root);
+
+        let large_page_count = level_page_count(1);
+        let base = VirtPageNum::new(0x10 * large_page_count);
+        let vpn = base + 3;
+        let perms = PageTablePerms::READ;
+
+        unsafe {
+            pt.map(
+                &mut TestAlloc,
+                &mut MappingPointer::new(vpn, 1),
+                PhysFrameNum::new(0x100),
+                perms,
+                CacheMode::Cached,
+            )
+            .unwrap();
+
+            assert_eq!(
+                pt.map(
+                    &mut TestAlloc,
+                    &mut MappingPointer::new(vpn, 1),
+                    PhysFrameNum::new(0x200),
+                    perms,
+                    CacheMode::Cached,
+                ),
+                Err(Error::RESOURCE_OVERLAP)
+            );
+
+            let mut gather = TestGather::default();
+            pt.map_replace(
+                &mut TestAlloc,
+                &mut gather,
+                &mut MappingPointer::new(vpn, 2),
+                PhysFrameNum::new(0x200),
+                perms,
+                CacheMode::Cached,
+            )
+            .unwrap();
+
+            // Only the page that was previously mapped needs to be flushed.
+            assert_eq!(gather.0, [vpn]);
+            assert_eq!(pt.query(vpn), Some(PhysFrameNum::new(0x200)));
+            assert_eq!(pt.query(vpn + 1), Some(PhysFrameNum::new(0x201)));
+
+            // Remapping the entire surrounding large page must not replace the existing table.
+            let mut gather = TestGather::default();
+            pt.map_replace(
+                &mut TestAlloc,
+                &mut gather,
+                &mut MappingPointer::new(base, large_page_count),
+                PhysFrameNum::new(0),
+                perms,
+                CacheMode::Cached,
+            )
+            .unwrap();
+
+            assert_eq!(gather.0, [vpn, vpn + 1]);
+            let mut table = root;
+            for level in (1..PT_LEVEL_COUNT).rev() {
+                table = pt
+                    .inner
+                    .next_table(table, base.pt_index(level), level)
+                    .ok()
+                    .expect("remapped range should still be covered by page tables");
+            }
+            for i in 0..large_page_count {
+                assert_eq!(pt.query(base + i), Some(PhysFrameNum::new(i)));
+            }
+
+            pt.unmap(
+                &mut NoopGather,
+                &mut MappingPointer::new(base, large_page_count),
+            )
+            .unwrap();
+            pt.cull_tables(&mut TestCull, base, large_page_count);
+            assert!(pt.inner.table_is_empty(root, PT_LEVEL_COUNT - 1));
+            pmm::deallocate(root, 0);
+        }
+    }
+}
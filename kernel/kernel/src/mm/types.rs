@@ -3,20 +3,35 @@ use core::{fmt, ops};
 use bitflags::bitflags;
 use num_utils::{align_down, align_up};
 
-use crate::arch::mmu::{PAGE_SHIFT, PAGE_SIZE, PT_LEVEL_MASK, PT_LEVEL_SHIFT};
+use crate::arch::mmu::{PAGE_SHIFT, PAGE_SIZE, PT_LEVEL_COUNT, PT_LEVEL_MASK, PT_LEVEL_SHIFT};
 
 use super::utils::write_flag;
 
 bitflags! {
     /// Protection that can be applied to a VM object.
+    ///
+    /// Mappings created with [`Protection::NONE`] are valid but can never be accessed: they are
+    /// never backed by present page table entries, so any access to them will fault. This makes
+    /// them suitable for guard pages.
     #[derive(Clone, Copy)]
     pub struct Protection: u8 {
+        const NONE = 0;
         const READ = 1 << 0;
         const WRITE = 1 << 1;
         const EXECUTE = 1 << 2;
     }
 }
 
+impl fmt::Debug for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_flag(f, self.contains(Self::READ), 'r')?;
+        write_flag(f, self.contains(Self::WRITE), 'w')?;
+        write_flag(f, self.contains(Self::EXECUTE), 'x')?;
+
+        Ok(())
+    }
+}
+
 /// Caching modes that can be applied to a range of memory.
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum CacheMode {
@@ -64,6 +79,20 @@ pub enum AccessMode {
     Kernel,
 }
 
+/// Architecture-independent information about a page fault.
+#[derive(Debug, Clone, Copy)]
+pub struct PageFaultInfo {
+    /// The address whose access caused the fault.
+    pub addr: VirtAddr,
+    /// The type of access that caused the fault.
+    pub access_type: AccessType,
+    /// The processor mode in which the fault occurred.
+    pub access_mode: AccessMode,
+    /// Whether the faulting page was present, in which case the fault was caused by a protection
+    /// violation.
+    pub was_present: bool,
+}
+
 bitflags! {
     /// Low-level page table permissions.
     #[derive(Clone, Copy)]
@@ -113,8 +142,13 @@ impl PhysAddr {
         PhysFrameNum::new(self.0 >> PAGE_SHIFT)
     }
 
+    /// Returns the first frame starting at or after this address, for use as the (exclusive) end
+    /// of a frame range covering everything below the address.
+    ///
+    /// This never overflows: for addresses in the last page of the address space, the result is
+    /// the frame number one past the last frame, which is still representable.
     pub const fn containing_tail_frame(self) -> PhysFrameNum {
-        PhysFrameNum::new((self.0 + PAGE_SIZE - 1) >> PAGE_SHIFT)
+        PhysFrameNum::new(div_ceil_page(self.0))
     }
 
     pub fn to_virt(self, f: impl FnOnce(PhysFrameNum) -> VirtPageNum) -> VirtAddr {
@@ -163,8 +197,13 @@ impl VirtAddr {
         VirtPageNum::new(self.0 >> PAGE_SHIFT)
     }
 
+    /// Returns the first page starting at or after this address, for use as the (exclusive) end
+    /// of a page range covering everything below the address.
+    ///
+    /// This never overflows: for addresses in the last page of the address space, the result is
+    /// the page number one past the last page, which is still representable.
     pub const fn containing_tail_page(self) -> VirtPageNum {
-        VirtPageNum::new((self.0 + PAGE_SIZE - 1) >> PAGE_SHIFT)
+        VirtPageNum::new(div_ceil_page(self.0))
     }
 
     pub fn to_phys(self, f: impl FnOnce(VirtPageNum) -> PhysFrameNum) -> PhysAddr {
@@ -215,7 +254,13 @@ impl VirtPageNum {
         VirtAddr::new(self.0 << PAGE_SHIFT)
     }
 
+    /// Returns the index of the page table entry covering this page at page table level `level`.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if `level` is not less than `PT_LEVEL_COUNT`.
     pub const fn pt_index(self, level: usize) -> usize {
+        debug_assert!(level < PT_LEVEL_COUNT, "page table level out of range");
         (self.0 >> (PT_LEVEL_SHIFT * level)) & PT_LEVEL_MASK
     }
 }
@@ -231,9 +276,36 @@ macro_rules! impl_arith_helpers {
                 Self(align_up(self.0, align))
             }
 
+            /// Aligns down to a multiple of `1 << order`.
+            pub const fn align_down_order(self, order: usize) -> Self {
+                self.align_down(1 << order)
+            }
+
+            /// Aligns up to a multiple of `1 << order`.
+            pub const fn align_up_order(self, order: usize) -> Self {
+                self.align_up(1 << order)
+            }
+
+            /// Returns whether this value is a multiple of `1 << order`.
+            pub const fn is_aligned_to_order(self, order: usize) -> bool {
+                self.0 & ((1 << order) - 1) == 0
+            }
+
             pub fn checked_add(self, rhs: usize) -> Option<Self> {
                 self.0.checked_add(rhs).map(Self)
             }
+
+            pub const fn saturating_add(self, rhs: usize) -> Self {
+                Self(self.0.saturating_add(rhs))
+            }
+
+            pub const fn wrapping_add(self, rhs: usize) -> Self {
+                Self(self.0.wrapping_add(rhs))
+            }
+
+            pub fn checked_sub(self, rhs: usize) -> Option<Self> {
+                self.0.checked_sub(rhs).map(Self)
+            }
         }
 
         impl fmt::Display for $t {
@@ -298,3 +370,112 @@ impl_arith_helpers!(PhysAddr);
 impl_arith_helpers!(VirtAddr);
 impl_arith_helpers!(PhysFrameNum);
 impl_arith_helpers!(VirtPageNum);
+
+/// Divides `addr` by the page size, rounding up, without overflowing near `usize::MAX`.
+const fn div_ceil_page(addr: usize) -> usize {
+    (addr >> PAGE_SHIFT) + (addr % PAGE_SIZE != 0) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::format;
+
+    use super::*;
+
+    #[test_case]
+    fn protection_debug_format() {
+        assert_eq!(
+            format!("{:?}", Protection::READ | Protection::EXECUTE),
+            "r-x"
+        );
+        assert_eq!(format!("{:?}", Protection::NONE), "---");
+        assert_eq!(format!("{:?}", Protection::all()), "rwx");
+    }
+
+    macro_rules! check_boundary_arith {
+        ($t:ty) => {
+            let max = <$t>::new(usize::MAX);
+            let zero = <$t>::new(0);
+
+            assert_eq!(max.checked_add(1), None);
+            assert_eq!(max.saturating_add(1), max);
+            assert_eq!(max.wrapping_add(1), zero);
+            assert_eq!((max - 1).saturating_add(1), max);
+
+            assert_eq!(zero.checked_sub(1), None);
+            assert_eq!(max.checked_sub(usize::MAX), Some(zero));
+        };
+    }
+
+    #[test_case]
+    fn addr_arith_at_boundary() {
+        check_boundary_arith!(PhysAddr);
+        check_boundary_arith!(VirtAddr);
+        check_boundary_arith!(PhysFrameNum);
+        check_boundary_arith!(VirtPageNum);
+    }
+
+    #[test_case]
+    fn containing_tail_at_boundary() {
+        let last = usize::MAX >> PAGE_SHIFT;
+
+        assert_eq!(
+            PhysAddr::new(usize::MAX).containing_tail_frame(),
+            PhysFrameNum::new(last + 1)
+        );
+        assert_eq!(
+            VirtAddr::new(usize::MAX).containing_tail_page(),
+            VirtPageNum::new(last + 1)
+        );
+
+        let last_page_start = last << PAGE_SHIFT;
+        assert_eq!(
+            PhysAddr::new(last_page_start).containing_tail_frame(),
+            PhysFrameNum::new(last)
+        );
+        assert_eq!(
+            VirtAddr::new(last_page_start + 1).containing_tail_page(),
+            VirtPageNum::new(last + 1)
+        );
+
+        assert_eq!(
+            PhysAddr::new(0).containing_tail_frame(),
+            PhysFrameNum::new(0)
+        );
+        assert_eq!(VirtAddr::new(1).containing_tail_page(), VirtPageNum::new(1));
+    }
+
+    macro_rules! check_order_alignment {
+        ($t:ty) => {
+            let val = <$t>::new(0x1234);
+
+            assert_eq!(val.align_down_order(4), <$t>::new(0x1230));
+            assert_eq!(val.align_up_order(4), <$t>::new(0x1240));
+            assert_eq!(val.align_down_order(0), val);
+            assert_eq!(val.align_up_order(0), val);
+            assert!(val.is_aligned_to_order(2));
+            assert!(!val.is_aligned_to_order(3));
+            assert_eq!(val.align_up_order(12), <$t>::new(0x2000));
+            assert!(val.align_up_order(12).is_aligned_to_order(12));
+        };
+    }
+
+    #[test_case]
+    fn order_alignment() {
+        check_order_alignment!(PhysAddr);
+        check_order_alignment!(VirtAddr);
+        check_order_alignment!(PhysFrameNum);
+        check_order_alignment!(VirtPageNum);
+    }
+
+    #[test_case]
+    fn pt_index_covers_all_levels() {
+        let vpn = VirtPageNum::new((0..PT_LEVEL_COUNT).fold(0, |vpn, level| {
+            vpn | ((level + 1) << (PT_LEVEL_SHIFT * level))
+        }));
+
+        for level in 0..PT_LEVEL_COUNT {
+            assert_eq!(vpn.pt_index(level), level + 1);
+        }
+    }
+}
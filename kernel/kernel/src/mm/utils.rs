@@ -1,5 +1,6 @@
 use core::fmt::{self, Write};
 use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use bootinfo::item::MemoryKind;
 use num_utils::div_ceil;
@@ -8,6 +9,8 @@ use crate::arch::mmu::PAGE_SIZE;
 
 use super::types::PhysFrameNum;
 
+const HEXDUMP_ROW_LEN: usize = 16;
+
 pub fn display_byte_size(bytes: usize) -> impl fmt::Display {
     struct DisplayByteSize(usize);
     impl fmt::Display for DisplayByteSize {
@@ -27,6 +30,100 @@ pub fn display_byte_size(bytes: usize) -> impl fmt::Display {
     DisplayByteSize(bytes)
 }
 
+/// Returns a value that displays a hexdump of `bytes`, labeling each row with its address.
+pub fn display_hexdump(bytes: &[u8]) -> impl fmt::Display + '_ {
+    struct DisplayHexdump<'a>(&'a [u8]);
+    impl fmt::Display for DisplayHexdump<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            hexdump(self.0, self.0.as_ptr() as usize, f)
+        }
+    }
+
+    DisplayHexdump(bytes)
+}
+
+/// Writes a hexdump of `bytes` to `out`, labeling each row with its offset from `base`.
+///
+/// Every row shows up to 16 bytes in hex followed by their ASCII representation, with
+/// non-printable bytes shown as `.`. The final row may be shorter if the length of `bytes` is not a
+/// multiple of 16.
+pub fn hexdump(bytes: &[u8], base: usize, out: &mut dyn fmt::Write) -> fmt::Result {
+    for (i, row) in bytes.chunks(HEXDUMP_ROW_LEN).enumerate() {
+        write!(out, "{:016x} ", base.wrapping_add(i * HEXDUMP_ROW_LEN))?;
+
+        for col in 0..HEXDUMP_ROW_LEN {
+            if col == HEXDUMP_ROW_LEN / 2 {
+                out.write_char(' ')?;
+            }
+
+            match row.get(col) {
+                Some(byte) => write!(out, " {byte:02x}")?,
+                None => out.write_str("   ")?,
+            }
+        }
+
+        out.write_str("  |")?;
+        for &byte in row {
+            let c = if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            };
+            out.write_char(c)?;
+        }
+        out.write_str("|\n")?;
+    }
+
+    Ok(())
+}
+
+/// A countdown that can be armed to make a chosen future allocation fail, used to exercise
+/// out-of-memory paths in tests.
+pub(super) struct FailureInjector {
+    countdown: AtomicUsize,
+}
+
+impl FailureInjector {
+    const DISARMED: usize = usize::MAX;
+
+    pub const fn new() -> Self {
+        Self {
+            countdown: AtomicUsize::new(Self::DISARMED),
+        }
+    }
+
+    /// Arms the injector so that the `n`th subsequent call to [`should_fail`](Self::should_fail)
+    /// (counting from 0) reports a failure. The injector is disarmed again after that call.
+    #[cfg(any(test, feature = "alloc-failure-injection"))]
+    pub fn fail_nth(&self, n: usize) {
+        assert_ne!(n, Self::DISARMED);
+        self.countdown.store(n, Ordering::Relaxed);
+    }
+
+    /// Disarms the injector, cancelling any pending failure.
+    #[cfg(any(test, feature = "alloc-failure-injection"))]
+    pub fn disarm(&self) {
+        self.countdown.store(Self::DISARMED, Ordering::Relaxed);
+    }
+
+    /// Advances the countdown, returning `true` if the current allocation should fail.
+    pub fn should_fail(&self) -> bool {
+        let prev = self
+            .countdown
+            .fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |countdown| match countdown {
+                    Self::DISARMED => None,
+                    0 => Some(Self::DISARMED),
+                    countdown => Some(countdown - 1),
+                },
+            );
+
+        prev == Ok(0)
+    }
+}
+
 pub fn to_page_count(bytes: usize) -> usize {
     div_ceil(bytes, PAGE_SIZE)
 }
@@ -96,3 +193,24 @@ pub(super) fn iter_usable_ranges(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+    use core::array;
+
+    use super::*;
+
+    #[test_case]
+    fn hexdump_formats_rows() {
+        let bytes: [u8; 20] = array::from_fn(|i| if i < 4 { b"AB\n~"[i] } else { i as u8 * 8 });
+        let mut out = String::new();
+        hexdump(&bytes, 0x1000, &mut out).unwrap();
+
+        assert_eq!(
+            out,
+            "0000000000001000  41 42 0a 7e 20 28 30 38  40 48 50 58 60 68 70 78  |AB.~ (08@HPX`hpx|\n\
+             0000000000001010  80 88 90 98                                       |....|\n"
+        );
+    }
+}
@@ -4,9 +4,10 @@ use crate::arch::mm::LOW_ASPACE_END;
 use crate::err::{Error, Result};
 use crate::sched::Thread;
 
-use super::types::{AccessType, VirtAddr};
+use super::types::{PageFaultInfo, VirtAddr};
+
+use self::aspace::{AddrSpace, AddrSpaceOps};
 
-pub use self::kernel_aspace::get as get_kernel_addr_space;
 pub use self::low_aspace::{make_low_addr_space, switch_to as switch_low_addr_space, LowAddrSpace};
 
 pub mod aspace;
@@ -21,18 +22,30 @@ pub fn init() {
     kernel_aspace::init();
 }
 
-/// Handles a page fault that occurred while accessing `addr` with the specified access type and
-/// mode.
-pub fn page_fault(addr: VirtAddr, access_type: AccessType) -> Result<()> {
-    if is_low_addr(addr) {
+/// Retrieves the global kernel address space.
+///
+/// # Panics
+///
+/// Panics if [`init`] has not yet been called.
+pub fn kernel_aspace() -> &'static AddrSpace<impl AddrSpaceOps> {
+    kernel_aspace::get()
+}
+
+/// Handles the page fault described by `info`.
+///
+/// Faults on present pages are still forwarded to the address space: genuine protection violations
+/// are rejected there, while spurious faults (for example, on a page committed concurrently) are
+/// resolved without changes.
+pub fn page_fault(info: &PageFaultInfo) -> Result<()> {
+    if is_low_addr(info.addr) {
         let current_thread = Thread::current().ok_or(Error::INVALID_STATE)?;
         let aspace = current_thread.addr_space().ok_or(Error::BAD_ADDRESS)?;
-        aspace.fault(addr.containing_page(), access_type)
+        aspace.fault(info.addr.containing_page(), info.access_type)
     } else {
         Err(Error::BAD_ADDRESS)
     }
 }
 
-fn is_low_addr(addr: VirtAddr) -> bool {
+pub(super) fn is_low_addr(addr: VirtAddr) -> bool {
     addr.containing_page() < LOW_ASPACE_END
 }
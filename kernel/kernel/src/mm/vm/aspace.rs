@@ -1,23 +1,25 @@
 use alloc::sync::Arc;
+use core::cmp;
 use core::ops::Range;
-use log::trace;
+use core::sync::atomic::{AtomicU64, Ordering};
+use log::{debug, trace};
 
 use arrayvec::ArrayVec;
 use qcell::QCellOwner;
 
 use crate::err::{Error, Result};
 use crate::mm::physmap::PhysmapPfnTranslator;
-use crate::mm::pmm;
+use crate::mm::pmm::FrameBox;
 use crate::mm::pt::{
     CullPageTables, GatherInvalidations, MappingPointer, PageTable, PageTableAlloc,
 };
-use crate::mm::types::{PageTablePerms, PhysFrameNum, Protection, VirtPageNum};
-use crate::sync::SpinLock;
+use crate::mm::types::{AccessType, PageTablePerms, PhysFrameNum, Protection, VirtPageNum};
+use crate::sync::lockrank::RANK_ADDR_SPACE;
+use crate::sync::{irq, SpinLock, SpinLockGuard};
 
-use self::tree::{Mapping, Slice};
+use self::tree::{Mapping, RangeRemoval, Reservation, Slice};
 
 use super::object::{CommitType, VmObject};
-use super::AccessType;
 
 mod tree;
 
@@ -98,6 +100,7 @@ pub unsafe trait AddrSpaceOps {
 /// responsible for providing access to the root page table for this address space and maintaining
 /// consistency across processors.
 pub struct AddrSpace<O> {
+    id: u64,
     // TODO: probably don't want a spinlock here
     inner: SpinLock<AddrSpaceInner>,
     root_slice: SliceHandle,
@@ -106,6 +109,21 @@ pub struct AddrSpace<O> {
 
 struct AddrSpaceInner {
     owner: QCellOwner,
+    committed_pages: usize,
+    reserved_pages: usize,
+    quota_pages: Option<usize>,
+}
+
+impl AddrSpaceInner {
+    /// Checks that `page_count` more pages can be reserved without exceeding the quota.
+    fn check_quota(&self, page_count: usize) -> Result<()> {
+        match self.quota_pages {
+            Some(quota) if page_count > quota.saturating_sub(self.reserved_pages) => {
+                Err(Error::QUOTA_EXCEEDED)
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<O: AddrSpaceOps> AddrSpace<O> {
@@ -118,6 +136,7 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     pub unsafe fn new(range: Range<VirtPageNum>, ops: O) -> Result<Self> {
         assert!(range.end >= range.start);
 
+        let id = NEXT_ADDR_SPACE_ID.fetch_add(1, Ordering::Relaxed);
         let owner = QCellOwner::new();
 
         let root_slice = {
@@ -128,11 +147,23 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
                 range.start,
                 range.end - range.start,
             )?;
-            SliceHandle { slice }
+            SliceHandle {
+                slice,
+                aspace_id: id,
+            }
         };
 
         Ok(AddrSpace {
-            inner: SpinLock::new(AddrSpaceInner { owner }),
+            id,
+            inner: SpinLock::new_ranked(
+                AddrSpaceInner {
+                    owner,
+                    committed_pages: 0,
+                    reserved_pages: 0,
+                    quota_pages: None,
+                },
+                RANK_ADDR_SPACE,
+            ),
             root_slice,
             ops,
         })
@@ -148,15 +179,75 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         &self.root_slice
     }
 
+    /// Returns the number of pages currently committed in this address space, that is, backed by
+    /// page table entries.
+    pub fn committed_pages(&self) -> usize {
+        self.inner.with(|inner, _| inner.committed_pages)
+    }
+
+    /// Returns the number of pages spanned by mappings in this address space, whether or not they
+    /// have been committed.
+    ///
+    /// Ranges set aside with [`reserve`](AddrSpace::reserve) are not included, as they can never
+    /// be committed.
+    pub fn reserved_pages(&self) -> usize {
+        self.inner.with(|inner, _| inner.reserved_pages)
+    }
+
+    /// Returns the maximum number of pages that may be reserved by mappings in this address space,
+    /// or `None` if only the range of the root slice limits it.
+    pub fn quota(&self) -> Option<usize> {
+        self.inner.with(|inner, _| inner.quota_pages)
+    }
+
+    /// Sets the maximum number of pages that may be reserved by mappings in this address space, as
+    /// reported by [`reserved_pages`](AddrSpace::reserved_pages). Passing `None` removes the limit.
+    ///
+    /// The quota is only checked when new mappings or subslices are created: lowering it below the
+    /// number of pages already reserved is allowed, and causes any further attempts to map pages to
+    /// fail until enough existing mappings are removed.
+    pub fn set_quota(&self, quota_pages: Option<usize>) {
+        self.inner.with(|inner, _| inner.quota_pages = quota_pages);
+    }
+
+    /// Returns the physical frame currently mapped at `vpn` in this address space, if any.
+    ///
+    /// Pages that are part of a mapping but have not yet been committed are reported as unmapped.
+    pub fn translate(&self, vpn: VirtPageNum) -> Option<PhysFrameNum> {
+        // Hold the lock so that the page tables can't be culled from under us.
+        self.with_inner(|_| self.pt().query(vpn))
+    }
+
+    /// Logs the page usage of this address space, followed by the full tree of slices, mappings and
+    /// reservations in it.
+    pub fn dump(&self) {
+        debug!(
+            "{} pages reserved (quota {:?}), {} committed",
+            self.reserved_pages(),
+            self.quota(),
+            self.committed_pages()
+        );
+        self.with_owner(|owner| {
+            self.root_slice
+                .slice
+                .dump(owner)
+                .expect("root slice should never be detached")
+        });
+    }
+
     /// Handles a page fault accessing `vpn` with access type `access_type`.
     ///
     /// This may ultimately call into [`provide_page`](VmObject::provide_page) on the object mapped
-    /// at the specified address.
+    /// at the specified address. If the object provides a nonzero
+    /// [`fault_around_order`](VmObject::fault_around_order), any pages in the surrounding window
+    /// that are not yet mapped will be committed as well.
     ///
     /// # Errors
     ///
     /// * `BAD_ADDRESS` - `vpn` is not mapped into this address space.
     /// * `NO_PERMS` - `vpn` is mapped with permissions incompatible with `access_type`.
+    /// * `INVALID_STATE` - `vpn` is covered by a special page table entry, which this address
+    ///                     space does not know how to resolve.
     /// * Any errors returned by the underlying `provide_page` call.
     pub fn fault(&self, vpn: VirtPageNum, access_type: AccessType) -> Result<()> {
         struct GetCommitRangeByVpn {
@@ -179,15 +270,24 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
                 }
 
                 let offset = self.vpn - mapping.start();
+                let window = fault_around_window(mapping, offset);
+
                 Ok(CommitRange {
                     mapping,
                     commit_type: get_commit_type(self.access_type),
-                    offset,
-                    page_count: 1,
+                    offset: window.start,
+                    page_count: window.end - window.start,
+                    skip_mapped: true,
                 })
             }
         }
 
+        // Hold the lock so that the page tables can't be culled from under us.
+        if let Some(tag) = self.with_inner(|_| self.pt().special_tag(vpn)) {
+            debug!("fault on page {vpn} with special entry {tag:#x}");
+            return Err(Error::INVALID_STATE);
+        }
+
         self.do_commit(GetCommitRangeByVpn { vpn, access_type })
     }
 
@@ -207,6 +307,8 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     /// * `OUT_OF_MEMORY` - Allocation of the new metadata failed.
     /// * `RESOURCE_OVERLAP` - The requested range overlaps an existing subslice or mapping.
     /// * `OUT_OF_RESOURCES` - No available regions of the requested size were found.
+    /// * `QUOTA_EXCEEDED` - The subslice could not be filled with mappings without exceeding the
+    ///                      [quota](AddrSpace::set_quota) of this address space.
     ///
     /// # Panics
     ///
@@ -218,7 +320,11 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         base: MapBase,
         page_count: usize,
     ) -> Result<SliceHandle> {
-        let subslice = self.with_owner(|owner| {
+        self.debug_assert_owns(slice.aspace_id);
+        let subslice = self.with_inner(|inner| {
+            inner.check_quota(page_count)?;
+
+            let owner = &mut inner.owner;
             let id = owner.id();
 
             slice.slice.alloc_spot(owner, base, page_count, |start| {
@@ -232,7 +338,10 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
             })
         })?;
 
-        Ok(SliceHandle { slice: subslice })
+        Ok(SliceHandle {
+            slice: subslice,
+            aspace_id: self.id,
+        })
     }
 
     /// Unmaps `slice` from this address space, recursively unmapping all nested mappings and
@@ -254,7 +363,9 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     ///
     /// * The range unmapped must not be accessed after this function returns
     pub unsafe fn unmap_slice(&self, slice: &SliceHandle) -> Result<()> {
-        self.with_owner(|owner| {
+        self.debug_assert_owns(slice.aspace_id);
+        self.with_inner(|inner| {
+            let owner = &mut inner.owner;
             let parent = slice.slice.parent(owner)?.ok_or(Error::INVALID_ARGUMENT)?;
 
             trace!(
@@ -266,11 +377,9 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
             );
 
             parent.remove_child(owner, slice.start())?;
-            slice.slice.detach_children(owner);
+            inner.reserved_pages -= slice.slice.detach_children(owner);
 
-            unsafe {
-                self.do_unmap(slice.start(), slice.page_count());
-            }
+            inner.committed_pages -= unsafe { self.do_unmap(slice.start(), slice.page_count()) };
 
             Ok(())
         })
@@ -280,6 +389,10 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     ///
     /// The mapping will be created with the permissions specified in `perms`.
     ///
+    /// The same object may be mapped any number of times, including into other address spaces, to
+    /// share memory: all mappings of a given object page will refer to the same frame (see
+    /// [`VmObject`]).
+    ///
     /// If `start` is provided, the mapping will be created at the requested virtual page number.
     /// Otherwise, a sufficiently large available region will be found and used.
     ///
@@ -292,6 +405,8 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     /// * `OUT_OF_MEMORY` - Allocation of the new metadata failed.
     /// * `RESOURCE_OVERLAP` - The requested range overlaps an existing subslice or mapping.
     /// * `OUT_OF_RESOURCES` - No available regions of the requested size were found.
+    /// * `QUOTA_EXCEEDED` - Reserving `page_count` more pages would exceed the
+    ///                      [quota](AddrSpace::set_quota) of this address space.
     ///
     /// # Panics
     ///
@@ -305,36 +420,44 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         object: Arc<dyn VmObject>,
         prot: Protection,
     ) -> Result<MappingHandle> {
+        self.debug_assert_owns(slice.aspace_id);
         let total_page_count = object.page_count();
 
         if object_offset > total_page_count || page_count > total_page_count - object_offset {
             return Err(Error::INVALID_ARGUMENT);
         }
 
-        let mapping = self.with_owner(|owner| {
+        let mapping = self.with_inner(|inner| -> Result<_> {
+            inner.check_quota(page_count)?;
+
+            let owner = &mut inner.owner;
             let id = owner.id();
-            slice
-                .slice
-                .alloc_spot(owner, base, total_page_count, |start| {
-                    trace!(
-                        "creating mapping at pages {}-{} in '{}'",
-                        start,
-                        start + page_count,
-                        slice.slice.name()
-                    );
-                    Mapping::new(
-                        id,
-                        Arc::clone(&slice.slice),
-                        start,
-                        page_count,
-                        object,
-                        object_offset,
-                        prot,
-                    )
-                })
+            let mapping = slice.slice.alloc_spot(owner, base, page_count, |start| {
+                trace!(
+                    "creating mapping at pages {}-{} in '{}'",
+                    start,
+                    start + page_count,
+                    slice.slice.name()
+                );
+                Mapping::new(
+                    id,
+                    Arc::clone(&slice.slice),
+                    start,
+                    page_count,
+                    object,
+                    object_offset,
+                    prot,
+                )
+            })?;
+
+            inner.reserved_pages += page_count;
+            Ok(mapping)
         })?;
 
-        Ok(MappingHandle { mapping })
+        Ok(MappingHandle {
+            mapping,
+            aspace_id: self.id,
+        })
     }
 
     /// Maps the range `object_offset..object_offset + page_count` of `object` into `slice`, and
@@ -355,6 +478,8 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     /// * `OUT_OF_MEMORY` - Allocation of the new metadata failed.
     /// * `RESOURCE_OVERLAP` - The requested range overlaps an existing subslice or mapping.
     /// * `OUT_OF_RESOURCES` - No available regions of the requested size were found.
+    /// * `QUOTA_EXCEEDED` - Reserving `page_count` more pages would exceed the
+    ///                      [quota](AddrSpace::set_quota) of this address space.
     ///
     /// # Panics
     ///
@@ -373,24 +498,37 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         Ok(mapping)
     }
 
-    /// Unmaps `mapping` from this address space.
+    /// Unmaps `target`, which may be either a mapping or a reservation, from this address space.
     ///
-    /// When this function returns, `mapping` will be detached, and any address space operations on
-    /// it will return `INVALID_STATE`.
+    /// When this function returns, `target` will be detached, and any address space operations on
+    /// it will return `INVALID_STATE`. Unmapping a reservation makes its range available for other
+    /// uses.
     ///
     /// # Errors
     ///
-    /// * `INVALID_STATE` - `mapping` is already detached.
+    /// * `INVALID_STATE` - `target` is already detached.
     ///
     /// # Panics
     ///
-    /// Panics if `mapping` belongs to a different address space.
+    /// Panics if `target` belongs to a different address space.
     ///
     /// # Safety
     ///
     /// * The range unmapped must not be accessed after this function returns
-    pub unsafe fn unmap(&self, mapping: &MappingHandle) -> Result<()> {
-        self.with_owner(|owner| {
+    pub unsafe fn unmap<'a>(&self, target: impl Into<UnmapTarget<'a>>) -> Result<()> {
+        match target.into() {
+            UnmapTarget::Mapping(mapping) => unsafe { self.unmap_mapping(mapping) },
+            UnmapTarget::Reservation(reservation) => self.unmap_reservation(reservation),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// See [`unmap`](AddrSpace::unmap).
+    unsafe fn unmap_mapping(&self, mapping: &MappingHandle) -> Result<()> {
+        self.debug_assert_owns(mapping.aspace_id);
+        self.with_inner(|inner| {
+            let owner = &mut inner.owner;
             let parent = mapping.mapping.parent(owner)?;
             parent.remove_child(owner, mapping.start())?;
 
@@ -401,10 +539,141 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
                 parent.name()
             );
 
-            unsafe {
-                self.do_unmap(mapping.start(), mapping.page_count());
+            inner.reserved_pages -= mapping.page_count();
+            inner.committed_pages -=
+                unsafe { self.do_unmap(mapping.start(), mapping.page_count()) };
+
+            Ok(())
+        })
+    }
+
+    /// Unmaps the range of `page_count` pages starting at `start` from the mappings in `slice`.
+    ///
+    /// Every mapping in `slice` intersecting the range is detached. Mappings that extend past
+    /// either end of the range are split, with new mappings created to cover the portions outside
+    /// the range; handles to these are returned in the [`SplitMappings`]. Parts of the range that
+    /// are not mapped are skipped.
+    ///
+    /// The address space is left unchanged if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_STATE` - This function was called on a [detached](SliceHandle#states) slice.
+    /// * `INVALID_ARGUMENT` - The requested range does not lie within `slice`.
+    /// * `RESOURCE_OVERLAP` - The requested range intersects a subslice or reservation of `slice`.
+    /// * `OUT_OF_MEMORY` - Allocation of the split mappings failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slice` belongs to a different address space.
+    ///
+    /// # Safety
+    ///
+    /// * The range unmapped must not be accessed after this function returns
+    pub unsafe fn unmap_range(
+        &self,
+        slice: &SliceHandle,
+        start: VirtPageNum,
+        page_count: usize,
+    ) -> Result<SplitMappings> {
+        self.debug_assert_owns(slice.aspace_id);
+        let RangeRemoval {
+            unmapped_pages: _,
+            head,
+            tail,
+        } = self.with_inner(|inner| -> Result<_> {
+            let removal = slice
+                .slice
+                .unmap_range(&mut inner.owner, start, page_count)?;
+
+            trace!(
+                "unmapping page range {}-{} from '{}'",
+                start,
+                start + page_count,
+                slice.name()
+            );
+
+            // Only pages covered by mappings can have been committed.
+            if removal.unmapped_pages != 0 {
+                inner.reserved_pages -= removal.unmapped_pages;
+                inner.committed_pages -= unsafe { self.do_unmap(start, page_count) };
             }
 
+            Ok(removal)
+        })?;
+
+        let handle = |mapping| MappingHandle {
+            mapping,
+            aspace_id: self.id,
+        };
+
+        Ok(SplitMappings {
+            head: head.map(handle),
+            tail: tail.map(handle),
+        })
+    }
+
+    /// Reserves the range of `page_count` pages within `slice`, without mapping anything into it.
+    ///
+    /// The reserved range will not be used for any other subslices or mappings until the reservation
+    /// is removed with [`unmap`](AddrSpace::unmap). Accesses to the range will fault.
+    ///
+    /// If `start` is provided, the reservation will be created at the requested virtual page
+    /// number. Otherwise, a sufficiently large available region will be found and used.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_STATE` - This function was called on a [detached](SliceHandle#states) slice.
+    /// * `INVALID_ARGUMENT` - The requested range is too large or does not lie in the virtual
+    ///                        address range managed by this slice.
+    /// * `OUT_OF_MEMORY` - Allocation of the new metadata failed.
+    /// * `RESOURCE_OVERLAP` - The requested range overlaps an existing subslice or mapping.
+    /// * `OUT_OF_RESOURCES` - No available regions of the requested size were found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slice` belongs to a different address space.
+    pub fn reserve(
+        &self,
+        slice: &SliceHandle,
+        base: MapBase,
+        page_count: usize,
+    ) -> Result<ReservationHandle> {
+        self.debug_assert_owns(slice.aspace_id);
+        let reservation = self.with_owner(|owner| {
+            let id = owner.id();
+
+            slice.slice.alloc_spot(owner, base, page_count, |start| {
+                trace!(
+                    "reserving pages {}-{} in '{}'",
+                    start,
+                    start + page_count,
+                    slice.slice.name()
+                );
+                Reservation::new(id, Arc::clone(&slice.slice), start, page_count)
+            })
+        })?;
+
+        Ok(ReservationHandle {
+            reservation,
+            aspace_id: self.id,
+        })
+    }
+
+    fn unmap_reservation(&self, reservation: &ReservationHandle) -> Result<()> {
+        self.debug_assert_owns(reservation.aspace_id);
+        self.with_owner(|owner| {
+            let parent = reservation.reservation.parent(owner)?;
+            parent.remove_child(owner, reservation.start())?;
+            reservation.reservation.detach(owner);
+
+            trace!(
+                "unmapping reserved pages {}-{} from '{}'",
+                reservation.start(),
+                reservation.end(),
+                parent.name()
+            );
+
             Ok(())
         })
     }
@@ -429,6 +698,7 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     ///
     /// Panics if `mapping` belongs to a different address space.
     pub fn commit(&self, mapping: &MappingHandle, offset: usize, page_count: usize) -> Result<()> {
+        self.debug_assert_owns(mapping.aspace_id);
         struct GetRequestedCommitRange<'a> {
             mapping: &'a Mapping,
             offset: usize,
@@ -455,6 +725,7 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
                     commit_type,
                     offset: self.offset,
                     page_count: self.page_count,
+                    skip_mapped: false,
                 })
             }
         }
@@ -466,79 +737,155 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         })
     }
 
+    /// Decommits `page_count` pages in `mapping`, starting at `offset`.
+    ///
+    /// The pages are removed from the page tables, but the mapping itself remains in place:
+    /// subsequent accesses to the range will fault and commit the pages again. Pages in the range
+    /// that were not committed are skipped.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_STATE` - This function was called on a [detached](MappingHandle#states) mapping.
+    /// * `INVALID_ARGUMENT` - The requested range does not lie within the mapping.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mapping` belongs to a different address space.
+    pub fn decommit(
+        &self,
+        mapping: &MappingHandle,
+        offset: usize,
+        page_count: usize,
+    ) -> Result<()> {
+        self.debug_assert_owns(mapping.aspace_id);
+        if offset > mapping.page_count() || page_count > mapping.page_count() - offset {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        self.with_inner(|inner| {
+            // Make sure the mapping is still attached before touching the page tables.
+            mapping.mapping.parent(&inner.owner)?;
+
+            let start = mapping.start() + offset;
+            trace!("decommitting page range {}-{}", start, start + page_count);
+
+            // Safety: we're holding the lock, and the mapping remains in place, so any later
+            // accesses to the range will simply fault the pages back in.
+            inner.committed_pages -= unsafe { self.do_unmap(start, page_count) };
+
+            Ok(())
+        })
+    }
+
     fn do_commit<'a>(&'a self, g: impl GetCommitRange<'a>) -> Result<()> {
+        // TODO: be more careful about this lock when `provide_page` can sleep.
+        self.with_inner(|inner| {
+            let mut committed = 0;
+            let res = self.commit_locked(&inner.owner, g, &mut committed);
+            inner.committed_pages += committed;
+            res
+        })
+    }
+
+    /// Commits the range requested by `g`, adding the number of newly mapped pages to `committed`
+    /// (even if an error occurs partway through).
+    ///
+    /// This function must be called with the lock held.
+    fn commit_locked<'a>(
+        &'a self,
+        owner: &QCellOwner,
+        g: impl GetCommitRange<'a>,
+        committed: &mut usize,
+    ) -> Result<()> {
         struct MappingRun {
             base_off: usize,
             base_pfn: PhysFrameNum,
             size: usize,
         }
 
-        // TODO: be more careful about this lock when `provide_page` can sleep.
-        self.with_owner(|owner| {
-            let range = g.get_range(self, owner)?;
-            let mapping = range.mapping;
-            let prot = mapping.prot(owner)?;
-
-            let object = mapping.object().as_ref();
-            let cache_mode = object.cache_mode();
-            let commit_type = range.commit_type;
-
-            let start_offset = range.offset;
-            let end_offset = start_offset + range.page_count;
+        let range = g.get_range(self, owner)?;
+        let mapping = range.mapping;
+        let prot = mapping.prot(owner)?;
 
-            trace!(
-                "committing page range {}-{} for type {commit_type:?}",
-                mapping.start() + start_offset,
-                mapping.start() + end_offset
-            );
+        if prot.is_empty() {
+            // Inaccessible mappings are never backed by page table entries, as not all
+            // architectures can express a present mapping with no access.
+            return Ok(());
+        }
 
-            let do_map = |run: &MappingRun| {
-                // Safety: we're holding the page table lock, and our translator and allocator perform
-                // correctly.
-                unsafe {
-                    self.pt().map(
-                        &mut AspacePageTableAlloc,
-                        &mut MappingPointer::new(mapping.start() + run.base_off, run.size),
-                        run.base_pfn,
-                        self.perms_for_prot(prot),
-                        cache_mode,
-                    )
-                }
+        let object = mapping.object().as_ref();
+        let cache_mode = object.cache_mode();
+        let commit_type = range.commit_type;
+
+        let start_offset = range.offset;
+        let end_offset = start_offset + range.page_count;
+
+        trace!(
+            "committing page range {}-{} for type {commit_type:?}",
+            mapping.start() + start_offset,
+            mapping.start() + end_offset
+        );
+
+        let mut do_map = |run: &MappingRun| {
+            let mut pointer = MappingPointer::new(mapping.start() + run.base_off, run.size);
+
+            // Safety: we're holding the page table lock, and our translator and allocator perform
+            // correctly.
+            let res = unsafe {
+                self.pt().map(
+                    &mut AspacePageTableAlloc,
+                    &mut pointer,
+                    run.base_pfn,
+                    self.perms_for_prot(prot),
+                    cache_mode,
+                )
             };
 
-            let mut cur_run: Option<MappingRun> = None;
-
-            // TODO: refactor this and find some way for `provide_page` to block outside the
-            // critical section
-            for offset in range.offset..range.offset + range.page_count {
-                let pfn = object.provide_page(offset + mapping.object_offset(), commit_type)?;
+            // Account for any pages mapped before a failure as well, as they remain in place.
+            *committed += pointer.offset();
+            res
+        };
 
-                if let Some(run) = &mut cur_run {
-                    if run.base_pfn.checked_add(run.size) == Some(pfn) {
-                        // The newly-provided frame can be added to the current run, so don't update
-                        // the page tables just yet.
-                        run.size += 1;
-                        continue;
-                    }
+        let mut cur_run: Option<MappingRun> = None;
 
-                    do_map(run)?;
+        // TODO: refactor this and find some way for `provide_page` to block outside the
+        // critical section
+        for offset in range.offset..range.offset + range.page_count {
+            if range.skip_mapped && self.pt_slot_occupied(mapping.start() + offset) {
+                // Flush the current run, as it can't extend past this page.
+                if let Some(run) = cur_run.take() {
+                    do_map(&run)?;
                 }
-
-                // This frame can't be added into the existing run, so start tracking a new one.
-                cur_run = Some(MappingRun {
-                    base_off: offset,
-                    base_pfn: pfn,
-                    size: 1,
-                });
+                continue;
             }
 
-            // Map in the run left over by the last iteration if there is one.
-            if let Some(run) = &cur_run {
+            let pfn = object.provide_page(offset + mapping.object_offset(), commit_type)?;
+
+            if let Some(run) = &mut cur_run {
+                if run.base_pfn.checked_add(run.size) == Some(pfn) {
+                    // The newly-provided frame can be added to the current run, so don't update
+                    // the page tables just yet.
+                    run.size += 1;
+                    continue;
+                }
+
                 do_map(run)?;
             }
 
-            Ok(())
-        })
+            // This frame can't be added into the existing run, so start tracking a new one.
+            cur_run = Some(MappingRun {
+                base_off: offset,
+                base_pfn: pfn,
+                size: 1,
+            });
+        }
+
+        // Map in the run left over by the last iteration if there is one.
+        if let Some(run) = &cur_run {
+            do_map(run)?;
+        }
+
+        Ok(())
     }
 
     /// # Safety
@@ -546,20 +893,48 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
     /// * This function must be called with the lock held
     /// * The range must not be accessed when this function returns
     /// * The page tables mapping the range must have been allocated by the PMM
-    unsafe fn do_unmap(&self, start: VirtPageNum, page_count: usize) {
+    ///
+    /// Returns the number of pages in the range that were actually mapped.
+    unsafe fn do_unmap(&self, start: VirtPageNum, page_count: usize) -> usize {
         let mut pt = self.pt();
         let mut gather = PendingInvalidationGather::new();
 
         unsafe {
-            pt.unmap(&mut gather, &mut MappingPointer::new(start, page_count))
+            let unmapped_pages = pt
+                .unmap(&mut gather, &mut MappingPointer::new(start, page_count))
                 .expect("failed to unmap page range");
             self.ops.flush(gather.as_tlb_flush());
             pt.cull_tables(&mut AspaceCullTables(&self.ops), start, page_count);
+            unmapped_pages
         }
     }
 
+    /// Asserts (in debug builds) that a handle tagged with `aspace_id` belongs to this address
+    /// space.
+    #[track_caller]
+    fn debug_assert_owns(&self, aspace_id: u64) {
+        debug_assert!(
+            self.owns(aspace_id),
+            "handle from address space {} used with address space {}",
+            aspace_id,
+            self.id
+        );
+    }
+
+    fn owns(&self, aspace_id: u64) -> bool {
+        aspace_id == self.id
+    }
+
     fn with_owner<R>(&self, f: impl FnOnce(&mut QCellOwner) -> R) -> R {
-        self.inner.with(|inner, _| f(&mut inner.owner))
+        irq::disable_with(|irq_disabled| {
+            let mut owner =
+                SpinLockGuard::map(self.inner.lock(irq_disabled), |inner| &mut inner.owner);
+            f(&mut owner)
+        })
+    }
+
+    fn with_inner<R>(&self, f: impl FnOnce(&mut AddrSpaceInner) -> R) -> R {
+        self.inner.with(|inner, _| f(inner))
     }
 
     fn pt(&self) -> PageTable<PhysmapPfnTranslator> {
@@ -568,7 +943,19 @@ impl<O: AddrSpaceOps> AddrSpace<O> {
         unsafe { PageTable::new(self.ops.root_pt(), PhysmapPfnTranslator) }
     }
 
+    /// Checks whether `vpn` is either mapped or holds a special entry in the page tables.
+    fn pt_slot_occupied(&self, vpn: VirtPageNum) -> bool {
+        let pt = self.pt();
+        pt.is_mapped(vpn) || pt.special_tag(vpn).is_some()
+    }
+
+    /// Returns the page table permissions corresponding to `prot`.
+    ///
+    /// This should never be called with [`Protection::NONE`], as such mappings are never entered
+    /// into the page tables.
     fn perms_for_prot(&self, prot: Protection) -> PageTablePerms {
+        debug_assert!(!prot.is_empty(), "attempted to map inaccessible pages");
+
         let mut perms = self.ops.base_perms();
 
         perms.set(PageTablePerms::READ, prot.contains(Protection::READ));
@@ -598,6 +985,7 @@ impl<O> Drop for AddrSpace<O> {
 #[derive(Clone)]
 pub struct SliceHandle {
     slice: Arc<Slice>,
+    aspace_id: u64,
 }
 
 impl SliceHandle {
@@ -636,6 +1024,7 @@ impl SliceHandle {
 #[derive(Clone)]
 pub struct MappingHandle {
     mapping: Arc<Mapping>,
+    aspace_id: u64,
 }
 
 impl MappingHandle {
@@ -665,12 +1054,101 @@ impl MappingHandle {
     }
 }
 
+/// The mappings left over after splitting mappings with [`AddrSpace::unmap_range`].
+pub struct SplitMappings {
+    /// The remainder of a split mapping preceding the unmapped range, if any.
+    pub head: Option<MappingHandle>,
+    /// The remainder of a split mapping following the unmapped range, if any.
+    pub tail: Option<MappingHandle>,
+}
+
+/// A handle to a reserved range of an address space.
+///
+/// # States
+///
+/// Like slices, every reservation may be either *attached* or *detached*. Unmapping a reservation
+/// detaches it.
+#[derive(Clone)]
+pub struct ReservationHandle {
+    reservation: Arc<Reservation>,
+    aspace_id: u64,
+}
+
+impl ReservationHandle {
+    /// Returns the first page number covered by this reservation.
+    pub fn start(&self) -> VirtPageNum {
+        self.reservation.start()
+    }
+
+    /// Returns the page number just after the last page covered by this reservation.
+    pub fn end(&self) -> VirtPageNum {
+        self.reservation.end()
+    }
+}
+
+/// A child of a slice that can be removed with [`AddrSpace::unmap`].
+///
+/// Mapping and reservation handles convert into this type, so they can be passed to `unmap`
+/// directly.
+#[derive(Clone, Copy)]
+pub enum UnmapTarget<'a> {
+    Mapping(&'a MappingHandle),
+    Reservation(&'a ReservationHandle),
+}
+
+impl<'a> From<&'a MappingHandle> for UnmapTarget<'a> {
+    fn from(mapping: &'a MappingHandle) -> Self {
+        Self::Mapping(mapping)
+    }
+}
+
+impl<'a> From<&'a ReservationHandle> for UnmapTarget<'a> {
+    fn from(reservation: &'a ReservationHandle) -> Self {
+        Self::Reservation(reservation)
+    }
+}
+
+/// Source of the IDs used to check that handles are only used with the address space that created
+/// them.
+static NEXT_ADDR_SPACE_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Clone, Copy)]
 struct CommitRange<'a> {
     mapping: &'a Mapping,
     commit_type: CommitType,
     offset: usize,
     page_count: usize,
+    /// Whether pages in the range that are already mapped should be left alone instead of being
+    /// treated as an error.
+    skip_mapped: bool,
+}
+
+/// Upper bound on [`VmObject::fault_around_order`], to avoid committing huge amounts of memory on a
+/// single fault.
+const MAX_FAULT_AROUND_ORDER: usize = 6;
+
+/// Computes the range of offsets within `mapping` that should be committed when handling a fault
+/// at `offset`, according to the object's fault-around hint.
+fn fault_around_window(mapping: &Mapping, offset: usize) -> Range<usize> {
+    let object = mapping.object();
+    let order = cmp::min(object.fault_around_order(), MAX_FAULT_AROUND_ORDER);
+    let window_size = 1 << order;
+
+    // Align the window in the object's offset space, so that the same object pages are grouped
+    // together regardless of where the object is mapped.
+    let object_offset = mapping.object_offset() + offset;
+    let window_start = object_offset - object_offset % window_size;
+    let window_end = window_start + window_size;
+
+    let object_end = cmp::min(
+        object.page_count(),
+        mapping.object_offset() + mapping.page_count(),
+    );
+
+    let start = cmp::max(window_start, mapping.object_offset());
+    let end = cmp::min(window_end, object_end);
+
+    start - mapping.object_offset()..end - mapping.object_offset()
 }
 
 trait GetCommitRange<'a> {
@@ -723,7 +1201,8 @@ struct AspacePageTableAlloc;
 
 impl PageTableAlloc for AspacePageTableAlloc {
     fn allocate(&mut self) -> Result<PhysFrameNum> {
-        pmm::allocate(0).ok_or(Error::OUT_OF_MEMORY)
+        // Ownership of the frame passes to the page table until it is culled.
+        Ok(FrameBox::<0>::new()?.into_pfn())
     }
 }
 
@@ -731,7 +1210,9 @@ struct AspaceCullTables<'a, O>(&'a O);
 
 impl<O: AddrSpaceOps> CullPageTables for AspaceCullTables<'_, O> {
     fn cull(&mut self, pt: PhysFrameNum, _level: usize) {
-        unsafe { pmm::deallocate(pt, 0) }
+        // Safety: the address space only ever culls tables it allocated with
+        // `AspacePageTableAlloc`, and each table is culled exactly once.
+        drop(unsafe { FrameBox::<0>::from_pfn(pt) });
     }
 
     fn can_cull(&self, pt: PhysFrameNum, level: usize) -> bool {
@@ -753,3 +1234,540 @@ fn access_allowed(access_type: AccessType, prot: Protection) -> bool {
         AccessType::Execute => prot.contains(Protection::EXECUTE),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::mm::types::AccessMode;
+    use crate::mm::vm::kernel_aspace;
+    use crate::mm::vm::low_aspace::make_low_addr_space;
+    use crate::mm::vm::object::{EagerVmObject, FnVmObject, LazyVmObject};
+
+    #[test_case]
+    fn reservation_blocks_overlapping_maps() {
+        let aspace = kernel_aspace();
+        let slice = aspace
+            .create_subslice(aspace.root_slice(), "test", MapBase::any(), 8)
+            .unwrap();
+
+        let reservation = aspace
+            .reserve(&slice, MapBase::Fixed(slice.start() + 2), 4)
+            .unwrap();
+
+        let object = EagerVmObject::new(2).unwrap();
+        let res = aspace.map(
+            &slice,
+            MapBase::Fixed(slice.start() + 1),
+            2,
+            0,
+            object.clone(),
+            Protection::READ,
+        );
+        assert_eq!(res.err(), Some(Error::RESOURCE_OVERLAP));
+
+        unsafe {
+            aspace.unmap(&reservation).unwrap();
+            assert_eq!(aspace.unmap(&reservation), Err(Error::INVALID_STATE));
+        }
+
+        let mapping = aspace
+            .map(
+                &slice,
+                MapBase::Fixed(slice.start() + 1),
+                2,
+                0,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+
+        unsafe {
+            aspace.unmap(&mapping).unwrap();
+            aspace.unmap_slice(&slice).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn none_protection_always_faults() {
+        let aspace = kernel_aspace();
+        let object = EagerVmObject::new(2).unwrap();
+        let mapping = aspace
+            .map_committed(
+                aspace.root_slice(),
+                MapBase::any(),
+                2,
+                0,
+                object,
+                Protection::NONE,
+            )
+            .unwrap();
+
+        for access_type in [AccessType::Read, AccessType::Write, AccessType::Execute] {
+            assert_eq!(
+                aspace.fault(mapping.start(), access_type),
+                Err(Error::NO_PERMS)
+            );
+        }
+
+        unsafe {
+            aspace.unmap(&mapping).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn partial_map_reserves_only_mapped_pages() {
+        let aspace = kernel_aspace();
+        let slice = aspace
+            .create_subslice(aspace.root_slice(), "test", MapBase::any(), 4)
+            .unwrap();
+
+        let object = EagerVmObject::new(16).unwrap();
+        let mapping = aspace
+            .map(
+                &slice,
+                MapBase::Fixed(slice.start()),
+                2,
+                4,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+        assert_eq!(mapping.page_count(), 2);
+        assert_eq!(mapping.end(), slice.start() + 2);
+
+        // The remainder of the slice should still be available, even though the object is larger.
+        let tail = aspace
+            .map(
+                &slice,
+                MapBase::Fixed(slice.start() + 2),
+                2,
+                0,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+
+        unsafe {
+            aspace.unmap(&tail).unwrap();
+            aspace.unmap(&mapping).unwrap();
+            aspace.unmap_slice(&slice).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn object_shared_between_aspaces() {
+        let kernel_aspace = kernel_aspace();
+        let low_aspace = make_low_addr_space(AccessMode::Kernel).unwrap();
+        let object = LazyVmObject::new(4).unwrap();
+
+        let kernel_mapping = kernel_aspace
+            .map(
+                kernel_aspace.root_slice(),
+                MapBase::any(),
+                4,
+                0,
+                object.clone(),
+                Protection::READ | Protection::WRITE,
+            )
+            .unwrap();
+        let low_mapping = low_aspace
+            .map(
+                low_aspace.root_slice(),
+                MapBase::any(),
+                3,
+                1,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+
+        // Commit different pages first in each address space, so that both end up allocating some
+        // of the object's frames.
+        kernel_aspace.commit(&kernel_mapping, 0, 2).unwrap();
+        low_aspace.commit(&low_mapping, 1, 2).unwrap();
+        kernel_aspace.commit(&kernel_mapping, 2, 2).unwrap();
+        low_aspace.commit(&low_mapping, 0, 1).unwrap();
+
+        for i in 0..3 {
+            let shared = kernel_aspace.translate(kernel_mapping.start() + 1 + i);
+            assert!(shared.is_some());
+            assert_eq!(low_aspace.translate(low_mapping.start() + i), shared);
+        }
+
+        unsafe {
+            low_aspace.unmap(&low_mapping).unwrap();
+            kernel_aspace.unmap(&kernel_mapping).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn unmap_range_splits_mapping() {
+        let aspace = kernel_aspace();
+        let slice = aspace
+            .create_subslice(aspace.root_slice(), "test", MapBase::any(), 10)
+            .unwrap();
+
+        let object = EagerVmObject::new(8).unwrap();
+        let mapping = aspace
+            .map_committed(
+                &slice,
+                MapBase::Fixed(slice.start() + 1),
+                8,
+                0,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+
+        let pfns: Vec<_> = (0..8)
+            .map(|i| aspace.translate(mapping.start() + i).unwrap())
+            .collect();
+        let committed = aspace.committed_pages();
+        let reserved = aspace.reserved_pages();
+
+        let split = unsafe { aspace.unmap_range(&slice, mapping.start() + 3, 2).unwrap() };
+        let head = split.head.unwrap();
+        let tail = split.tail.unwrap();
+
+        assert_eq!(aspace.commit(&mapping, 0, 1), Err(Error::INVALID_STATE));
+        assert_eq!(aspace.committed_pages(), committed - 2);
+        assert_eq!(aspace.reserved_pages(), reserved - 2);
+
+        assert_eq!((head.start(), head.page_count()), (mapping.start(), 3));
+        assert_eq!(head.object_offset(), 0);
+        assert_eq!((tail.start(), tail.page_count()), (mapping.start() + 5, 3));
+        assert_eq!(tail.object_offset(), 5);
+
+        for (i, &pfn) in pfns.iter().enumerate() {
+            let expected = (!(3..5).contains(&i)).then_some(pfn);
+            assert_eq!(aspace.translate(mapping.start() + i), expected);
+        }
+
+        // The hole can be reused, and the fragments behave like ordinary mappings.
+        let filler = aspace
+            .map(
+                &slice,
+                MapBase::Fixed(mapping.start() + 3),
+                2,
+                0,
+                EagerVmObject::new(2).unwrap(),
+                Protection::READ,
+            )
+            .unwrap();
+
+        // Ranges touching reservations or subslices are rejected without changing anything.
+        let reservation = aspace
+            .reserve(&slice, MapBase::Fixed(slice.start()), 1)
+            .unwrap();
+        assert_eq!(
+            unsafe { aspace.unmap_range(&slice, slice.start(), 3) }.err(),
+            Some(Error::RESOURCE_OVERLAP)
+        );
+        assert_eq!(aspace.translate(head.start()), Some(pfns[0]));
+
+        // Unmapping across several mappings only splits the outermost ones.
+        let split = unsafe { aspace.unmap_range(&slice, head.start() + 2, 5) }.unwrap();
+        assert_eq!(aspace.commit(&filler, 0, 1), Err(Error::INVALID_STATE));
+        let head = split.head.unwrap();
+        let tail = split.tail.unwrap();
+        assert_eq!((head.start(), head.page_count()), (mapping.start(), 2));
+        assert_eq!((tail.start(), tail.page_count()), (mapping.start() + 7, 1));
+        assert_eq!(tail.object_offset(), 7);
+        assert_eq!(aspace.translate(tail.start()), Some(pfns[7]));
+
+        unsafe {
+            aspace.unmap(&head).unwrap();
+            aspace.unmap(&tail).unwrap();
+            aspace.unmap(&reservation).unwrap();
+            aspace.unmap_slice(&slice).unwrap();
+        }
+    }
+
+    /// Creates a test object with fault-around order `order`, backed by a new lazy object.
+    fn fault_around_object(
+        page_count: usize,
+        order: usize,
+    ) -> Arc<FnVmObject<impl Fn(usize, CommitType) -> Result<PhysFrameNum> + Send + Sync>> {
+        let backing = LazyVmObject::new(page_count).unwrap();
+        FnVmObject::new(page_count, order, move |offset, commit_type| {
+            backing.provide_page(offset, commit_type)
+        })
+        .unwrap()
+    }
+
+    #[test_case]
+    fn fault_commits_surrounding_window() {
+        let aspace = kernel_aspace();
+        let object = fault_around_object(12, 2);
+
+        // Map object pages 1-11, so that the outer windows are clamped by the mapping bounds.
+        let mapping = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                10,
+                1,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+
+        let read = |offsets: Range<usize>| {
+            offsets
+                .map(|offset| (offset, CommitType::Read))
+                .collect::<Vec<_>>()
+        };
+
+        let mapped_offsets = |expected: Range<usize>| {
+            (0..10).all(|offset| {
+                aspace.pt().is_mapped(mapping.start() + offset) == expected.contains(&offset)
+            })
+        };
+
+        // Object page 6 lies in the window 4-8.
+        aspace.fault(mapping.start() + 5, AccessType::Read).unwrap();
+        assert!(mapped_offsets(3..7));
+        assert_eq!(object.take_requests(), read(4..8));
+
+        // Object page 1 lies in the window 0-4, which is clamped to the start of the mapping.
+        aspace.fault(mapping.start(), AccessType::Read).unwrap();
+        assert!(mapped_offsets(0..7));
+        assert_eq!(object.take_requests(), read(1..4));
+
+        // Faults on pages that are already mapped should leave them alone.
+        aspace.fault(mapping.start() + 4, AccessType::Read).unwrap();
+        assert!(mapped_offsets(0..7));
+        assert!(object.take_requests().is_empty());
+
+        // Object page 10 lies in the window 8-12, which is clamped to the end of the mapping.
+        aspace.fault(mapping.start() + 9, AccessType::Read).unwrap();
+        assert!(mapped_offsets(0..10));
+        assert_eq!(object.take_requests(), read(8..11));
+
+        unsafe {
+            aspace.unmap(&mapping).unwrap();
+        }
+    }
+
+    #[test_case]
+    fn special_entries_survive_nearby_faults() {
+        let aspace = kernel_aspace();
+        let object = fault_around_object(4, 2);
+
+        let mapping = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                4,
+                0,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+
+        let special = mapping.start() + 2;
+        aspace.with_inner(|_| unsafe {
+            aspace
+                .pt()
+                .set_special(&mut AspacePageTableAlloc, special, 0x5a5a)
+                .unwrap();
+        });
+
+        // The fault-around window covers the special entry, which should be left in place.
+        aspace.fault(mapping.start() + 1, AccessType::Read).unwrap();
+        assert_eq!(aspace.pt().special_tag(special), Some(0x5a5a));
+        assert!(!aspace.pt().is_mapped(special));
+        assert!(aspace.pt().is_mapped(mapping.start() + 3));
+        assert_eq!(
+            object.take_requests(),
+            [
+                (0, CommitType::Read),
+                (1, CommitType::Read),
+                (3, CommitType::Read)
+            ]
+        );
+
+        // Faults on the special entry itself should never reach the object.
+        assert_eq!(
+            aspace.fault(special, AccessType::Read),
+            Err(Error::INVALID_STATE)
+        );
+        assert!(object.take_requests().is_empty());
+
+        unsafe {
+            aspace.unmap(&mapping).unwrap();
+        }
+        assert_eq!(aspace.pt().special_tag(special), None);
+    }
+
+    #[test_case]
+    fn handles_are_tagged_with_their_aspace() {
+        let aspace = make_low_addr_space(AccessMode::Kernel).unwrap();
+        let other_aspace = make_low_addr_space(AccessMode::Kernel).unwrap();
+        assert_ne!(aspace.id, other_aspace.id);
+
+        let slice = aspace
+            .create_subslice(aspace.root_slice(), "tagged", MapBase::any(), 4)
+            .unwrap();
+        let mapping = aspace
+            .map(
+                &slice,
+                MapBase::any(),
+                2,
+                0,
+                LazyVmObject::new(2).unwrap(),
+                Protection::READ,
+            )
+            .unwrap();
+        let reservation = aspace.reserve(&slice, MapBase::any(), 1).unwrap();
+
+        // A panic can't be recovered from in the test runner, so check the predicate behind the
+        // assertion directly for the foreign case.
+        for aspace_id in [
+            aspace.root_slice().aspace_id,
+            slice.aspace_id,
+            mapping.aspace_id,
+            reservation.aspace_id,
+        ] {
+            aspace.debug_assert_owns(aspace_id);
+            assert!(aspace.owns(aspace_id));
+            assert!(!other_aspace.owns(aspace_id));
+        }
+        assert!(!aspace.owns(other_aspace.root_slice().aspace_id));
+
+        unsafe { aspace.unmap_slice(&slice).unwrap() };
+    }
+
+    #[test_case]
+    fn commit_accounting() {
+        let aspace = make_low_addr_space(AccessMode::Kernel).unwrap();
+        let object = LazyVmObject::new(4).unwrap();
+
+        let mapping = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                4,
+                0,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+        assert_eq!(aspace.reserved_pages(), 4);
+        assert_eq!(aspace.committed_pages(), 0);
+
+        aspace.commit(&mapping, 1, 2).unwrap();
+        assert_eq!(aspace.committed_pages(), 2);
+
+        aspace.fault(mapping.start() + 3, AccessType::Read).unwrap();
+        assert_eq!(aspace.committed_pages(), 3);
+
+        // Only page 1 is committed in this range.
+        aspace.decommit(&mapping, 0, 2).unwrap();
+        assert_eq!(aspace.committed_pages(), 2);
+        assert_eq!(aspace.reserved_pages(), 4);
+
+        assert_eq!(
+            aspace.decommit(&mapping, 3, 2),
+            Err(Error::INVALID_ARGUMENT)
+        );
+
+        unsafe {
+            aspace.unmap(&mapping).unwrap();
+        }
+        assert_eq!(aspace.committed_pages(), 0);
+        assert_eq!(aspace.reserved_pages(), 0);
+    }
+
+    #[test_case]
+    fn quota_limits_reserved_pages() {
+        let aspace = make_low_addr_space(AccessMode::Kernel).unwrap();
+        let object = LazyVmObject::new(4).unwrap();
+        aspace.set_quota(Some(6));
+        assert_eq!(aspace.quota(), Some(6));
+
+        let first = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                4,
+                0,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+
+        // Only 2 pages of the quota remain.
+        assert_eq!(
+            aspace
+                .map(
+                    aspace.root_slice(),
+                    MapBase::any(),
+                    3,
+                    0,
+                    object.clone(),
+                    Protection::READ,
+                )
+                .err(),
+            Some(Error::QUOTA_EXCEEDED)
+        );
+        assert_eq!(
+            aspace
+                .create_subslice(aspace.root_slice(), "test", MapBase::any(), 3)
+                .err(),
+            Some(Error::QUOTA_EXCEEDED)
+        );
+        assert_eq!(aspace.reserved_pages(), 4);
+
+        let slice = aspace
+            .create_subslice(aspace.root_slice(), "test", MapBase::any(), 2)
+            .unwrap();
+        aspace
+            .map(
+                &slice,
+                MapBase::any(),
+                2,
+                0,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+        assert_eq!(aspace.reserved_pages(), 6);
+
+        // Unmapping returns pages to the quota, and lifting the quota removes the limit entirely.
+        unsafe {
+            aspace.unmap_slice(&slice).unwrap();
+        }
+        let third = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                2,
+                0,
+                object.clone(),
+                Protection::READ,
+            )
+            .unwrap();
+
+        aspace.set_quota(None);
+        let fourth = aspace
+            .map(
+                aspace.root_slice(),
+                MapBase::any(),
+                4,
+                0,
+                object,
+                Protection::READ,
+            )
+            .unwrap();
+        assert_eq!(aspace.reserved_pages(), 10);
+
+        unsafe {
+            aspace.unmap(&fourth).unwrap();
+            aspace.unmap(&third).unwrap();
+            aspace.unmap(&first).unwrap();
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use core::ops::ControlFlow;
+use core::ptr::{self, NonNull};
 
-use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
+use intrusive_collections::rbtree::{AtomicLink, AtomicLinkOps, RBTree};
+use intrusive_collections::{offset_of, Adapter, Bound, KeyAdapter, PointerOps};
+use log::debug;
 use object_name::Name;
 use qcell::{QCell, QCellOwner, QCellOwnerID};
 
@@ -11,28 +14,170 @@ use crate::mm::vm::object::VmObject;
 
 use super::MapBase;
 
-/// A child of an address space slice, containing either another slice or a mapping.
+/// A child of an address space slice, containing either another slice, a mapping, or an opaque
+/// reservation.
 pub enum SliceChild {
     Subslice(Arc<Slice>),
     Mapping(Arc<Mapping>),
+    Reservation(Arc<Reservation>),
 }
 
-impl SliceChild {
-    fn start(&self) -> VirtPageNum {
-        match self {
-            SliceChild::Subslice(subslice) => subslice.start(),
-            SliceChild::Mapping(mapping) => mapping.start(),
+/// A borrowed reference to a child of an address space slice.
+pub enum SliceChildRef<'a> {
+    Subslice(&'a Slice),
+    Mapping(&'a Mapping),
+    Reservation(&'a Reservation),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChildKind {
+    Subslice,
+    Mapping,
+    Reservation,
+}
+
+/// Retrieves a pointer to the object of type `T` containing the field pointed to by `field`, which
+/// lies at offset `offset` within `T`.
+///
+/// # Safety
+///
+/// `field` must point into an object of type `T` at offset `offset`.
+unsafe fn container_of<T>(field: *const impl Sized, offset: usize) -> *const T {
+    unsafe { field.cast::<u8>().sub(offset).cast() }
+}
+
+/// Retrieves a pointer to the `$ty` whose `node` field is pointed to by `$node`.
+macro_rules! node_container {
+    ($node:expr, $ty:ident) => {
+        container_of::<$ty>($node, offset_of!($ty, node))
+    };
+}
+
+/// The intrusive tree node embedded in every slice, mapping and reservation, used to link it into
+/// its parent slice's child tree without requiring a separate allocation.
+struct ChildNode {
+    link: AtomicLink,
+    kind: ChildKind,
+    start: VirtPageNum,
+    page_count: usize,
+}
+
+impl ChildNode {
+    fn new(kind: ChildKind, start: VirtPageNum, page_count: usize) -> Self {
+        Self {
+            link: AtomicLink::new(),
+            kind,
+            start,
+            page_count,
         }
     }
 
     fn end(&self) -> VirtPageNum {
-        match self {
-            SliceChild::Subslice(subslice) => subslice.end(),
-            SliceChild::Mapping(mapping) => mapping.end(),
+        self.start + self.page_count
+    }
+
+    /// Retrieves a reference to the child containing this node.
+    fn child(&self) -> SliceChildRef<'_> {
+        // Safety: every node is embedded in an object of the type indicated by its kind.
+        unsafe {
+            match self.kind {
+                ChildKind::Subslice => SliceChildRef::Subslice(&*node_container!(self, Slice)),
+                ChildKind::Mapping => SliceChildRef::Mapping(&*node_container!(self, Mapping)),
+                ChildKind::Reservation => {
+                    SliceChildRef::Reservation(&*node_container!(self, Reservation))
+                }
+            }
         }
     }
 }
 
+/// Pointer operations converting between owned [`SliceChild`]s and their embedded nodes.
+#[derive(Clone, Copy, Default)]
+struct SliceChildPointerOps;
+
+unsafe impl PointerOps for SliceChildPointerOps {
+    type Value = ChildNode;
+    type Pointer = SliceChild;
+
+    unsafe fn from_raw(&self, node: *const ChildNode) -> SliceChild {
+        // Safety: the node was obtained from `into_raw`, which leaked a strong reference to the
+        // containing object, whose type is indicated by the kind.
+        unsafe {
+            match (*node).kind {
+                ChildKind::Subslice => {
+                    SliceChild::Subslice(Arc::from_raw(node_container!(node, Slice)))
+                }
+                ChildKind::Mapping => {
+                    SliceChild::Mapping(Arc::from_raw(node_container!(node, Mapping)))
+                }
+                ChildKind::Reservation => {
+                    SliceChild::Reservation(Arc::from_raw(node_container!(node, Reservation)))
+                }
+            }
+        }
+    }
+
+    fn into_raw(&self, child: SliceChild) -> *const ChildNode {
+        // Safety: `Arc::into_raw` returns a valid pointer to the object.
+        unsafe {
+            match child {
+                SliceChild::Subslice(subslice) => ptr::addr_of!((*Arc::into_raw(subslice)).node),
+                SliceChild::Mapping(mapping) => ptr::addr_of!((*Arc::into_raw(mapping)).node),
+                SliceChild::Reservation(reservation) => {
+                    ptr::addr_of!((*Arc::into_raw(reservation)).node)
+                }
+            }
+        }
+    }
+}
+
+/// Intrusive adapter for slice child trees, keyed by child start address.
+#[derive(Clone, Copy, Default)]
+struct SliceChildAdapter {
+    link_ops: AtomicLinkOps,
+    pointer_ops: SliceChildPointerOps,
+}
+
+impl SliceChildAdapter {
+    const NEW: Self = Self {
+        link_ops: AtomicLinkOps,
+        pointer_ops: SliceChildPointerOps,
+    };
+}
+
+unsafe impl Adapter for SliceChildAdapter {
+    type LinkOps = AtomicLinkOps;
+    type PointerOps = SliceChildPointerOps;
+
+    unsafe fn get_value(&self, link: NonNull<AtomicLink>) -> *const ChildNode {
+        unsafe { container_of::<ChildNode>(link.as_ptr(), offset_of!(ChildNode, link)) }
+    }
+
+    unsafe fn get_link(&self, node: *const ChildNode) -> NonNull<AtomicLink> {
+        unsafe { NonNull::new_unchecked(ptr::addr_of!((*node).link).cast_mut()) }
+    }
+
+    fn link_ops(&self) -> &AtomicLinkOps {
+        &self.link_ops
+    }
+
+    fn link_ops_mut(&mut self) -> &mut AtomicLinkOps {
+        &mut self.link_ops
+    }
+
+    fn pointer_ops(&self) -> &SliceChildPointerOps {
+        &self.pointer_ops
+    }
+}
+
+impl<'a> KeyAdapter<'a> for SliceChildAdapter {
+    type Key = VirtPageNum;
+
+    fn get_key(&self, node: &'a ChildNode) -> VirtPageNum {
+        node.start
+    }
+}
+
 impl From<Arc<Mapping>> for SliceChild {
     fn from(v: Arc<Mapping>) -> Self {
         Self::Mapping(v)
@@ -45,11 +190,26 @@ impl From<Arc<Slice>> for SliceChild {
     }
 }
 
+impl From<Arc<Reservation>> for SliceChild {
+    fn from(v: Arc<Reservation>) -> Self {
+        Self::Reservation(v)
+    }
+}
+
+/// The result of removing a range from a slice with [`Slice::unmap_range`].
+pub struct RangeRemoval {
+    /// The number of pages in the range that were covered by mappings.
+    pub unmapped_pages: usize,
+    /// The new mapping covering the part of a removed mapping preceding the range, if any.
+    pub head: Option<Arc<Mapping>>,
+    /// The new mapping covering the part of a removed mapping following the range, if any.
+    pub tail: Option<Arc<Mapping>>,
+}
+
 /// Represents a slice of an address space.
 pub struct Slice {
     name: Name,
-    start: VirtPageNum,
-    page_count: usize,
+    node: ChildNode,
     inner: QCell<Option<SliceInner>>,
 }
 
@@ -63,13 +223,12 @@ impl Slice {
     ) -> Result<Arc<Self>> {
         let slice = Arc::try_new(Slice {
             name: Name::new(name),
-            start,
-            page_count,
+            node: ChildNode::new(ChildKind::Subslice, start, page_count),
             inner: QCell::new(
                 owner,
                 Some(SliceInner {
                     parent,
-                    children: BTreeMap::new(),
+                    children: RBTree::new(SliceChildAdapter::NEW),
                 }),
             ),
         })?;
@@ -82,15 +241,15 @@ impl Slice {
     }
 
     pub fn start(&self) -> VirtPageNum {
-        self.start
+        self.node.start
     }
 
     pub fn page_count(&self) -> usize {
-        self.page_count
+        self.node.page_count
     }
 
     pub fn end(&self) -> VirtPageNum {
-        self.start + self.page_count
+        self.node.end()
     }
 
     pub fn parent(&self, owner: &QCellOwner) -> Result<Option<Arc<Slice>>> {
@@ -111,12 +270,57 @@ impl Slice {
             let inner = slice.inner(owner)?;
             let child = inner.get_child(vpn).ok_or(Error::BAD_ADDRESS)?;
             match child {
-                SliceChild::Subslice(subslice) => slice = subslice,
-                SliceChild::Mapping(mapping) => return Ok(mapping),
+                SliceChildRef::Subslice(subslice) => slice = subslice,
+                SliceChildRef::Mapping(mapping) => return Ok(mapping),
+                SliceChildRef::Reservation(_) => return Err(Error::BAD_ADDRESS),
             }
         }
     }
 
+    /// Logs the tree of children rooted at this slice, including the names of any mapped objects.
+    pub fn dump(&self, owner: &QCellOwner) -> Result<()> {
+        self.dump_at_depth(owner, 0)
+    }
+
+    fn dump_at_depth(&self, owner: &QCellOwner, depth: usize) -> Result<()> {
+        let indent = 2 * depth;
+        debug!(
+            "{:indent$}slice {}-{}: '{}'",
+            "",
+            self.start(),
+            self.end(),
+            self.name()
+        );
+
+        let child_indent = indent + 2;
+        for node in self.inner(owner)?.children.iter() {
+            match node.child() {
+                SliceChildRef::Subslice(subslice) => subslice.dump_at_depth(owner, depth + 1)?,
+                SliceChildRef::Mapping(mapping) => {
+                    let object_name = mapping.object().name().unwrap_or("<anonymous>");
+                    debug!(
+                        "{:child_indent$}mapping {}-{} -> \"{}\" (offset {:#x})",
+                        "",
+                        mapping.start(),
+                        mapping.end(),
+                        object_name,
+                        mapping.object_offset()
+                    );
+                }
+                SliceChildRef::Reservation(reservation) => {
+                    debug!(
+                        "{:child_indent$}reservation {}-{}",
+                        "",
+                        reservation.start(),
+                        reservation.end()
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Allocates a child of size `page_count` from within this slice, invoking `f` to construct it
     /// once a suitable area has been found.
     ///
@@ -149,34 +353,173 @@ impl Slice {
     pub fn remove_child(&self, owner: &mut QCellOwner, start: VirtPageNum) -> Result<()> {
         self.inner_mut(owner)?
             .children
-            .remove(&start)
+            .find_mut(&start)
+            .remove()
             .expect("no child for provided start address");
         Ok(())
     }
 
+    /// Removes all direct child mappings of `self` intersecting `start..start + page_count`.
+    ///
+    /// Mappings extending past either end of the range are split: the removed mapping is detached
+    /// and replaced with a new mapping covering only the portion outside of the range, which is
+    /// returned in the [`RangeRemoval`]. Parts of the range not covered by any mapping are
+    /// ignored.
+    ///
+    /// The slice is left unchanged if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_STATE` - `self` is detached.
+    /// * `INVALID_ARGUMENT` - The range does not lie within this slice.
+    /// * `RESOURCE_OVERLAP` - The range intersects a subslice or reservation.
+    /// * `OUT_OF_MEMORY` - Allocation of the split mappings failed.
+    pub fn unmap_range(
+        self: &Arc<Self>,
+        owner: &mut QCellOwner,
+        start: VirtPageNum,
+        page_count: usize,
+    ) -> Result<RangeRemoval> {
+        let end = start
+            .checked_add(page_count)
+            .ok_or(Error::INVALID_ARGUMENT)?;
+
+        if start < self.start() || end > self.end() {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let id = owner.id();
+        let mut unmapped_pages = 0;
+        let mut head = None;
+        let mut tail = None;
+
+        if page_count == 0 {
+            // Make sure we're still attached before reporting success.
+            self.inner(owner)?;
+            return Ok(RangeRemoval {
+                unmapped_pages,
+                head,
+                tail,
+            });
+        }
+
+        // First pass: validate the range and allocate any split mappings, so that nothing needs to
+        // be undone if we fail.
+        {
+            let inner = self.inner(owner)?;
+            let mut cursor = inner.children.upper_bound(Bound::Included(&start));
+            if cursor.get().map_or(true, |node| node.end() <= start) {
+                cursor.move_next();
+            }
+
+            while let Some(node) = cursor.get() {
+                if node.start >= end {
+                    break;
+                }
+
+                let SliceChildRef::Mapping(mapping) = node.child() else {
+                    return Err(Error::RESOURCE_OVERLAP);
+                };
+
+                let overlap_start = node.start.max(start);
+                let overlap_end = node.end().min(end);
+                unmapped_pages += overlap_end - overlap_start;
+
+                let prot = mapping.prot(owner)?;
+
+                if mapping.start() < start {
+                    head = Some(Mapping::new(
+                        id,
+                        Arc::clone(self),
+                        mapping.start(),
+                        start - mapping.start(),
+                        Arc::clone(mapping.object()),
+                        mapping.object_offset(),
+                        prot,
+                    )?);
+                }
+
+                if mapping.end() > end {
+                    tail = Some(Mapping::new(
+                        id,
+                        Arc::clone(self),
+                        end,
+                        mapping.end() - end,
+                        Arc::clone(mapping.object()),
+                        mapping.object_offset() + (end - mapping.start()),
+                        prot,
+                    )?);
+                }
+
+                cursor.move_next();
+            }
+        }
+
+        // Second pass: remove and detach the intersecting mappings one at a time, then insert
+        // their remainders.
+        loop {
+            let inner = self.inner_mut(owner)?;
+            let mut cursor = inner.children.upper_bound_mut(Bound::Included(&start));
+            if cursor.get().map_or(true, |node| node.end() <= start) {
+                cursor.move_next();
+            }
+
+            if !cursor.get().map_or(false, |node| node.start < end) {
+                break;
+            }
+
+            let Some(SliceChild::Mapping(mapping)) = cursor.remove() else {
+                unreachable!("range should only intersect mappings");
+            };
+            mapping.detach(owner);
+        }
+
+        let inner = self.inner_mut(owner)?;
+        if let Some(head) = &head {
+            inner.children.insert(Arc::clone(head).into());
+        }
+        if let Some(tail) = &tail {
+            inner.children.insert(Arc::clone(tail).into());
+        }
+
+        Ok(RangeRemoval {
+            unmapped_pages,
+            head,
+            tail,
+        })
+    }
+
     /// Recursively detaches all subslices and of `self`.
     ///
-    /// When this operation completes, `self` will be in the detached state.
+    /// When this operation completes, `self` will be in the detached state. Returns the total
+    /// number of pages spanned by the mappings that were removed.
     ///
     /// # Panics
     ///
     /// Panics if `self` is already detached.
-    pub fn detach_children(self: &Arc<Self>, owner: &mut QCellOwner) {
+    pub fn detach_children(self: &Arc<Self>, owner: &mut QCellOwner) -> usize {
         let mut cur = Arc::clone(self);
+        let mut mapped_pages = 0;
 
         loop {
             let first_child = cur
                 .inner_mut(owner)
                 .expect("current slice should still be attached")
                 .children
-                .pop_first();
+                .front_mut()
+                .remove();
 
-            if let Some((_, child)) = first_child {
+            if let Some(child) = first_child {
                 match child {
                     SliceChild::Subslice(subslice) => {
                         cur = subslice;
                     }
-                    SliceChild::Mapping(_) => {}
+                    SliceChild::Reservation(reservation) => {
+                        reservation.inner.rw(owner).take();
+                    }
+                    SliceChild::Mapping(mapping) => {
+                        mapped_pages += mapping.page_count();
+                    }
                 }
             } else {
                 // Now that we've finished detaching children, mark the current slice as detached
@@ -195,6 +538,8 @@ impl Slice {
                 }
             }
         }
+
+        mapped_pages
     }
 
     /// Allocates a child of size `page_count` from within this slice, invoking `f` to construct it
@@ -206,11 +551,9 @@ impl Slice {
         page_count: usize,
         f: impl FnOnce(VirtPageNum) -> Result<C>,
     ) -> Result<C> {
-        let align = 1usize << align_order;
-
         let gap_start = self
             .iter_gaps(owner, |gap_start, gap_page_count| {
-                let aligned_gap_start = gap_start.align_up(align);
+                let aligned_gap_start = gap_start.align_up_order(align_order);
                 let gap_padding = aligned_gap_start - gap_start;
                 let aligned_page_count = gap_page_count - gap_padding;
 
@@ -226,7 +569,7 @@ impl Slice {
         self.inner_mut(owner)
             .expect("slice should still be attached")
             .children
-            .insert(gap_start, child.clone().into());
+            .insert(child.clone().into());
 
         Ok(child)
     }
@@ -244,25 +587,25 @@ impl Slice {
             .checked_add(page_count)
             .ok_or(Error::INVALID_ARGUMENT)?;
 
-        if start < self.start || end > self.end() {
+        if start < self.start() || end > self.end() {
             return Err(Error::INVALID_ARGUMENT);
         }
 
         let inner = self.inner_mut(owner)?;
 
-        if let Some((_, prev)) = inner.children.range(..start).next_back() {
+        if let Some(prev) = inner.children.upper_bound(Bound::Excluded(&start)).get() {
             if prev.end() > start {
                 return Err(Error::RESOURCE_OVERLAP);
             }
         }
 
-        if let Some((_, next)) = inner.children.range(start..).next() {
-            if end > next.start() {
+        if let Some(next) = inner.children.lower_bound(Bound::Included(&start)).get() {
+            if end > next.start {
                 return Err(Error::RESOURCE_OVERLAP);
             }
         }
 
-        inner.children.insert(start, child);
+        inner.children.insert(child);
         Ok(())
     }
 
@@ -280,8 +623,8 @@ impl Slice {
 
         let mut iter = inner.children.iter();
 
-        let Some((&first_start, first)) = iter.next() else {
-            let retval = match f(self.start, self.page_count) {
+        let Some(first) = iter.next() else {
+            let retval = match f(self.start(), self.page_count()) {
                 ControlFlow::Break(val) => Some(val),
                 ControlFlow::Continue(_) => None,
             };
@@ -289,17 +632,17 @@ impl Slice {
             return Ok(retval);
         };
 
-        if self.start < first_start {
-            if let ControlFlow::Break(val) = f(self.start, first_start - self.start) {
+        if self.start() < first.start {
+            if let ControlFlow::Break(val) = f(self.start(), first.start - self.start()) {
                 return Ok(Some(val));
             }
         }
 
         let mut last_end = first.end();
 
-        for (&cur_start, cur) in iter {
-            if last_end < cur_start {
-                if let ControlFlow::Break(val) = f(last_end, cur_start - last_end) {
+        for cur in iter {
+            if last_end < cur.start {
+                if let ControlFlow::Break(val) = f(last_end, cur.start - last_end) {
                     return Ok(Some(val));
                 }
             }
@@ -317,7 +660,7 @@ impl Slice {
 
     /// Checks that `vpn` lies within this slice's range, returning `BAD_ADDRESS` if it does not.
     fn check_vpn(&self, vpn: VirtPageNum) -> Result<()> {
-        if (self.start..self.end()).contains(&vpn) {
+        if (self.start()..self.end()).contains(&vpn) {
             Ok(())
         } else {
             Err(Error::BAD_ADDRESS)
@@ -335,8 +678,7 @@ impl Slice {
 
 /// Represents a mapping of a VM object in an address space.
 pub struct Mapping {
-    start: VirtPageNum,
-    page_count: usize,
+    node: ChildNode,
     object_offset: usize,
     object: Arc<dyn VmObject>,
     inner: QCell<Option<MappingInner>>,
@@ -353,8 +695,7 @@ impl Mapping {
         prot: Protection,
     ) -> Result<Arc<Self>> {
         let mapping = Arc::try_new(Mapping {
-            start,
-            page_count,
+            node: ChildNode::new(ChildKind::Mapping, start, page_count),
             object_offset,
             object,
             inner: QCell::new(owner, Some(MappingInner { parent, prot })),
@@ -363,15 +704,15 @@ impl Mapping {
     }
 
     pub fn start(&self) -> VirtPageNum {
-        self.start
+        self.node.start
     }
 
     pub fn page_count(&self) -> usize {
-        self.page_count
+        self.node.page_count
     }
 
     pub fn end(&self) -> VirtPageNum {
-        self.start + self.page_count
+        self.node.end()
     }
 
     pub fn object_offset(&self) -> usize {
@@ -390,26 +731,74 @@ impl Mapping {
         self.inner(owner).map(|inner| inner.prot)
     }
 
+    /// Marks this mapping as detached, after it has been removed from its parent.
+    pub fn detach(&self, owner: &mut QCellOwner) {
+        self.inner.rw(owner).take();
+    }
+
     fn inner<'a>(&'a self, owner: &'a QCellOwner) -> Result<&'a MappingInner> {
         self.inner.ro(owner).as_ref().ok_or(Error::INVALID_STATE)
     }
 }
 
+/// Represents a reserved range of an address space, which has no backing object or page table
+/// entries.
+pub struct Reservation {
+    node: ChildNode,
+    inner: QCell<Option<ReservationInner>>,
+}
+
+impl Reservation {
+    pub fn new(
+        owner: QCellOwnerID,
+        parent: Arc<Slice>,
+        start: VirtPageNum,
+        page_count: usize,
+    ) -> Result<Arc<Self>> {
+        let reservation = Arc::try_new(Reservation {
+            node: ChildNode::new(ChildKind::Reservation, start, page_count),
+            inner: QCell::new(owner, Some(ReservationInner { parent })),
+        })?;
+        Ok(reservation)
+    }
+
+    pub fn start(&self) -> VirtPageNum {
+        self.node.start
+    }
+
+    pub fn end(&self) -> VirtPageNum {
+        self.node.end()
+    }
+
+    pub fn parent(&self, owner: &QCellOwner) -> Result<Arc<Slice>> {
+        Ok(Arc::clone(&self.inner(owner)?.parent))
+    }
+
+    /// Marks this reservation as detached, after it has been removed from its parent.
+    pub fn detach(&self, owner: &mut QCellOwner) {
+        self.inner.rw(owner).take();
+    }
+
+    fn inner<'a>(&'a self, owner: &'a QCellOwner) -> Result<&'a ReservationInner> {
+        self.inner.ro(owner).as_ref().ok_or(Error::INVALID_STATE)
+    }
+}
+
 struct SliceInner {
     // This apparent cycle is broken by calls to `detach_children`, which guarantee that this whole
     // inner structure is destroyed when appropriate.
     parent: Option<Arc<Slice>>,
-    children: BTreeMap<VirtPageNum, SliceChild>,
+    children: RBTree<SliceChildAdapter>,
 }
 
 impl SliceInner {
     /// Retrives the direct child of `self` containing `vpn`, if one exists.
-    fn get_child(&self, vpn: VirtPageNum) -> Option<&SliceChild> {
+    fn get_child(&self, vpn: VirtPageNum) -> Option<SliceChildRef<'_>> {
         self.children
-            .range(..vpn)
-            .next_back()
-            .map(|(_, child)| child)
-            .filter(|child| vpn < child.end())
+            .upper_bound(Bound::Included(&vpn))
+            .get()
+            .filter(|node| vpn < node.end())
+            .map(|node| node.child())
     }
 }
 
@@ -419,3 +808,85 @@ struct MappingInner {
     parent: Arc<Slice>,
     prot: Protection,
 }
+
+struct ReservationInner {
+    parent: Arc<Slice>,
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn reserve(
+        owner: &mut QCellOwner,
+        slice: &Arc<Slice>,
+        base: MapBase,
+        page_count: usize,
+    ) -> Result<Arc<Reservation>> {
+        let id = owner.id();
+        slice.alloc_spot(owner, base, page_count, |start| {
+            Reservation::new(id, Arc::clone(slice), start, page_count)
+        })
+    }
+
+    fn child_start(owner: &QCellOwner, slice: &Slice, vpn: VirtPageNum) -> Option<VirtPageNum> {
+        match slice.inner(owner).unwrap().get_child(vpn)? {
+            SliceChildRef::Subslice(subslice) => Some(subslice.start()),
+            SliceChildRef::Mapping(mapping) => Some(mapping.start()),
+            SliceChildRef::Reservation(reservation) => Some(reservation.start()),
+        }
+    }
+
+    fn gaps(owner: &QCellOwner, slice: &Slice) -> Vec<(VirtPageNum, usize)> {
+        let mut gaps = Vec::new();
+        slice
+            .iter_gaps::<()>(owner, |start, page_count| {
+                gaps.push((start, page_count));
+                ControlFlow::Continue(())
+            })
+            .unwrap();
+        gaps
+    }
+
+    #[test_case]
+    fn slice_children_ordered_lookup() {
+        let mut owner = QCellOwner::new();
+        let base = VirtPageNum::new(0x1000);
+        let slice = Slice::new(owner.id(), None, "test", base, 0x100).unwrap();
+
+        reserve(&mut owner, &slice, MapBase::Fixed(base + 0x40), 0x10).unwrap();
+        reserve(&mut owner, &slice, MapBase::Fixed(base + 0x10), 0x10).unwrap();
+
+        for (start, page_count) in [(0x18, 0x4), (0x8, 0x10), (0x3f, 0x2), (0x10, 0x1)] {
+            assert_eq!(
+                reserve(&mut owner, &slice, MapBase::Fixed(base + start), page_count).err(),
+                Some(Error::RESOURCE_OVERLAP)
+            );
+        }
+
+        reserve(&mut owner, &slice, MapBase::Fixed(base + 0x20), 0x20).unwrap();
+
+        assert_eq!(child_start(&owner, &slice, base + 0xf), None);
+        assert_eq!(child_start(&owner, &slice, base + 0x10), Some(base + 0x10));
+        assert_eq!(child_start(&owner, &slice, base + 0x1f), Some(base + 0x10));
+        assert_eq!(child_start(&owner, &slice, base + 0x20), Some(base + 0x20));
+        assert_eq!(child_start(&owner, &slice, base + 0x4f), Some(base + 0x40));
+        assert_eq!(child_start(&owner, &slice, base + 0x50), None);
+
+        assert_eq!(gaps(&owner, &slice), [(base, 0x10), (base + 0x50, 0xb0)]);
+
+        let dynamic = reserve(&mut owner, &slice, MapBase::any(), 0x8).unwrap();
+        assert_eq!(dynamic.start(), base);
+
+        slice.remove_child(&mut owner, base + 0x20).unwrap();
+        assert_eq!(child_start(&owner, &slice, base + 0x20), None);
+        assert_eq!(
+            gaps(&owner, &slice),
+            [(base + 0x8, 0x8), (base + 0x20, 0x20), (base + 0x50, 0xb0)]
+        );
+
+        slice.detach_children(&mut owner);
+    }
+}
@@ -18,7 +18,7 @@ use super::aspace::{AddrSpace, AddrSpaceOps, MapBase, TlbFlush};
 /// # Panics
 ///
 /// Panics if [`init`] has not yet been called.
-pub fn get() -> &'static AddrSpace<impl AddrSpaceOps> {
+pub(super) fn get() -> &'static AddrSpace<KernelAddrSpaceOps> {
     KERNEL_ASPACE
         .get()
         .expect("kernel address space not initialized")
@@ -94,7 +94,8 @@ unsafe fn protect_kimage() {
     }
 }
 
-struct KernelAddrSpaceOps;
+/// The [`AddrSpaceOps`] implementation backing the global kernel address space.
+pub(super) struct KernelAddrSpaceOps;
 
 unsafe impl AddrSpaceOps for KernelAddrSpaceOps {
     fn root_pt(&self) -> PhysFrameNum {
@@ -125,3 +126,36 @@ unsafe impl AddrSpaceOps for KernelAddrSpaceOps {
 }
 
 static KERNEL_ASPACE: Once<AddrSpace<KernelAddrSpaceOps>> = Once::new();
+
+#[cfg(test)]
+mod tests {
+    use crate::mm::types::Protection;
+    use crate::mm::vm;
+    use crate::mm::vm::object::EagerVmObject;
+
+    use super::*;
+
+    #[test_case]
+    fn kernel_aspace_maps_object() {
+        let aspace = vm::kernel_aspace();
+
+        let object = EagerVmObject::new_named("test", 2).unwrap();
+        let mapping = aspace
+            .map_committed(
+                aspace.root_slice(),
+                MapBase::any(),
+                2,
+                0,
+                object,
+                Protection::READ | Protection::WRITE,
+            )
+            .unwrap();
+
+        let ptr: *mut u64 = mapping.start().addr().as_mut_ptr();
+        unsafe {
+            ptr.write_volatile(0x1234);
+            assert_eq!(ptr.read_volatile(), 0x1234);
+            aspace.unmap(&mapping).unwrap();
+        }
+    }
+}
@@ -1,22 +1,20 @@
-use core::{ptr, slice};
+use core::ptr;
 
 use alloc::sync::Arc;
 
 use crate::arch::mm::{LOW_ASPACE_BASE, LOW_ASPACE_END};
 use crate::arch::mmu::{
-    flush_low_tlb, flush_low_tlb_page, prepare_low_pt_root, set_low_root_pt, PT_ENTRY_COUNT,
+    flush_low_tlb, flush_low_tlb_page, prepare_low_pt_root, set_low_root_pt, PageTableSpace,
 };
 use crate::err::Result;
-use crate::mm::physmap::pfn_to_physmap;
-use crate::mm::pmm::FrameBox;
-use crate::mm::pt::clear_page_table;
+use crate::mm::pmm::PhysBox;
 use crate::mm::types::{AccessMode, PageTablePerms, PhysFrameNum};
 use crate::sync::resched::ReschedDisabled;
 
 use super::aspace::{AddrSpace, AddrSpaceOps, TlbFlush};
 
 pub struct LowAddrSpaceOps {
-    root_pt: FrameBox,
+    root_pt: PhysBox<PageTableSpace>,
     allowed_access_mode: AccessMode,
 }
 
@@ -78,15 +76,13 @@ fn raw_aspace_ptr(aspace: Option<&LowAddrSpace>) -> *const LowAddrSpace {
     aspace.map_or(ptr::null(), |p| p)
 }
 
-fn make_root_pt() -> Result<FrameBox> {
-    let root_pt = FrameBox::new()?;
-    let root_pt_addr = pfn_to_physmap(root_pt.pfn()).addr();
+fn make_root_pt() -> Result<PhysBox<PageTableSpace>> {
+    let mut root_pt = PhysBox::new(PageTableSpace::new())?;
 
-    // Safety: we own the page table as we have just allocated it, and it contains sufficient space.
+    // Safety: the top-level kernel page table is never modified once the memory manager has been
+    // initialized, which must happen before any low address spaces are created.
     unsafe {
-        clear_page_table(root_pt_addr.as_mut_ptr());
-        let root_pt_slice = slice::from_raw_parts_mut(root_pt_addr.as_mut_ptr(), PT_ENTRY_COUNT);
-        prepare_low_pt_root(root_pt_slice);
+        prepare_low_pt_root(root_pt.entries_mut());
     }
 
     Ok(root_pt)
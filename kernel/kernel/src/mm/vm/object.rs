@@ -1,8 +1,12 @@
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+#[cfg(test)]
+use core::mem;
 
-use crate::err::Result;
-use crate::mm::pmm::FrameBox;
+use object_name::Name;
+
+use crate::err::{Error, Result};
+use crate::mm::pmm::{self, FrameBox};
 use crate::mm::types::{CacheMode, PhysFrameNum};
 use crate::sync::SpinLock;
 
@@ -17,6 +21,11 @@ pub enum CommitType {
 
 /// A virtual memory object that can be mapped into an address space.
 ///
+/// Objects can be mapped multiple times, possibly into different address spaces, in which case
+/// [`provide_page`](VmObject::provide_page) may be called concurrently from all of them.
+/// Implementations should return the same frame for every request of a given offset, so that all
+/// mappings share the object's memory.
+///
 /// # Safety
 ///
 /// * The implementation of [`provide_page`](VmObject::provide_page) must return a frame that can be
@@ -40,6 +49,26 @@ pub unsafe trait VmObject: Send + Sync {
     fn cache_mode(&self) -> CacheMode {
         CacheMode::Cached
     }
+
+    /// Returns a hint indicating how many pages around a faulting page should be committed along
+    /// with it, as a power of two.
+    ///
+    /// When a page fault is handled, the naturally aligned window of `2 ** order` pages containing
+    /// the faulting page (clamped to the bounds of the mapping) will be committed at once.
+    /// Objects expecting sequential access patterns can return a nonzero value here to reduce the
+    /// number of faults taken.
+    ///
+    /// By default, returns 0, meaning that only the faulting page will be committed.
+    fn fault_around_order(&self) -> usize {
+        0
+    }
+
+    /// Returns a descriptive name for this object, for use in diagnostics.
+    ///
+    /// By default, objects are unnamed.
+    fn name(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// A VM object that allocates all of its backing page frames upon construction.
@@ -48,11 +77,22 @@ pub unsafe trait VmObject: Send + Sync {
 /// immediately after being mapped (as is the case for kernel mappings), as it will use less memory
 /// for redundant metadata.
 pub struct EagerVmObject {
+    name: Option<Name>,
     frames: Vec<FrameBox>,
 }
 
 impl EagerVmObject {
+    /// Creates a new unnamed object.
     pub fn new(page_count: usize) -> Result<Arc<Self>> {
+        Self::new_with_name(None, page_count)
+    }
+
+    /// Creates a new object named `name`, as reported by [`VmObject::name`].
+    pub fn new_named(name: &str, page_count: usize) -> Result<Arc<Self>> {
+        Self::new_with_name(Some(Name::new(name)), page_count)
+    }
+
+    fn new_with_name(name: Option<Name>, page_count: usize) -> Result<Arc<Self>> {
         let mut frames = Vec::new();
         frames.try_reserve_exact(page_count)?;
 
@@ -61,7 +101,7 @@ impl EagerVmObject {
             frames.push(FrameBox::new()?);
         }
 
-        Ok(Arc::try_new(Self { frames })?)
+        Ok(Arc::try_new(Self { name, frames })?)
     }
 }
 
@@ -73,6 +113,10 @@ unsafe impl VmObject for EagerVmObject {
     fn provide_page(&self, offset: usize, _commit_type: CommitType) -> Result<PhysFrameNum> {
         Ok(self.frames[offset].pfn())
     }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.as_ref())
+    }
 }
 
 /// A VM object that lazily allocates its backing page frames as they are requested.
@@ -81,6 +125,7 @@ unsafe impl VmObject for EagerVmObject {
 /// all kernel mappings), prefer [`EagerVmObject`], as it will behave identically but use less
 /// memory for bookkeeping.
 pub struct LazyVmObject {
+    name: Option<Name>,
     page_count: usize,
     // TODO: maybe not a spinlock?
     frames: SpinLock<Vec<Option<FrameBox>>>,
@@ -88,6 +133,15 @@ pub struct LazyVmObject {
 
 impl LazyVmObject {
     pub fn new(page_count: usize) -> Result<Arc<Self>> {
+        Self::new_with_name(None, page_count)
+    }
+
+    /// Creates a new object named `name`, as reported by [`VmObject::name`].
+    pub fn new_named(name: &str, page_count: usize) -> Result<Arc<Self>> {
+        Self::new_with_name(Some(Name::new(name)), page_count)
+    }
+
+    fn new_with_name(name: Option<Name>, page_count: usize) -> Result<Arc<Self>> {
         let mut frames = Vec::new();
         frames.try_reserve_exact(page_count)?;
 
@@ -97,6 +151,7 @@ impl LazyVmObject {
         }
 
         Ok(Arc::try_new(Self {
+            name,
             page_count,
             frames: SpinLock::new(frames),
         })?)
@@ -123,26 +178,81 @@ unsafe impl VmObject for LazyVmObject {
             Ok(frame)
         })
     }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| name.as_ref())
+    }
+}
+
+/// A VM object whose backing page frames are allocated as a single physically contiguous range upon
+/// construction.
+///
+/// This is useful for buffers that will be accessed by devices (such as DMA buffers); for other
+/// uses, prefer [`EagerVmObject`], which does not require any contiguous memory to be available.
+pub struct ContiguousVmObject {
+    base: PhysFrameNum,
+    page_count: usize,
+}
+
+impl ContiguousVmObject {
+    pub fn new(page_count: usize) -> Result<Arc<Self>> {
+        if page_count == 0 {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        let base = pmm::allocate_contiguous(page_count).ok_or(Error::OUT_OF_MEMORY)?;
+        let object = Self { base, page_count };
+
+        // Note: if this fails, `object` will be dropped and the frames freed.
+        Ok(Arc::try_new(object)?)
+    }
+
+    /// Returns the physical base of the object's backing memory.
+    pub fn base(&self) -> PhysFrameNum {
+        self.base
+    }
+}
+
+impl Drop for ContiguousVmObject {
+    fn drop(&mut self) {
+        unsafe { pmm::deallocate_contiguous(self.base, self.page_count) }
+    }
+}
+
+unsafe impl VmObject for ContiguousVmObject {
+    fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    fn provide_page(&self, offset: usize, _commit_type: CommitType) -> Result<PhysFrameNum> {
+        assert!(offset < self.page_count);
+        Ok(self.base + offset)
+    }
 }
 
 /// A VM object backed by a contiguous range of physical memory.
 pub struct PhysVmObject {
+    name: Name,
     base: PhysFrameNum,
     page_count: usize,
     cache_mode: CacheMode,
 }
 
 impl PhysVmObject {
+    /// Creates a new object named `name`, as reported by [`VmObject::name`].
+    ///
     /// # Safety
     ///
     /// The caller must guarantee that the specified range of physical memory is safe to access with
     /// the specified cache mode.
-    pub unsafe fn new(
+    pub unsafe fn new_named(
+        name: &str,
         base: PhysFrameNum,
         page_count: usize,
         cache_mode: CacheMode,
     ) -> Result<Arc<Self>> {
         Ok(Arc::try_new(Self {
+            name: Name::new(name),
             base,
             page_count,
             cache_mode,
@@ -163,4 +273,118 @@ unsafe impl VmObject for PhysVmObject {
     fn cache_mode(&self) -> CacheMode {
         self.cache_mode
     }
+
+    fn name(&self) -> Option<&str> {
+        Some(self.name.as_ref())
+    }
+}
+
+/// A test-only VM object that obtains its frames from a caller-provided function, recording every
+/// request made of it.
+///
+/// This allows tests to check exactly which pages the commit and fault machinery asks for.
+#[cfg(test)]
+pub struct FnVmObject<F> {
+    page_count: usize,
+    fault_around_order: usize,
+    provide: F,
+    requests: SpinLock<Vec<(usize, CommitType)>>,
+}
+
+#[cfg(test)]
+impl<F> FnVmObject<F>
+where
+    F: Fn(usize, CommitType) -> Result<PhysFrameNum> + Send + Sync,
+{
+    /// Creates a new object spanning `page_count` pages, whose pages are provided by `provide` and
+    /// which reports a fault-around order of `fault_around_order`.
+    pub fn new(page_count: usize, fault_around_order: usize, provide: F) -> Result<Arc<Self>> {
+        Ok(Arc::try_new(Self {
+            page_count,
+            fault_around_order,
+            provide,
+            requests: SpinLock::new(Vec::new()),
+        })?)
+    }
+
+    /// Returns the `(offset, commit_type)` pairs requested from this object since the last call,
+    /// in the order they were made.
+    pub fn take_requests(&self) -> Vec<(usize, CommitType)> {
+        self.requests.with(|requests, _| mem::take(requests))
+    }
+}
+
+#[cfg(test)]
+unsafe impl<F> VmObject for FnVmObject<F>
+where
+    F: Fn(usize, CommitType) -> Result<PhysFrameNum> + Send + Sync,
+{
+    fn page_count(&self) -> usize {
+        self.page_count
+    }
+
+    fn provide_page(&self, offset: usize, commit_type: CommitType) -> Result<PhysFrameNum> {
+        self.requests.with(|requests, _| -> Result<()> {
+            requests.try_reserve(1)?;
+            requests.push((offset, commit_type));
+            Ok(())
+        })?;
+
+        (self.provide)(offset, commit_type)
+    }
+
+    fn fault_around_order(&self) -> usize {
+        self.fault_around_order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mm::heap;
+
+    use super::*;
+
+    #[test_case]
+    fn eager_object_creation_survives_injected_failures() {
+        let free_pages = pmm::free_page_count();
+
+        // Fail the second frame allocation, after one frame has already been allocated.
+        pmm::fail_nth_allocation(1);
+        assert_eq!(EagerVmObject::new(3).err(), Some(Error::OUT_OF_MEMORY));
+        pmm::clear_allocation_failure();
+        assert_eq!(pmm::free_page_count(), free_pages);
+
+        // Fail the initial reservation of the frame vector.
+        heap::fail_nth_allocation(0);
+        assert_eq!(EagerVmObject::new(3).err(), Some(Error::OUT_OF_MEMORY));
+        heap::clear_allocation_failure();
+        assert_eq!(pmm::free_page_count(), free_pages);
+
+        assert!(EagerVmObject::new(3).is_ok());
+    }
+
+    #[test_case]
+    fn contiguous_object_pages_are_contiguous() {
+        let object = ContiguousVmObject::new(5).expect("out of memory");
+
+        let base = object.base();
+        assert_eq!(object.provide_page(0, CommitType::Read), Ok(base));
+        for offset in 1..object.page_count() {
+            let pfn = object.provide_page(offset, CommitType::Write).unwrap();
+            assert_eq!(pfn, base + offset);
+        }
+    }
+
+    #[test_case]
+    fn object_names() {
+        assert_eq!(EagerVmObject::new(1).unwrap().name(), None);
+        assert_eq!(
+            EagerVmObject::new_named("eager", 1).unwrap().name(),
+            Some("eager")
+        );
+        assert_eq!(
+            LazyVmObject::new_named("lazy", 1).unwrap().name(),
+            Some("lazy")
+        );
+    }
 }
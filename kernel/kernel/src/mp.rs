@@ -1,20 +1,135 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
 use spin_once::TakeOnce;
 
+use crate::deferred::DeferredQueue;
 use crate::sync::irq::IrqDisabled;
-use crate::sync::resched::ReschedDisabled;
+use crate::sync::lockrank::HeldLockRanks;
+use crate::sync::resched::{ReschedDisabled, ReschedGuard};
 use crate::{arch, sched};
 
+/// The maximum number of cores supported by the kernel.
+pub const MAX_CPUS: usize = 64;
+
+const HW_ID_NONE: u32 = u32::MAX;
+
+/// Maps dense CPU numbers to the hardware IDs of the corresponding cores.
+static CPU_HW_IDS: [AtomicU32; MAX_CPUS] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const NONE: AtomicU32 = AtomicU32::new(HW_ID_NONE);
+    [NONE; MAX_CPUS]
+};
+static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A set of CPUs, identified by their dense CPU numbers.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct CpuMask(u64);
+
+// Every CPU number must be representable in the mask.
+const _: () = assert!(MAX_CPUS <= u64::BITS as usize);
+
+impl CpuMask {
+    /// The mask containing every CPU.
+    pub const ALL: Self = Self(u64::MAX);
+
+    /// The mask containing no CPUs.
+    pub const EMPTY: Self = Self(0);
+
+    /// Creates a mask from its raw bit representation, in which bit `n` corresponds to CPU `n`.
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw bit representation of the mask.
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Creates a mask containing only `cpu_num`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cpu_num` is not smaller than [`MAX_CPUS`].
+    pub const fn single(cpu_num: u32) -> Self {
+        assert!((cpu_num as usize) < MAX_CPUS, "cpu number out of range");
+        Self(1 << cpu_num)
+    }
+
+    /// Returns a mask containing only the CPUs that have been brought up.
+    pub fn online() -> Self {
+        let count = cpu_count();
+        if count >= MAX_CPUS {
+            Self::ALL
+        } else {
+            Self((1 << count) - 1)
+        }
+    }
+
+    /// Checks whether `cpu_num` is in the mask.
+    pub const fn contains(self, cpu_num: u32) -> bool {
+        (cpu_num as usize) < MAX_CPUS && self.0 & (1 << cpu_num) != 0
+    }
+
+    /// Checks whether the mask contains no CPUs.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the CPUs contained in both `self` and `other`.
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the lowest-numbered CPU in the mask, if any.
+    pub const fn first(self) -> Option<u32> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.0.trailing_zeros())
+        }
+    }
+}
+
+impl fmt::Debug for CpuMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CpuMask({:#x})", self.0)
+    }
+}
+
 #[repr(align(64))]
 pub struct PerCpu {
     pub cpu_num: u32,
+    pub hw_id: u32,
     pub sched: sched::CpuState,
+    pub lock_ranks: HeldLockRanks,
+    pub deferred: DeferredQueue,
+    nmi_dump_requested: AtomicBool,
 }
 
 impl PerCpu {
-    fn new(cpu_num: u32) -> Self {
+    fn new(cpu_num: u32, hw_id: u32) -> Self {
         Self {
             cpu_num,
-            sched: sched::CpuState::new(),
+            hw_id,
+            sched: sched::CpuState::new(cpu_num),
+            lock_ranks: HeldLockRanks::new(),
+            deferred: DeferredQueue::new(),
+            nmi_dump_requested: AtomicBool::new(false),
+        }
+    }
+
+    /// Asserts (in debug builds) that this structure belongs to the core we are currently running
+    /// on.
+    #[track_caller]
+    pub fn debug_assert_owned(&self) {
+        if cfg!(debug_assertions) {
+            let hw_id = arch::cpu::current_percpu_hw_id();
+            assert_eq!(
+                self.hw_id, hw_id,
+                "per-CPU state of cpu {} accessed from hardware id {}",
+                self.cpu_num, hw_id
+            );
         }
     }
 }
@@ -24,6 +139,62 @@ pub fn current_percpu(_resched_disabled: &ReschedDisabled) -> &PerCpu {
     unsafe { &*arch::cpu::current_percpu().cast() }
 }
 
+/// Returns the dense index of the current core, assigned when it was brought up.
+///
+/// CPU numbers are stable for the lifetime of the system and lie in the range `0..cpu_count()`,
+/// with the bootstrap processor always numbered 0.
+pub fn current_cpu_id(resched_disabled: &ReschedDisabled) -> u32 {
+    current_percpu(resched_disabled).cpu_num
+}
+
+/// Returns the number of cores that have been brought up.
+pub fn cpu_count() -> usize {
+    CPU_COUNT.load(Ordering::Acquire)
+}
+
+/// Returns the dense CPU number assigned to the core with hardware ID `hw_id`, if it has been
+/// brought up.
+pub fn cpu_id_from_hw_id(hw_id: u32) -> Option<u32> {
+    CPU_HW_IDS[..cpu_count().min(MAX_CPUS)]
+        .iter()
+        .position(|id| id.load(Ordering::Acquire) == hw_id)
+        .map(|cpu_num| cpu_num as u32)
+}
+
+/// Assigns the next dense CPU number to the core with hardware ID `hw_id`.
+fn register_cpu(hw_id: u32) -> u32 {
+    assert!(cpu_id_from_hw_id(hw_id).is_none(), "cpu registered twice");
+
+    let cpu_num = CPU_COUNT.fetch_add(1, Ordering::AcqRel);
+    assert!(cpu_num < MAX_CPUS, "too many cpus");
+    CPU_HW_IDS[cpu_num].store(hw_id, Ordering::Release);
+
+    cpu_num as u32
+}
+
+/// Requests that all cores dump their register state and current thread to the serial console, by
+/// sending each of them an NMI.
+///
+/// Only the bootstrap processor is currently brought up, so this just dumps the state of the current
+/// core, returning once it has done so.
+pub fn broadcast_nmi_dump() {
+    let resched_guard = ReschedGuard::new();
+    current_percpu(&resched_guard)
+        .nmi_dump_requested
+        .store(true, Ordering::Release);
+    arch::cpu::send_self_nmi();
+}
+
+/// Checks whether a state dump was requested on the current core by [`broadcast_nmi_dump`],
+/// clearing the request.
+///
+/// This should be called by the architecture-specific NMI handler.
+pub fn take_nmi_dump_request(resched_disabled: &ReschedDisabled) -> bool {
+    current_percpu(resched_disabled)
+        .nmi_dump_requested
+        .swap(false, Ordering::AcqRel)
+}
+
 /// Performs early initialization of the bootstrap processor (BSP), including early interrupt
 /// handlers and per-CPU data.
 ///
@@ -35,8 +206,12 @@ pub fn current_percpu(_resched_disabled: &ReschedDisabled) -> &PerCpu {
 /// * This function must be called only once on the BSP.
 pub unsafe fn init_bsp_early(irq_disabled: &IrqDisabled) {
     static BSP_PERCPU: TakeOnce<PerCpu> = TakeOnce::new();
+
+    let hw_id = arch::cpu::current_hw_id();
+    let cpu_num = register_cpu(hw_id);
+
     let percpu = BSP_PERCPU
-        .take_init(PerCpu::new(0))
+        .take_init(PerCpu::new(cpu_num, hw_id))
         .expect("BSP percpu already initialized");
 
     unsafe {
@@ -49,3 +224,49 @@ fn percpu_must_be_sync(p: &PerCpu) {
     fn requires_sync(_s: &impl Sync) {}
     requires_sync(p)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn self_nmi_dump_is_handled() {
+        broadcast_nmi_dump();
+
+        // The NMI handler should have consumed the request before `broadcast_nmi_dump` returned.
+        assert!(!take_nmi_dump_request(&ReschedGuard::new()));
+    }
+
+    #[test_case]
+    fn cpu_mask_operations() {
+        let mask = CpuMask::single(0).bits() | CpuMask::single(5).bits();
+        let mask = CpuMask::from_bits(mask);
+
+        assert!(mask.contains(0));
+        assert!(mask.contains(5));
+        assert!(!mask.contains(1));
+        assert!(!mask.contains(MAX_CPUS as u32));
+        assert_eq!(mask.first(), Some(0));
+        assert_eq!(mask.intersection(CpuMask::single(5)).first(), Some(5));
+        assert!(mask.intersection(CpuMask::single(3)).is_empty());
+        assert_eq!(CpuMask::EMPTY.first(), None);
+
+        assert!(CpuMask::online().contains(0));
+        assert!(!CpuMask::online().contains(cpu_count() as u32));
+    }
+
+    #[test_case]
+    fn bsp_has_dense_cpu_id() {
+        let resched_guard = ReschedGuard::new();
+        let percpu = current_percpu(&resched_guard);
+        percpu.debug_assert_owned();
+
+        assert_eq!(current_cpu_id(&resched_guard), 0);
+        assert_eq!(arch::cpu::current_percpu_hw_id(), percpu.hw_id);
+        assert!(cpu_count() >= 1);
+        assert_eq!(
+            cpu_id_from_hw_id(arch::cpu::current_hw_id()),
+            Some(current_cpu_id(&resched_guard))
+        );
+    }
+}
@@ -0,0 +1,44 @@
+//! A global interning table for object names, allowing repeated names (such as those of threads and
+//! address spaces) to be referred to by compact ids in logs.
+
+use object_name::{Name, NameId, NameTable};
+use spin_once::Once;
+
+use crate::sync::SpinLock;
+
+/// The maximum number of distinct names that can be interned.
+pub const MAX_INTERNED_NAMES: usize = 128;
+
+/// Returns the id of `name`, interning it if necessary.
+///
+/// Returns `None` if `name` has not been interned before and the table is already full.
+pub fn intern(name: &Name) -> Option<NameId> {
+    table().with(|table, _| table.intern(name))
+}
+
+/// Returns the name corresponding to `id`, if it was previously returned by [`intern`].
+pub fn resolve(id: NameId) -> Option<Name> {
+    table().with(|table, _| table.resolve(id).copied())
+}
+
+fn table() -> &'static SpinLock<NameTable<MAX_INTERNED_NAMES>> {
+    NAME_TABLE.get_or_init_with(|| SpinLock::new(NameTable::new()))
+}
+
+static NAME_TABLE: Once<SpinLock<NameTable<MAX_INTERNED_NAMES>>> = Once::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn equal_names_intern_to_same_id() {
+        let root = intern(&Name::new("test-root")).unwrap();
+        let idle = intern(&Name::new("test-idle")).unwrap();
+
+        assert_ne!(root, idle);
+        assert_eq!(intern(&Name::new("test-root")), Some(root));
+        assert_eq!(resolve(root), Some(Name::new("test-root")));
+        assert_eq!(resolve(idle), Some(Name::new("test-idle")));
+    }
+}
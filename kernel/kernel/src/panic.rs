@@ -3,22 +3,32 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
 use crate::arch::cpu;
 
+// Note: we may have panicked while holding the console lock, so always use forced output here.
+macro_rules! panic_println {
+    ($($args:tt)*) => {
+        force_println!($($args)*)
+    };
+}
+
 #[panic_handler]
 fn handle_panic(info: &PanicInfo<'_>) -> ! {
     if !PANICKING.swap(true, Ordering::Relaxed) {
-        println!("\n************ KERNEL PANIC ************");
+        panic_println!("\n************ KERNEL PANIC ************");
 
         if let Some(message) = info.message() {
-            println!("{}", message);
+            panic_println!("{}", message);
         }
 
         if let Some(location) = info.location() {
-            println!("\nat {}", location);
+            panic_println!("\nat {}", location);
         }
 
-        println!("**************************************\n");
+        panic_println!("**************************************\n");
     }
 
+    #[cfg(test)]
+    crate::testing::fail();
+
     cpu::halt();
 }
 
@@ -1,6 +1,7 @@
+use core::array;
 use core::cell::UnsafeCell;
 use core::hint;
-use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 
 use alloc::boxed::Box;
 use alloc::sync::Arc;
@@ -11,11 +12,12 @@ use object_name::Name;
 
 use crate::arch::context::ThreadContext as ArchContext;
 use crate::arch::{self, cpu};
-use crate::err::Result;
+use crate::err::{Error, Result};
 use crate::mm::kmap::KernelStack;
 use crate::mm::types::VirtAddr;
 use crate::mm::vm::{self, LowAddrSpace};
-use crate::mp::current_percpu;
+use crate::mp::{current_cpu_id, current_percpu, CpuMask};
+use crate::names;
 use crate::sync::irq::{self, IrqDisabled};
 use crate::sync::resched::{ReschedDisabled, ReschedGuard};
 use crate::sync::{resched, SpinLock};
@@ -25,6 +27,11 @@ const STATE_RUNNING: u32 = 2;
 const STATE_PARKED: u32 = 3;
 const STATE_DEAD: u32 = 4;
 
+/// The number of thread-local storage slots available to every thread.
+pub const TLS_SLOT_COUNT: usize = 8;
+
+static SCHED_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
 struct Context {
     // Only ever touched during context switches
     arch: UnsafeCell<ArchContext>,
@@ -35,9 +42,11 @@ pub struct Thread {
     sched_ownwer_link: LinkedListLink,
     run_queue_link: LinkedListLink,
     state: AtomicU32,
+    affinity: AtomicU64,
     stack: KernelStack,
     context: Context,
     name: Name,
+    tls: [AtomicUsize; TLS_SLOT_COUNT],
 }
 
 impl Thread {
@@ -53,20 +62,91 @@ impl Thread {
         })
     }
 
+    /// Invokes `f` on the currently running thread without blocking, panicking or touching its
+    /// reference count, for use in contexts that may have interrupted the scheduler itself (such as
+    /// NMI handlers).
+    ///
+    /// Returns `None` without calling `f` if there is no current thread or if the scheduler state of
+    /// the current core is in the middle of being modified.
+    pub fn try_with_current<R>(
+        resched_disabled: &ReschedDisabled,
+        f: impl FnOnce(&Thread) -> R,
+    ) -> Option<R> {
+        let cpu_state = current_percpu(resched_disabled)
+            .sched
+            .inner
+            .try_borrow()
+            .ok()?;
+        // Note: the current thread is kept alive by its scheduler owner for as long as it is
+        // running, so borrowing it here is enough.
+        cpu_state.current_thread.as_deref().map(f)
+    }
+
+    /// Retrieves the value stored in thread-local storage slot `index` of the current thread.
+    ///
+    /// All slots of a new thread start out as 0.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - `index` is not smaller than [`TLS_SLOT_COUNT`].
+    /// * `INVALID_STATE` - There is no thread running on the current core.
+    pub fn get_tls(resched_disabled: &ReschedDisabled, index: usize) -> Result<usize> {
+        Self::try_with_current(resched_disabled, |thread| {
+            Ok(thread.tls_slot(index)?.load(Ordering::Relaxed))
+        })
+        .ok_or(Error::INVALID_STATE)?
+    }
+
+    /// Stores `value` in thread-local storage slot `index` of the current thread.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - `index` is not smaller than [`TLS_SLOT_COUNT`].
+    /// * `INVALID_STATE` - There is no thread running on the current core.
+    pub fn set_tls(resched_disabled: &ReschedDisabled, index: usize, value: usize) -> Result<()> {
+        Self::try_with_current(resched_disabled, |thread| {
+            thread.tls_slot(index)?.store(value, Ordering::Relaxed);
+            Ok(())
+        })
+        .ok_or(Error::INVALID_STATE)?
+    }
+
+    /// Creates a new thread named `name` running `entry_fn` and adds it to the current core's run
+    /// queue.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_STATE` - The scheduler has not yet been initialized with [`init`].
+    /// * `OUT_OF_MEMORY` - Allocation of the thread or its stack failed.
     pub fn spawn<F: FnOnce() + Send + 'static>(
         name: &str,
         entry_fn: F,
         addr_space: Option<Arc<LowAddrSpace>>,
     ) -> Result<Arc<Self>> {
+        if !is_initialized() {
+            debug!("attempted to spawn thread '{}' before scheduler init", name);
+            return Err(Error::INVALID_STATE);
+        }
+
         let thread = Self::new(name, entry_fn, addr_space)?;
 
-        debug!("starting thread '{}'", name);
+        match names::intern(&thread.name) {
+            Some(name_id) => debug!("starting thread '{}' as {}", name, name_id),
+            None => debug!("starting thread '{}'", name),
+        }
 
         irq::disable_with(|irq_disabled| {
             let thread_ref = unsafe { UnsafeRef::from_raw(Arc::as_ptr(&thread)) };
             SCHED_THREAD_OWNERS
                 .lock(irq_disabled)
                 .push_back(thread.clone());
+            // Only the current core's run queue is reachable for now, so make sure it's one the
+            // thread is allowed on.
+            let cpu_num = current_cpu_id(irq_disabled.resched_disabled());
+            debug_assert!(
+                thread.can_run_on(cpu_num),
+                "spawning thread on disallowed cpu {cpu_num}"
+            );
             with_cpu_state_mut(irq_disabled, |cpu_state| {
                 cpu_state.run_queue.push_back(thread_ref)
             });
@@ -79,6 +159,39 @@ impl Thread {
         self.name.as_ref()
     }
 
+    /// Returns the set of CPUs this thread is allowed to run on.
+    ///
+    /// New threads may run on any CPU.
+    pub fn affinity(&self) -> CpuMask {
+        CpuMask::from_bits(self.affinity.load(Ordering::Relaxed))
+    }
+
+    /// Restricts this thread to running only on the CPUs in `affinity`.
+    ///
+    /// The new mask is taken into account the next time the thread is selected to run; it does not
+    /// preempt the thread if it is currently running elsewhere.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_ARGUMENT` - `affinity` does not contain any CPU that has been brought up.
+    pub fn set_affinity(&self, affinity: CpuMask) -> Result<()> {
+        if affinity.intersection(CpuMask::online()).is_empty() {
+            return Err(Error::INVALID_ARGUMENT);
+        }
+
+        debug!(
+            "setting affinity of thread '{}' to {affinity:?}",
+            self.name()
+        );
+        self.affinity.store(affinity.bits(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Checks whether this thread may be run on the CPU numbered `cpu_num`.
+    fn can_run_on(&self, cpu_num: u32) -> bool {
+        self.affinity().contains(cpu_num)
+    }
+
     pub fn stack(&self) -> &KernelStack {
         &self.stack
     }
@@ -87,6 +200,10 @@ impl Thread {
         self.context.addr_space.as_ref()
     }
 
+    fn tls_slot(&self, index: usize) -> Result<&AtomicUsize> {
+        self.tls.get(index).ok_or(Error::INVALID_ARGUMENT)
+    }
+
     fn new<F: FnOnce() + Send + 'static>(
         name: &str,
         entry_fn: F,
@@ -116,12 +233,14 @@ impl Thread {
             sched_ownwer_link: LinkedListLink::new(),
             run_queue_link: LinkedListLink::new(),
             state: AtomicU32::new(STATE_READY),
+            affinity: AtomicU64::new(CpuMask::ALL.bits()),
             stack,
             context: Context {
                 arch: UnsafeCell::new(arch_context),
                 addr_space,
             },
             name: Name::new(name),
+            tls: array::from_fn(|_| AtomicUsize::new(0)),
         })?;
 
         Ok(thread)
@@ -133,6 +252,19 @@ unsafe impl Sync for Thread {}
 intrusive_adapter!(ThreadSchedOwnerAdapter = Arc<Thread>: Thread { sched_ownwer_link: LinkedListLink });
 intrusive_adapter!(ThreadRunQueueAdapter = UnsafeRef<Thread>: Thread { run_queue_link: LinkedListLink });
 
+/// Marks the scheduler as ready to accept new threads via [`Thread::spawn`].
+///
+/// This should be called on the BSP once per-CPU data and the memory manager have been
+/// initialized; threads spawned before this point would be queued on uninitialized state.
+pub fn init() {
+    SCHED_INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Returns whether the scheduler has been initialized with [`init`].
+pub fn is_initialized() -> bool {
+    SCHED_INITIALIZED.load(Ordering::Acquire)
+}
+
 /// Starts the scheduler on the current core, creating the idle thread and switching to the next
 /// ready thread.
 ///
@@ -143,7 +275,16 @@ intrusive_adapter!(ThreadRunQueueAdapter = UnsafeRef<Thread>: Thread { run_queue
 ///
 /// This function must be called at most once per core, in a state where it is safe to enable
 /// interrupts.
+///
+/// # Panics
+///
+/// Panics if the scheduler has not been initialized with [`init`].
 pub unsafe fn start() -> ! {
+    assert!(
+        is_initialized(),
+        "attempted to start uninitialized scheduler"
+    );
+
     let irq_disabled = unsafe { IrqDisabled::new() };
     let new_thread = with_cpu_state_mut(&irq_disabled, |cpu_state| {
         let new_thread = cpu_state.take_ready_thread();
@@ -151,9 +292,15 @@ pub unsafe fn start() -> ! {
         new_thread
     });
 
+    // Every core has its own idle thread, which must never be picked up by another core.
+    let idle_thread =
+        Thread::new("idle", || cpu::idle_loop(), None).expect("failed to create idle thread");
+    let cpu_num = current_cpu_id(irq_disabled.resched_disabled());
+    idle_thread
+        .set_affinity(CpuMask::single(cpu_num))
+        .expect("current core should be online");
+
     with_cpu_state_mut(&irq_disabled, |cpu_state| {
-        let idle_thread =
-            Thread::new("idle", || cpu::idle_loop(), None).expect("failed to create idle thread");
         cpu_state.idle_thread = Some(unsafe { UnsafeRef::from_raw(Arc::into_raw(idle_thread)) });
     });
 
@@ -365,10 +512,11 @@ pub struct CpuState {
 }
 
 impl CpuState {
-    pub fn new() -> Self {
+    pub fn new(cpu_num: u32) -> Self {
         Self {
             resched_pending: AtomicBool::new(false),
             inner: AtomicRefCell::new(CpuStateInner {
+                cpu_num,
                 current_thread: None,
                 idle_thread: None,
                 run_queue: LinkedList::new(ThreadRunQueueAdapter::new()),
@@ -384,6 +532,7 @@ struct HandoffState {
 }
 
 struct CpuStateInner {
+    cpu_num: u32,
     current_thread: Option<UnsafeRef<Thread>>,
     idle_thread: Option<UnsafeRef<Thread>>,
     run_queue: LinkedList<ThreadRunQueueAdapter>,
@@ -391,12 +540,19 @@ struct CpuStateInner {
 }
 
 impl CpuStateInner {
+    /// Removes and returns the first thread in the run queue that is allowed to run on this core,
+    /// falling back to the idle thread if there is none.
     #[track_caller]
     fn take_ready_thread(&mut self) -> UnsafeRef<Thread> {
-        self.run_queue
-            .pop_front()
-            .or_else(|| self.idle_thread.clone())
-            .expect("no threads ready")
+        let mut cursor = self.run_queue.front_mut();
+        while let Some(thread) = cursor.get() {
+            if thread.can_run_on(self.cpu_num) {
+                return cursor.remove().unwrap();
+            }
+            cursor.move_next();
+        }
+
+        self.idle_thread.clone().expect("no threads ready")
     }
 }
 
@@ -407,10 +563,9 @@ fn with_cpu_state_mut<R>(irq_disabled: &IrqDisabled, f: impl FnOnce(&mut CpuStat
         "attempted to mutate scheduler state with rescheduling disabled"
     );
 
-    f(&mut current_percpu(irq_disabled.resched_disabled())
-        .sched
-        .inner
-        .borrow_mut())
+    let percpu = current_percpu(irq_disabled.resched_disabled());
+    percpu.debug_assert_owned();
+    f(&mut percpu.sched.inner.borrow_mut())
 }
 
 fn with_cpu_state<R>(resched_disabled: &ReschedDisabled, f: impl FnOnce(&CpuStateInner) -> R) -> R {
@@ -419,3 +574,111 @@ fn with_cpu_state<R>(resched_disabled: &ReschedDisabled, f: impl FnOnce(&CpuStat
 
 static SCHED_THREAD_OWNERS: SpinLock<LinkedList<ThreadSchedOwnerAdapter>> =
     SpinLock::new(LinkedList::new(ThreadSchedOwnerAdapter::NEW));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn spawn_before_init_fails() {
+        SCHED_INITIALIZED.store(false, Ordering::Release);
+        let res = Thread::spawn("early", || {}, None);
+        SCHED_INITIALIZED.store(true, Ordering::Release);
+
+        assert_eq!(res.err(), Some(Error::INVALID_STATE));
+    }
+
+    #[test_case]
+    fn ready_threads_respect_affinity() {
+        let idle = Thread::new("idle", || {}, None).unwrap();
+        let pinned = Thread::new("pinned", || {}, None).unwrap();
+        let floating = Thread::new("floating", || {}, None).unwrap();
+
+        pinned.set_affinity(CpuMask::single(0)).unwrap();
+        assert_eq!(pinned.affinity(), CpuMask::single(0));
+        assert_eq!(floating.affinity(), CpuMask::ALL);
+        assert_eq!(
+            floating.set_affinity(CpuMask::EMPTY),
+            Err(Error::INVALID_ARGUMENT)
+        );
+
+        let as_ref = |thread: &Arc<Thread>| unsafe { UnsafeRef::from_raw(Arc::as_ptr(thread)) };
+        let is = |taken: &UnsafeRef<Thread>, thread: &Arc<Thread>| {
+            core::ptr::eq(&**taken, Arc::as_ptr(thread))
+        };
+
+        // Simulate the run queues of two different cores, each holding both threads in turn.
+        for cpu_num in [0, 1] {
+            let mut cpu_state = CpuState::new(cpu_num).inner.into_inner();
+            cpu_state.idle_thread = Some(as_ref(&idle));
+            cpu_state.run_queue.push_back(as_ref(&pinned));
+            cpu_state.run_queue.push_back(as_ref(&floating));
+
+            if cpu_num == 0 {
+                assert!(is(&cpu_state.take_ready_thread(), &pinned));
+                assert!(is(&cpu_state.take_ready_thread(), &floating));
+            } else {
+                // The pinned thread should be skipped over and left in the queue.
+                assert!(is(&cpu_state.take_ready_thread(), &floating));
+                assert!(is(&cpu_state.take_ready_thread(), &idle));
+                assert!(is(&cpu_state.run_queue.pop_front().unwrap(), &pinned));
+            }
+
+            assert!(cpu_state.run_queue.is_empty());
+        }
+    }
+
+    /// Switches to the next ready thread, leaving the current one on the run queue.
+    fn yield_current() {
+        assert!(resched::enabled());
+        irq::disable();
+        do_resched();
+        unsafe {
+            irq::enable();
+        }
+    }
+
+    #[test_case]
+    fn tls_slots_are_per_thread() {
+        static OTHER_INITIAL: AtomicUsize = AtomicUsize::new(usize::MAX);
+        static OTHER_FINAL: AtomicUsize = AtomicUsize::new(0);
+
+        Thread::set_tls(&ReschedGuard::new(), 1, 42).unwrap();
+
+        let other = Thread::spawn(
+            "tls",
+            || {
+                let resched_guard = ReschedGuard::new();
+                OTHER_INITIAL.store(
+                    Thread::get_tls(&resched_guard, 1).unwrap(),
+                    Ordering::Relaxed,
+                );
+                Thread::set_tls(&resched_guard, 1, 7).unwrap();
+                OTHER_FINAL.store(
+                    Thread::get_tls(&resched_guard, 1).unwrap(),
+                    Ordering::Relaxed,
+                );
+            },
+            None,
+        )
+        .unwrap();
+
+        // Wait for the other thread to run to completion.
+        while other.state.load(Ordering::Relaxed) != STATE_DEAD {
+            yield_current();
+        }
+
+        assert_eq!(OTHER_INITIAL.load(Ordering::Relaxed), 0);
+        assert_eq!(OTHER_FINAL.load(Ordering::Relaxed), 7);
+        assert_eq!(other.tls_slot(1).unwrap().load(Ordering::Relaxed), 7);
+
+        let resched_guard = ReschedGuard::new();
+        assert_eq!(Thread::get_tls(&resched_guard, 1), Ok(42));
+        assert_eq!(
+            Thread::get_tls(&resched_guard, TLS_SLOT_COUNT),
+            Err(Error::INVALID_ARGUMENT)
+        );
+
+        Thread::set_tls(&resched_guard, 1, 0).unwrap();
+    }
+}
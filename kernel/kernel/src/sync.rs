@@ -1,6 +1,13 @@
-pub use spinlock::SpinLock;
+pub use counter::Counter;
+pub use ring::RingBuffer;
+pub use spin_once::Backoff;
+pub use spinlock::{SpinLock, SpinLockGuard, SpinLockIrq};
 
 pub mod irq;
+pub mod lockrank;
 pub mod resched;
+pub mod seqlock;
 
+mod counter;
+mod ring;
 mod spinlock;
@@ -0,0 +1,88 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::mp::{current_cpu_id, MAX_CPUS};
+
+use super::resched::ReschedGuard;
+
+/// A statistics counter that can be incremented cheaply from any core.
+///
+/// Rather than contending on a single atomic, every core increments its own cell, each of which
+/// lives on a separate cache line. Reading the counter sums all of the cells, so it is much more
+/// expensive than incrementing it and only reflects increments that have completed by the time each
+/// cell is read.
+pub struct Counter {
+    cells: [CounterCell; MAX_CPUS],
+}
+
+#[repr(align(64))]
+struct CounterCell(AtomicU64);
+
+impl Counter {
+    /// Creates a new counter with a value of 0.
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: CounterCell = CounterCell(AtomicU64::new(0));
+
+        Self {
+            cells: [ZERO; MAX_CPUS],
+        }
+    }
+
+    /// Increments the counter by 1.
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Increments the counter by `n`.
+    pub fn add(&self, n: u64) {
+        let cpu_num = current_cpu_id(&ReschedGuard::new());
+
+        // Being migrated after reading the CPU number is harmless, as the cell is still updated
+        // atomically; it just means that the increment may briefly contend with another core.
+        self.add_on_cpu(cpu_num, n);
+    }
+
+    /// Returns the sum of all increments made to the counter.
+    pub fn sum(&self) -> u64 {
+        self.cells
+            .iter()
+            .map(|cell| cell.0.load(Ordering::Relaxed))
+            .fold(0, u64::wrapping_add)
+    }
+
+    fn add_on_cpu(&self, cpu_num: u32, n: u64) {
+        self.cells[cpu_num as usize]
+            .0
+            .fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn counter_sums_all_cpus() {
+        let counter = Counter::new();
+        assert_eq!(counter.sum(), 0);
+
+        // Only the BSP is running, so simulate concurrent increments from other cores by updating
+        // their cells directly, interleaved with increments on the current core.
+        for i in 0..100 {
+            counter.inc();
+            counter.add_on_cpu(1 + i % 3, 2);
+            counter.add_on_cpu(MAX_CPUS as u32 - 1, 1);
+        }
+
+        assert_eq!(counter.sum(), 100 + 200 + 100);
+
+        counter.add(5);
+        assert_eq!(counter.sum(), 405);
+    }
+}
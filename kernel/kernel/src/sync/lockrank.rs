@@ -0,0 +1,163 @@
+use core::fmt;
+
+use arrayvec::ArrayVec;
+use atomic_refcell::AtomicRefCell;
+
+use crate::mp;
+
+use super::resched::ReschedDisabled;
+
+/// The maximum number of ranked locks that can be held simultaneously on a single core.
+const MAX_HELD_RANKS: usize = 16;
+
+/// A rank used to validate lock ordering in debug builds.
+///
+/// Ranked locks must always be acquired in non-decreasing rank order: acquiring a lock while
+/// holding another lock of a strictly higher rank is considered an ordering violation and will
+/// panic when `debug_assertions` are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LockRank(pub u32);
+
+// Ranks of the kernel's global locks, in the order in which they may be nested. Gaps are left so
+// that new locks can be slotted in between.
+
+/// The lock protecting an address space's mappings; page table updates may allocate from the heap
+/// and the PMM while holding it.
+pub const RANK_ADDR_SPACE: LockRank = LockRank(10);
+/// The locks protecting the kernel heap's size classes, which request slabs from the PMM.
+pub const RANK_HEAP: LockRank = LockRank(20);
+/// The lock protecting the physical memory manager.
+pub const RANK_PMM: LockRank = LockRank(30);
+/// The console locks, which may be taken to log from anywhere and so must come last.
+pub const RANK_CONSOLE: LockRank = LockRank(40);
+
+/// Describes an attempt to acquire a lock out of rank order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockRankViolation {
+    pub acquired: LockRank,
+    pub held: LockRank,
+}
+
+impl fmt::Display for LockRankViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "lock order violation: acquiring rank {} while holding rank {}",
+            self.acquired.0, self.held.0
+        )
+    }
+}
+
+/// Per-CPU stack of the ranks of all ranked locks currently held by the core.
+pub struct HeldLockRanks {
+    ranks: AtomicRefCell<ArrayVec<LockRank, MAX_HELD_RANKS>>,
+}
+
+impl HeldLockRanks {
+    pub const fn new() -> Self {
+        Self {
+            ranks: AtomicRefCell::new(ArrayVec::new_const()),
+        }
+    }
+
+    /// Records the acquisition of a lock with rank `rank`, returning an error if a lock with a
+    /// higher rank is already held.
+    ///
+    /// If this interrupted another update of the same stack (which can only happen from an NMI),
+    /// the acquisition is not recorded or validated.
+    ///
+    /// # Panics
+    ///
+    /// Panics if too many ranked locks are held at once.
+    pub fn try_push(&self, rank: LockRank) -> Result<(), LockRankViolation> {
+        let Ok(mut ranks) = self.ranks.try_borrow_mut() else {
+            return Ok(());
+        };
+
+        // Ranks are always pushed in non-decreasing order, so the last entry is the maximum.
+        if let Some(&held) = ranks.last() {
+            if held > rank {
+                return Err(LockRankViolation {
+                    acquired: rank,
+                    held,
+                });
+            }
+        }
+
+        ranks.push(rank);
+        Ok(())
+    }
+
+    /// Records the release of a lock with rank `rank`.
+    ///
+    /// Locks need not be released in the order in which they were acquired. As with
+    /// [`try_push`](Self::try_push), releases that interrupt another update are ignored; such
+    /// releases always correspond to acquisitions that were ignored in the same way.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no lock with rank `rank` is currently recorded as held.
+    pub fn pop(&self, rank: LockRank) {
+        let Ok(mut ranks) = self.ranks.try_borrow_mut() else {
+            return;
+        };
+        let pos = ranks
+            .iter()
+            .rposition(|&held| held == rank)
+            .expect("releasing lock rank that is not held");
+        ranks.remove(pos);
+    }
+}
+
+/// Records the acquisition of a lock with rank `rank` on the current core.
+///
+/// # Panics
+///
+/// Panics if the current core already holds a lock with a higher rank.
+pub fn acquire(rank: LockRank, resched_disabled: &ReschedDisabled) {
+    if let Err(violation) = try_acquire(rank, resched_disabled) {
+        panic!("{violation}");
+    }
+}
+
+/// Records the acquisition of a lock with rank `rank` on the current core, returning an error
+/// instead if the current core already holds a lock with a higher rank.
+pub fn try_acquire(
+    rank: LockRank,
+    resched_disabled: &ReschedDisabled,
+) -> Result<(), LockRankViolation> {
+    mp::current_percpu(resched_disabled)
+        .lock_ranks
+        .try_push(rank)
+}
+
+/// Records the release of a lock with rank `rank` on the current core.
+pub fn release(rank: LockRank, resched_disabled: &ReschedDisabled) {
+    mp::current_percpu(resched_disabled).lock_ranks.pop(rank);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn lock_rank_inversion_detected() {
+        let held = HeldLockRanks::new();
+
+        held.try_push(LockRank(1)).unwrap();
+        held.try_push(LockRank(2)).unwrap();
+        held.try_push(LockRank(2)).unwrap();
+
+        assert_eq!(
+            held.try_push(LockRank(1)),
+            Err(LockRankViolation {
+                acquired: LockRank(1),
+                held: LockRank(2)
+            })
+        );
+
+        held.pop(LockRank(2));
+        held.pop(LockRank(2));
+        held.try_push(LockRank(1)).unwrap();
+    }
+}
@@ -0,0 +1,235 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use super::Backoff;
+
+/// A fixed-capacity, lock-free queue holding up to `N` values.
+///
+/// Any number of producers may push concurrently, and are never blocked by the consumer. The queue
+/// is intended to be drained by a single consumer; concurrent calls to [`pop`](Self::pop) are
+/// still safe, but contend with each other.
+///
+/// Every slot carries a stamp recording which "lap" around the buffer it belongs to and whether it
+/// is currently full, so that producers and the consumer can claim positions with a single
+/// compare-exchange and then fill or drain the slot without further synchronization.
+pub struct RingBuffer<T, const N: usize> {
+    slots: [Slot<T>; N],
+    /// The position of the next value to be popped.
+    head: AtomicUsize,
+    /// The position at which the next value will be pushed.
+    tail: AtomicUsize,
+}
+
+struct Slot<T> {
+    /// `2 * lap` when the slot is empty and ready for a push in lap `lap`, and `2 * lap + 1` once
+    /// a value has been pushed in that lap.
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+impl<T> Slot<T> {
+    const EMPTY: Self = Self {
+        stamp: AtomicUsize::new(0),
+        value: UnsafeCell::new(MaybeUninit::uninit()),
+    };
+}
+
+impl<T, const N: usize> RingBuffer<T, N> {
+    const NONEMPTY: () = assert!(N > 0, "ring buffer capacity must be nonzero");
+
+    /// Creates a new, empty ring buffer.
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::NONEMPTY;
+
+        Self {
+            slots: [Slot::EMPTY; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the maximum number of values the buffer can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Attempts to append `value` to the buffer, handing it back if the buffer is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let backoff = Backoff::new();
+        let mut tail = self.tail.load(Ordering::Relaxed);
+
+        loop {
+            let (slot, lap_stamp) = self.slot_for(tail);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            match stamp.wrapping_sub(lap_stamp) as isize {
+                0 => match self.tail.compare_exchange_weak(
+                    tail,
+                    tail.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: winning the compare-exchange gives us exclusive access to the
+                        // slot until we publish it by updating its stamp.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.stamp.store(lap_stamp + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(cur) => tail = cur,
+                },
+                // The slot still holds a value from the previous lap that hasn't been popped.
+                diff if diff < 0 => return Err(value),
+                // Another producer has already claimed this position; catch up.
+                _ => {
+                    backoff.spin();
+                    tail = self.tail.load(Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Removes and returns the oldest value in the buffer, or `None` if the buffer is empty.
+    ///
+    /// A value whose push is still in progress is not considered to be in the buffer yet.
+    pub fn pop(&self) -> Option<T> {
+        let backoff = Backoff::new();
+        let mut head = self.head.load(Ordering::Relaxed);
+
+        loop {
+            let (slot, lap_stamp) = self.slot_for(head);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            match stamp.wrapping_sub(lap_stamp + 1) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    head,
+                    head.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: the stamp tells us the slot has been filled, and winning the
+                        // compare-exchange gives us exclusive access to it until we release it
+                        // for the next lap.
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.stamp.store(lap_stamp + 2, Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(cur) => head = cur,
+                },
+                // Nothing has been pushed at this position yet.
+                diff if diff < 0 => return None,
+                // Another consumer has already claimed this position; catch up.
+                _ => {
+                    backoff.spin();
+                    head = self.head.load(Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Returns the slot used for `pos`, along with the stamp it carries when empty in the lap
+    /// containing `pos`.
+    fn slot_for(&self, pos: usize) -> (&Slot<T>, usize) {
+        (&self.slots[pos % N], (pos / N).wrapping_mul(2))
+    }
+}
+
+impl<T, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for RingBuffer<T, N> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+// Safety: values are only ever moved in and out of the buffer, each by a single thread that has
+// exclusively claimed the corresponding slot, so sharing the buffer only requires that values can be
+// sent between threads.
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[test_case]
+    fn ring_buffer_wraps_and_reports_full() {
+        let ring = RingBuffer::<usize, 3>::new();
+        assert_eq!(ring.pop(), None);
+
+        // Go around the buffer several times, leaving it partially full each time.
+        let mut next_push = 0;
+        let mut next_pop = 0;
+        for _ in 0..5 {
+            while ring.try_push(next_push).is_ok() {
+                next_push += 1;
+            }
+            assert_eq!(next_push - next_pop, ring.capacity());
+            assert_eq!(ring.try_push(usize::MAX), Err(usize::MAX));
+
+            for _ in 0..2 {
+                assert_eq!(ring.pop(), Some(next_pop));
+                next_pop += 1;
+            }
+        }
+
+        while let Some(value) = ring.pop() {
+            assert_eq!(value, next_pop);
+            next_pop += 1;
+        }
+        assert_eq!(next_pop, next_push);
+    }
+
+    #[test_case]
+    fn ring_buffer_interleaved_producers() {
+        const PRODUCERS: usize = 3;
+        const PER_PRODUCER: usize = 20;
+
+        let ring = RingBuffer::<(usize, usize), 4>::new();
+        let mut sent = [0; PRODUCERS];
+        let mut received: [Vec<usize>; PRODUCERS] = Default::default();
+
+        // Producers take turns pushing until full, with the consumer draining one value per round.
+        while sent.iter().any(|&count| count < PER_PRODUCER) {
+            for (producer, count) in sent.iter_mut().enumerate() {
+                if *count < PER_PRODUCER && ring.try_push((producer, *count)).is_ok() {
+                    *count += 1;
+                }
+            }
+
+            if let Some((producer, value)) = ring.pop() {
+                received[producer].push(value);
+            }
+        }
+
+        while let Some((producer, value)) = ring.pop() {
+            received[producer].push(value);
+        }
+
+        // Values from each producer must come out in the order they went in, with nothing lost.
+        for values in &received {
+            assert!(values.iter().copied().eq(0..PER_PRODUCER));
+        }
+    }
+
+    #[test_case]
+    fn ring_buffer_drops_remaining_values() {
+        let value = Arc::new(());
+        let ring = RingBuffer::<_, 4>::new();
+        ring.try_push(value.clone()).unwrap();
+        ring.try_push(value.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&value), 3);
+
+        drop(ring);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -1,26 +1,50 @@
 use core::cell::UnsafeCell;
 use core::hint;
+use core::marker::PhantomData;
+use core::mem;
 use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
 use core::sync::atomic::{AtomicBool, Ordering};
 
 use super::irq::{self, IrqDisabled};
-use super::resched;
+use super::lockrank::{self, LockRank, LockRankViolation};
+use super::resched::{self, ReschedDisabled};
+use super::Backoff;
 
 /// A lock that protects shared data by spinning until it is available.
 ///
 /// These locks may only be held when interrupts are disabled, to avoid various starvation and
 /// latency issues.
+///
+/// Spinlocks created with [`new_ranked`](SpinLock::new_ranked) participate in lock order
+/// validation when `debug_assertions` are enabled: acquiring such a lock while the current core
+/// holds a ranked lock of a higher rank will panic.
 pub struct SpinLock<T> {
     data: UnsafeCell<T>,
     raw: RawSpinLock,
+    rank: Option<LockRank>,
 }
 
 impl<T> SpinLock<T> {
     /// Creates a new unlocked spinlock holding `value`.
+    ///
+    /// The returned lock is unranked and does not participate in lock order validation.
     pub const fn new(value: T) -> Self {
         Self {
             data: UnsafeCell::new(value),
             raw: RawSpinLock::new(),
+            rank: None,
+        }
+    }
+
+    /// Creates a new unlocked spinlock holding `value`, with lock rank `rank`.
+    ///
+    /// In debug builds, acquiring the lock while holding a lock of a higher rank will panic.
+    pub const fn new_ranked(value: T, rank: LockRank) -> Self {
+        Self {
+            data: UnsafeCell::new(value),
+            raw: RawSpinLock::new(),
+            rank: Some(rank),
         }
     }
 
@@ -37,15 +61,84 @@ impl<T> SpinLock<T> {
     ///
     /// The lock may only be held as long as interrupts are disabled, as indicated by the
     /// [`IrqDisabled`] parameter.
-    pub fn lock<'a>(&'a self, _irq_disabled: &'a IrqDisabled) -> SpinLockGuard<'a, T> {
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the lock is ranked and the current core already holds a lock of
+    /// a higher rank.
+    pub fn lock<'a>(&'a self, irq_disabled: &'a IrqDisabled) -> SpinLockGuard<'a, T> {
+        match self.lock_ordered(irq_disabled) {
+            Ok(guard) => guard,
+            Err(violation) => panic!("{violation}"),
+        }
+    }
+
+    /// Acquires the lock like [`lock`](Self::lock), but returns an error instead of acquiring the
+    /// lock if doing so would violate lock ordering.
+    ///
+    /// Ordering is only validated in debug builds; release builds always acquire the lock.
+    fn lock_ordered<'a>(
+        &'a self,
+        irq_disabled: &'a IrqDisabled,
+    ) -> Result<SpinLockGuard<'a, T>, LockRankViolation> {
+        if cfg!(debug_assertions) {
+            if let Some(rank) = self.rank {
+                lockrank::try_acquire(rank, irq_disabled.resched_disabled())?;
+            }
+        }
+
         self.raw.lock();
-        SpinLockGuard { lock: self }
+        Ok(SpinLockGuard { lock: self })
+    }
+
+    /// Attempts to acquire the lock, giving up after spinning `spin_budget` times.
+    ///
+    /// This is intended for best-effort paths (such as diagnostics) that must not deadlock if the
+    /// lock is held by a core that will never release it.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the lock is acquired, is ranked, and the current core already
+    /// holds a lock of a higher rank.
+    pub fn try_lock_spinning<'a>(
+        &'a self,
+        irq_disabled: &'a IrqDisabled,
+        spin_budget: usize,
+    ) -> Option<SpinLockGuard<'a, T>> {
+        if !self.raw.try_lock_spinning(spin_budget) {
+            return None;
+        }
+
+        if cfg!(debug_assertions) {
+            if let Some(rank) = self.rank {
+                lockrank::acquire(rank, irq_disabled.resched_disabled());
+            }
+        }
+
+        Some(SpinLockGuard { lock: self })
     }
 
     /// Disables interrupts, locks the lock and invokes `f` on the protected data.
     pub fn with<R>(&self, f: impl FnOnce(&mut T, &IrqDisabled) -> R) -> R {
         irq::disable_with(|irq_disabled| f(&mut self.lock(irq_disabled), irq_disabled))
     }
+
+    /// Disables interrupts and attempts to lock the lock, giving up after spinning `spin_budget`
+    /// times. If the lock was acquired, invokes `f` on the protected data and returns its result;
+    /// otherwise, returns `None`.
+    ///
+    /// Note: the budget is currently measured in spin iterations rather than in time, as the kernel
+    /// does not yet have a time source.
+    pub fn with_timeout<R>(
+        &self,
+        spin_budget: usize,
+        f: impl FnOnce(&mut T, &IrqDisabled) -> R,
+    ) -> Option<R> {
+        irq::disable_with(|irq_disabled| {
+            let mut guard = self.try_lock_spinning(irq_disabled, spin_budget)?;
+            Some(f(&mut guard, irq_disabled))
+        })
+    }
 }
 
 // Safety: we provide the necessary synchronization around accesses to the stored data when multiple
@@ -63,10 +156,58 @@ pub struct SpinLockGuard<'a, T> {
     lock: &'a SpinLock<T>,
 }
 
+impl<'a, T> SpinLockGuard<'a, T> {
+    /// Converts `guard` into a guard for the component of the protected data returned by `f`.
+    ///
+    /// The lock remains held until the returned guard is dropped.
+    ///
+    /// This is an associated function rather than a method, to avoid conflicts with methods on the
+    /// protected data.
+    pub fn map<U: ?Sized>(
+        guard: Self,
+        f: impl FnOnce(&mut T) -> &mut U,
+    ) -> MappedSpinLockGuard<'a, U> {
+        let lock = guard.lock;
+        mem::forget(guard);
+
+        // Safety: we have exclusive access whenever the lock is locked, and the lock is now owned by
+        // the mapped guard.
+        let data = NonNull::from(f(unsafe { &mut *lock.data.get() }));
+
+        MappedSpinLockGuard {
+            raw: &lock.raw,
+            rank: lock.rank,
+            data,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Attempts to convert `guard` into a guard for the component of the protected data returned by
+    /// `f`, returning the original guard if `f` returns `None`.
+    pub fn filter_map<U: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut T) -> Option<&mut U>,
+    ) -> Result<MappedSpinLockGuard<'a, U>, Self> {
+        let Some(data) = f(&mut guard).map(NonNull::from) else {
+            return Err(guard);
+        };
+
+        let lock = guard.lock;
+        mem::forget(guard);
+
+        Ok(MappedSpinLockGuard {
+            raw: &lock.raw,
+            rank: lock.rank,
+            data,
+            _marker: PhantomData,
+        })
+    }
+}
+
 impl<'a, T> Drop for SpinLockGuard<'a, T> {
     fn drop(&mut self) {
         // Safety: the raw lock was locked on this core when the object was constructed.
-        unsafe { self.lock.raw.unlock() }
+        unsafe { release_ranked(&self.lock.raw, self.lock.rank) }
     }
 }
 
@@ -86,6 +227,189 @@ impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
     }
 }
 
+/// An RAII guard for a component of the data protected by a locked [`SpinLock`], created by
+/// [`SpinLockGuard::map`] or [`SpinLockGuard::filter_map`].
+///
+/// The spinlock is unlocked when this guard goes out of scope.
+pub struct MappedSpinLockGuard<'a, U: ?Sized> {
+    raw: &'a RawSpinLock,
+    rank: Option<LockRank>,
+    data: NonNull<U>,
+    _marker: PhantomData<&'a mut U>,
+}
+
+impl<'a, U: ?Sized> MappedSpinLockGuard<'a, U> {
+    /// Converts `guard` into a guard for the component of the protected data returned by `f`.
+    pub fn map<V: ?Sized>(
+        mut guard: Self,
+        f: impl FnOnce(&mut U) -> &mut V,
+    ) -> MappedSpinLockGuard<'a, V> {
+        let data = NonNull::from(f(&mut guard));
+        let (raw, rank) = (guard.raw, guard.rank);
+        mem::forget(guard);
+
+        MappedSpinLockGuard {
+            raw,
+            rank,
+            data,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, U: ?Sized> Drop for MappedSpinLockGuard<'a, U> {
+    fn drop(&mut self) {
+        // Safety: the raw lock was locked on this core when the original guard was constructed, and
+        // ownership of it was transferred to us.
+        unsafe { release_ranked(self.raw, self.rank) }
+    }
+}
+
+impl<'a, U: ?Sized> Deref for MappedSpinLockGuard<'a, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        // Safety: the pointer was derived from the locked data, to which we have exclusive access.
+        unsafe { self.data.as_ref() }
+    }
+}
+
+impl<'a, U: ?Sized> DerefMut for MappedSpinLockGuard<'a, U> {
+    fn deref_mut(&mut self) -> &mut U {
+        // Safety: the pointer was derived from the locked data, to which we have exclusive access.
+        unsafe { self.data.as_mut() }
+    }
+}
+
+/// Releases `raw` along with its lock rank, if it has one.
+///
+/// # Safety
+///
+/// `raw` must be locked by the current core.
+unsafe fn release_ranked(raw: &RawSpinLock, rank: Option<LockRank>) {
+    if cfg!(debug_assertions) {
+        if let Some(rank) = rank {
+            // Safety: we still hold the lock, so rescheduling is disabled.
+            let resched_disabled = unsafe { ReschedDisabled::new_unchecked() };
+            lockrank::release(rank, &resched_disabled);
+        }
+    }
+
+    // Safety: guaranteed by the caller.
+    unsafe { raw.unlock() }
+}
+
+/// A spinlock that automatically disables interrupts while it is held.
+///
+/// Unlike [`SpinLock`], this lock does not require the caller to supply an [`IrqDisabled`] token:
+/// [`lock`](SpinLockIrq::lock) disables interrupts before acquiring the lock, and the returned
+/// guard restores the previous interrupt state after releasing it.
+pub struct SpinLockIrq<T> {
+    data: UnsafeCell<T>,
+    raw: RawSpinLock,
+    rank: Option<LockRank>,
+}
+
+impl<T> SpinLockIrq<T> {
+    /// Creates a new unlocked spinlock holding `value`.
+    ///
+    /// The returned lock is unranked and does not participate in lock order validation.
+    pub const fn new(value: T) -> Self {
+        Self {
+            data: UnsafeCell::new(value),
+            raw: RawSpinLock::new(),
+            rank: None,
+        }
+    }
+
+    /// Creates a new unlocked spinlock holding `value`, with lock rank `rank`.
+    ///
+    /// In debug builds, acquiring the lock while holding a lock of a higher rank will panic.
+    pub const fn new_ranked(value: T, rank: LockRank) -> Self {
+        Self {
+            data: UnsafeCell::new(value),
+            raw: RawSpinLock::new(),
+            rank: Some(rank),
+        }
+    }
+
+    /// Disables interrupts and acquires the lock, spinning until it is ready if necessary.
+    ///
+    /// The returned [`SpinLockIrqGuard`] can be used to access the protected data, and will
+    /// automatically unlock the spinlock and restore the previous interrupt state when it exits
+    /// scope. If this function is called on a core already holding the lock, it will deadlock.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if the lock is ranked and the current core already holds a lock of
+    /// a higher rank.
+    pub fn lock(&self) -> SpinLockIrqGuard<'_, T> {
+        let restore_irq = irq::enabled();
+        irq::disable();
+
+        // Safety: we have just disabled interrupts.
+        let irq_disabled = unsafe { IrqDisabled::new_unchecked() };
+
+        if cfg!(debug_assertions) {
+            if let Some(rank) = self.rank {
+                lockrank::acquire(rank, irq_disabled.resched_disabled());
+            }
+        }
+
+        self.raw.lock();
+
+        SpinLockIrqGuard {
+            lock: self,
+            _irq_disabled: irq_disabled,
+            restore_irq,
+        }
+    }
+}
+
+// Safety: see the corresponding implementations for `SpinLock`.
+unsafe impl<T: Send> Sync for SpinLockIrq<T> {}
+
+unsafe impl<T: Send> Send for SpinLockIrq<T> {}
+
+/// An RAII guard for a locked [`SpinLockIrq`].
+///
+/// This guard enables access to the protected value. When it goes out of scope, it will unlock the
+/// spinlock and then restore the interrupt state that was in effect when the lock was acquired.
+pub struct SpinLockIrqGuard<'a, T> {
+    lock: &'a SpinLockIrq<T>,
+    // Ties the guard to the current core, as it must be dropped where interrupts were disabled.
+    _irq_disabled: IrqDisabled,
+    restore_irq: bool,
+}
+
+impl<'a, T> Drop for SpinLockIrqGuard<'a, T> {
+    fn drop(&mut self) {
+        // Safety: the raw lock was locked on this core when the object was constructed.
+        unsafe { release_ranked(&self.lock.raw, self.lock.rank) }
+
+        if self.restore_irq {
+            // Safety: interrupts were enabled when the lock was acquired, and we no longer hold it.
+            unsafe { irq::enable() }
+        }
+    }
+}
+
+impl<'a, T> Deref for SpinLockIrqGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: we have exclusive access whenever the lock is locked.
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinLockIrqGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: we have exclusive access whenever the lock is locked.
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
 /// A "raw" spinlock primitive around which higher-level abstractions can be built.
 ///
 /// This structure provides direct `lock()` and `unlock()` methods for interacting with the lock.
@@ -107,9 +431,37 @@ impl RawSpinLock {
     /// This function will deadlock if the lock is already held by the current core when called.
     pub fn lock(&self) {
         resched::disable();
+
+        let backoff = Backoff::new();
         while self.locked.swap(true, Ordering::Acquire) {
+            // Wait for the lock to look free before retrying the swap, so that waiters don't keep
+            // stealing the cache line from the owner.
+            while self.locked.load(Ordering::Relaxed) {
+                backoff.spin();
+            }
+        }
+    }
+
+    /// Attempts to lock the spinlock, spinning at most `spin_budget` times if it is already locked.
+    ///
+    /// Returns `true` if the lock was acquired, in which case it must later be released with
+    /// `unlock()`.
+    pub fn try_lock_spinning(&self, spin_budget: usize) -> bool {
+        resched::disable();
+
+        for _ in 0..=spin_budget {
+            if !self.locked.swap(true, Ordering::Acquire) {
+                return true;
+            }
             hint::spin_loop();
         }
+
+        // Safety: we disabled rescheduling above, and did not acquire the lock.
+        unsafe {
+            resched::enable_no_resched();
+        }
+
+        false
     }
 
     /// Unlocks the spinlock.
@@ -130,3 +482,117 @@ impl RawSpinLock {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn backoff_completes_and_resets() {
+        let backoff = Backoff::new();
+        while !backoff.is_completed() {
+            backoff.spin();
+        }
+
+        // Spinning past the limit should remain bounded.
+        backoff.spin();
+        assert!(backoff.is_completed());
+
+        backoff.reset();
+        assert!(!backoff.is_completed());
+    }
+
+    #[test_case]
+    fn spinlock_irq_restores_irq_state() {
+        let lock = SpinLockIrq::new(0);
+        assert!(irq::enabled());
+
+        {
+            let mut guard = lock.lock();
+            assert!(!irq::enabled());
+            *guard += 1;
+        }
+        assert!(irq::enabled());
+
+        irq::disable_with(|_| {
+            *lock.lock() += 1;
+            assert!(!irq::enabled());
+        });
+        assert!(irq::enabled());
+
+        assert_eq!(*lock.lock(), 2);
+    }
+
+    #[test_case]
+    fn spinlock_guard_projection() {
+        let lock = SpinLock::new((1, Some(2)));
+
+        irq::disable_with(|irq_disabled| {
+            let mut first = SpinLockGuard::map(lock.lock(irq_disabled), |(first, _)| first);
+            *first += 10;
+        });
+
+        irq::disable_with(|irq_disabled| {
+            let guard = lock.lock(irq_disabled);
+            let second = SpinLockGuard::filter_map(guard, |(_, second)| second.as_mut())
+                .ok()
+                .unwrap();
+            let mut second = MappedSpinLockGuard::map(second, |second| second);
+            *second += 20;
+        });
+
+        irq::disable_with(|irq_disabled| {
+            let mut guard = lock.lock(irq_disabled);
+            guard.1 = None;
+            assert!(SpinLockGuard::filter_map(guard, |(_, second)| second.as_mut()).is_err());
+        });
+
+        // The projected guards should have released the lock when dropped.
+        assert_eq!(lock.with_timeout(100, |value, _| *value), Some((11, None)));
+    }
+
+    #[test_case]
+    fn spinlock_rank_inversion_detected() {
+        let heap_like = SpinLock::new_ranked(0, lockrank::RANK_HEAP);
+        let pmm_like = SpinLock::new_ranked(0, lockrank::RANK_PMM);
+
+        irq::disable_with(|irq_disabled| {
+            // Nesting in rank order is allowed.
+            let heap_guard = heap_like.lock(irq_disabled);
+            let pmm_guard = pmm_like.lock(irq_disabled);
+            drop(heap_guard);
+            drop(pmm_guard);
+
+            let pmm_guard = pmm_like.lock(irq_disabled);
+            let res = heap_like.lock_ordered(irq_disabled);
+            if cfg!(debug_assertions) {
+                assert_eq!(
+                    res.err(),
+                    Some(LockRankViolation {
+                        acquired: lockrank::RANK_HEAP,
+                        held: lockrank::RANK_PMM,
+                    })
+                );
+            } else {
+                assert!(res.is_ok());
+            }
+            drop(pmm_guard);
+
+            // The failed acquisition must not have left the lock held or its rank recorded.
+            let _heap_guard = heap_like.lock(irq_disabled);
+            let _pmm_guard = pmm_like.lock(irq_disabled);
+        });
+    }
+
+    #[test_case]
+    fn spinlock_timeout_gives_up() {
+        let lock = SpinLock::new(0);
+
+        lock.with(|_, _| {
+            assert_eq!(lock.with_timeout(100, |value, _| *value += 1), None);
+        });
+
+        assert_eq!(lock.with_timeout(100, |value, _| *value += 1), Some(()));
+        assert_eq!(lock.with(|value, _| *value), 1);
+    }
+}
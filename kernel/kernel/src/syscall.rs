@@ -0,0 +1,54 @@
+use crate::err::{Error, Result};
+
+/// The maximum number of arguments that can be passed to a system call.
+pub const MAX_ARGS: usize = 6;
+
+/// Does nothing and returns 0.
+pub const SYSCALL_NOP: u64 = 0;
+
+/// Returns its first argument unchanged; useful for testing the syscall path.
+pub const SYSCALL_ECHO: u64 = 1;
+
+/// The arguments passed to a system call, as marshaled from registers by the architecture-specific
+/// entry path.
+#[derive(Debug, Clone, Copy)]
+pub struct SyscallArgs(pub [u64; MAX_ARGS]);
+
+type SyscallHandler = fn(&SyscallArgs) -> Result<u64>;
+
+const SYSCALL_COUNT: usize = 2;
+
+static SYSCALL_TABLE: [SyscallHandler; SYSCALL_COUNT] = {
+    let mut table: [SyscallHandler; SYSCALL_COUNT] = [sys_unimplemented; SYSCALL_COUNT];
+    table[SYSCALL_NOP as usize] = sys_nop;
+    table[SYSCALL_ECHO as usize] = sys_echo;
+    table
+};
+
+/// Dispatches system call `num` with arguments `args` to its handler, returning the handler's
+/// result.
+///
+/// # Errors
+///
+/// * `INVALID_ARGUMENT` - `num` is not a valid system call number.
+/// * Any errors returned by the system call handler itself.
+pub fn dispatch(num: u64, args: &SyscallArgs) -> Result<u64> {
+    let handler = usize::try_from(num)
+        .ok()
+        .and_then(|num| SYSCALL_TABLE.get(num))
+        .ok_or(Error::INVALID_ARGUMENT)?;
+
+    handler(args)
+}
+
+fn sys_unimplemented(_args: &SyscallArgs) -> Result<u64> {
+    Err(Error::INVALID_ARGUMENT)
+}
+
+fn sys_nop(_args: &SyscallArgs) -> Result<u64> {
+    Ok(0)
+}
+
+fn sys_echo(args: &SyscallArgs) -> Result<u64> {
+    Ok(args.0[0])
+}
@@ -0,0 +1,46 @@
+//! In-kernel test runner, used when the kernel is built with `cargo test`.
+//!
+//! Tests are marked with `#[test_case]` and run in the bootstrap thread once the kernel is fully
+//! initialized. Results are reported over the console, and QEMU is terminated via the
+//! `isa-debug-exit` device with a status indicating overall success or failure.
+
+use crate::arch::qemu::debug_exit;
+
+const EXIT_SUCCESS: u8 = 0x10;
+const EXIT_FAILURE: u8 = 0x11;
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        println!("test {} ...", core::any::type_name::<T>());
+        self();
+        println!("ok");
+    }
+}
+
+pub fn run_tests(tests: &[&dyn Testable]) {
+    println!("running {} tests", tests.len());
+
+    for test in tests {
+        test.run();
+    }
+
+    println!("test result: ok. {} passed", tests.len());
+    // Safety: tests are only ever run under QEMU by the host tools.
+    unsafe {
+        debug_exit(EXIT_SUCCESS);
+    }
+}
+
+/// Reports failure of the running test to the host; called from the panic handler, so this must
+/// not rely on the console lock being available.
+pub fn fail() {
+    force_println!("test result: FAILED");
+    // Safety: tests are only ever run under QEMU by the host tools.
+    unsafe {
+        debug_exit(EXIT_FAILURE);
+    }
+}
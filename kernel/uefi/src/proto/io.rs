@@ -1,5 +1,7 @@
 use core::fmt;
 
+use struct_enum::struct_enum;
+
 use crate::{Result, Status, U16CStr};
 
 use super::{abi_call, unsafe_protocol, Protocol};
@@ -11,9 +13,9 @@ pub struct SimpleTextOutputAbi {
     test_string: unsafe extern "efiapi" fn(*mut Self, *const u16) -> Status,
     query_mode: *const (),
     set_mode: *const (),
-    set_attribute: *const (),
+    set_attribute: unsafe extern "efiapi" fn(*mut Self, usize) -> Status,
     clear_screen: unsafe extern "efiapi" fn(*mut Self) -> Status,
-    set_cursor_pos: *const (),
+    set_cursor_pos: unsafe extern "efiapi" fn(*mut Self, usize, usize) -> Status,
     enable_cursor: unsafe extern "efiapi" fn(*mut Self, bool) -> Status,
     mode: *const (),
 }
@@ -22,11 +24,68 @@ unsafe_protocol! {
     SimpleTextOutput(SimpleTextOutputAbi, "387477c2-69c7-11d2-8e39-00a0c969723b");
 }
 
+struct_enum! {
+    /// A text color supported by [`SimpleTextOutput`].
+    ///
+    /// Only the first eight colors (up to and including [`LIGHT_GRAY`](Self::LIGHT_GRAY)) may be
+    /// used as background colors.
+    pub struct TextColor: u8 {
+        BLACK = 0;
+        BLUE = 1;
+        GREEN = 2;
+        CYAN = 3;
+        RED = 4;
+        MAGENTA = 5;
+        BROWN = 6;
+        LIGHT_GRAY = 7;
+        DARK_GRAY = 8;
+        LIGHT_BLUE = 9;
+        LIGHT_GREEN = 0xa;
+        LIGHT_CYAN = 0xb;
+        LIGHT_RED = 0xc;
+        LIGHT_MAGENTA = 0xd;
+        YELLOW = 0xe;
+        WHITE = 0xf;
+    }
+}
+
 impl SimpleTextOutput {
     pub fn reset(&mut self) -> Result<()> {
         unsafe { abi_call!(self, reset(false)) }.to_result()
     }
 
+    /// Sets the foreground and background colors used for subsequent output.
+    ///
+    /// Returns `INVALID_PARAMETER` if `bg` is not usable as a background color.
+    pub fn set_attribute(&mut self, fg: TextColor, bg: TextColor) -> Result<()> {
+        if bg.to_raw() > TextColor::LIGHT_GRAY.to_raw() {
+            return Err(Status::INVALID_PARAMETER);
+        }
+
+        let attribute = usize::from(fg.to_raw()) | (usize::from(bg.to_raw()) << 4);
+        unsafe { abi_call!(self, set_attribute(attribute)) }.to_result()
+    }
+
+    /// Clears the screen with the current background color and moves the cursor to the top-left
+    /// corner.
+    pub fn clear_screen(&mut self) -> Result<()> {
+        unsafe { abi_call!(self, clear_screen()) }.to_result()
+    }
+
+    /// Moves the cursor to column `col` and row `row`, both zero-based.
+    ///
+    /// Returns `UNSUPPORTED` if the position lies outside the current text mode.
+    pub fn set_cursor_position(&mut self, col: usize, row: usize) -> Result<()> {
+        unsafe { abi_call!(self, set_cursor_pos(col, row)) }.to_result()
+    }
+
+    /// Makes the cursor visible or invisible.
+    ///
+    /// Returns `UNSUPPORTED` if the output device cannot control cursor visibility.
+    pub fn enable_cursor(&mut self, visible: bool) -> Result<()> {
+        unsafe { abi_call!(self, enable_cursor(visible)) }.to_result()
+    }
+
     /// # Safety
     ///
     /// Pointer must be valid and point to a nul-terminated buffer.
@@ -80,3 +139,53 @@ impl fmt::Write for SimpleTextOutput {
         self.output_str(s).map_err(|_| fmt::Error)
     }
 }
+
+#[cfg(test)]
+impl SimpleTextOutputAbi {
+    /// Creates a fake console for tests, which passes all output to `output_string` and does not
+    /// support any other operations.
+    pub(crate) fn with_output(
+        output_string: unsafe extern "efiapi" fn(*mut Self, *const u16) -> Status,
+    ) -> Self {
+        extern "efiapi" fn reset(_: *mut SimpleTextOutputAbi, _: bool) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn test_string(_: *mut SimpleTextOutputAbi, _: *const u16) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn set_attribute(_: *mut SimpleTextOutputAbi, _: usize) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn clear_screen(_: *mut SimpleTextOutputAbi) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn set_cursor_pos(
+            _: *mut SimpleTextOutputAbi,
+            _: usize,
+            _: usize,
+        ) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        extern "efiapi" fn enable_cursor(_: *mut SimpleTextOutputAbi, _: bool) -> Status {
+            Status::UNSUPPORTED
+        }
+
+        Self {
+            reset,
+            output_string,
+            test_string,
+            query_mode: core::ptr::null(),
+            set_mode: core::ptr::null(),
+            set_attribute,
+            clear_screen,
+            set_cursor_pos,
+            enable_cursor,
+            mode: core::ptr::null(),
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use core::mem;
 use core::ptr::NonNull;
-use core::{iter, mem, slice};
+use core::slice;
+
+use struct_enum::struct_enum;
 
 use crate::{Result, Status, U16CStr};
 
@@ -20,22 +23,48 @@ unsafe_protocol! {
 }
 
 impl DevicePath {
-    pub fn nodes(&self) -> impl Iterator<Item = DeviceNode<'_>> + Clone {
+    /// Returns an iterator over the nodes of the first instance in this device path.
+    ///
+    /// Iteration stops before the first end-of-instance or end-of-path node, which is not yielded,
+    /// or at the first node with a malformed length.
+    pub fn nodes(&self) -> DeviceNodes<'_> {
         // Safety: ABI pointer is valid.
-        let init = DeviceNode(unsafe { &*(self.abi() as *const DeviceNodeHeaderAbi) });
-
-        iter::successors(Some(init), |cur| {
-            if cur.node_type() == DeviceNode::TYPE_END
-                && cur.sub_type() == DeviceNode::SUB_TYPE_END_ENTIRE
-            {
-                None
-            } else {
-                // Safety: `length` bytes ahead there should be another device node header.
-                let next =
-                    unsafe { cur.ptr().add(cur.0.length as usize) } as *const DeviceNodeHeaderAbi;
-                Some(DeviceNode(unsafe { &*next }))
-            }
-        })
+        let first = DeviceNode(unsafe { &*(self.abi() as *const DeviceNodeHeaderAbi) });
+        DeviceNodes { next: Some(first) }
+    }
+}
+
+#[derive(Clone)]
+pub struct DeviceNodes<'a> {
+    next: Option<DeviceNode<'a>>,
+}
+
+impl<'a> Iterator for DeviceNodes<'a> {
+    type Item = DeviceNode<'a>;
+
+    fn next(&mut self) -> Option<DeviceNode<'a>> {
+        let cur = self.next.take()?;
+        if cur.is_end_node() || cur.is_end_instance() || !cur.has_valid_length() {
+            return None;
+        }
+
+        // Safety: `length` bytes ahead there should be another device node header, as this node is
+        // not an end node.
+        let next = unsafe { cur.ptr().add(cur.length() as usize) } as *const DeviceNodeHeaderAbi;
+        self.next = Some(DeviceNode(unsafe { &*next }));
+
+        Some(cur)
+    }
+}
+
+struct_enum! {
+    pub struct DeviceNodeType: u8 {
+        HARDWARE = 0x1;
+        ACPI = 0x2;
+        MESSAGING = 0x3;
+        MEDIA = 0x4;
+        BIOS = 0x5;
+        END = 0x7f;
     }
 }
 
@@ -43,12 +72,12 @@ impl DevicePath {
 pub struct DeviceNode<'a>(&'a DeviceNodeHeaderAbi);
 
 impl<'a> DeviceNode<'a> {
-    pub const TYPE_HARDWARE: u8 = 0x1;
-    pub const TYPE_ACPI: u8 = 0x2;
-    pub const TYPE_MESSAGING: u8 = 0x3;
-    pub const TYPE_MEDIA: u8 = 0x4;
-    pub const TYPE_BIOS: u8 = 0x5;
-    pub const TYPE_END: u8 = 0x7f;
+    pub const TYPE_HARDWARE: u8 = DeviceNodeType::HARDWARE.to_raw();
+    pub const TYPE_ACPI: u8 = DeviceNodeType::ACPI.to_raw();
+    pub const TYPE_MESSAGING: u8 = DeviceNodeType::MESSAGING.to_raw();
+    pub const TYPE_MEDIA: u8 = DeviceNodeType::MEDIA.to_raw();
+    pub const TYPE_BIOS: u8 = DeviceNodeType::BIOS.to_raw();
+    pub const TYPE_END: u8 = DeviceNodeType::END.to_raw();
 
     pub const SUB_TYPE_END_ENTIRE: u8 = 0xff;
     pub const SUB_TYPE_END_DEVICE: u8 = 0x1;
@@ -57,19 +86,34 @@ impl<'a> DeviceNode<'a> {
         self.0 as *const _ as *const u8
     }
 
-    pub fn node_type(&self) -> u8 {
-        self.0.node_type
+    pub fn node_type(&self) -> DeviceNodeType {
+        DeviceNodeType::from_raw(self.0.node_type)
     }
 
+    /// Returns the raw sub-type of this node, whose meaning depends on [`node_type`](Self::node_type).
     pub fn sub_type(&self) -> u8 {
         self.0.sub_type
     }
 
+    /// Returns the length of this node in bytes, including its header.
+    pub fn length(&self) -> u16 {
+        self.0.length
+    }
+
+    /// Returns `true` if this node terminates the entire device path.
+    pub fn is_end_node(&self) -> bool {
+        self.node_type() == DeviceNodeType::END && self.sub_type() == Self::SUB_TYPE_END_ENTIRE
+    }
+
+    /// Returns `true` if this node terminates a single instance in a multi-instance device path.
+    pub fn is_end_instance(&self) -> bool {
+        self.node_type() == DeviceNodeType::END && self.sub_type() == Self::SUB_TYPE_END_DEVICE
+    }
+
     pub fn data(&self) -> &'a [u8] {
-        let full_length = self.0.length as usize;
-        assert!(full_length >= mem::size_of::<DeviceNodeHeaderAbi>());
+        assert!(self.has_valid_length());
 
-        let length = full_length - mem::size_of::<DeviceNodeHeaderAbi>();
+        let length = self.length() as usize - mem::size_of::<DeviceNodeHeaderAbi>();
         unsafe {
             slice::from_raw_parts(
                 self.ptr().add(mem::size_of::<DeviceNodeHeaderAbi>()),
@@ -77,6 +121,10 @@ impl<'a> DeviceNode<'a> {
             )
         }
     }
+
+    fn has_valid_length(&self) -> bool {
+        self.length() as usize >= mem::size_of::<DeviceNodeHeaderAbi>()
+    }
 }
 
 #[repr(C)]
@@ -125,3 +173,140 @@ impl DevicePathToText {
         Ok(unsafe { NonNull::new_unchecked(U16CStr::from_ptr(p) as *const _ as *mut _) })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Appends a device node with the specified type, sub-type and data to `path`.
+    fn push_node(path: &mut Vec<u8>, node_type: u8, sub_type: u8, data: &[u8]) {
+        let length = (mem::size_of::<DeviceNodeHeaderAbi>() + data.len()) as u16;
+        path.extend_from_slice(&[node_type, sub_type]);
+        path.extend_from_slice(&length.to_le_bytes());
+        path.extend_from_slice(data);
+    }
+
+    fn with_device_path<R>(path: &[u8], f: impl FnOnce(&DevicePath) -> R) -> R {
+        // Safety: `path` outlives the protocol instance, and device paths are only ever read.
+        let device_path = unsafe { DevicePath::from_abi(path.as_ptr() as *mut DevicePathAbi) };
+        f(&device_path)
+    }
+
+    #[test]
+    fn nodes_stop_at_end() {
+        let mut path = Vec::new();
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_ACPI,
+            1,
+            &[0xd0, 0x41, 0x03, 0x0a, 0, 0, 0, 0],
+        );
+        push_node(&mut path, DeviceNode::TYPE_HARDWARE, 1, &[0, 0x1f]);
+        push_node(&mut path, DeviceNode::TYPE_MEDIA, 4, &[b'\\', 0, 0, 0]);
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_END,
+            DeviceNode::SUB_TYPE_END_ENTIRE,
+            &[],
+        );
+
+        with_device_path(&path, |device_path| {
+            let nodes: Vec<_> = device_path
+                .nodes()
+                .map(|node| {
+                    (
+                        node.node_type(),
+                        node.sub_type(),
+                        node.length(),
+                        node.data(),
+                    )
+                })
+                .collect();
+
+            assert_eq!(
+                nodes,
+                [
+                    (
+                        DeviceNodeType::ACPI,
+                        1,
+                        12,
+                        &[0xd0, 0x41, 0x03, 0x0a, 0, 0, 0, 0][..]
+                    ),
+                    (DeviceNodeType::HARDWARE, 1, 6, &[0, 0x1f][..]),
+                    (DeviceNodeType::MEDIA, 4, 8, &[b'\\', 0, 0, 0][..]),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn nodes_stop_at_end_instance() {
+        let mut path = Vec::new();
+        push_node(&mut path, DeviceNode::TYPE_HARDWARE, 1, &[0, 0x1f]);
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_END,
+            DeviceNode::SUB_TYPE_END_DEVICE,
+            &[],
+        );
+        push_node(&mut path, DeviceNode::TYPE_MESSAGING, 5, &[1, 2]);
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_END,
+            DeviceNode::SUB_TYPE_END_ENTIRE,
+            &[],
+        );
+
+        with_device_path(&path, |device_path| {
+            let nodes: Vec<_> = device_path.nodes().map(|node| node.node_type()).collect();
+            assert_eq!(nodes, [DeviceNodeType::HARDWARE]);
+        });
+    }
+
+    #[test]
+    fn nodes_stop_at_malformed_length() {
+        let mut path = Vec::new();
+        push_node(&mut path, DeviceNode::TYPE_HARDWARE, 1, &[0, 0x1f]);
+        path.extend_from_slice(&[DeviceNode::TYPE_MEDIA, 4, 2, 0]);
+
+        with_device_path(&path, |device_path| {
+            assert_eq!(device_path.nodes().count(), 1);
+        });
+    }
+
+    #[test]
+    fn end_node_predicates() {
+        let mut path = Vec::new();
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_END,
+            DeviceNode::SUB_TYPE_END_ENTIRE,
+            &[],
+        );
+        push_node(
+            &mut path,
+            DeviceNode::TYPE_END,
+            DeviceNode::SUB_TYPE_END_DEVICE,
+            &[],
+        );
+        push_node(&mut path, DeviceNode::TYPE_MEDIA, 0xff, &[]);
+
+        // Safety: every offset used below is the start of a node header within `path`.
+        let node = |off: usize| DeviceNode(unsafe { &*(path[off..].as_ptr().cast()) });
+
+        assert!(node(0).is_end_node());
+        assert!(!node(0).is_end_instance());
+        assert!(!node(4).is_end_node());
+        assert!(node(4).is_end_instance());
+        assert!(!node(8).is_end_node());
+        assert!(!node(8).is_end_instance());
+
+        with_device_path(&path, |device_path| {
+            assert_eq!(device_path.nodes().count(), 0);
+        });
+    }
+}
@@ -40,6 +40,33 @@ impl Status {
         !self.is_success() && !self.is_err()
     }
 
+    /// Returns `true` if this is [`NOT_FOUND`](Self::NOT_FOUND), as returned when a requested
+    /// protocol, file or other item does not exist.
+    pub fn is_not_found(self) -> bool {
+        self == Self::NOT_FOUND
+    }
+
+    /// Returns `true` if this is [`UNSUPPORTED`](Self::UNSUPPORTED), as returned when a handle
+    /// does not support a requested protocol or operation.
+    pub fn is_unsupported(self) -> bool {
+        self == Self::UNSUPPORTED
+    }
+
+    /// Returns `true` if this is [`INVALID_PARAMETER`](Self::INVALID_PARAMETER).
+    pub fn is_invalid_parameter(self) -> bool {
+        self == Self::INVALID_PARAMETER
+    }
+
+    /// Returns `true` if this is [`BUFFER_TOO_SMALL`](Self::BUFFER_TOO_SMALL).
+    pub fn is_buffer_too_small(self) -> bool {
+        self == Self::BUFFER_TOO_SMALL
+    }
+
+    /// Returns `true` if this is [`OUT_OF_RESOURCES`](Self::OUT_OF_RESOURCES).
+    pub fn is_out_of_resources(self) -> bool {
+        self == Self::OUT_OF_RESOURCES
+    }
+
     pub fn to_result(self) -> Result<()> {
         if self.is_err() {
             Err(self)
@@ -48,3 +75,49 @@ impl Status {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severity() {
+        assert!(Status::SUCCESS.is_success());
+        assert!(!Status::SUCCESS.is_err());
+        assert!(!Status::SUCCESS.is_warn());
+
+        assert!(Status::WARN_UNKNOWN_GLYPH.is_warn());
+        assert!(!Status::WARN_UNKNOWN_GLYPH.is_err());
+
+        assert!(Status::NOT_FOUND.is_err());
+        assert!(!Status::NOT_FOUND.is_warn());
+        assert!(!Status::NOT_FOUND.is_success());
+    }
+
+    #[test]
+    fn error_predicates() {
+        type Predicate = fn(Status) -> bool;
+
+        let predicates: [(Status, Predicate); 5] = [
+            (Status::NOT_FOUND, Status::is_not_found),
+            (Status::UNSUPPORTED, Status::is_unsupported),
+            (Status::INVALID_PARAMETER, Status::is_invalid_parameter),
+            (Status::BUFFER_TOO_SMALL, Status::is_buffer_too_small),
+            (Status::OUT_OF_RESOURCES, Status::is_out_of_resources),
+        ];
+
+        for (expected, predicate) in predicates {
+            for &raw in Status::all_values() {
+                let status = Status::from_raw(raw);
+                assert_eq!(predicate(status), status == expected, "{status:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn to_result() {
+        assert_eq!(Status::SUCCESS.to_result(), Ok(()));
+        assert_eq!(Status::WARN_UNKNOWN_GLYPH.to_result(), Ok(()));
+        assert_eq!(Status::NOT_FOUND.to_result(), Err(Status::NOT_FOUND));
+    }
+}
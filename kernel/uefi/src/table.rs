@@ -1,3 +1,4 @@
+use core::fmt::Write;
 use core::iter::FusedIterator;
 use core::marker::PhantomData;
 use core::ops::{Deref, DerefMut};
@@ -11,9 +12,13 @@ use crate::proto::io::{SimpleTextOutput, SimpleTextOutputAbi};
 use crate::proto::{Protocol, ProtocolHandle};
 use crate::{
     ConfigTableEntry, Guid, Handle, MemoryDescriptor, MemoryMapKey, MemoryType, Result, Status,
-    U16CStr,
+    Tpl, U16CStr,
 };
 
+/// The maximum number of times [`BootTable::exit_boot_services`] will attempt to exit boot services
+/// when the memory map keeps changing underneath it.
+pub const EXIT_BOOT_SERVICES_MAX_ATTEMPTS: usize = 3;
+
 pub struct OpenProtocolHandle<'a, P: Protocol> {
     proto: P,
     handle: Handle,
@@ -73,6 +78,28 @@ impl<P: Protocol> Drop for OpenProtocolHandle<'_, P> {
     }
 }
 
+/// An RAII guard returned by [`BootServices::raise_tpl`], which restores the previous task priority
+/// level when dropped.
+pub struct TplGuard<'a> {
+    boot_services: &'a BootServices,
+    old_tpl: Tpl,
+}
+
+impl TplGuard<'_> {
+    /// Returns the task priority level that will be restored when this guard is dropped.
+    pub fn old_tpl(&self) -> Tpl {
+        self.old_tpl
+    }
+}
+
+impl Drop for TplGuard<'_> {
+    fn drop(&mut self) {
+        // Safety: `old_tpl` was returned by the matching `raise_tpl` call, and guards are dropped
+        // in reverse order of creation.
+        unsafe { (self.boot_services.restore_tpl)(self.old_tpl) }
+    }
+}
+
 #[derive(Clone)]
 pub struct MemoryMapIter<'a> {
     ptr: NonNull<u8>,
@@ -140,8 +167,8 @@ enum AllocModeAbi {
 pub struct BootServices {
     header: TableHeader,
 
-    raise_tpl: *const (),
-    restore_tpl: *const (),
+    raise_tpl: unsafe extern "efiapi" fn(Tpl) -> Tpl,
+    restore_tpl: unsafe extern "efiapi" fn(Tpl),
 
     allocate_pages: unsafe extern "efiapi" fn(AllocModeAbi, MemoryType, usize, *mut u64) -> Status,
     free_pages: unsafe extern "efiapi" fn(u64, usize) -> Status,
@@ -197,6 +224,33 @@ pub struct BootServices {
 }
 
 impl BootServices {
+    /// Raises the task priority level to `tpl`, returning a guard that will restore the previous
+    /// level when dropped.
+    ///
+    /// This can be used to protect short critical sections from being interrupted by event
+    /// notifications:
+    ///
+    /// ```ignore
+    /// {
+    ///     let _tpl_guard = unsafe { boot_services.raise_tpl(Tpl::NOTIFY) };
+    ///     // Notification functions at `Tpl::NOTIFY` and below cannot run here.
+    /// }
+    /// // The previous level has been restored.
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// * `tpl` must not be lower than the current task priority level
+    /// * Nested guards must be dropped in the reverse order of their creation
+    pub unsafe fn raise_tpl(&self, tpl: Tpl) -> TplGuard<'_> {
+        // Safety: the caller guarantees that we are not lowering the level.
+        let old_tpl = unsafe { (self.raise_tpl)(tpl) };
+        TplGuard {
+            boot_services: self,
+            old_tpl,
+        }
+    }
+
     pub fn memory_map_size(&self) -> Result<(usize, usize)> {
         let mut mmap_size = 0;
         let mut key = MemoryMapKey(0);
@@ -299,6 +353,12 @@ impl BootServices {
             .expect("invalid page allocation")
     }
 
+    /// Opens protocol `P` on `handle` on behalf of the agent `image_handle`.
+    ///
+    /// # Errors
+    ///
+    /// * `UNSUPPORTED` - `handle` does not support protocol `P`.
+    /// * `INVALID_PARAMETER` - `handle` or `image_handle` is not a valid handle.
     pub fn open_protocol<P: Protocol>(
         &self,
         handle: Handle,
@@ -323,6 +383,11 @@ impl BootServices {
         Ok(unsafe { OpenProtocolHandle::from_abi(abi, handle, self, image_handle) })
     }
 
+    /// Returns the first installed instance of protocol `P`.
+    ///
+    /// # Errors
+    ///
+    /// * `NOT_FOUND` - No instances of protocol `P` are installed.
     pub fn locate_protocol<P: Protocol>(&self) -> Result<ProtocolHandle<'_, P>> {
         let mut abi = ptr::null_mut();
 
@@ -390,6 +455,15 @@ impl<S: TableState> SystemTable<S> {
     pub fn config_table(&self) -> &[ConfigTableEntry] {
         unsafe { slice::from_raw_parts(self.0.config_table, self.0.config_table_entries) }
     }
+
+    /// Returns the pointer stored in the first configuration table entry identified by `guid`, if
+    /// any.
+    pub fn find_config_table(&self, guid: &Guid) -> Option<*const ()> {
+        self.config_table()
+            .iter()
+            .find(|entry| entry.guid == *guid)
+            .map(|entry| entry.ptr as *const ())
+    }
 }
 
 impl BootTable {
@@ -398,13 +472,24 @@ impl BootTable {
         unsafe { &*self.0.boot_services }
     }
 
+    /// Retrieves the memory map into `mmap_buf` and exits boot services, invoking `runtime_func`
+    /// with the runtime table and final memory map.
+    ///
+    /// If the memory map changes between retrieving it and exiting boot services, the operation is
+    /// retried up to [`EXIT_BOOT_SERVICES_MAX_ATTEMPTS`] times, with each retry reported on the
+    /// console.
+    ///
+    /// # Errors
+    ///
+    /// * `INVALID_PARAMETER` - The memory map kept changing, and all attempts were exhausted.
+    /// * Any errors returned when retrieving the memory map or exiting boot services.
     pub fn exit_boot_services(
         self,
         image_handle: Handle,
         mut mmap_buf: Out<'_, [u8]>,
         runtime_func: impl FnOnce(RuntimeTable, MemoryMapIter<'_>) -> Never,
     ) -> Result<Never> {
-        loop {
+        for attempt in 1..=EXIT_BOOT_SERVICES_MAX_ATTEMPTS {
             // Work around rust-lang/rust#51526.
             // Safety: We never actually create overlapping mutable references, as each reborrow
             // lasts only for the current iteration.
@@ -413,7 +498,13 @@ impl BootTable {
 
             let status = unsafe { (self.boot_services().exit_boot_services)(image_handle, key) };
             if status == Status::INVALID_PARAMETER {
-                // Memory map invalidated, try again.
+                // Memory map invalidated, try again. Boot services are still available at this
+                // point, so the console can be used.
+                let _ = writeln!(
+                    self.stdout(),
+                    "memory map changed while exiting boot services (attempt {attempt}/{})",
+                    EXIT_BOOT_SERVICES_MAX_ATTEMPTS
+                );
                 continue;
             }
             status.to_result()?;
@@ -421,9 +512,369 @@ impl BootTable {
             let runtime_table = unsafe { RuntimeTable::from_abi(self.abi()) };
             runtime_func(runtime_table, mmap);
         }
+
+        Err(Status::INVALID_PARAMETER)
     }
 
     pub fn stdout(&self) -> ProtocolHandle<'_, SimpleTextOutput> {
         unsafe { ProtocolHandle::from_abi(self.0.console_out_protocol) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::cell::{Cell, RefCell};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::string::{String, ToString};
+
+    use core::mem::MaybeUninit;
+
+    use uninit::extension_traits::AsOut;
+
+    use super::*;
+    use crate::guid;
+
+    const ACPI_20: Guid = guid!("8868e871-e4f1-11d3-bc22-0080c73c8881");
+    const SMBIOS: Guid = guid!("eb9d2d31-2d88-11d3-9a16-0090273fc14d");
+    const MISSING: Guid = guid!("eb9d2d30-2d88-11d3-9a16-0090273fc14d");
+
+    fn system_table_abi(config_table: &[ConfigTableEntry]) -> SystemTableAbi {
+        SystemTableAbi {
+            header: TableHeader {
+                signature: 0,
+                revision: 0,
+                header_size: mem::size_of::<SystemTableAbi>() as u32,
+                crc32: 0,
+                reserved: 0,
+            },
+            firmware_vendor: ptr::null(),
+            firmware_revision: 0,
+            console_in_handle: Handle(ptr::null()),
+            console_in_protocol: ptr::null(),
+            console_out_handle: Handle(ptr::null()),
+            console_out_protocol: ptr::null_mut(),
+            stderr_handle: Handle(ptr::null()),
+            stderr_protocol: ptr::null_mut(),
+            runtime_services: ptr::null(),
+            boot_services: ptr::null(),
+            config_table_entries: config_table.len(),
+            config_table: config_table.as_ptr(),
+        }
+    }
+
+    #[test]
+    fn find_config_table() {
+        let config_table = [
+            ConfigTableEntry {
+                guid: SMBIOS,
+                ptr: 0x1000,
+            },
+            ConfigTableEntry {
+                guid: ACPI_20,
+                ptr: 0x2000,
+            },
+            ConfigTableEntry {
+                guid: ACPI_20,
+                ptr: 0x3000,
+            },
+        ];
+        let abi = system_table_abi(&config_table);
+
+        // Safety: `abi` and the configuration table outlive `table`.
+        let table = unsafe { RuntimeTable::from_abi(&abi) };
+
+        assert_eq!(table.config_table().len(), 3);
+        assert_eq!(table.find_config_table(&SMBIOS), Some(0x1000 as *const ()));
+        assert_eq!(table.find_config_table(&ACPI_20), Some(0x2000 as *const ()));
+        assert_eq!(table.find_config_table(&MISSING), None);
+    }
+
+    #[test]
+    fn find_config_table_empty() {
+        let abi = system_table_abi(&[]);
+
+        // Safety: `abi` outlives `table`.
+        let table = unsafe { RuntimeTable::from_abi(&abi) };
+
+        assert!(table.config_table().is_empty());
+        assert_eq!(table.find_config_table(&ACPI_20), None);
+    }
+
+    std::thread_local! {
+        /// The number of times the fake `exit_boot_services` should report a stale memory map.
+        static STALE_EXITS: Cell<usize> = Cell::new(0);
+        static MMAP_CALLS: Cell<usize> = Cell::new(0);
+        static EXIT_CALLS: Cell<usize> = Cell::new(0);
+        static CONSOLE_OUTPUT: RefCell<String> = RefCell::new(String::new());
+    }
+
+    const FAKE_MMAP: [MemoryDescriptor; 2] = [
+        MemoryDescriptor {
+            mem_type: MemoryType::CONVENTIONAL,
+            phys_start: 0,
+            virt_start: 0,
+            page_count: 0x9f,
+            attr: 0,
+        },
+        MemoryDescriptor {
+            mem_type: MemoryType::LOADER_DATA,
+            phys_start: 0x100000,
+            virt_start: 0,
+            page_count: 0x100,
+            attr: 0,
+        },
+    ];
+
+    /// Returns [`FAKE_MMAP`], with a new key on every call.
+    extern "efiapi" fn fake_get_memory_map(
+        size: *mut usize,
+        descs: *mut MemoryDescriptor,
+        key: *mut MemoryMapKey,
+        desc_size: *mut usize,
+        version: *mut u32,
+    ) -> Status {
+        let calls = MMAP_CALLS.with(|calls| {
+            calls.set(calls.get() + 1);
+            calls.get()
+        });
+
+        // Safety: `memory_map` passes valid pointers and a buffer of `*size` bytes.
+        unsafe {
+            if *size < mem::size_of_val(&FAKE_MMAP) {
+                return Status::BUFFER_TOO_SMALL;
+            }
+
+            ptr::copy_nonoverlapping(FAKE_MMAP.as_ptr(), descs, FAKE_MMAP.len());
+            *size = mem::size_of_val(&FAKE_MMAP);
+            *key = MemoryMapKey(calls);
+            *desc_size = mem::size_of::<MemoryDescriptor>();
+            *version = 1;
+        }
+
+        Status::SUCCESS
+    }
+
+    /// Succeeds once [`STALE_EXITS`] attempts have been rejected, provided that `key` is the one
+    /// returned by the latest call to [`fake_get_memory_map`].
+    extern "efiapi" fn fake_exit_boot_services(_: Handle, key: MemoryMapKey) -> Status {
+        let calls = EXIT_CALLS.with(|calls| {
+            calls.set(calls.get() + 1);
+            calls.get()
+        });
+
+        if key != MemoryMapKey(MMAP_CALLS.with(Cell::get)) || calls <= STALE_EXITS.with(Cell::get) {
+            Status::INVALID_PARAMETER
+        } else {
+            Status::SUCCESS
+        }
+    }
+
+    extern "efiapi" fn fake_output_string(_: *mut SimpleTextOutputAbi, s: *const u16) -> Status {
+        // Safety: `output_str` always passes a nul-terminated buffer.
+        let s = unsafe { U16CStr::from_ptr(s) };
+        CONSOLE_OUTPUT.with(|output| output.borrow_mut().push_str(&s.to_string()));
+        Status::SUCCESS
+    }
+
+    extern "efiapi" fn unsupported_raise_tpl(tpl: Tpl) -> Tpl {
+        tpl
+    }
+
+    extern "efiapi" fn unsupported_restore_tpl(_: Tpl) {}
+
+    extern "efiapi" fn unsupported_allocate_pages(
+        _: AllocModeAbi,
+        _: MemoryType,
+        _: usize,
+        _: *mut u64,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_free_pages(_: u64, _: usize) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_allocate_pool(
+        _: MemoryType,
+        _: usize,
+        _: *mut *mut u8,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_free_pool(_: *mut u8) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_open_protocol(
+        _: Handle,
+        _: *const Guid,
+        _: *mut *mut u8,
+        _: Handle,
+        _: Handle,
+        _: u32,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_close_protocol(
+        _: Handle,
+        _: *const Guid,
+        _: Handle,
+        _: Handle,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    extern "efiapi" fn unsupported_locate_protocol(
+        _: *const Guid,
+        _: *const u8,
+        _: *mut *mut u8,
+    ) -> Status {
+        Status::UNSUPPORTED
+    }
+
+    fn fake_boot_services() -> BootServices {
+        BootServices {
+            header: TableHeader {
+                signature: 0,
+                revision: 0,
+                header_size: mem::size_of::<BootServices>() as u32,
+                crc32: 0,
+                reserved: 0,
+            },
+            raise_tpl: unsupported_raise_tpl,
+            restore_tpl: unsupported_restore_tpl,
+            allocate_pages: unsupported_allocate_pages,
+            free_pages: unsupported_free_pages,
+            get_memory_map: fake_get_memory_map,
+            allocate_pool: unsupported_allocate_pool,
+            free_pool: unsupported_free_pool,
+            create_event: ptr::null(),
+            set_timer: ptr::null(),
+            wait_for_event: ptr::null(),
+            signal_event: ptr::null(),
+            close_event: ptr::null(),
+            check_event: ptr::null(),
+            install_protocol_interface: ptr::null(),
+            reinstall_protocol_interface: ptr::null(),
+            uninstall_protocol_interface: ptr::null(),
+            handle_protocol: ptr::null(),
+            reserved: ptr::null(),
+            register_protocol_notify: ptr::null(),
+            locate_handle: ptr::null(),
+            locate_device_path: ptr::null(),
+            install_configuration_table: ptr::null(),
+            load_image: ptr::null(),
+            start_image: ptr::null(),
+            exit: ptr::null(),
+            unload_image: ptr::null(),
+            exit_boot_services: fake_exit_boot_services,
+            get_next_monotonic_count: ptr::null(),
+            stall: ptr::null(),
+            set_watchdog_timer: ptr::null(),
+            connect_controller: ptr::null(),
+            disconnect_controller: ptr::null(),
+            open_protocol: unsupported_open_protocol,
+            close_protocol: unsupported_close_protocol,
+            open_protocol_information: ptr::null(),
+            protocols_per_handle: ptr::null(),
+            locate_handle_buffer: ptr::null(),
+            locate_protocol: unsupported_locate_protocol,
+        }
+    }
+
+    /// Payload used to unwind out of the diverging `runtime_func`.
+    struct Booted {
+        mmap_len: usize,
+    }
+
+    /// Runs [`BootTable::exit_boot_services`] against fake firmware that reports a stale memory
+    /// map `stale_exits` times.
+    ///
+    /// Returns the result of the call, mapping a successful exit to `Ok` with the number of
+    /// descriptors passed to the runtime function.
+    fn exit_with_stale_maps(stale_exits: usize) -> Result<usize> {
+        STALE_EXITS.with(|stale| stale.set(stale_exits));
+        MMAP_CALLS.with(|calls| calls.set(0));
+        EXIT_CALLS.with(|calls| calls.set(0));
+        CONSOLE_OUTPUT.with(|output| output.borrow_mut().clear());
+
+        let boot_services = fake_boot_services();
+        let mut console = SimpleTextOutputAbi::with_output(fake_output_string);
+        let mut abi = system_table_abi(&[]);
+        abi.boot_services = &boot_services;
+        abi.console_out_protocol = &mut console;
+
+        // Safety: `abi` and everything it points to outlive `table`.
+        let table = unsafe { BootTable::from_abi(&abi) };
+
+        let mut mmap_buf = [MaybeUninit::<MemoryDescriptor>::uninit(); 4];
+        // Safety: the buffer is valid for its full size in bytes, and `MaybeUninit<u8>` has no
+        // validity requirements.
+        let mmap_buf = unsafe {
+            slice::from_raw_parts_mut(
+                mmap_buf.as_mut_ptr().cast::<MaybeUninit<u8>>(),
+                mem::size_of_val(&mmap_buf),
+            )
+        };
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            table.exit_boot_services(Handle(ptr::null()), mmap_buf.as_out(), |_, mmap| {
+                panic::panic_any(Booted {
+                    mmap_len: mmap.len(),
+                })
+            })
+        }));
+
+        match res {
+            Ok(res) => res.map(|never| match never {}),
+            Err(payload) => Ok(payload.downcast::<Booted>().unwrap().mmap_len),
+        }
+    }
+
+    #[test]
+    fn exit_boot_services_first_attempt() {
+        assert_eq!(exit_with_stale_maps(0), Ok(FAKE_MMAP.len()));
+        assert_eq!(EXIT_CALLS.with(Cell::get), 1);
+        assert_eq!(CONSOLE_OUTPUT.with(|output| output.borrow().clone()), "");
+    }
+
+    #[test]
+    fn exit_boot_services_retries() {
+        assert_eq!(
+            exit_with_stale_maps(EXIT_BOOT_SERVICES_MAX_ATTEMPTS - 1),
+            Ok(FAKE_MMAP.len())
+        );
+        assert_eq!(EXIT_CALLS.with(Cell::get), EXIT_BOOT_SERVICES_MAX_ATTEMPTS);
+        assert_eq!(MMAP_CALLS.with(Cell::get), EXIT_BOOT_SERVICES_MAX_ATTEMPTS);
+
+        let output = CONSOLE_OUTPUT.with(|output| output.borrow().clone());
+        assert_eq!(
+            output.lines().count(),
+            EXIT_BOOT_SERVICES_MAX_ATTEMPTS - 1,
+            "{output:?}"
+        );
+        assert!(output.contains("(attempt 1/3)"), "{output:?}");
+    }
+
+    #[test]
+    fn exit_boot_services_gives_up() {
+        assert_eq!(
+            exit_with_stale_maps(usize::MAX),
+            Err(Status::INVALID_PARAMETER)
+        );
+        assert_eq!(EXIT_CALLS.with(Cell::get), EXIT_BOOT_SERVICES_MAX_ATTEMPTS);
+
+        let output = CONSOLE_OUTPUT.with(|output| output.borrow().clone());
+        assert_eq!(
+            output.lines().count(),
+            EXIT_BOOT_SERVICES_MAX_ATTEMPTS,
+            "{output:?}"
+        );
+        assert!(output.contains("(attempt 3/3)"), "{output:?}");
+    }
+}
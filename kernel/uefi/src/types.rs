@@ -1,3 +1,5 @@
+use core::fmt;
+
 use struct_enum::struct_enum;
 
 #[derive(Debug, Clone, Copy)]
@@ -8,6 +10,73 @@ pub struct Handle(pub(crate) *const ());
 #[repr(C)]
 pub struct Guid(pub u32, pub u16, pub u16, pub [u8; 8]);
 
+/// Error returned by [`Guid::parse`] when the input is not a GUID in canonical form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuidParseError;
+
+impl fmt::Display for GuidParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid GUID")
+    }
+}
+
+impl Guid {
+    /// Parses a GUID in the canonical `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form.
+    ///
+    /// The resulting field layout matches the one produced by the [`guid!`](crate::guid) macro.
+    pub fn parse(s: &str) -> Result<Self, GuidParseError> {
+        let mut parts = s.split('-');
+        let mut next_part = |digits| {
+            parts
+                .next()
+                .ok_or(GuidParseError)
+                .and_then(|part| parse_hex(part, digits))
+        };
+
+        let time_low = next_part(8)? as u32;
+        let time_mid = next_part(4)? as u16;
+        let time_high_ver = next_part(4)? as u16;
+        let clock = (next_part(4)? as u16).to_be_bytes();
+        let node = next_part(12)?.to_be_bytes();
+
+        if parts.next().is_some() {
+            return Err(GuidParseError);
+        }
+
+        Ok(Self(
+            time_low,
+            time_mid,
+            time_high_ver,
+            [
+                clock[0], clock[1], node[2], node[3], node[4], node[5], node[6], node[7],
+            ],
+        ))
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tail = &self.3;
+        write!(
+            f,
+            "{:08x}-{:04x}-{:04x}-{:02x}{:02x}-",
+            self.0, self.1, self.2, tail[0], tail[1]
+        )?;
+        for byte in &tail[2..] {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(input: &str, digits: usize) -> Result<u64, GuidParseError> {
+    if input.len() != digits || !input.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return Err(GuidParseError);
+    }
+
+    u64::from_str_radix(input, 16).map_err(|_| GuidParseError)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct Timestamp {
@@ -43,7 +112,35 @@ struct_enum! {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl MemoryType {
+    /// Returns whether memory of this type is free for general use once boot services have been
+    /// exited.
+    ///
+    /// This includes memory used by boot services and by the loader itself, which the firmware no
+    /// longer needs at that point.
+    pub fn is_usable_after_exit(self) -> bool {
+        matches!(
+            self,
+            Self::CONVENTIONAL
+                | Self::LOADER_CODE
+                | Self::LOADER_DATA
+                | Self::BOOT_SERVICES_CODE
+                | Self::BOOT_SERVICES_DATA
+        )
+    }
+}
+
+struct_enum! {
+    /// A UEFI task priority level (TPL).
+    pub struct Tpl: usize {
+        APPLICATION = 4;
+        CALLBACK = 8;
+        NOTIFY = 16;
+        HIGH_LEVEL = 31;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct MemoryDescriptor {
     pub mem_type: MemoryType,
@@ -53,9 +150,173 @@ pub struct MemoryDescriptor {
     pub attr: u64,
 }
 
+/// Summary statistics for a memory map, computed in a single pass over its descriptors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryMapSummary {
+    /// The number of descriptors in the map.
+    pub descriptor_count: usize,
+    /// The total number of pages described by the map.
+    pub total_pages: u64,
+    /// The number of pages that will be [usable](MemoryType::is_usable_after_exit) once boot
+    /// services have been exited.
+    pub usable_pages: u64,
+    /// The largest single usable descriptor in the map, if any.
+    pub largest_usable: Option<MemoryDescriptor>,
+}
+
+impl MemoryMapSummary {
+    /// Accounts for `desc` in the summary.
+    ///
+    /// This is useful when the summary needs to be gathered alongside another traversal of the
+    /// map.
+    pub fn add(&mut self, desc: &MemoryDescriptor) {
+        self.descriptor_count += 1;
+        self.total_pages += desc.page_count;
+
+        if desc.mem_type.is_usable_after_exit() {
+            self.usable_pages += desc.page_count;
+            if self
+                .largest_usable
+                .map_or(true, |largest| desc.page_count > largest.page_count)
+            {
+                self.largest_usable = Some(*desc);
+            }
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a MemoryDescriptor> for MemoryMapSummary {
+    fn from_iter<I: IntoIterator<Item = &'a MemoryDescriptor>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::default(), |mut summary, desc| {
+            summary.add(desc);
+            summary
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 #[repr(C)]
 pub struct ConfigTableEntry {
     pub guid: Guid,
     pub ptr: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::ToString;
+
+    use super::*;
+    use crate::guid;
+
+    #[test]
+    fn guid_parse_matches_macro() {
+        assert_eq!(
+            Guid::parse("964e5b22-6459-11d2-8e39-00a0c969723b"),
+            Ok(guid!("964e5b22-6459-11d2-8e39-00a0c969723b"))
+        );
+        assert_eq!(
+            Guid::parse("8868E871-E4F1-11D3-BC22-0080C73C8881"),
+            Ok(guid!("8868e871-e4f1-11d3-bc22-0080c73c8881"))
+        );
+    }
+
+    #[test]
+    fn guid_display() {
+        assert_eq!(
+            guid!("09576e92-6d3f-11d2-8e39-00a0c969723b").to_string(),
+            "09576e92-6d3f-11d2-8e39-00a0c969723b"
+        );
+        assert_eq!(
+            Guid(1, 2, 3, [4, 5, 6, 7, 8, 9, 10, 11]).to_string(),
+            "00000001-0002-0003-0405-060708090a0b"
+        );
+    }
+
+    #[test]
+    fn guid_round_trip() {
+        let guids = [
+            // Simple file system protocol.
+            guid!("964e5b22-6459-11d2-8e39-00a0c969723b"),
+            // File info.
+            guid!("09576e92-6d3f-11d2-8e39-00a0c969723b"),
+            // ACPI 2.0 table.
+            guid!("8868e871-e4f1-11d3-bc22-0080c73c8881"),
+            Guid(0, 0, 0, [0; 8]),
+            Guid(u32::MAX, u16::MAX, u16::MAX, [0xff; 8]),
+        ];
+
+        for guid in guids {
+            assert_eq!(Guid::parse(&guid.to_string()), Ok(guid));
+        }
+    }
+
+    #[test]
+    fn guid_parse_invalid() {
+        for s in [
+            "",
+            "964e5b22",
+            "964e5b22-6459-11d2-8e39",
+            "964e5b22-6459-11d2-8e39-00a0c969723",
+            "964e5b22-6459-11d2-8e39-00a0c969723b0",
+            "964e5b22-6459-11d2-8e39-00a0c969723b-",
+            "964e5b2-26459-11d2-8e39-00a0c969723b",
+            "964e5b22-6459-11d2-8e39-00a0c96972xb",
+            "+64e5b22-6459-11d2-8e39-00a0c969723b",
+            "964e5b22_6459_11d2_8e39_00a0c969723b",
+        ] {
+            assert_eq!(Guid::parse(s), Err(GuidParseError), "{s:?}");
+        }
+    }
+
+    fn desc(mem_type: MemoryType, phys_start: u64, page_count: u64) -> MemoryDescriptor {
+        MemoryDescriptor {
+            mem_type,
+            phys_start,
+            virt_start: 0,
+            page_count,
+            attr: 0,
+        }
+    }
+
+    #[test]
+    fn memory_map_summary() {
+        let mmap = [
+            desc(MemoryType::CONVENTIONAL, 0, 0x9f),
+            desc(MemoryType::RESERVED, 0x9f000, 0x61),
+            desc(MemoryType::LOADER_DATA, 0x100000, 0x20),
+            desc(MemoryType::BOOT_SERVICES_DATA, 0x120000, 0x300),
+            desc(MemoryType::RUNTIME_SERVICES_CODE, 0x420000, 0x10),
+            desc(MemoryType::CONVENTIONAL, 0x430000, 0x100),
+        ];
+
+        let summary: MemoryMapSummary = mmap.iter().collect();
+        assert_eq!(summary.descriptor_count, 6);
+        assert_eq!(
+            summary.total_pages,
+            0x9f + 0x61 + 0x20 + 0x300 + 0x10 + 0x100
+        );
+        assert_eq!(summary.usable_pages, 0x9f + 0x20 + 0x300 + 0x100);
+        assert_eq!(summary.largest_usable, Some(mmap[3]));
+    }
+
+    #[test]
+    fn memory_map_summary_without_usable_memory() {
+        let mmap = [
+            desc(MemoryType::RESERVED, 0, 0x10),
+            desc(MemoryType::UNUSABLE, 0x10000, 0x10),
+        ];
+
+        let summary: MemoryMapSummary = mmap.iter().collect();
+        assert_eq!(summary.descriptor_count, 2);
+        assert_eq!(summary.total_pages, 0x20);
+        assert_eq!(summary.usable_pages, 0);
+        assert_eq!(summary.largest_usable, None);
+
+        assert_eq!(
+            MemoryMapSummary::default(),
+            [].iter().collect::<MemoryMapSummary>()
+        );
+    }
+}
@@ -3,6 +3,7 @@
 #![no_std]
 
 use core::borrow::{Borrow, BorrowMut};
+use core::fmt;
 
 use num_utils::div_ceil;
 
@@ -33,11 +34,56 @@ impl<B: Borrow<[u8]>> Bitmap<B> {
         (0..limit).find(|&index| !self.get(index))
     }
 
+    /// Writes the first `bit_len` bits of the bitmap to `out`, using `#` for set bits and `.` for
+    /// clear bits.
+    ///
+    /// Output is split into rows of 64 bits, each prefixed with the index of its first bit.
+    pub fn dump(&self, bit_len: usize, out: &mut dyn fmt::Write) -> fmt::Result {
+        for row_start in (0..bit_len).step_by(DUMP_ROW_BITS) {
+            write!(out, "{row_start:8}: ")?;
+            let row_end = bit_len.min(row_start + DUMP_ROW_BITS);
+            for index in row_start..row_end {
+                out.write_char(if self.get(index) { '#' } else { '.' })?;
+            }
+            out.write_char('\n')?;
+        }
+
+        Ok(())
+    }
+
+    fn bit_len(&self) -> usize {
+        self.bytes().len() * 8
+    }
+
     fn bytes(&self) -> &[u8] {
         self.bytes.borrow()
     }
 }
 
+/// Formats the bitmap as a run-length summary, e.g. `Bitmap[16: 0s:3, 1s:5, 0s:8]` for 3 clear bits,
+/// followed by 5 set bits and 8 more clear bits.
+impl<B: Borrow<[u8]>> fmt::Debug for Bitmap<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.bit_len();
+        write!(f, "Bitmap[{len}:")?;
+
+        let mut index = 0;
+        let mut sep = " ";
+        while index < len {
+            let val = self.get(index);
+            let run_start = index;
+            while index < len && self.get(index) == val {
+                index += 1;
+            }
+
+            write!(f, "{sep}{}s:{}", val as u8, index - run_start)?;
+            sep = ", ";
+        }
+
+        f.write_str("]")
+    }
+}
+
 impl<B: BorrowMut<[u8]>> Bitmap<B> {
     pub fn set(&mut self, index: usize) {
         let (byte, bit) = split_index(index);
@@ -59,6 +105,73 @@ impl<B: BorrowMut<[u8]>> Bitmap<B> {
     }
 }
 
+const DUMP_ROW_BITS: usize = 64;
+
 fn split_index(index: usize) -> (usize, usize) {
     (index / 8, index % 8)
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+    use std::string::String;
+
+    use super::*;
+
+    #[test]
+    fn debug_runs() {
+        let mut bytes = [0u8; 2];
+        let mut bitmap = Bitmap::new(&mut bytes[..]);
+        for index in 3..8 {
+            bitmap.set(index);
+        }
+
+        assert_eq!(format!("{bitmap:?}"), "Bitmap[16: 0s:3, 1s:5, 0s:8]");
+    }
+
+    #[test]
+    fn debug_single_run() {
+        assert_eq!(format!("{:?}", Bitmap::new([0u8; 2])), "Bitmap[16: 0s:16]");
+        assert_eq!(format!("{:?}", Bitmap::new([0xffu8])), "Bitmap[8: 1s:8]");
+        assert_eq!(format!("{:?}", Bitmap::new([0u8; 0])), "Bitmap[0:]");
+    }
+
+    #[test]
+    fn debug_multi_digit_runs() {
+        let mut bitmap = Bitmap::new([0u8; 16]);
+        for index in 10..110 {
+            bitmap.set(index);
+        }
+
+        assert_eq!(format!("{bitmap:?}"), "Bitmap[128: 0s:10, 1s:100, 0s:18]");
+    }
+
+    #[test]
+    fn dump_rows() {
+        let mut bitmap = Bitmap::new([0u8; 10]);
+        bitmap.set(0);
+        bitmap.set(2);
+        bitmap.set(63);
+        bitmap.set(64);
+        bitmap.set(69);
+
+        let mut out = String::new();
+        bitmap.dump(70, &mut out).unwrap();
+
+        let mut expected = String::new();
+        expected.push_str("       0: #.#");
+        expected.push_str(&".".repeat(60));
+        expected.push_str("#\n");
+        expected.push_str("      64: #....#\n");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn dump_empty() {
+        let mut out = String::new();
+        Bitmap::new([0xffu8]).dump(0, &mut out).unwrap();
+        assert_eq!(out, "");
+    }
+}
@@ -0,0 +1,207 @@
+#![feature(allocator_api)]
+#![warn(rust_2018_idioms)]
+#![deny(unsafe_op_in_unsafe_fn)]
+#![no_std]
+
+use core::alloc::{AllocError, Allocator, Layout};
+use core::cell::Cell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+use num_utils::align_up;
+
+/// A bump allocator handing out aligned byte ranges from a fixed buffer.
+///
+/// Individual deallocations are not tracked, except that freeing the most recent allocation
+/// returns its space to the arena. All memory can be reclaimed at once with [`Arena::reset`].
+///
+/// This type implements [`Allocator`], so it can be used to back collections such as `Box` and
+/// `Vec` before a general-purpose heap is available.
+pub struct Arena<'a> {
+    base: NonNull<u8>,
+    size: usize,
+    cur: Cell<usize>,
+    last: Cell<usize>,
+    _marker: PhantomData<&'a mut [MaybeUninit<u8>]>,
+}
+
+impl<'a> Arena<'a> {
+    /// Creates a new arena allocating out of `buf`.
+    pub fn new(buf: &'a mut [MaybeUninit<u8>]) -> Self {
+        Self {
+            base: NonNull::new(buf.as_mut_ptr().cast()).unwrap(),
+            size: buf.len(),
+            cur: Cell::new(0),
+            last: Cell::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the total size of the arena's buffer, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the number of bytes consumed so far, including alignment padding.
+    pub fn used(&self) -> usize {
+        self.cur.get()
+    }
+
+    /// Returns the number of bytes still available, not accounting for alignment padding.
+    pub fn remaining(&self) -> usize {
+        self.size - self.cur.get()
+    }
+
+    /// Releases all allocations made from the arena.
+    ///
+    /// Taking `&mut self` guarantees that no outstanding allocations can be borrowing from the
+    /// arena.
+    pub fn reset(&mut self) {
+        self.cur.set(0);
+        self.last.set(0);
+    }
+
+    fn alloc_offset(&self, layout: Layout) -> Option<usize> {
+        let base_addr = self.base.as_ptr() as usize;
+        let start_addr = align_up(base_addr.checked_add(self.cur.get())?, layout.align());
+        let start = start_addr - base_addr;
+
+        if start > self.size || layout.size() > self.size - start {
+            return None;
+        }
+
+        self.last.set(start);
+        self.cur.set(start + layout.size());
+        Some(start)
+    }
+}
+
+unsafe impl Allocator for Arena<'_> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let offset = self.alloc_offset(layout).ok_or(AllocError)?;
+
+        // Safety: `alloc_offset` guarantees that the range lies within our buffer.
+        let ptr = unsafe { NonNull::new_unchecked(self.base.as_ptr().add(offset)) };
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let offset = ptr.as_ptr() as usize - self.base.as_ptr() as usize;
+        if offset == self.last.get() && offset + layout.size() == self.cur.get() {
+            self.cur.set(offset);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::boxed::Box;
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    #[repr(align(64))]
+    struct Buf([MaybeUninit<u8>; 256]);
+
+    impl Buf {
+        fn new() -> Self {
+            Self([MaybeUninit::uninit(); 256])
+        }
+    }
+
+    #[test]
+    fn mixed_alignment() {
+        let mut buf = Buf::new();
+        let arena = Arena::new(&mut buf.0);
+        assert_eq!(arena.size(), 256);
+        assert_eq!(arena.used(), 0);
+
+        let byte = arena.allocate(Layout::new::<u8>()).unwrap();
+        assert_eq!(arena.used(), 1);
+
+        let word = arena.allocate(Layout::new::<u64>()).unwrap();
+        assert_eq!(word.as_ptr().cast::<u8>() as usize % 8, 0);
+        assert_eq!(arena.used(), 16);
+
+        let line = arena
+            .allocate(Layout::from_size_align(10, 64).unwrap())
+            .unwrap();
+        assert_eq!(line.as_ptr().cast::<u8>() as usize % 64, 0);
+        assert_eq!(line.len(), 10);
+        assert_eq!(arena.used(), 74);
+        assert_eq!(arena.remaining(), 256 - 74);
+
+        assert!(byte.as_ptr().cast::<u8>() < word.as_ptr().cast());
+        assert!(word.as_ptr().cast::<u8>() < line.as_ptr().cast());
+    }
+
+    #[test]
+    fn exhaustion() {
+        let mut buf = Buf::new();
+        let arena = Arena::new(&mut buf.0);
+
+        assert!(arena.allocate(Layout::array::<u8>(257).unwrap()).is_err());
+        assert_eq!(arena.used(), 0);
+
+        arena.allocate(Layout::array::<u8>(250).unwrap()).unwrap();
+        assert!(arena.allocate(Layout::new::<u64>()).is_err());
+        assert_eq!(arena.used(), 250);
+
+        arena.allocate(Layout::array::<u8>(6).unwrap()).unwrap();
+        assert_eq!(arena.remaining(), 0);
+        assert!(arena.allocate(Layout::new::<u8>()).is_err());
+        arena.allocate(Layout::new::<()>()).unwrap();
+    }
+
+    #[test]
+    fn free_last_allocation() {
+        let mut buf = Buf::new();
+        let arena = Arena::new(&mut buf.0);
+
+        let first = arena.allocate(Layout::new::<u32>()).unwrap();
+        let second = arena.allocate(Layout::new::<u32>()).unwrap();
+        assert_eq!(arena.used(), 8);
+
+        // Freeing anything other than the most recent allocation is a no-op.
+        // Safety: `first` was allocated from `arena` with this layout.
+        unsafe { arena.deallocate(first.cast(), Layout::new::<u32>()) };
+        assert_eq!(arena.used(), 8);
+
+        // Safety: `second` was allocated from `arena` with this layout.
+        unsafe { arena.deallocate(second.cast(), Layout::new::<u32>()) };
+        assert_eq!(arena.used(), 4);
+
+        let third = arena.allocate(Layout::new::<u32>()).unwrap();
+        assert_eq!(third, second);
+    }
+
+    #[test]
+    fn reset() {
+        let mut buf = Buf::new();
+        let mut arena = Arena::new(&mut buf.0);
+
+        arena.allocate(Layout::array::<u8>(200).unwrap()).unwrap();
+        arena.reset();
+        assert_eq!(arena.used(), 0);
+        arena.allocate(Layout::array::<u8>(256).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn backs_collections() {
+        let mut buf = Buf::new();
+        let arena = Arena::new(&mut buf.0);
+
+        let boxed = Box::new_in(0x1234_5678_u32, &arena);
+        let mut vec = Vec::with_capacity_in(4, &arena);
+        vec.extend([1_u16, 2, 3, 4]);
+
+        assert_eq!(*boxed, 0x1234_5678);
+        assert_eq!(vec, [1, 2, 3, 4]);
+
+        // Growing the vector past the space left in the arena fails gracefully.
+        assert!(vec.try_reserve(1000).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::vec::Vec;
+use core::{mem, slice};
+
+use crate::{
+    Header, NoteHeader, ProgramHeader, ABI_SYSV, ABI_VERSION_CURRENT, CLASS_64, DATA_LE,
+    ELF_TYPE_EXEC, IDENT_VERSION_CURRENT, MAGIC, SEGMENT_FLAG_READ, SEGMENT_TYPE_LOAD,
+    SEGMENT_TYPE_NOTE, VERSION_CURRENT,
+};
+
+const MACHINE_X86_64: u16 = 62;
+const DEFAULT_SEGMENT_ALIGN: u64 = 0x1000;
+const NOTE_ALIGN: usize = 4;
+
+/// A segment to be emitted by an [`ElfBuilder`].
+///
+/// The file offset and file size of the segment are filled in when the image is built.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub ty: u32,
+    pub flags: u32,
+    pub virt_addr: u64,
+    pub phys_addr: u64,
+    pub mem_size: u64,
+    pub align: u64,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Creates a page-aligned loadable segment with the specified flags, identity-mapped at
+    /// `addr` and containing exactly `data`.
+    pub fn load(flags: u32, addr: u64, data: Vec<u8>) -> Self {
+        Self {
+            ty: SEGMENT_TYPE_LOAD,
+            flags,
+            virt_addr: addr,
+            phys_addr: addr,
+            mem_size: data.len() as u64,
+            align: DEFAULT_SEGMENT_ALIGN,
+            data,
+        }
+    }
+
+    /// Creates a non-loadable note segment containing a single note with the specified name, type
+    /// and contents.
+    ///
+    /// `name` should not include a terminating NUL; one is added automatically.
+    pub fn note(name: &[u8], ty: u32, desc: &[u8]) -> Self {
+        let header = NoteHeader {
+            name_size: (name.len() + 1) as u32,
+            desc_size: desc.len() as u32,
+            ty,
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(as_bytes(&header));
+        data.extend_from_slice(name);
+        data.push(0);
+        data.resize(data.len().next_multiple_of(NOTE_ALIGN), 0);
+        data.extend_from_slice(desc);
+        data.resize(data.len().next_multiple_of(NOTE_ALIGN), 0);
+
+        Self {
+            ty: SEGMENT_TYPE_NOTE,
+            flags: SEGMENT_FLAG_READ,
+            virt_addr: 0,
+            phys_addr: 0,
+            mem_size: data.len() as u64,
+            align: NOTE_ALIGN as u64,
+            data,
+        }
+    }
+
+    /// Extends the in-memory size of the segment to `mem_size`, leaving the remainder
+    /// zero-initialized when loaded.
+    pub fn with_mem_size(mut self, mem_size: u64) -> Self {
+        self.mem_size = mem_size;
+        self
+    }
+}
+
+/// Assembles a 64-bit little-endian ELF image in memory.
+///
+/// The image consists of the ELF header, immediately followed by the program header table and then
+/// the data of every segment. Segment data is placed at file offsets congruent to the segment's
+/// virtual address modulo its alignment, as loaders expect.
+#[derive(Debug, Clone)]
+pub struct ElfBuilder {
+    ty: u16,
+    entry: u64,
+    segments: Vec<Segment>,
+}
+
+impl ElfBuilder {
+    /// Creates a builder for an executable image with entry point `entry` and no segments.
+    pub fn new(entry: u64) -> Self {
+        Self {
+            ty: ELF_TYPE_EXEC,
+            entry,
+            segments: Vec::new(),
+        }
+    }
+
+    /// Sets the ELF type of the image.
+    pub fn ty(mut self, ty: u16) -> Self {
+        self.ty = ty;
+        self
+    }
+
+    /// Appends `segment` to the image.
+    pub fn segment(mut self, segment: Segment) -> Self {
+        self.segments.push(segment);
+        self
+    }
+
+    /// Builds the image.
+    pub fn build(&self) -> Vec<u8> {
+        self.build_with(|_, _| {})
+    }
+
+    /// Builds the image, invoking `fixup` on the laid-out headers before they are written.
+    ///
+    /// This is useful for crafting deliberately malformed images.
+    pub fn build_with(&self, fixup: impl FnOnce(&mut Header, &mut [ProgramHeader])) -> Vec<u8> {
+        let ph_off = mem::size_of::<Header>() as u64;
+        let mut data_off = ph_off + (self.segments.len() * mem::size_of::<ProgramHeader>()) as u64;
+
+        let mut pheaders: Vec<_> = self
+            .segments
+            .iter()
+            .map(|segment| {
+                let align = segment.align.max(1);
+                data_off += segment.virt_addr.wrapping_sub(data_off) % align;
+
+                let pheader = ProgramHeader {
+                    ty: segment.ty,
+                    flags: segment.flags,
+                    off: data_off,
+                    virt_addr: segment.virt_addr,
+                    phys_addr: segment.phys_addr,
+                    file_size: segment.data.len() as u64,
+                    mem_size: segment.mem_size,
+                    align: segment.align,
+                };
+
+                data_off += pheader.file_size;
+                pheader
+            })
+            .collect();
+
+        let mut header = Header {
+            magic: MAGIC,
+            class: CLASS_64,
+            data: DATA_LE,
+            ident_version: IDENT_VERSION_CURRENT,
+            abi: ABI_SYSV,
+            abi_version: ABI_VERSION_CURRENT,
+            pad: [0; 7],
+            ty: self.ty,
+            machine: MACHINE_X86_64,
+            version: VERSION_CURRENT,
+            entry: self.entry,
+            ph_off,
+            sh_off: 0,
+            flags: 0,
+            header_size: mem::size_of::<Header>() as u16,
+            ph_entry_size: mem::size_of::<ProgramHeader>() as u16,
+            ph_entry_num: pheaders.len() as u16,
+            sh_entry_size: 0,
+            sh_entry_num: 0,
+            sh_str_index: 0,
+        };
+
+        // Segment placement is computed before the fixup, so that headers can be corrupted without
+        // moving any data.
+        let offsets: Vec<_> = pheaders.iter().map(|pheader| pheader.off).collect();
+
+        fixup(&mut header, &mut pheaders);
+
+        let mut image = Vec::with_capacity(data_off as usize);
+        image.extend_from_slice(as_bytes(&header));
+        for pheader in &pheaders {
+            image.extend_from_slice(as_bytes(pheader));
+        }
+
+        for (segment, off) in self.segments.iter().zip(offsets) {
+            image.resize(off as usize, 0);
+            image.extend_from_slice(&segment.data);
+        }
+
+        image
+    }
+}
+
+fn as_bytes<T: Copy>(val: &T) -> &[u8] {
+    // Safety: only used with the `repr(C)` header structures, which contain no padding.
+    unsafe { slice::from_raw_parts(val as *const T as *const u8, mem::size_of::<T>()) }
+}
@@ -2,6 +2,17 @@
 #![deny(unsafe_op_in_unsafe_fn)]
 #![no_std]
 
+#[cfg(any(test, feature = "alloc"))]
+extern crate alloc;
+
+#[cfg(any(test, feature = "alloc"))]
+mod builder;
+mod parse;
+
+#[cfg(any(test, feature = "alloc"))]
+pub use builder::{ElfBuilder, Segment};
+pub use parse::{parse, Elf, Note, ParseError};
+
 pub const MAGIC: [u8; 4] = *b"\x7fELF";
 pub const CLASS_64: u8 = 2;
 pub const DATA_LE: u8 = 1;
@@ -15,11 +26,15 @@ pub const ELF_TYPE_DYN: u16 = 3;
 
 pub const SEGMENT_TYPE_NULL: u32 = 0;
 pub const SEGMENT_TYPE_LOAD: u32 = 1;
+pub const SEGMENT_TYPE_NOTE: u32 = 4;
 
 pub const SEGMENT_FLAG_READ: u32 = 4;
 pub const SEGMENT_FLAG_WRITE: u32 = 2;
 pub const SEGMENT_FLAG_EXEC: u32 = 1;
 
+pub const NOTE_NAME_GNU: &[u8] = b"GNU";
+pub const NOTE_TYPE_GNU_BUILD_ID: u32 = 3;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub struct Header {
@@ -69,3 +84,11 @@ pub struct ProgramHeader {
     pub mem_size: u64,
     pub align: u64,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct NoteHeader {
+    pub name_size: u32,
+    pub desc_size: u32,
+    pub ty: u32,
+}
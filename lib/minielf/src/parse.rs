@@ -0,0 +1,550 @@
+use core::mem::{self, MaybeUninit};
+use core::{fmt, ptr};
+
+use crate::{
+    Header, NoteHeader, ProgramHeader, NOTE_NAME_GNU, NOTE_TYPE_GNU_BUILD_ID, SEGMENT_TYPE_NOTE,
+};
+
+/// Errors that can occur when parsing an in-memory ELF image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The image is too short to contain the requested structure.
+    Truncated,
+    /// The ELF header does not describe a 64-bit, little-endian, System V image.
+    InvalidHeader,
+    /// The program header entry size recorded in the ELF header does not match
+    /// [`ProgramHeader`].
+    BadProgramHeaderSize,
+    /// A note segment lies outside of the image or does not consist of well-formed notes.
+    BadNote,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated => f.write_str("image truncated"),
+            ParseError::InvalidHeader => f.write_str("invalid ELF header"),
+            ParseError::BadProgramHeaderSize => f.write_str("bad program header entry size"),
+            ParseError::BadNote => f.write_str("malformed note segment"),
+        }
+    }
+}
+
+impl Header {
+    /// Reads a header from the start of `bytes`, which need not be suitably aligned for
+    /// [`Header`].
+    ///
+    /// The header is copied out of `bytes`; any data following it is ignored. The contents of the
+    /// header are not validated, see [`Header::is_valid`].
+    ///
+    /// # Errors
+    ///
+    /// * [`ParseError::Truncated`] - `bytes` is shorter than a header.
+    pub fn read_from(bytes: &[u8]) -> Result<Header, ParseError> {
+        let bytes = bytes
+            .get(..mem::size_of::<Header>())
+            .ok_or(ParseError::Truncated)?;
+
+        // Safety: `Header` is valid for any bit pattern and `bytes` is exactly the right size.
+        Ok(unsafe { read_unaligned(bytes) })
+    }
+}
+
+impl TryFrom<&[u8]> for Header {
+    type Error = ParseError;
+
+    /// Equivalent to [`Header::read_from`].
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Header::read_from(bytes)
+    }
+}
+
+/// A parsed view of an ELF image stored in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Elf<'a> {
+    bytes: &'a [u8],
+    header: Header,
+}
+
+impl<'a> Elf<'a> {
+    /// Returns the ELF header of the image.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns an iterator over the program headers of the image.
+    pub fn program_headers(&self) -> impl Iterator<Item = ProgramHeader> + 'a {
+        let off = self.header.ph_off as usize;
+        let len = self.header.ph_entry_num as usize * mem::size_of::<ProgramHeader>();
+
+        // `parse` has already checked that a non-empty program header table is in bounds, but the
+        // offset of an empty table is not meaningful and may lie anywhere.
+        self.bytes
+            .get(off..off + len)
+            .unwrap_or_default()
+            .chunks_exact(mem::size_of::<ProgramHeader>())
+            .map(|chunk| {
+                // Safety: `ProgramHeader` is valid for any bit pattern and `chunk` is exactly the
+                // right size.
+                unsafe { read_unaligned(chunk) }
+            })
+    }
+
+    /// Returns the file contents of the segment described by `pheader`, or `None` if they lie
+    /// outside of the image.
+    pub fn segment_data(&self, pheader: &ProgramHeader) -> Option<&'a [u8]> {
+        let start = usize::try_from(pheader.off).ok()?;
+        let len = usize::try_from(pheader.file_size).ok()?;
+        self.bytes.get(start..start.checked_add(len)?)
+    }
+
+    /// Returns an iterator over the notes contained in all note segments of the image.
+    pub fn notes(&self) -> impl Iterator<Item = Note<'a>> + 'a {
+        let elf = *self;
+        self.program_headers()
+            .filter(|pheader| pheader.ty == SEGMENT_TYPE_NOTE)
+            .flat_map(move |pheader| {
+                // `parse` has already checked that every note segment is in bounds.
+                let data = elf.segment_data(&pheader).unwrap_or_default();
+                Notes {
+                    data,
+                    align: note_align(&pheader),
+                }
+            })
+    }
+
+    /// Returns the contents of the GNU build-id note of the image, if it has one.
+    pub fn build_id(&self) -> Option<&'a [u8]> {
+        self.notes()
+            .find(|note| note.name == NOTE_NAME_GNU && note.ty == NOTE_TYPE_GNU_BUILD_ID)
+            .map(|note| note.desc)
+    }
+}
+
+/// A single note stored in a note segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note<'a> {
+    /// The name of the note's originator, without its terminating NUL.
+    pub name: &'a [u8],
+    /// The originator-specific type of the note.
+    pub ty: u32,
+    /// The contents of the note.
+    pub desc: &'a [u8],
+}
+
+/// Iterates over the notes in the contents of a single note segment.
+struct Notes<'a> {
+    data: &'a [u8],
+    align: usize,
+}
+
+impl<'a> Iterator for Notes<'a> {
+    type Item = Note<'a>;
+
+    fn next(&mut self) -> Option<Note<'a>> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        // `parse` has already checked that the segment is well-formed, so stop quietly on error.
+        let (note, rest) = split_note(self.data, self.align).ok()?;
+        self.data = rest;
+        Some(note)
+    }
+}
+
+/// Parses the ELF image in `bytes`, validating its header and the bounds of its program header
+/// table.
+///
+/// `bytes` need not be suitably aligned for [`Header`] or [`ProgramHeader`]; all structures are
+/// copied out of the image.
+///
+/// # Errors
+///
+/// * [`ParseError::Truncated`] - The image is too short to contain its ELF header or program
+///   header table.
+/// * [`ParseError::InvalidHeader`] - The ELF header is not [valid](Header::is_valid).
+/// * [`ParseError::BadProgramHeaderSize`] - The program header entry size is not the size of
+///   [`ProgramHeader`].
+pub fn parse(bytes: &[u8]) -> Result<Elf<'_>, ParseError> {
+    let header = Header::read_from(bytes)?;
+
+    if !header.is_valid() {
+        return Err(ParseError::InvalidHeader);
+    }
+
+    if header.ph_entry_num != 0 {
+        if header.ph_entry_size as usize != mem::size_of::<ProgramHeader>() {
+            return Err(ParseError::BadProgramHeaderSize);
+        }
+
+        let table_len = header.ph_entry_num as usize * mem::size_of::<ProgramHeader>();
+        let table_end = usize::try_from(header.ph_off)
+            .ok()
+            .and_then(|off| off.checked_add(table_len))
+            .ok_or(ParseError::Truncated)?;
+
+        if table_end > bytes.len() {
+            return Err(ParseError::Truncated);
+        }
+    }
+
+    let elf = Elf { bytes, header };
+
+    for pheader in elf.program_headers() {
+        if pheader.ty == SEGMENT_TYPE_NOTE {
+            let mut data = elf.segment_data(&pheader).ok_or(ParseError::BadNote)?;
+            while !data.is_empty() {
+                data = split_note(data, note_align(&pheader))?.1;
+            }
+        }
+    }
+
+    Ok(elf)
+}
+
+/// Returns the alignment of the name and contents of notes in the segment described by
+/// `pheader`.
+///
+/// Notes are normally 4-byte aligned, but some toolchains emit 8-byte aligned notes and mark their
+/// segments accordingly.
+fn note_align(pheader: &ProgramHeader) -> usize {
+    if pheader.align == 8 {
+        8
+    } else {
+        4
+    }
+}
+
+/// Splits the first note off of `data`, returning it along with the remaining notes.
+///
+/// # Errors
+///
+/// * [`ParseError::BadNote`] - `data` does not start with a complete note.
+fn split_note(data: &[u8], align: usize) -> Result<(Note<'_>, &[u8]), ParseError> {
+    let header_bytes = data
+        .get(..mem::size_of::<NoteHeader>())
+        .ok_or(ParseError::BadNote)?;
+
+    // Safety: `NoteHeader` is valid for any bit pattern and `header_bytes` is exactly the right
+    // size.
+    let header: NoteHeader = unsafe { read_unaligned(header_bytes) };
+
+    let name_off = mem::size_of::<NoteHeader>();
+    let name_size = header.name_size as usize;
+    let desc_off = name_off
+        .checked_add(name_size)
+        .and_then(|name_end| name_end.checked_next_multiple_of(align))
+        .ok_or(ParseError::BadNote)?;
+    let desc_size = header.desc_size as usize;
+    let desc_end = desc_off.checked_add(desc_size).ok_or(ParseError::BadNote)?;
+
+    let name = data
+        .get(name_off..name_off + name_size)
+        .ok_or(ParseError::BadNote)?;
+    let desc = data.get(desc_off..desc_end).ok_or(ParseError::BadNote)?;
+
+    // The final note in a segment may omit its trailing padding.
+    let rest = desc_end
+        .checked_next_multiple_of(align)
+        .and_then(|next| data.get(next..))
+        .unwrap_or_default();
+
+    let note = Note {
+        name: name.strip_suffix(&[0]).unwrap_or(name),
+        ty: header.ty,
+        desc,
+    };
+
+    Ok((note, rest))
+}
+
+/// Copies a `T` out of `bytes`, which need not be aligned.
+///
+/// # Safety
+///
+/// `T` must be valid for any bit pattern.
+///
+/// # Panics
+///
+/// Panics if `bytes` is not exactly the size of `T`.
+unsafe fn read_unaligned<T>(bytes: &[u8]) -> T {
+    assert_eq!(bytes.len(), mem::size_of::<T>());
+
+    let mut val = MaybeUninit::<T>::uninit();
+
+    // Safety: the destination is valid for `size_of::<T>()` bytes and cannot overlap `bytes`, and
+    // the caller guarantees that the copied bytes form a valid `T`.
+    unsafe {
+        ptr::copy_nonoverlapping(bytes.as_ptr(), val.as_mut_ptr() as *mut u8, bytes.len());
+        val.assume_init()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::{
+        ElfBuilder, Segment, ELF_TYPE_DYN, ELF_TYPE_EXEC, SEGMENT_FLAG_EXEC, SEGMENT_FLAG_READ,
+        SEGMENT_FLAG_WRITE, SEGMENT_TYPE_LOAD,
+    };
+
+    fn test_image() -> ElfBuilder {
+        ElfBuilder::new(0x201000)
+            .segment(Segment::load(
+                SEGMENT_FLAG_READ | SEGMENT_FLAG_EXEC,
+                0x201000,
+                vec![0xcc; 0x30],
+            ))
+            .segment(
+                Segment::load(
+                    SEGMENT_FLAG_READ | SEGMENT_FLAG_WRITE,
+                    0x202040,
+                    vec![1, 2, 3],
+                )
+                .with_mem_size(0x100),
+            )
+    }
+
+    #[test]
+    fn build_parse_round_trip() {
+        let image = test_image().build();
+        let elf = parse(&image).unwrap();
+
+        let header = elf.header();
+        assert!(header.is_valid());
+        assert_eq!(header.ty, ELF_TYPE_EXEC);
+        assert_eq!(header.entry, 0x201000);
+        assert_eq!(header.ph_entry_num, 2);
+
+        let pheaders: Vec<_> = elf.program_headers().collect();
+        assert_eq!(pheaders.len(), 2);
+
+        let text = &pheaders[0];
+        assert_eq!(text.ty, SEGMENT_TYPE_LOAD);
+        assert_eq!(text.flags, SEGMENT_FLAG_READ | SEGMENT_FLAG_EXEC);
+        assert_eq!(text.virt_addr, 0x201000);
+        assert_eq!(text.off % 0x1000, 0);
+        assert_eq!(text.mem_size, 0x30);
+        assert_eq!(elf.segment_data(text), Some(&[0xcc; 0x30][..]));
+
+        let data = &pheaders[1];
+        assert_eq!(data.flags, SEGMENT_FLAG_READ | SEGMENT_FLAG_WRITE);
+        assert_eq!(data.virt_addr, 0x202040);
+        assert_eq!(data.off % 0x1000, 0x40);
+        assert_eq!(data.file_size, 3);
+        assert_eq!(data.mem_size, 0x100);
+        assert_eq!(elf.segment_data(data), Some(&[1, 2, 3][..]));
+
+        assert_eq!(elf.build_id(), None);
+    }
+
+    #[test]
+    fn build_sets_type() {
+        let image = ElfBuilder::new(0).ty(ELF_TYPE_DYN).build();
+        let elf = parse(&image).unwrap();
+        assert_eq!(elf.header().ty, ELF_TYPE_DYN);
+        assert_eq!(elf.program_headers().count(), 0);
+    }
+
+    #[test]
+    fn parse_rejects_bad_magic() {
+        let image = test_image().build_with(|header, _| header.magic = *b"\x7fELG");
+        assert_eq!(parse(&image).unwrap_err(), ParseError::InvalidHeader);
+    }
+
+    #[test]
+    fn parse_rejects_wrong_class() {
+        let image = test_image().build_with(|header, _| header.class = 1);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::InvalidHeader);
+    }
+
+    #[test]
+    fn parse_rejects_bad_program_header_size() {
+        let image = test_image().build_with(|header, _| header.ph_entry_size -= 1);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadProgramHeaderSize);
+    }
+
+    #[test]
+    fn parse_rejects_out_of_bounds_program_headers() {
+        let image = test_image().build_with(|header, _| header.ph_entry_num = 0x1000);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::Truncated);
+
+        let image = test_image().build_with(|header, _| header.ph_off = u64::MAX);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::Truncated);
+    }
+
+    #[test]
+    fn parse_ignores_offset_of_empty_program_header_table() {
+        for ph_off in [0x1000, u64::MAX] {
+            let image = ElfBuilder::new(0).build_with(|header, _| header.ph_off = ph_off);
+            let elf = parse(&image).unwrap();
+            assert_eq!(elf.program_headers().count(), 0);
+        }
+    }
+
+    #[test]
+    fn parse_rejects_truncated_image() {
+        let image = test_image().build();
+        assert_eq!(
+            parse(&image[..mem::size_of::<Header>() - 1]).unwrap_err(),
+            ParseError::Truncated
+        );
+        assert_eq!(
+            parse(&image[..mem::size_of::<Header>() + 1]).unwrap_err(),
+            ParseError::Truncated
+        );
+    }
+
+    #[test]
+    fn read_header_misaligned() {
+        let image = test_image().build();
+
+        // Offset the image by one byte so that the header is misaligned for every possible
+        // placement of the buffer.
+        let mut buf = vec![0; image.len() + 1];
+        let start = if buf.as_ptr() as usize % mem::align_of::<Header>() == 0 {
+            1
+        } else {
+            0
+        };
+        buf[start..start + image.len()].copy_from_slice(&image);
+        let misaligned = &buf[start..start + image.len()];
+        assert_ne!(misaligned.as_ptr() as usize % mem::align_of::<Header>(), 0);
+
+        let header = Header::read_from(misaligned).unwrap();
+        assert_eq!(header, *parse(&image).unwrap().header());
+        assert_eq!(header.entry, 0x201000);
+        assert_eq!(Header::try_from(misaligned), Ok(header));
+
+        let elf = parse(misaligned).unwrap();
+        assert_eq!(elf.program_headers().count(), 2);
+    }
+
+    #[test]
+    fn read_header_truncated() {
+        let image = test_image().build();
+        let header_size = mem::size_of::<Header>();
+
+        assert!(Header::read_from(&image[..header_size]).is_ok());
+        assert_eq!(
+            Header::read_from(&image[..header_size - 1]),
+            Err(ParseError::Truncated)
+        );
+        assert_eq!(Header::read_from(&[]), Err(ParseError::Truncated));
+    }
+
+    const BUILD_ID: [u8; 20] = [
+        0x3a, 0x61, 0x5f, 0x42, 0x90, 0x1b, 0xc7, 0x08, 0xe4, 0x2d, 0x55, 0x6e, 0x71, 0x0c, 0x9f,
+        0xb3, 0x28, 0x47, 0xd6, 0x11,
+    ];
+
+    fn note_image() -> ElfBuilder {
+        test_image()
+            .segment(Segment::note(b"Xen", 1, &[1, 2, 3, 4, 5]))
+            .segment(Segment::note(
+                NOTE_NAME_GNU,
+                NOTE_TYPE_GNU_BUILD_ID,
+                &BUILD_ID,
+            ))
+    }
+
+    #[test]
+    fn build_id() {
+        let image = note_image().build();
+        let elf = parse(&image).unwrap();
+
+        let notes: Vec<_> = elf.notes().collect();
+        assert_eq!(
+            notes,
+            [
+                Note {
+                    name: b"Xen",
+                    ty: 1,
+                    desc: &[1, 2, 3, 4, 5],
+                },
+                Note {
+                    name: b"GNU",
+                    ty: NOTE_TYPE_GNU_BUILD_ID,
+                    desc: &BUILD_ID,
+                },
+            ]
+        );
+
+        assert_eq!(elf.build_id(), Some(&BUILD_ID[..]));
+    }
+
+    #[test]
+    fn build_id_wrong_type() {
+        let image = test_image()
+            .segment(Segment::note(NOTE_NAME_GNU, 1, &BUILD_ID))
+            .build();
+        let elf = parse(&image).unwrap();
+        assert_eq!(elf.notes().count(), 1);
+        assert_eq!(elf.build_id(), None);
+    }
+
+    #[test]
+    fn build_id_multiple_notes_in_segment() {
+        let mut segment = Segment::note(b"Xen", 1, &[1, 2, 3]);
+        let build_id = Segment::note(NOTE_NAME_GNU, NOTE_TYPE_GNU_BUILD_ID, &BUILD_ID);
+        segment.data.extend_from_slice(&build_id.data);
+        segment.mem_size = segment.data.len() as u64;
+
+        let image = test_image().segment(segment).build();
+        let elf = parse(&image).unwrap();
+        assert_eq!(elf.notes().count(), 2);
+        assert_eq!(elf.build_id(), Some(&BUILD_ID[..]));
+    }
+
+    #[test]
+    fn parse_rejects_truncated_note() {
+        // Cut the build-id note off in the middle of its contents.
+        let image = note_image().build_with(|_, pheaders| pheaders[3].file_size -= 8);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadNote);
+
+        // Leave only part of the note header.
+        let image = note_image().build_with(|_, pheaders| pheaders[3].file_size = 6);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadNote);
+
+        // Claim more contents than the segment holds.
+        let mut segment = Segment::note(NOTE_NAME_GNU, NOTE_TYPE_GNU_BUILD_ID, &BUILD_ID);
+        segment.data[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let image = test_image().segment(segment).build();
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadNote);
+    }
+
+    #[test]
+    fn parse_rejects_out_of_bounds_note() {
+        let image = note_image().build_with(|_, pheaders| pheaders[3].off = 0x100000);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadNote);
+
+        let image = note_image().build_with(|_, pheaders| pheaders[3].off = u64::MAX);
+        assert_eq!(parse(&image).unwrap_err(), ParseError::BadNote);
+    }
+
+    #[test]
+    fn parse_skips_note_validation_without_program_headers() {
+        // The note segments are still present in the image, but the header no longer refers to
+        // them.
+        let image = note_image().build_with(|header, _| {
+            header.ph_entry_num = 0;
+            header.ph_off = 0x100000;
+        });
+        let elf = parse(&image).unwrap();
+        assert_eq!(elf.notes().count(), 0);
+        assert_eq!(elf.build_id(), None);
+    }
+
+    #[test]
+    fn segment_data_out_of_bounds() {
+        let image = test_image().build_with(|_, pheaders| pheaders[1].file_size = 0x10000);
+        let elf = parse(&image).unwrap();
+        let pheaders: Vec<_> = elf.program_headers().collect();
+        assert!(elf.segment_data(&pheaders[0]).is_some());
+        assert_eq!(elf.segment_data(&pheaders[1]), None);
+    }
+}
@@ -25,3 +25,86 @@ pub const fn log2_ceil(val: usize) -> usize {
 
     log2(val - 1) + 1
 }
+
+/// Returns the smallest power of two greater than or equal to `val`.
+///
+/// Both 0 and 1 are rounded up to 1. If the result would not fit in a `usize`, 0 is returned
+/// instead.
+pub const fn next_power_of_two(val: usize) -> usize {
+    let shift = log2_ceil(val);
+    if shift >= usize::BITS as usize {
+        return 0;
+    }
+
+    1 << shift
+}
+
+/// Returns the largest power of two less than or equal to `val`, or 0 if `val` is 0.
+pub const fn prev_power_of_two(val: usize) -> usize {
+    if val == 0 {
+        return 0;
+    }
+
+    1 << log2(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP_BIT: usize = 1 << (usize::BITS - 1);
+
+    #[test]
+    fn next_power_of_two_small() {
+        assert_eq!(next_power_of_two(0), 1);
+        assert_eq!(next_power_of_two(1), 1);
+        assert_eq!(next_power_of_two(2), 2);
+        assert_eq!(next_power_of_two(3), 4);
+        assert_eq!(next_power_of_two(5), 8);
+    }
+
+    #[test]
+    fn next_power_of_two_exact() {
+        for shift in 0..usize::BITS {
+            assert_eq!(next_power_of_two(1 << shift), 1 << shift);
+        }
+    }
+
+    #[test]
+    fn next_power_of_two_overflow() {
+        assert_eq!(next_power_of_two(TOP_BIT - 1), TOP_BIT);
+        assert_eq!(next_power_of_two(TOP_BIT), TOP_BIT);
+        assert_eq!(next_power_of_two(TOP_BIT + 1), 0);
+        assert_eq!(next_power_of_two(usize::MAX), 0);
+    }
+
+    #[test]
+    fn prev_power_of_two_small() {
+        assert_eq!(prev_power_of_two(0), 0);
+        assert_eq!(prev_power_of_two(1), 1);
+        assert_eq!(prev_power_of_two(2), 2);
+        assert_eq!(prev_power_of_two(3), 2);
+        assert_eq!(prev_power_of_two(7), 4);
+    }
+
+    #[test]
+    fn prev_power_of_two_exact() {
+        for shift in 0..usize::BITS {
+            assert_eq!(prev_power_of_two(1 << shift), 1 << shift);
+        }
+    }
+
+    #[test]
+    fn prev_power_of_two_max() {
+        assert_eq!(prev_power_of_two(TOP_BIT + 1), TOP_BIT);
+        assert_eq!(prev_power_of_two(usize::MAX), TOP_BIT);
+    }
+
+    #[test]
+    fn usable_in_const_context() {
+        const NEXT: usize = next_power_of_two(600);
+        const PREV: usize = prev_power_of_two(600);
+        assert_eq!(NEXT, 1024);
+        assert_eq!(PREV, 512);
+    }
+}
@@ -5,9 +5,10 @@
 #![no_std]
 
 use core::borrow::Borrow;
+use core::fmt::Write;
 use core::{cmp, fmt};
 
-use arrayvec::ArrayString;
+use arrayvec::{ArrayString, ArrayVec};
 
 const MAX_NAME_LEN: usize = 32;
 
@@ -25,6 +26,25 @@ impl Name {
     pub fn new(name: &str) -> Self {
         Self(ArrayString::from(&name[..cmp::min(name.len(), MAX_NAME_LEN)]).unwrap())
     }
+
+    /// Creates a new name of the form `{prefix}-{index}`, such as `worker-3`.
+    ///
+    /// If the result is too long, `prefix` is truncated so that the index is always preserved in
+    /// full.
+    pub fn with_index(prefix: &str, index: usize) -> Self {
+        // Large enough for a `-` followed by the longest possible `usize`.
+        let mut suffix = ArrayString::<21>::new();
+        write!(suffix, "-{index}").unwrap();
+
+        let mut prefix_len = cmp::min(prefix.len(), MAX_NAME_LEN - suffix.len());
+        while !prefix.is_char_boundary(prefix_len) {
+            prefix_len -= 1;
+        }
+
+        let mut name = ArrayString::from(&prefix[..prefix_len]).unwrap();
+        name.push_str(&suffix);
+        Self(name)
+    }
 }
 
 impl fmt::Display for Name {
@@ -44,3 +64,176 @@ impl Borrow<str> for Name {
         &self.0
     }
 }
+
+/// A small integer identifying a [`Name`] interned in a [`NameTable`].
+///
+/// Ids are only meaningful with respect to the table that produced them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NameId(u32);
+
+impl NameId {
+    /// Returns the raw value of this id.
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for NameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// A fixed-capacity table mapping equal [`Name`]s to stable [`NameId`]s.
+///
+/// Names are never removed from the table once interned, so an id remains valid for the lifetime
+/// of the table.
+pub struct NameTable<const N: usize> {
+    names: ArrayVec<Name, N>,
+}
+
+impl<const N: usize> NameTable<N> {
+    /// Creates a new, empty table.
+    pub const fn new() -> Self {
+        Self {
+            names: ArrayVec::new_const(),
+        }
+    }
+
+    /// Returns the id of `name`, interning it if it has not been seen before.
+    ///
+    /// Returns `None` if `name` is not already present and the table is full.
+    pub fn intern(&mut self, name: &Name) -> Option<NameId> {
+        if let Some(index) = self.names.iter().position(|existing| existing == name) {
+            return Some(NameId(index as u32));
+        }
+
+        let index = self.names.len();
+        self.names.try_push(*name).ok()?;
+        Some(NameId(index as u32))
+    }
+
+    /// Returns the name corresponding to `id`, if it was interned in this table.
+    pub fn resolve(&self, id: NameId) -> Option<&Name> {
+        self.names.get(id.0 as usize)
+    }
+
+    /// Returns the number of distinct names interned in the table.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if no names have been interned in the table.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl<const N: usize> Default for NameTable<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::{BTreeSet, HashSet};
+    use std::hash::{Hash, Hasher};
+
+    use super::*;
+
+    fn hash_of<T: Hash + ?Sized>(val: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        val.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn with_index_short() {
+        assert_eq!(Name::with_index("worker", 3).as_ref(), "worker-3");
+        assert_eq!(Name::with_index("", 0).as_ref(), "-0");
+    }
+
+    #[test]
+    fn with_index_truncates_prefix() {
+        let prefix = "a-very-long-thread-name-prefix-that-overflows";
+        let name = Name::with_index(prefix, 1234);
+        assert_eq!(name.as_ref().len(), MAX_NAME_LEN);
+        assert_eq!(name.as_ref(), "a-very-long-thread-name-pre-1234");
+
+        // A prefix that fits exactly is kept whole.
+        let name = Name::with_index(&prefix[..MAX_NAME_LEN - 2], 5);
+        assert_eq!(name.as_ref(), "a-very-long-thread-name-prefix-5");
+    }
+
+    #[test]
+    fn with_index_truncates_on_char_boundary() {
+        // Each `é` is two bytes; 29 bytes are available for the prefix, which would otherwise split
+        // the 15th character.
+        let prefix = "éééééééééééééééééééé";
+        let name = Name::with_index(prefix, 10);
+        assert_eq!(name.as_ref(), "éééééééééééééé-10");
+
+        // Three-byte characters: 29 bytes are available, which fit only 9 of them.
+        let prefix = "€€€€€€€€€€€€";
+        let name = Name::with_index(prefix, 42);
+        assert_eq!(name.as_ref(), "€€€€€€€€€-42");
+        assert_eq!(name.as_ref().len(), MAX_NAME_LEN - 2);
+    }
+
+    #[test]
+    fn with_index_max() {
+        let name = Name::with_index("thread", usize::MAX);
+        assert_eq!(name.as_ref(), std::format!("thread-{}", usize::MAX));
+
+        let name = Name::with_index("a-long-thread-name", usize::MAX);
+        assert_eq!(name.as_ref().len(), MAX_NAME_LEN);
+        assert!(name.as_ref().ends_with(&std::format!("-{}", usize::MAX)));
+        assert!(name.as_ref().starts_with("a-long-thre"));
+    }
+
+    #[test]
+    fn name_ord_by_content() {
+        assert!(Name::new("ab") < Name::new("abc"));
+        assert!(Name::new("abc") < Name::new("abd"));
+        assert!(Name::new("b") > Name::new("abc"));
+        assert!(Name::new("") < Name::new("a"));
+        assert_eq!(
+            Name::new("abc").cmp(&Name::new("abc")),
+            cmp::Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn name_eq_hash_match_str() {
+        let a = Name::new("worker");
+        let b = Name::new("worker");
+        assert_eq!(a, b);
+        assert_eq!(hash_of(&a), hash_of(&b));
+        assert_eq!(hash_of(&a), hash_of("worker"));
+        assert_ne!(a, Name::new("worker2"));
+    }
+
+    #[test]
+    fn name_borrow_lookup() {
+        let names: HashSet<_> = [Name::new("init"), Name::new("idle")].into_iter().collect();
+        assert!(names.contains("init"));
+        assert!(names.contains("idle"));
+        assert!(!names.contains("ini"));
+
+        let sorted: BTreeSet<_> = [Name::new("b"), Name::new("ab"), Name::new("abc")]
+            .into_iter()
+            .collect();
+        assert!(sorted.contains("ab"));
+        assert_eq!(
+            sorted
+                .iter()
+                .map(|name| name.as_ref())
+                .collect::<std::vec::Vec<_>>(),
+            ["ab", "abc", "b"]
+        );
+    }
+}
@@ -7,10 +7,62 @@ use core::hint;
 use core::mem::MaybeUninit;
 use core::sync::atomic::{fence, AtomicBool, AtomicU8, Ordering};
 
+const BACKOFF_SPIN_LIMIT: u32 = 6;
+
 const UNINITIALIZED: u8 = 0;
 const INITIALIZING: u8 = 1;
 const INITIALIZED: u8 = 2;
 
+/// Exponential backoff for spin-wait loops.
+///
+/// Each call to [`spin`](Backoff::spin) busy-waits for twice as many iterations as the previous
+/// one, up to a fixed limit, reducing the amount of cache-line traffic generated by contending
+/// cores.
+///
+/// There is currently no scheduler to yield to, so waiters keep spinning at the maximum step once
+/// the limit is reached. When a scheduler hook becomes available, callers should check
+/// [`is_completed`](Backoff::is_completed) and yield the CPU instead of spinning further.
+pub struct Backoff {
+    step: Cell<u32>,
+}
+
+impl Backoff {
+    /// Creates a new backoff in its initial (shortest) step.
+    pub const fn new() -> Self {
+        Self { step: Cell::new(0) }
+    }
+
+    /// Resets the backoff to its initial step.
+    pub fn reset(&self) {
+        self.step.set(0);
+    }
+
+    /// Busy-waits for the current number of iterations, then advances to the next step.
+    pub fn spin(&self) {
+        let step = self.step.get();
+
+        for _ in 0..1 << step.min(BACKOFF_SPIN_LIMIT) {
+            hint::spin_loop();
+        }
+
+        if step <= BACKOFF_SPIN_LIMIT {
+            self.step.set(step + 1);
+        }
+    }
+
+    /// Returns `true` once spinning has reached its maximum length, indicating that the caller
+    /// would be better served by yielding the CPU.
+    pub fn is_completed(&self) -> bool {
+        self.step.get() > BACKOFF_SPIN_LIMIT
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// A cell-like type for storing a value that can only be initialized once.
 pub struct Once<T> {
     value: UnsafeCell<MaybeUninit<T>>,
@@ -77,8 +129,9 @@ impl<T> Once<T> {
                 unsafe { self.get_unchecked() }
             }
             Err(INITIALIZING) => {
+                let backoff = Backoff::new();
                 while self.state.load(Ordering::Relaxed) == INITIALIZING {
-                    hint::spin_loop();
+                    backoff.spin();
                 }
                 fence(Ordering::Acquire);
                 unsafe { self.get_unchecked() }
@@ -89,6 +142,21 @@ impl<T> Once<T> {
         }
     }
 
+    /// Waits (spins) until some other caller initializes this `Once`, then returns the contained
+    /// value.
+    ///
+    /// Unlike [`Once::get_or_init_with`], this function never initializes the value itself; it is
+    /// intended for consumers that need to wait for a one-shot "ready" signal from another
+    /// subsystem. If nobody ever initializes the `Once`, this function will never return.
+    pub fn wait(&self) -> &T {
+        let backoff = Backoff::new();
+        while self.state.load(Ordering::Relaxed) != INITIALIZED {
+            backoff.spin();
+        }
+        fence(Ordering::Acquire);
+        unsafe { self.get_unchecked() }
+    }
+
     /// Initializes the contained value with `value`.
     ///
     /// This function should be used when there is a single, known initializer at a
@@ -109,6 +177,36 @@ impl<T> Once<T> {
         }
     }
 
+    /// Attempts to initialize the contained value with `value`, returning a reference to it on
+    /// success.
+    ///
+    /// Unlike [`Once::init`], this function does not panic if the `Once` is already initialized;
+    /// instead, `value` is handed back to the caller as an error. The same happens if another
+    /// caller is initializing the `Once` concurrently, in which case this function does not wait
+    /// for that initialization to complete.
+    #[inline]
+    pub fn set(&self, value: T) -> Result<&T, T> {
+        if self
+            .state
+            .compare_exchange(
+                UNINITIALIZED,
+                INITIALIZING,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_err()
+        {
+            return Err(value);
+        }
+
+        // Safety: we initialize the slot
+        Ok(unsafe {
+            self.init_with_unchecked(move |slot| {
+                slot.write(value);
+            })
+        })
+    }
+
     /// Initializes the contained value by invoking `f` on its underlying storage.
     ///
     /// This function should be used when there is a single, known initializer at a
@@ -245,3 +343,89 @@ impl<T> TakeOnce<T> {
 
 // Safety: only one caller is ever allowed access to the inner `T` value.
 unsafe impl<T> Sync for TakeOnce<T> {}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::sync::Barrier;
+    use std::thread;
+    use std::vec::Vec;
+
+    use super::*;
+
+    #[test]
+    fn wait_for_other_initializer() {
+        let once = Once::new();
+        let ready = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            let waiter = scope.spawn(|| {
+                ready.store(true, Ordering::Relaxed);
+                *once.wait()
+            });
+
+            while !ready.load(Ordering::Relaxed) {
+                hint::spin_loop();
+            }
+            assert_eq!(once.get(), None);
+            once.init(42);
+
+            assert_eq!(waiter.join().unwrap(), 42);
+        });
+    }
+
+    #[test]
+    fn wait_already_initialized() {
+        let once = Once::new();
+        once.init(7);
+        assert_eq!(once.wait(), &7);
+    }
+
+    #[test]
+    fn set_first_wins() {
+        let once = Once::new();
+        assert_eq!(once.get(), None);
+
+        assert_eq!(once.set(5), Ok(&5));
+        assert_eq!(once.set(6), Err(6));
+        assert_eq!(once.get(), Some(&5));
+    }
+
+    #[test]
+    fn set_after_init_fails() {
+        let once = Once::new();
+        once.init(1);
+        assert_eq!(once.set(2), Err(2));
+        assert_eq!(once.get(), Some(&1));
+    }
+
+    #[test]
+    fn set_racing() {
+        const THREADS: usize = 8;
+
+        let once = Once::new();
+        let barrier = Barrier::new(THREADS);
+
+        let winners: Vec<_> = thread::scope(|scope| {
+            let threads: Vec<_> = (0..THREADS)
+                .map(|i| {
+                    let once = &once;
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        barrier.wait();
+                        once.set(i).is_ok().then_some(i)
+                    })
+                })
+                .collect();
+
+            threads
+                .into_iter()
+                .filter_map(|thread| thread.join().unwrap())
+                .collect()
+        });
+
+        assert_eq!(winners.len(), 1);
+        assert_eq!(once.get(), Some(&winners[0]));
+    }
+}
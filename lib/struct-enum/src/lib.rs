@@ -5,6 +5,10 @@
 #[doc(hidden)]
 pub use core as _core;
 
+/// Defines a newtype over the integer type `$inner` with named constants for known values.
+///
+/// Unlike a Rust `enum`, the generated type can hold any value of `$inner`, making it suitable for
+/// values read from external sources (such as firmware or file formats) that may not be known.
 #[macro_export]
 macro_rules! struct_enum {
     (
@@ -28,6 +32,11 @@ macro_rules! struct_enum {
             pub const fn from_raw(val: $inner) -> Self {
                 Self(val)
             }
+
+            /// Returns the raw values of all named constants, in declaration order.
+            pub const fn all_values() -> &'static [$inner] {
+                &[$(Self::$variants.0),*]
+            }
         }
 
         impl $crate::_core::fmt::Debug for $name {
@@ -40,3 +49,71 @@ macro_rules! struct_enum {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    struct_enum! {
+        struct Color: u8 {
+            RED = 1;
+            GREEN = 2;
+            BLUE = 0x10;
+        }
+    }
+
+    struct_enum! {
+        struct Empty: u32 {}
+    }
+
+    const fn shifted(bit: u32) -> u32 {
+        1 << bit
+    }
+
+    struct_enum! {
+        struct Flags: u32 {
+            LOW = shifted(0);
+            HIGH = shifted(31);
+        }
+    }
+
+    #[test]
+    fn all_values_match_variants() {
+        assert_eq!(
+            Color::all_values(),
+            [
+                Color::RED.to_raw(),
+                Color::GREEN.to_raw(),
+                Color::BLUE.to_raw()
+            ]
+        );
+        assert_eq!(Color::all_values(), [1, 2, 0x10]);
+        assert_eq!(Flags::all_values(), [1, 0x8000_0000]);
+        assert!(Empty::all_values().is_empty());
+    }
+
+    #[test]
+    fn all_values_in_const_context() {
+        const VALUES: &[u8] = Color::all_values();
+        assert_eq!(VALUES.len(), 3);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        for &raw in Color::all_values() {
+            assert_eq!(Color::from_raw(raw).to_raw(), raw);
+        }
+        assert_eq!(Color::from_raw(2), Color::GREEN);
+        assert_eq!(Flags::from_raw(0x8000_0000), Flags::HIGH);
+        assert_eq!(Flags::LOW.to_raw(), 1);
+        assert_eq!(Empty::from_raw(5).to_raw(), 5);
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!(format!("{:?}", Color::BLUE), "BLUE");
+        assert_eq!(format!("{:?}", Color::from_raw(7)), "Color(7)");
+    }
+}